@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use tokio::{
+    fs,
+    io::{
+        BufReader,
+        BufWriter,
+    },
+};
+
+use alloc_pool::bytes::BytesPool;
+
+use ero::{
+    supervisor::SupervisorGenServer,
+};
+
+use ero_blockwheel_fs as blockwheel;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    /// Filename for blockwheel data
+    #[structopt(short = "w", long = "wheel-filename", default_value = "wheel")]
+    wheel_filename: PathBuf,
+    /// Initial wheel size when creating new file (in bytes)
+    #[structopt(short = "s", long = "init-wheel-size-bytes", default_value = "67108864")]
+    init_wheel_size_bytes: usize,
+    /// work io buffer size (in bytes)
+    #[structopt(long = "work-block-size", default_value = "8388608")]
+    work_block_size: usize,
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Write all wheel blocks into archive file
+    Export {
+        /// Archive filename to create
+        #[structopt(short = "a", long = "archive-filename")]
+        archive_filename: PathBuf,
+    },
+    /// Write all archive blocks into wheel (it is created if not exists)
+    Import {
+        /// Archive filename to read
+        #[structopt(short = "a", long = "archive-filename")]
+        archive_filename: PathBuf,
+        /// Keep original block ids from archive
+        #[structopt(long = "preserve-block-ids")]
+        preserve_block_ids: bool,
+    },
+    /// Convert version 1 wheel into a new current version wheel file
+    UpgradeV1 {
+        /// Wheel filename to create
+        #[structopt(short = "t", long = "target-wheel-filename")]
+        target_wheel_filename: PathBuf,
+    },
+}
+
+#[derive(Debug)]
+enum Error {
+    ThreadPool(edeltraud::BuildError),
+    ArchiveCreate(std::io::Error),
+    ArchiveOpen(std::io::Error),
+    Export(blockwheel::archive::ExportError),
+    Import(blockwheel::archive::ImportError),
+    Upgrade(blockwheel::upgrade::UpgradeError),
+    WheelGoneDuringFlush,
+}
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+    let opts = Opt::from_args();
+
+    if let Err(error) = run(opts).await {
+        log::error!("archive failed: {:?}", error);
+        std::process::exit(1);
+    }
+}
+
+async fn run(opts: Opt) -> Result<(), Error> {
+    // v1 wheel cannot be opened, so it is converted without starting a wheel
+    if let Command::UpgradeV1 { target_wheel_filename, } = opts.cmd {
+        let upgraded = blockwheel::upgrade::upgrade_v1(blockwheel::upgrade::UpgradeParams {
+            source_wheel_filename: &opts.wheel_filename,
+            target_wheel_filename: &target_wheel_filename,
+            block_size_max: opts.work_block_size,
+        }).map_err(Error::Upgrade)?;
+        log::info!("upgraded into {:?}: {:?}", target_wheel_filename, upgraded);
+        return Ok(());
+    }
+
+    let supervisor_gen_server = SupervisorGenServer::new();
+    let mut supervisor_pid = supervisor_gen_server.pid();
+    tokio::spawn(supervisor_gen_server.run());
+
+    let blocks_pool = BytesPool::new();
+    let thread_pool: edeltraud::Edeltraud<blockwheel::job::Job> = edeltraud::Builder::new()
+        .build()
+        .map_err(Error::ThreadPool)?;
+
+    let blockwheel_gen_server = blockwheel::GenServer::new();
+    let mut blockwheel_pid = blockwheel_gen_server.pid();
+    supervisor_pid.spawn_link_permanent(blockwheel_gen_server.run(
+        supervisor_pid.clone(),
+        thread_pool,
+        blocks_pool.clone(),
+        blockwheel::Params {
+            interpreter: blockwheel::InterpreterParams::FixedFile(blockwheel::FixedFileInterpreterParams {
+                wheel_filename: opts.wheel_filename,
+                init_wheel_size_bytes: opts.init_wheel_size_bytes,
+                create_mode: blockwheel::WheelCreateMode::ZeroFill,
+                direct_io: false,
+                punch_holes_min_bytes: None,
+                read_only: false,
+            }),
+            work_block_size_bytes: opts.work_block_size,
+            ..Default::default()
+        },
+    ));
+
+    match opts.cmd {
+        Command::Export { archive_filename, } => {
+            let archive_file = fs::File::create(&archive_filename).await
+                .map_err(Error::ArchiveCreate)?;
+            let exported = blockwheel_pid.export(BufWriter::new(archive_file)).await
+                .map_err(Error::Export)?;
+            log::info!("exported into {:?}: {:?}", archive_filename, exported);
+        },
+        Command::Import { archive_filename, preserve_block_ids, } => {
+            let archive_file = fs::File::open(&archive_filename).await
+                .map_err(Error::ArchiveOpen)?;
+            let imported = blockwheel_pid
+                .import(
+                    BufReader::new(archive_file),
+                    &blocks_pool,
+                    blockwheel::archive::ImportParams {
+                        preserve_block_ids,
+                        block_size_max: opts.work_block_size,
+                    },
+                )
+                .await
+                .map_err(Error::Import)?;
+            let blockwheel::Flushed = blockwheel_pid.flush().await
+                .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+            log::info!("imported from {:?}: {:?}", archive_filename, imported);
+        },
+        Command::UpgradeV1 { .. } =>
+            unreachable!(),
+    }
+
+    Ok(())
+}
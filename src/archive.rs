@@ -0,0 +1,330 @@
+//! Portable archive stream of wheel contents.
+//!
+//! An archive is a plain byte stream which does not depend on wheel size or storage layout:
+//!
+//! ```text
+//! ArchiveHeader { magic, version }
+//! BlockRecord { magic, block_id, block_size, crc } block_size bytes of block contents
+//! ...
+//! TerminatorRecord { magic }
+//! ```
+//!
+//! All structures are encoded with the same bincode options as wheel storage itself (big endian,
+//! fixed width integers), so every `u64` and `usize` field occupies exactly eight bytes.
+//! `crc` is `block::crc` of block contents and is verified during import.
+
+use std::io;
+
+use futures::StreamExt;
+
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+};
+
+use serde_derive::{
+    Serialize,
+    Deserialize,
+};
+
+use bincode::Options;
+
+use alloc_pool::bytes::BytesPool;
+
+use crate::{
+    block,
+    storage,
+    Pid,
+    IterBlocksItem,
+    IterBlocksError,
+    WriteBlockError,
+};
+
+pub const ARCHIVE_MAGIC: u64 = 0x5e7a1cb0a4c1d3e9;
+pub const ARCHIVE_VERSION: usize = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveHeader {
+    pub magic: u64,
+    pub version: usize,
+}
+
+impl Default for ArchiveHeader {
+    fn default() -> ArchiveHeader {
+        ArchiveHeader {
+            magic: ARCHIVE_MAGIC,
+            version: ARCHIVE_VERSION,
+        }
+    }
+}
+
+pub const BLOCK_RECORD_MAGIC: u64 = 0x0b1d6c3a27e4f8a1;
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct BlockRecord {
+    pub magic: u64,
+    pub block_id: block::Id,
+    pub block_size: usize,
+    pub crc: u64,
+}
+
+impl Default for BlockRecord {
+    fn default() -> BlockRecord {
+        BlockRecord {
+            magic: BLOCK_RECORD_MAGIC,
+            block_id: block::Id::default(),
+            block_size: 0,
+            crc: 0,
+        }
+    }
+}
+
+pub const TERMINATOR_RECORD_MAGIC: u64 = 0x9f3e57d2c60b18a4;
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct TerminatorRecord {
+    pub magic: u64,
+}
+
+impl Default for TerminatorRecord {
+    fn default() -> TerminatorRecord {
+        TerminatorRecord {
+            magic: TERMINATOR_RECORD_MAGIC,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Exported {
+    pub blocks_count: usize,
+    pub blocks_total_size: usize,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    IterBlocks(IterBlocksError),
+    IterBlocksRxDropped,
+    HeaderSerialize(bincode::Error),
+    HeaderWrite(io::Error),
+    BlockRecordSerialize(bincode::Error),
+    BlockRecordWrite(io::Error),
+    BlockWrite(io::Error),
+    TerminatorRecordSerialize(bincode::Error),
+    TerminatorRecordWrite(io::Error),
+    Flush(io::Error),
+}
+
+pub async fn export<W>(pid: &mut Pid, mut writer: W) -> Result<Exported, ExportError> where W: AsyncWrite + Unpin {
+    let mut iter_blocks = pid.iter_blocks().await
+        .map_err(ExportError::IterBlocks)?;
+
+    let mut work_block = Vec::new();
+    storage::bincode_options()
+        .serialize_into(&mut work_block, &ArchiveHeader::default())
+        .map_err(ExportError::HeaderSerialize)?;
+    writer.write_all(&work_block).await
+        .map_err(ExportError::HeaderWrite)?;
+
+    let mut exported = Exported::default();
+    loop {
+        match iter_blocks.blocks_rx.next().await {
+            None =>
+                return Err(ExportError::IterBlocksRxDropped),
+            Some(IterBlocksItem::Block { block_id, block_bytes, }) => {
+                let block_record = BlockRecord {
+                    block_id,
+                    block_size: block_bytes.len(),
+                    crc: block::crc(&block_bytes),
+                    ..Default::default()
+                };
+                work_block.clear();
+                storage::bincode_options()
+                    .serialize_into(&mut work_block, &block_record)
+                    .map_err(ExportError::BlockRecordSerialize)?;
+                writer.write_all(&work_block).await
+                    .map_err(ExportError::BlockRecordWrite)?;
+                writer.write_all(&block_bytes).await
+                    .map_err(ExportError::BlockWrite)?;
+
+                exported.blocks_count += 1;
+                exported.blocks_total_size += block_bytes.len();
+            },
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                break,
+        }
+    }
+
+    work_block.clear();
+    storage::bincode_options()
+        .serialize_into(&mut work_block, &TerminatorRecord::default())
+        .map_err(ExportError::TerminatorRecordSerialize)?;
+    writer.write_all(&work_block).await
+        .map_err(ExportError::TerminatorRecordWrite)?;
+    writer.flush().await
+        .map_err(ExportError::Flush)?;
+
+    log::debug!("export done: {:?}", exported);
+    Ok(exported)
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportParams {
+    pub preserve_block_ids: bool,
+    // records of larger blocks are rejected before anything is allocated for them,
+    // should be `Params::work_block_size_bytes` of the target wheel
+    pub block_size_max: usize,
+}
+
+impl Default for ImportParams {
+    fn default() -> ImportParams {
+        ImportParams {
+            preserve_block_ids: false,
+            block_size_max: crate::Params::default().work_block_size_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Imported {
+    pub blocks_count: usize,
+    pub blocks_total_size: usize,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    LayoutCalculate(bincode::Error),
+    HeaderRead(io::Error),
+    HeaderDeserialize(bincode::Error),
+    HeaderInvalidMagic {
+        provided: u64,
+        expected: u64,
+    },
+    HeaderVersionMismatch {
+        provided: usize,
+        expected: usize,
+    },
+    RecordMagicRead(io::Error),
+    RecordMagicDeserialize(bincode::Error),
+    RecordInvalidMagic {
+        provided: u64,
+    },
+    BlockRecordRead(io::Error),
+    BlockRecordDeserialize(bincode::Error),
+    BlockTooLarge {
+        block_id: block::Id,
+        block_size: usize,
+        block_size_max: usize,
+    },
+    BlockRead(io::Error),
+    BlockCrcMismatch {
+        block_id: block::Id,
+        record_crc: u64,
+        block_crc: u64,
+    },
+    WriteBlock(WriteBlockError),
+}
+
+pub async fn import<R>(
+    pid: &mut Pid,
+    mut reader: R,
+    blocks_pool: &BytesPool,
+    params: ImportParams,
+)
+    -> Result<Imported, ImportError>
+where R: AsyncRead + Unpin,
+{
+    let archive_header_size = storage::bincode_options()
+        .serialized_size(&ArchiveHeader::default())
+        .map_err(ImportError::LayoutCalculate)? as usize;
+    let block_record_size = storage::bincode_options()
+        .serialized_size(&BlockRecord::default())
+        .map_err(ImportError::LayoutCalculate)? as usize;
+    let record_magic_size = storage::bincode_options()
+        .serialized_size(&TerminatorRecord::default())
+        .map_err(ImportError::LayoutCalculate)? as usize;
+
+    let mut work_block = Vec::with_capacity(block_record_size);
+
+    // read archive header
+    work_block.resize(archive_header_size, 0);
+    reader.read_exact(&mut work_block).await
+        .map_err(ImportError::HeaderRead)?;
+    let archive_header: ArchiveHeader = storage::bincode_options()
+        .deserialize_from(&work_block[..])
+        .map_err(ImportError::HeaderDeserialize)?;
+    if archive_header.magic != ARCHIVE_MAGIC {
+        return Err(ImportError::HeaderInvalidMagic {
+            provided: archive_header.magic,
+            expected: ARCHIVE_MAGIC,
+        });
+    }
+    if archive_header.version != ARCHIVE_VERSION {
+        return Err(ImportError::HeaderVersionMismatch {
+            provided: archive_header.version,
+            expected: ARCHIVE_VERSION,
+        });
+    }
+
+    let mut imported = Imported::default();
+    loop {
+        // every record starts with magic
+        work_block.resize(record_magic_size, 0);
+        reader.read_exact(&mut work_block).await
+            .map_err(ImportError::RecordMagicRead)?;
+        let magic: u64 = storage::bincode_options()
+            .deserialize_from(&work_block[..])
+            .map_err(ImportError::RecordMagicDeserialize)?;
+        match magic {
+            TERMINATOR_RECORD_MAGIC =>
+                break,
+            BLOCK_RECORD_MAGIC =>
+                (),
+            provided =>
+                return Err(ImportError::RecordInvalidMagic { provided, }),
+        }
+
+        work_block.resize(block_record_size, 0);
+        reader.read_exact(&mut work_block[record_magic_size ..]).await
+            .map_err(ImportError::BlockRecordRead)?;
+        let block_record: BlockRecord = storage::bincode_options()
+            .deserialize_from(&work_block[..])
+            .map_err(ImportError::BlockRecordDeserialize)?;
+        if block_record.block_size > params.block_size_max {
+            return Err(ImportError::BlockTooLarge {
+                block_id: block_record.block_id,
+                block_size: block_record.block_size,
+                block_size_max: params.block_size_max,
+            });
+        }
+
+        let mut block_bytes = blocks_pool.lend();
+        block_bytes.resize(block_record.block_size, 0);
+        reader.read_exact(&mut block_bytes[..]).await
+            .map_err(ImportError::BlockRead)?;
+        let block_crc = block::crc(&block_bytes);
+        if block_crc != block_record.crc {
+            return Err(ImportError::BlockCrcMismatch {
+                block_id: block_record.block_id,
+                record_crc: block_record.crc,
+                block_crc,
+            });
+        }
+        let block_bytes = block_bytes.freeze();
+
+        let block_id = if params.preserve_block_ids {
+            pid.write_block_with_id(block_record.block_id, block_bytes).await
+        } else {
+            pid.write_block(block_bytes).await
+        }.map_err(ImportError::WriteBlock)?;
+
+        log::debug!("imported block {:?} of {} bytes", block_id, block_record.block_size);
+
+        imported.blocks_count += 1;
+        imported.blocks_total_size += block_record.block_size;
+    }
+
+    log::debug!("import done: {:?}", imported);
+    Ok(imported)
+}
@@ -1,3 +1,9 @@
+use std::{
+    fmt,
+    str::FromStr,
+    num::ParseIntError,
+};
+
 use serde_derive::{
     Serialize,
     Deserialize,
@@ -26,6 +32,49 @@ impl Id {
             serial: self.serial + 1,
         }
     }
+
+    // no id can follow this one
+    pub fn is_exhausted(&self) -> bool {
+        self.serial == u64::MAX
+    }
+
+    /// Composite id of a sharded wheel: shard number is kept in the upper `SHARD_BITS` of the serial.
+    ///
+    /// `None` is returned if the shard or the id itself does not fit.
+    pub fn with_shard(&self, shard: usize) -> Option<Id> {
+        if shard >> SHARD_BITS != 0 || self.serial >> SHARD_SERIAL_BITS != 0 {
+            return None;
+        }
+        Some(Id {
+            serial: ((shard as u64) << SHARD_SERIAL_BITS) | self.serial,
+        })
+    }
+
+    /// Splits composite id into shard number and id within the shard.
+    pub fn shard(&self) -> (usize, Id) {
+        let shard = (self.serial >> SHARD_SERIAL_BITS) as usize;
+        let id = Id {
+            serial: self.serial & ((1 << SHARD_SERIAL_BITS) - 1),
+        };
+        (shard, id)
+    }
+}
+
+pub const SHARD_BITS: u32 = 16;
+const SHARD_SERIAL_BITS: u32 = 64 - SHARD_BITS;
+
+impl fmt::Display for Id {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}", self.serial)
+    }
+}
+
+impl FromStr for Id {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Id, ParseIntError> {
+        Ok(Id { serial: s.parse()?, })
+    }
 }
 
 pub fn crc(bytes: &[u8]) -> u64 {
@@ -0,0 +1,960 @@
+//! Erasure coded wheel: one logical wheel over `data_shards + parity_shards` member wheels.
+//!
+//! Every block is split into `data_shards` pieces of equal size, then Reed-Solomon parity pieces are computed
+//! over them and each of the pieces is stored on its own member under the block id. Shards are protected with
+//! the usual crc of a member wheel, so a damaged shard is never returned. A block is read from data shards
+//! only, parity shards are read and the block is reconstructed when some data shards are missing, so a block
+//! survives losing any `parity_shards` members.
+//!
+//! Members failing on start are left out, the wheel keeps working while at least `data_shards` of them remain.
+//! Ids and sizes of blocks are kept in memory and rebuilt on start by iterating all blocks of every member,
+//! which reads the whole contents of the wheels. A block exists while at least `data_shards` of its shards are
+//! stored, so a delete succeeds only when fewer than that are left behind, and the leftovers are removed on the
+//! next start with all members available.
+//!
+//! Blocks missing some of their shards (written while a member failed, found damaged or lost with a replaced
+//! member) are counted in `Info::blocks_degraded`, `Pid::rebuild` restores their shards from the others.
+
+use std::{
+    cmp,
+    sync::Arc,
+    convert::TryInto,
+    collections::{
+        HashMap,
+        HashSet,
+    },
+};
+
+use futures::{
+    future::{
+        self,
+        BoxFuture,
+    },
+    stream::FuturesUnordered,
+    channel::{
+        mpsc,
+        oneshot,
+    },
+    FutureExt,
+    SinkExt,
+    StreamExt,
+};
+
+use serde_derive::{
+    Serialize,
+    Deserialize,
+};
+
+use bincode::Options;
+
+use reed_solomon_erasure::{
+    galois_8::ReedSolomon,
+};
+
+use edeltraud::{
+    Edeltraud,
+};
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use ero::{
+    supervisor::SupervisorPid,
+};
+
+use crate::{
+    job,
+    block,
+    storage,
+    fanout::{
+        self,
+        Source,
+    },
+    Deleted,
+    Flushed,
+    IterBlocks,
+    IterBlocksItem,
+    WriteBlockError,
+    IterBlocksError,
+};
+
+// every shard starts with a header
+#[derive(Serialize, Deserialize, Debug)]
+struct ShardHeader {
+    // size of the whole block
+    block_size: u64,
+}
+
+// `ShardHeader` encoded with `storage::bincode_options()` (fixed int encoding)
+const SHARD_HEADER_SIZE: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct Params {
+    // shard `i` of every block is stored on member `i`, the last `members.len() - data_shards` hold parity
+    pub members: Vec<crate::Params>,
+    pub data_shards: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Info {
+    // in the same order as `Params::members`, `None` for a member which is not available
+    pub members: Vec<Option<crate::Info>>,
+    // blocks with some of their shards missing or damaged
+    pub blocks_degraded: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Written {
+    pub block_id: block::Id,
+    // shards which have not been stored, the block is degraded until `Pid::rebuild` when nonzero
+    pub shards_missing: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Rebuilt {
+    pub blocks_rebuilt: usize,
+    // blocks still missing some of their shards, for instance because of unavailable members
+    pub blocks_degraded: usize,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    ReedSolomon(reed_solomon_erasure::Error),
+    Encode(EncodeError),
+    NotEnoughMembers {
+        available: usize,
+        required: usize,
+    },
+}
+
+#[derive(Debug)]
+pub enum EncodeError {
+    ReedSolomon(reed_solomon_erasure::Error),
+    ShardHeaderSerialize(bincode::Error),
+}
+
+#[derive(Debug)]
+pub enum ReadBlockError {
+    GenServer(ero::NoProcError),
+    NotFound,
+    NotEnoughShards {
+        found: usize,
+        required: usize,
+    },
+}
+
+#[derive(Debug)]
+pub enum DeleteBlockError {
+    GenServer(ero::NoProcError),
+    NotFound,
+    // the block is kept: enough of its shards remain to bring it back on start
+    TooManyShardsLeft {
+        left: usize,
+        allowed: usize,
+    },
+}
+
+pub struct GenServer {
+    request_tx: mpsc::Sender<Request>,
+    request_rx: mpsc::Receiver<Request>,
+}
+
+#[derive(Clone)]
+pub struct Pid {
+    request_tx: mpsc::Sender<Request>,
+}
+
+enum Request {
+    Info {
+        reply_tx: oneshot::Sender<Info>,
+    },
+    Flush {
+        reply_tx: oneshot::Sender<Flushed>,
+    },
+    WriteBlock {
+        block_id: Option<block::Id>,
+        block_bytes: Bytes,
+        reply_tx: oneshot::Sender<Result<Written, WriteBlockError>>,
+    },
+    ReadBlock {
+        block_id: block::Id,
+        reply_tx: oneshot::Sender<Result<Bytes, ReadBlockError>>,
+    },
+    DeleteBlock {
+        block_id: block::Id,
+        reply_tx: oneshot::Sender<Result<Deleted, DeleteBlockError>>,
+    },
+    IterBlocks {
+        reply_tx: oneshot::Sender<IterBlocks>,
+    },
+    Rebuild {
+        reply_tx: oneshot::Sender<Rebuilt>,
+    },
+}
+
+impl GenServer {
+    pub fn new() -> GenServer {
+        let (request_tx, request_rx) = mpsc::channel(0);
+        GenServer { request_tx, request_rx, }
+    }
+
+    pub fn pid(&self) -> Pid {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub async fn run<J>(
+        self,
+        parent_supervisor: SupervisorPid,
+        thread_pool: Edeltraud<J>,
+        blocks_pool: BytesPool,
+        params: Params,
+    )
+    where J: edeltraud::Job + From<job::Job>,
+          J::Output: From<job::JobOutput>,
+          job::JobOutput: From<J::Output>,
+    {
+        let GenServer { request_rx, .. } = self;
+        let data_shards = params.data_shards;
+        let busyloop_blocks_pool = blocks_pool.clone();
+        fanout::run(
+            parent_supervisor,
+            thread_pool,
+            blocks_pool,
+            params.members,
+            |members_pids| busyloop(request_rx, members_pids, data_shards, busyloop_blocks_pool),
+        ).await
+    }
+}
+
+impl Pid {
+    pub async fn info(&mut self) -> Result<Info, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Info { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Flush { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+
+    /// Succeeds once at least `data_shards` shards of the block are stored, `Written::shards_missing` tells how many were not.
+    pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<Written, WriteBlockError> {
+        self.write_block_request(None, block_bytes).await
+    }
+
+    pub async fn write_block_with_id(&mut self, block_id: block::Id, block_bytes: Bytes) -> Result<Written, WriteBlockError> {
+        self.write_block_request(Some(block_id), block_bytes).await
+    }
+
+    async fn write_block_request(
+        &mut self,
+        block_id: Option<block::Id>,
+        block_bytes: Bytes,
+    )
+        -> Result<Written, WriteBlockError>
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::WriteBlock { block_id, block_bytes, reply_tx, }).await
+            .map_err(|_send_error| WriteBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| WriteBlockError::GenServer(ero::NoProcError))?
+    }
+
+    pub async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::ReadBlock { block_id, reply_tx, }).await
+            .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ReadBlockError::GenServer(ero::NoProcError))?
+    }
+
+    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::DeleteBlock { block_id, reply_tx, }).await
+            .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| DeleteBlockError::GenServer(ero::NoProcError))?
+    }
+
+    /// Streams all blocks in the order of their ids, blocks which cannot be reconstructed are skipped.
+    ///
+    /// Members have independent change sequences, so returned `IterBlocks::change_seq` is always zero.
+    pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::IterBlocks { reply_tx, }).await
+            .map_err(|_send_error| IterBlocksError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| IterBlocksError::GenServer(ero::NoProcError))
+    }
+
+    /// Restores missing and damaged shards of degraded blocks on available members.
+    ///
+    /// Other requests wait until the rebuild is finished.
+    pub async fn rebuild(&mut self) -> Result<Rebuilt, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Rebuild { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+}
+
+#[derive(Clone)]
+struct Coder {
+    reed_solomon: Arc<ReedSolomon>,
+    blocks_pool: BytesPool,
+}
+
+enum Done {
+    Written {
+        block_id: block::Id,
+        degraded: bool,
+    },
+    WriteFailed {
+        block_id: block::Id,
+    },
+    ReadDegraded {
+        block_id: block::Id,
+    },
+    Deleted {
+        block_id: block::Id,
+    },
+    DeleteFailed {
+        block_id: block::Id,
+    },
+    Nothing,
+}
+
+struct ShardsFound {
+    block_size: usize,
+    members: Vec<usize>,
+}
+
+async fn busyloop(
+    request_rx: mpsc::Receiver<Request>,
+    members_pids: Vec<crate::Pid>,
+    data_shards: usize,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+{
+    let reed_solomon = ReedSolomon::new(data_shards, members_pids.len().saturating_sub(data_shards))
+        .map_err(Error::ReedSolomon)?;
+    let coder = Coder { reed_solomon: Arc::new(reed_solomon), blocks_pool, };
+
+    // member which cannot be started is left out: its shards are treated as lost
+    let mut members = Vec::with_capacity(members_pids.len());
+    let mut shards_found: HashMap<block::Id, ShardsFound> = HashMap::new();
+    let mut next_block_id = block::Id::init();
+    for (member, mut pid) in members_pids.into_iter().enumerate() {
+        match scan_member(&mut pid).await {
+            Ok(shards) => {
+                for (block_id, block_size) in shards {
+                    next_block_id = cmp::max(next_block_id, block_id.next());
+                    shards_found.entry(block_id)
+                        .or_insert_with(|| ShardsFound { block_size, members: Vec::new(), })
+                        .members
+                        .push(member);
+                }
+                members.push(Some(pid));
+            },
+            Err(error) => {
+                log::warn!("member {} is not available: {:?}", member, error);
+                members.push(None);
+            },
+        }
+    }
+    let available = members.iter().filter(|maybe_pid| maybe_pid.is_some()).count();
+    if available < data_shards {
+        return Err(Error::NotEnoughMembers { available, required: data_shards, });
+    }
+
+    let mut blocks = HashMap::new();
+    let mut degraded = HashSet::new();
+    for (block_id, found) in shards_found {
+        if found.members.len() >= data_shards {
+            if found.members.len() < members.len() {
+                degraded.insert(block_id.clone());
+            }
+            blocks.insert(block_id, found.block_size);
+        } else if available == members.len() {
+            // leftovers of a delete or a write which has not reached enough members
+            for member in found.members {
+                if let Some(pid) = &mut members[member] {
+                    if let Err(error) = pid.delete_block(block_id.clone()).await {
+                        log::warn!("failed to remove leftover shard of block {} on member {}: {:?}", block_id, member, error);
+                    }
+                }
+            }
+        }
+    }
+    log::debug!(
+        "erasure coded wheel of {}/{} members started with {} blocks ({} degraded)",
+        available,
+        members.len(),
+        blocks.len(),
+        degraded.len(),
+    );
+
+    let mut fused_request_rx = request_rx.fuse();
+    let mut pending: FuturesUnordered<BoxFuture<'static, Done>> = FuturesUnordered::new();
+
+    loop {
+        match fanout::next_source(&mut fused_request_rx, &mut pending).await {
+
+            Source::Pid(None) => {
+                log::debug!("all Pid frontends have been terminated");
+                return Ok(());
+            },
+
+            Source::Pid(Some(Request::Info { reply_tx, })) => {
+                let pids = members.clone();
+                let blocks_degraded = degraded.len();
+                pending.push(async move {
+                    let mut infos = Vec::with_capacity(pids.len());
+                    for maybe_pid in pids {
+                        let maybe_info = match maybe_pid {
+                            Some(mut pid) =>
+                                pid.info().await.ok(),
+                            None =>
+                                None,
+                        };
+                        infos.push(maybe_info);
+                    }
+                    if let Err(_send_error) = reply_tx.send(Info { members: infos, blocks_degraded, }) {
+                        log::warn!("reply channel has been closed during Info result send");
+                    }
+                    Done::Nothing
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::Flush { reply_tx, })) => {
+                let flushes: Vec<_> = members.iter()
+                    .flatten()
+                    .map(|pid| {
+                        let mut pid = pid.clone();
+                        async move { pid.flush().await }
+                    })
+                    .collect();
+                pending.push(async move {
+                    for result in future::join_all(flushes).await {
+                        if let Err(ero::NoProcError) = result {
+                            log::warn!("member has gone during flush");
+                        }
+                    }
+                    if let Err(_send_error) = reply_tx.send(Flushed) {
+                        log::warn!("reply channel has been closed during Flush result send");
+                    }
+                    Done::Nothing
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::WriteBlock { block_id: maybe_block_id, block_bytes, reply_tx, })) => {
+                let block_id = match maybe_block_id {
+                    Some(block_id) if blocks.contains_key(&block_id) => {
+                        if let Err(_send_error) = reply_tx.send(Err(WriteBlockError::BlockIdTaken)) {
+                            log::warn!("reply channel has been closed during WriteBlock result send");
+                        }
+                        continue;
+                    },
+                    Some(block_id) => {
+                        next_block_id = cmp::max(next_block_id, block_id.next());
+                        block_id
+                    },
+                    None => {
+                        let block_id = next_block_id.clone();
+                        next_block_id = next_block_id.next();
+                        block_id
+                    },
+                };
+
+                let shards = coder.encode(&block_bytes)
+                    .map_err(Error::Encode)?;
+                blocks.insert(block_id.clone(), block_bytes.len());
+                pending.push(write_block(block_id, shards, members.clone(), data_shards, reply_tx).boxed());
+            },
+
+            Source::Pid(Some(Request::ReadBlock { block_id, reply_tx, })) =>
+                if blocks.contains_key(&block_id) {
+                    let pids = members.clone();
+                    let coder = coder.clone();
+                    pending.push(async move {
+                        let (result, shards_missing) = read_block(&block_id, &pids, &coder).await;
+                        if let Err(_send_error) = reply_tx.send(result) {
+                            log::warn!("reply channel has been closed during ReadBlock result send");
+                        }
+                        if shards_missing {
+                            Done::ReadDegraded { block_id, }
+                        } else {
+                            Done::Nothing
+                        }
+                    }.boxed());
+                } else if let Err(_send_error) = reply_tx.send(Err(ReadBlockError::NotFound)) {
+                    log::warn!("reply channel has been closed during ReadBlock result send");
+                },
+
+            Source::Pid(Some(Request::DeleteBlock { block_id, reply_tx, })) =>
+                if blocks.contains_key(&block_id) {
+                    pending.push(delete_block(block_id, members.clone(), data_shards, reply_tx).boxed());
+                } else if let Err(_send_error) = reply_tx.send(Err(DeleteBlockError::NotFound)) {
+                    log::warn!("reply channel has been closed during DeleteBlock result send");
+                },
+
+            Source::Pid(Some(Request::IterBlocks { reply_tx, })) => {
+                let mut blocks_ids: Vec<_> = blocks.keys().cloned().collect();
+                blocks_ids.sort();
+                let blocks_total_size = blocks.values().sum();
+                pending.push(iter_blocks(blocks_ids, blocks_total_size, members.clone(), coder.clone(), reply_tx).boxed());
+            },
+
+            Source::Pid(Some(Request::Rebuild { reply_tx, })) => {
+                // performed in place, so blocks are not written or deleted underneath
+                let mut blocks_ids: Vec<_> = degraded.iter().cloned().collect();
+                blocks_ids.sort();
+                let mut blocks_rebuilt = 0;
+                for block_id in blocks_ids {
+                    match rebuild_block(&block_id, &members, &coder).await {
+                        Ok(true) => {
+                            degraded.remove(&block_id);
+                            blocks_rebuilt += 1;
+                        },
+                        Ok(false) =>
+                            (),
+                        Err(error) =>
+                            log::warn!("block {} cannot be rebuilt: {:?}", block_id, error),
+                    }
+                }
+                log::info!("rebuild finished: {} blocks rebuilt, {} still degraded", blocks_rebuilt, degraded.len());
+                if let Err(_send_error) = reply_tx.send(Rebuilt { blocks_rebuilt, blocks_degraded: degraded.len(), }) {
+                    log::warn!("reply channel has been closed during Rebuild result send");
+                }
+            },
+
+            Source::Pending(Done::Written { block_id, degraded: true, }) |
+            Source::Pending(Done::ReadDegraded { block_id, }) |
+            Source::Pending(Done::DeleteFailed { block_id, }) =>
+                if blocks.contains_key(&block_id) {
+                    degraded.insert(block_id);
+                },
+
+            Source::Pending(Done::Written { degraded: false, .. }) =>
+                (),
+
+            Source::Pending(Done::WriteFailed { block_id, }) |
+            Source::Pending(Done::Deleted { block_id, }) => {
+                blocks.remove(&block_id);
+                degraded.remove(&block_id);
+            },
+
+            Source::Pending(Done::Nothing) =>
+                (),
+
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ScanError {
+    Info(ero::NoProcError),
+    IterBlocks(IterBlocksError),
+    IterBlocksGone,
+}
+
+// collects ids and sizes of blocks from shards stored on the member
+async fn scan_member(pid: &mut crate::Pid) -> Result<Vec<(block::Id, usize)>, ScanError> {
+    pid.info().await
+        .map_err(ScanError::Info)?;
+    let mut iter_blocks = pid.iter_blocks().await
+        .map_err(ScanError::IterBlocks)?;
+    let mut shards = Vec::with_capacity(iter_blocks.blocks_total_count);
+    loop {
+        match iter_blocks.blocks_rx.next().await {
+            None =>
+                return Err(ScanError::IterBlocksGone),
+            Some(IterBlocksItem::Block { block_id, block_bytes, }) =>
+                match shard_block_size(&block_bytes) {
+                    Some(block_size) =>
+                        shards.push((block_id, block_size)),
+                    None =>
+                        log::warn!("block {} is not a valid shard, skipping", block_id),
+                },
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                return Ok(shards),
+        }
+    }
+}
+
+fn shard_block_size(shard_bytes: &[u8]) -> Option<usize> {
+    let header = shard_bytes.get(.. SHARD_HEADER_SIZE)?;
+    let shard_header: ShardHeader = storage::bincode_options()
+        .deserialize_from(header)
+        .ok()?;
+    shard_header.block_size.try_into().ok()
+}
+
+impl Coder {
+    fn encode(&self, block_bytes: &[u8]) -> Result<Vec<Bytes>, EncodeError> {
+        let data_shards = self.reed_solomon.data_shard_count();
+        let piece_size = cmp::max(1, (block_bytes.len() + data_shards - 1) / data_shards);
+        let mut pieces: Vec<Vec<u8>> = block_bytes.chunks(piece_size)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        pieces.resize(self.reed_solomon.total_shard_count(), Vec::new());
+        for piece in &mut pieces {
+            piece.resize(piece_size, 0);
+        }
+        self.reed_solomon.encode(&mut pieces)
+            .map_err(EncodeError::ReedSolomon)?;
+
+        let mut shard_header_bytes = Vec::with_capacity(SHARD_HEADER_SIZE);
+        storage::bincode_options()
+            .serialize_into(&mut shard_header_bytes, &ShardHeader { block_size: block_bytes.len() as u64, })
+            .map_err(EncodeError::ShardHeaderSerialize)?;
+        assert_eq!(shard_header_bytes.len(), SHARD_HEADER_SIZE);
+        let shards = pieces.into_iter()
+            .map(|piece| {
+                let mut shard = self.blocks_pool.lend();
+                shard.extend(shard_header_bytes.iter().cloned());
+                shard.extend(piece.into_iter());
+                shard.freeze()
+            })
+            .collect();
+        Ok(shards)
+    }
+
+    // shards disagreeing with the first one on sizes are dropped
+    fn decode(&self, shards: Vec<Option<Bytes>>) -> Result<Bytes, ReadBlockError> {
+        let data_shards = self.reed_solomon.data_shard_count();
+        let (block_size, shard_size) = shards.iter()
+            .flatten()
+            .find_map(|shard| shard_block_size(shard).map(|block_size| (block_size, shard.len())))
+            .ok_or(ReadBlockError::NotEnoughShards { found: 0, required: data_shards, })?;
+        let mut pieces: Vec<Option<Vec<u8>>> = shards.into_iter()
+            .map(|maybe_shard| {
+                let shard = maybe_shard?;
+                if shard.len() != shard_size || shard_block_size(&shard) != Some(block_size) {
+                    log::warn!("shard of {} bytes is inconsistent with others, dropping", shard.len());
+                    return None;
+                }
+                Some(shard[SHARD_HEADER_SIZE ..].to_vec())
+            })
+            .collect();
+        let found = pieces.iter().filter(|maybe_piece| maybe_piece.is_some()).count();
+        if found < data_shards || block_size > (shard_size - SHARD_HEADER_SIZE) * data_shards {
+            return Err(ReadBlockError::NotEnoughShards { found, required: data_shards, });
+        }
+        if pieces[.. data_shards].iter().any(Option::is_none) {
+            self.reed_solomon.reconstruct_data(&mut pieces)
+                .map_err(|_error| ReadBlockError::NotEnoughShards { found, required: data_shards, })?;
+        }
+
+        let mut block_bytes = self.blocks_pool.lend();
+        block_bytes.extend(
+            pieces[.. data_shards]
+                .iter()
+                .flatten()
+                .flat_map(|piece| piece.iter().cloned())
+                .take(block_size),
+        );
+        Ok(block_bytes.freeze())
+    }
+}
+
+async fn write_block(
+    block_id: block::Id,
+    shards: Vec<Bytes>,
+    pids: Vec<Option<crate::Pid>>,
+    data_shards: usize,
+    reply_tx: oneshot::Sender<Result<Written, WriteBlockError>>,
+)
+    -> Done
+{
+    let writes: Vec<_> = pids.iter()
+        .zip(shards)
+        .map(|(maybe_pid, shard)| {
+            let maybe_pid = maybe_pid.clone();
+            let block_id = block_id.clone();
+            async move {
+                match maybe_pid {
+                    Some(mut pid) =>
+                        Some(write_shard(&mut pid, block_id, shard).await),
+                    None =>
+                        None,
+                }
+            }
+        })
+        .collect();
+    let results = future::join_all(writes).await;
+
+    let written = results.iter()
+        .filter(|maybe_result| matches!(maybe_result, Some(Ok(..))))
+        .count();
+    let (result, done) = if written >= data_shards {
+        let shards_missing = results.len() - written;
+        if shards_missing > 0 {
+            log::warn!("block {} is stored with {} shards of {}", block_id, written, results.len());
+        }
+        (
+            Ok(Written { block_id: block_id.clone(), shards_missing, }),
+            Done::Written { block_id, degraded: shards_missing > 0, },
+        )
+    } else {
+        // not enough shards to ever restore the block, the ones stored are useless
+        for (maybe_pid, maybe_result) in pids.into_iter().zip(&results) {
+            if let (Some(mut pid), Some(Ok(..))) = (maybe_pid, maybe_result) {
+                if let Err(error) = pid.delete_block(block_id.clone()).await {
+                    log::warn!("failed to remove orphaned shard of block {}: {:?}", block_id, error);
+                }
+            }
+        }
+        let errors: Vec<_> = results.into_iter()
+            .flatten()
+            .filter_map(Result::err)
+            .collect();
+        let error = if errors.iter().any(|error| matches!(error, WriteBlockError::NoSpaceLeft)) {
+            WriteBlockError::NoSpaceLeft
+        } else {
+            errors.into_iter()
+                .next()
+                .unwrap_or(WriteBlockError::GenServer(ero::NoProcError))
+        };
+        (Err(error), Done::WriteFailed { block_id, })
+    };
+    if let Err(_send_error) = reply_tx.send(result) {
+        log::warn!("reply channel has been closed during WriteBlock result send");
+    }
+    done
+}
+
+// member id may still be held by a stale shard: left by an incomplete delete or damaged one being rebuilt
+async fn write_shard(pid: &mut crate::Pid, block_id: block::Id, shard: Bytes) -> Result<block::Id, WriteBlockError> {
+    match pid.write_block_with_id(block_id.clone(), shard.clone()).await {
+        Err(WriteBlockError::BlockIdTaken) => {
+            match pid.delete_block(block_id.clone()).await {
+                Ok(Deleted) | Err(crate::DeleteBlockError::NotFound) =>
+                    (),
+                Err(error) => {
+                    log::warn!("failed to remove stale shard of block {}: {:?}", block_id, error);
+                    return Err(WriteBlockError::BlockIdTaken);
+                },
+            }
+            pid.write_block_with_id(block_id, shard).await
+        },
+        result =>
+            result,
+    }
+}
+
+// data shards are tried first, parity ones are read only when some of them are unavailable,
+// also tells if any of the shards read is missing or damaged
+async fn read_block(block_id: &block::Id, pids: &[Option<crate::Pid>], coder: &Coder) -> (Result<Bytes, ReadBlockError>, bool) {
+    let data_shards = coder.reed_solomon.data_shard_count();
+    let mut shards = vec![None; pids.len()];
+    let mut not_found = 0;
+    let mut shards_missing = false;
+    for range in [0 .. data_shards, data_shards .. pids.len()] {
+        if shards[.. data_shards].iter().all(Option::is_some) {
+            break;
+        }
+        let reads: Vec<_> = range.clone()
+            .map(|index| {
+                let maybe_pid = pids[index].clone();
+                let block_id = block_id.clone();
+                async move {
+                    match maybe_pid {
+                        Some(mut pid) =>
+                            Some(pid.read_block(block_id).await),
+                        None =>
+                            None,
+                    }
+                }
+            })
+            .collect();
+        for (index, maybe_result) in range.zip(future::join_all(reads).await) {
+            match maybe_result {
+                Some(Ok(shard)) =>
+                    shards[index] = Some(shard),
+                Some(Err(crate::ReadBlockError::NotFound)) => {
+                    not_found += 1;
+                    shards_missing = true;
+                },
+                Some(Err(crate::ReadBlockError::Corrupted)) => {
+                    log::warn!("shard {} of block {} is damaged", index, block_id);
+                    shards_missing = true;
+                },
+                Some(Err(error)) =>
+                    log::warn!("shard {} of block {} cannot be read: {:?}", index, block_id, error),
+                None =>
+                    (),
+            }
+        }
+    }
+    if not_found > 0 && shards.iter().all(Option::is_none) {
+        return (Err(ReadBlockError::NotFound), false);
+    }
+    (coder.decode(shards), shards_missing)
+}
+
+#[derive(Debug)]
+enum RebuildBlockError {
+    Decode(ReadBlockError),
+    Encode(EncodeError),
+}
+
+// all shards are read, the missing and damaged ones are restored from the others,
+// `true` is returned when every member holds its shard afterwards
+async fn rebuild_block(block_id: &block::Id, pids: &[Option<crate::Pid>], coder: &Coder) -> Result<bool, RebuildBlockError> {
+    let reads: Vec<_> = pids.iter()
+        .map(|maybe_pid| {
+            let maybe_pid = maybe_pid.clone();
+            let block_id = block_id.clone();
+            async move {
+                match maybe_pid {
+                    Some(mut pid) =>
+                        Some(pid.read_block(block_id).await),
+                    None =>
+                        None,
+                }
+            }
+        })
+        .collect();
+    let mut shards = Vec::with_capacity(pids.len());
+    let mut restore = Vec::new();
+    let mut complete = true;
+    for (index, maybe_result) in future::join_all(reads).await.into_iter().enumerate() {
+        match maybe_result {
+            Some(Ok(shard)) => {
+                shards.push(Some(shard));
+                continue;
+            },
+            Some(Err(crate::ReadBlockError::NotFound)) | Some(Err(crate::ReadBlockError::Corrupted)) =>
+                restore.push(index),
+            Some(Err(crate::ReadBlockError::GenServer(ero::NoProcError))) | None =>
+                complete = false,
+        }
+        shards.push(None);
+    }
+    if restore.is_empty() {
+        return Ok(complete);
+    }
+
+    // shards are produced by the same encoding, so restored ones are identical to the lost
+    let block_bytes = coder.decode(shards)
+        .map_err(RebuildBlockError::Decode)?;
+    let restored = coder.encode(&block_bytes)
+        .map_err(RebuildBlockError::Encode)?;
+    for index in restore {
+        if let Some(mut pid) = pids[index].clone() {
+            match write_shard(&mut pid, block_id.clone(), restored[index].clone()).await {
+                Ok(..) =>
+                    (),
+                Err(error) => {
+                    log::warn!("shard {} of block {} cannot be restored: {:?}", index, block_id, error);
+                    complete = false;
+                },
+            }
+        }
+    }
+    Ok(complete)
+}
+
+async fn delete_block(
+    block_id: block::Id,
+    pids: Vec<Option<crate::Pid>>,
+    data_shards: usize,
+    reply_tx: oneshot::Sender<Result<Deleted, DeleteBlockError>>,
+)
+    -> Done
+{
+    let deletes: Vec<_> = pids.into_iter()
+        .map(|maybe_pid| {
+            let block_id = block_id.clone();
+            async move {
+                match maybe_pid {
+                    Some(mut pid) =>
+                        Some(pid.delete_block(block_id).await),
+                    None =>
+                        None,
+                }
+            }
+        })
+        .collect();
+    // shards missing on some members are fine, the ones not reached may be left while they cannot restore the block
+    let mut deleted = 0;
+    let mut left = 0;
+    for maybe_result in future::join_all(deletes).await {
+        match maybe_result {
+            Some(Ok(Deleted)) =>
+                deleted += 1,
+            Some(Err(crate::DeleteBlockError::NotFound)) =>
+                (),
+            Some(Err(error)) => {
+                log::warn!("shard of block {} cannot be deleted: {:?}", block_id, error);
+                left += 1;
+            },
+            None =>
+                left += 1,
+        }
+    }
+    let allowed = data_shards - 1;
+    let (result, done) = if left > allowed {
+        (Err(DeleteBlockError::TooManyShardsLeft { left, allowed, }), Done::DeleteFailed { block_id, })
+    } else if deleted == 0 && left == 0 {
+        (Err(DeleteBlockError::NotFound), Done::Deleted { block_id, })
+    } else {
+        (Ok(Deleted), Done::Deleted { block_id, })
+    };
+    if let Err(_send_error) = reply_tx.send(result) {
+        log::warn!("reply channel has been closed during DeleteBlock result send");
+    }
+    done
+}
+
+async fn iter_blocks(
+    blocks_ids: Vec<block::Id>,
+    blocks_total_size: usize,
+    pids: Vec<Option<crate::Pid>>,
+    coder: Coder,
+    reply_tx: oneshot::Sender<IterBlocks>,
+)
+    -> Done
+{
+    let (mut blocks_tx, blocks_rx) = mpsc::channel(0);
+    let iter_blocks = IterBlocks {
+        blocks_total_count: blocks_ids.len(),
+        blocks_total_size,
+        change_seq: 0,
+        deleted_block_ids: Vec::new(),
+        blocks_rx,
+    };
+    if let Err(_send_error) = reply_tx.send(iter_blocks) {
+        log::warn!("reply channel has been closed during IterBlocks result send");
+        return Done::Nothing;
+    }
+
+    for block_id in blocks_ids {
+        match read_block(&block_id, &pids, &coder).await {
+            (Ok(block_bytes), _shards_missing) =>
+                if let Err(_send_error) = blocks_tx.send(IterBlocksItem::Block { block_id, block_bytes, }).await {
+                    log::debug!("iter blocks receiver is gone");
+                    return Done::Nothing;
+                },
+            (Err(error), _shards_missing) =>
+                log::warn!("block {} is skipped during iteration: {:?}", block_id, error),
+        }
+    }
+    if let Err(_send_error) = blocks_tx.send(IterBlocksItem::NoMoreBlocks).await {
+        log::debug!("iter blocks receiver is gone");
+    }
+    Done::Nothing
+}
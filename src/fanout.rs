@@ -0,0 +1,221 @@
+//! Scaffolding shared by wheels built over several member wheels: `stripe`, `sharded` and `erasure`.
+
+use std::{
+    fmt,
+};
+
+use futures::{
+    future::{
+        self,
+        BoxFuture,
+    },
+    select,
+    stream::{
+        Fuse,
+        FuturesUnordered,
+    },
+    channel::mpsc,
+    Future,
+    SinkExt,
+    StreamExt,
+};
+
+use edeltraud::{
+    Edeltraud,
+};
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use ero::{
+    supervisor::SupervisorPid,
+};
+
+use crate::{
+    job,
+    block,
+    Flushed,
+    IterBlocks,
+    IterBlocksItem,
+    WriteBlockError,
+};
+
+#[derive(Debug)]
+pub enum WriteBlockWithIdError {
+    WriteBlock(WriteBlockError),
+    // block id does not address any of the member wheels
+    InvalidBlockId,
+}
+
+pub(crate) struct Member {
+    pub pid: crate::Pid,
+    // estimation between `Info` requests: decreased by every block placed here, refreshed after deletes
+    pub bytes_free: usize,
+}
+
+// member wheels are run within this future along with `busyloop`, so it finishes when all `Pid`s are dropped
+pub(crate) async fn run<J, F, B, E>(
+    parent_supervisor: SupervisorPid,
+    thread_pool: Edeltraud<J>,
+    blocks_pool: BytesPool,
+    members_params: Vec<crate::Params>,
+    busyloop: F,
+)
+where J: edeltraud::Job + From<job::Job>,
+      J::Output: From<job::JobOutput>,
+      job::JobOutput: From<J::Output>,
+      F: FnOnce(Vec<crate::Pid>) -> B,
+      B: Future<Output = Result<(), E>>,
+      E: fmt::Debug,
+{
+    let mut members_pids = Vec::with_capacity(members_params.len());
+    let mut members_runs = Vec::with_capacity(members_params.len());
+    for member_params in members_params {
+        let member_gen_server = crate::GenServer::new();
+        members_pids.push(member_gen_server.pid());
+        members_runs.push(member_gen_server.run(
+            parent_supervisor.clone(),
+            thread_pool.clone(),
+            blocks_pool.clone(),
+            member_params,
+        ));
+    }
+
+    let busyloop_task = busyloop(members_pids);
+    let busyloop_task = async move {
+        if let Err(error) = busyloop_task.await {
+            log::error!("fatal error: {:?}", error);
+        }
+    };
+    future::select(Box::pin(busyloop_task), Box::pin(future::join_all(members_runs))).await;
+}
+
+pub(crate) enum Source<R, D> {
+    Pid(Option<R>),
+    Pending(D),
+}
+
+pub(crate) async fn next_source<R, D>(
+    fused_request_rx: &mut Fuse<mpsc::Receiver<R>>,
+    pending: &mut FuturesUnordered<BoxFuture<'static, D>>,
+)
+    -> Source<R, D>
+{
+    if pending.is_empty() {
+        Source::Pid(fused_request_rx.next().await)
+    } else {
+        select! {
+            result = fused_request_rx.next() =>
+                Source::Pid(result),
+            result = pending.next() => match result {
+                None =>
+                    unreachable!(),
+                Some(done) =>
+                    Source::Pending(done),
+            },
+        }
+    }
+}
+
+pub(crate) async fn infos(pids: Vec<crate::Pid>) -> Result<Vec<crate::Info>, ero::NoProcError> {
+    let mut infos = Vec::with_capacity(pids.len());
+    for mut pid in pids {
+        infos.push(pid.info().await?);
+    }
+    Ok(infos)
+}
+
+pub(crate) async fn flush(pids: Vec<crate::Pid>) -> Result<Flushed, ero::NoProcError> {
+    let flushes = pids.into_iter()
+        .map(|mut pid| async move { pid.flush().await });
+    for result in future::join_all(flushes).await {
+        let Flushed = result?;
+    }
+    Ok(Flushed)
+}
+
+// candidates are tried in order until one of them has enough space for the block,
+// the composite id is returned along with members which reported `NoSpaceLeft`
+pub(crate) async fn write_block(
+    block_bytes: Bytes,
+    candidates: Vec<(usize, crate::Pid)>,
+)
+    -> (Result<block::Id, WriteBlockError>, Vec<usize>)
+{
+    let mut members_full = Vec::new();
+    for (member, mut pid) in candidates {
+        match pid.write_block(block_bytes.clone()).await {
+            Err(WriteBlockError::NoSpaceLeft) =>
+                members_full.push(member),
+            Ok(member_block_id) =>
+                match member_block_id.with_shard(member) {
+                    Some(block_id) =>
+                        return (Ok(block_id), members_full),
+                    None => {
+                        // member ran out of ids which could be encoded, treat it as full
+                        log::error!("block {} of member {} cannot be addressed with composite id, removing", member_block_id, member);
+                        if let Err(error) = pid.delete_block(member_block_id).await {
+                            log::warn!("failed to remove unaddressable block: {:?}", error);
+                        }
+                        members_full.push(member);
+                    },
+                },
+            Err(error) =>
+                return (Err(error), members_full),
+        }
+    }
+    (Err(WriteBlockError::NoSpaceLeft), members_full)
+}
+
+// blocks of all members are streamed one member after another under composite ids,
+// returned future forwards them and should be run once `IterBlocks` is handed over
+pub(crate) fn concat_iter_blocks(members_iter_blocks: Vec<IterBlocks>) -> (IterBlocks, impl Future<Output = ()>) {
+    let (mut blocks_tx, blocks_rx) = mpsc::channel(0);
+    let mut deleted_block_ids = Vec::new();
+    for (member, member_iter_blocks) in members_iter_blocks.iter().enumerate() {
+        deleted_block_ids.extend(
+            member_iter_blocks.deleted_block_ids
+                .iter()
+                .filter_map(|member_block_id| member_block_id.with_shard(member)),
+        );
+    }
+    let iter_blocks = IterBlocks {
+        blocks_total_count: members_iter_blocks.iter().map(|iter_blocks| iter_blocks.blocks_total_count).sum(),
+        blocks_total_size: members_iter_blocks.iter().map(|iter_blocks| iter_blocks.blocks_total_size).sum(),
+        change_seq: 0,
+        deleted_block_ids,
+        blocks_rx,
+    };
+
+    let forward = async move {
+        for (member, member_iter_blocks) in members_iter_blocks.into_iter().enumerate() {
+            let mut member_blocks_rx = member_iter_blocks.blocks_rx;
+            while let Some(item) = member_blocks_rx.next().await {
+                match item {
+                    IterBlocksItem::Block { block_id: member_block_id, block_bytes, } => {
+                        let block_id = match member_block_id.with_shard(member) {
+                            Some(block_id) =>
+                                block_id,
+                            None => {
+                                log::warn!("block {} of member {} cannot be addressed with composite id, skipping", member_block_id, member);
+                                continue;
+                            },
+                        };
+                        if let Err(_send_error) = blocks_tx.send(IterBlocksItem::Block { block_id, block_bytes, }).await {
+                            log::debug!("iter blocks receiver is gone");
+                            return;
+                        }
+                    },
+                    IterBlocksItem::NoMoreBlocks =>
+                        break,
+                }
+            }
+        }
+        if let Err(_send_error) = blocks_tx.send(IterBlocksItem::NoMoreBlocks).await {
+            log::debug!("iter blocks receiver is gone");
+        }
+    };
+    (iter_blocks, forward)
+}
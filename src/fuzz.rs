@@ -0,0 +1,61 @@
+use bincode::Options;
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    sim,
+    storage,
+    wheel::{
+        interpret,
+    },
+};
+
+// wheel image is loaded the same way as a `ram` snapshot, which shares the blocks scan with `fixed_file` open,
+// then a short simulation runs over the loaded blocks
+pub fn open_wheel_image(data: &[u8]) {
+    let params = sim::Params {
+        seed: 0,
+        actions: 64,
+        active_tasks: 4,
+        block_size_bytes: 256,
+        init_wheel_size_bytes: data.len(),
+        work_block_size_bytes: 64 * 1024,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 1,
+    };
+    match sim::run_image(params, data.to_vec()) {
+        Err(error @ sim::Error::Diverged { .. }) =>
+            panic!("loaded wheel image diverged from the model: {:?}", error),
+        Ok(..) | Err(..) =>
+            (),
+    }
+}
+
+// leading bytes are an expected block header, the rest is a raw block chunk as read from the wheel
+pub fn block_process_read(data: &[u8]) {
+    let storage_layout = storage::Layout::calculate(&mut Vec::new())
+        .unwrap();
+    let block_header: storage::BlockHeader = match storage::bincode_options().deserialize_from(data) {
+        Ok(block_header) =>
+            block_header,
+        Err(..) =>
+            return,
+    };
+    let mut block_bytes = BytesPool::new().lend();
+    block_bytes.extend(data[storage_layout.block_header_size ..].iter().cloned());
+    let _ = interpret::block_process_read_job(interpret::BlockProcessReadJobArgs {
+        storage_layout,
+        block_header,
+        block_bytes: block_bytes.freeze(),
+    });
+}
+
+pub fn storage_decode(data: &[u8]) {
+    let _ = storage::bincode_options().deserialize_from::<_, storage::WheelHeader>(data);
+    let _ = storage::bincode_options().deserialize_from::<_, storage::BlockHeader>(data);
+    let _ = storage::bincode_options().deserialize_from::<_, storage::CommitTag>(data);
+    let _ = storage::bincode_options().deserialize_from::<_, storage::TombstoneTag>(data);
+    let _ = storage::bincode_options().deserialize_from::<_, storage::TerminatorTag>(data);
+}
@@ -0,0 +1,280 @@
+//! HTTP gateway for a wheel (enabled with `http` feature).
+//!
+//! ```text
+//! POST   /blocks       body is stored as a new block, reply body is the new block id
+//! GET    /blocks/{id}  reply body is block contents
+//! DELETE /blocks/{id}
+//! GET    /info         reply body is `Info` as JSON
+//! POST   /flush
+//! ```
+//!
+//! `NotFound` errors are reported as 404, `NoSpaceLeft` as 507, a gone wheel as 503 and a block body
+//! larger than `ServeParams::body_size_max` as 413.
+
+use std::{
+    pin::Pin,
+    convert::Infallible,
+    net::SocketAddr,
+    task::{
+        Poll,
+        Context,
+    },
+};
+
+use hyper::{
+    header,
+    body::{
+        HttpBody,
+        SizeHint,
+    },
+    service::{
+        make_service_fn,
+        service_fn,
+    },
+    Body,
+    Method,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+};
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use crate::{
+    block,
+    Pid,
+    Deleted,
+    Flushed,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Bind(hyper::Error),
+    Serve(hyper::Error),
+}
+
+#[derive(Clone, Debug)]
+pub struct ServeParams {
+    // should be `Params::work_block_size_bytes` of the served wheel
+    pub body_size_max: usize,
+}
+
+impl Default for ServeParams {
+    fn default() -> ServeParams {
+        ServeParams {
+            body_size_max: crate::Params::default().work_block_size_bytes,
+        }
+    }
+}
+
+pub async fn serve(pid: Pid, blocks_pool: BytesPool, addr: SocketAddr, params: ServeParams) -> Result<(), Error> {
+    let builder = Server::try_bind(&addr)
+        .map_err(Error::Bind)?;
+    let make_service = make_service_fn(move |_conn| {
+        let pid = pid.clone();
+        let blocks_pool = blocks_pool.clone();
+        let params = params.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(pid.clone(), blocks_pool.clone(), params.clone(), request)
+            }))
+        }
+    });
+    log::info!("http gateway listening on {}", addr);
+    builder.serve(make_service).await
+        .map_err(Error::Serve)
+}
+
+async fn handle(
+    mut pid: Pid,
+    blocks_pool: BytesPool,
+    params: ServeParams,
+    request: Request<Body>,
+)
+    -> Result<Response<ReplyBody>, Infallible>
+{
+    let path = request.uri().path().to_string();
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    let response = match (request.method(), &segments[..]) {
+
+        (&Method::POST, ["blocks"]) => {
+            let mut body = request.into_body();
+            // content length is checked upfront, actual body size while receiving it
+            if body.size_hint().lower() > params.body_size_max as u64 {
+                return Ok(reply_status(StatusCode::PAYLOAD_TOO_LARGE));
+            }
+            let mut block_bytes = blocks_pool.lend();
+            while let Some(result) = body.data().await {
+                let chunk = match result {
+                    Ok(chunk) =>
+                        chunk,
+                    Err(error) => {
+                        log::debug!("failed to receive request body: {:?}", error);
+                        return Ok(reply_status(StatusCode::BAD_REQUEST));
+                    },
+                };
+                if block_bytes.len() + chunk.len() > params.body_size_max {
+                    return Ok(reply_status(StatusCode::PAYLOAD_TOO_LARGE));
+                }
+                block_bytes.extend_from_slice(&chunk);
+            }
+            match pid.write_block(block_bytes.freeze()).await {
+                Ok(block_id) =>
+                    reply(StatusCode::CREATED, "text/plain", &blocks_pool, block_id.to_string().as_bytes()),
+                Err(WriteBlockError::NoSpaceLeft) =>
+                    reply_status(StatusCode::INSUFFICIENT_STORAGE),
+                Err(WriteBlockError::BlockIdTaken) =>
+                    reply_status(StatusCode::CONFLICT),
+                Err(WriteBlockError::ReadOnly) =>
+                    reply_status(StatusCode::FORBIDDEN),
+                Err(WriteBlockError::GenServer(ero::NoProcError)) =>
+                    reply_status(StatusCode::SERVICE_UNAVAILABLE),
+            }
+        },
+
+        (&Method::GET, ["blocks", block_id]) =>
+            match block_id.parse::<block::Id>() {
+                Ok(block_id) =>
+                    match pid.read_block(block_id).await {
+                        Ok(block_bytes) =>
+                            reply_block(block_bytes),
+                        Err(ReadBlockError::NotFound) =>
+                            reply_status(StatusCode::NOT_FOUND),
+                        Err(ReadBlockError::Corrupted) =>
+                            reply_status(StatusCode::INTERNAL_SERVER_ERROR),
+                        Err(ReadBlockError::GenServer(ero::NoProcError)) =>
+                            reply_status(StatusCode::SERVICE_UNAVAILABLE),
+                    },
+                Err(..) =>
+                    reply_status(StatusCode::BAD_REQUEST),
+            },
+
+        (&Method::DELETE, ["blocks", block_id]) =>
+            match block_id.parse::<block::Id>() {
+                Ok(block_id) =>
+                    match pid.delete_block(block_id).await {
+                        Ok(Deleted) =>
+                            reply_status(StatusCode::NO_CONTENT),
+                        Err(DeleteBlockError::NotFound) =>
+                            reply_status(StatusCode::NOT_FOUND),
+                        Err(DeleteBlockError::ReadOnly) =>
+                            reply_status(StatusCode::FORBIDDEN),
+                        Err(DeleteBlockError::GenServer(ero::NoProcError)) =>
+                            reply_status(StatusCode::SERVICE_UNAVAILABLE),
+                    },
+                Err(..) =>
+                    reply_status(StatusCode::BAD_REQUEST),
+            },
+
+        (&Method::GET, ["info"]) =>
+            match pid.info().await {
+                Ok(info) =>
+                    match serde_json::to_vec(&info) {
+                        Ok(json) =>
+                            reply(StatusCode::OK, "application/json", &blocks_pool, &json),
+                        Err(error) => {
+                            log::error!("failed to serialize info {:?}: {:?}", info, error);
+                            reply_status(StatusCode::INTERNAL_SERVER_ERROR)
+                        },
+                    },
+                Err(ero::NoProcError) =>
+                    reply_status(StatusCode::SERVICE_UNAVAILABLE),
+            },
+
+        (&Method::POST, ["flush"]) =>
+            match pid.flush().await {
+                Ok(Flushed) =>
+                    reply_status(StatusCode::NO_CONTENT),
+                Err(ero::NoProcError) =>
+                    reply_status(StatusCode::SERVICE_UNAVAILABLE),
+            },
+
+        (_, ["blocks"]) | (_, ["blocks", _]) | (_, ["info"]) | (_, ["flush"]) =>
+            reply_status(StatusCode::METHOD_NOT_ALLOWED),
+
+        _ =>
+            reply_status(StatusCode::NOT_FOUND),
+
+    };
+    Ok(response)
+}
+
+fn reply(status: StatusCode, content_type: &'static str, blocks_pool: &BytesPool, body: &[u8]) -> Response<ReplyBody> {
+    let mut body_bytes = blocks_pool.lend();
+    body_bytes.extend_from_slice(body);
+    let mut response = Response::new(ReplyBody { body_bytes: Some(body_bytes.freeze()), });
+    *response.status_mut() = status;
+    response.headers_mut()
+        .insert(header::CONTENT_TYPE, header::HeaderValue::from_static(content_type));
+    response
+}
+
+fn reply_block(block_bytes: Bytes) -> Response<ReplyBody> {
+    let mut response = Response::new(ReplyBody { body_bytes: Some(block_bytes), });
+    response.headers_mut()
+        .insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/octet-stream"));
+    response
+}
+
+fn reply_status(status: StatusCode) -> Response<ReplyBody> {
+    let mut response = Response::new(ReplyBody { body_bytes: None, });
+    *response.status_mut() = status;
+    response
+}
+
+// block contents are sent straight from pool buffer without copying them into hyper body
+struct ReplyBody {
+    body_bytes: Option<Bytes>,
+}
+
+struct ReplyChunk {
+    body_bytes: Bytes,
+    offset: usize,
+}
+
+impl bytes::Buf for ReplyChunk {
+    fn remaining(&self) -> usize {
+        self.body_bytes.len() - self.offset
+    }
+
+    fn chunk(&self) -> &[u8] {
+        &self.body_bytes[self.offset ..]
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.remaining());
+        self.offset += cnt;
+    }
+}
+
+impl HttpBody for ReplyBody {
+    type Data = ReplyChunk;
+    type Error = Infallible;
+
+    fn poll_data(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Result<ReplyChunk, Infallible>>> {
+        Poll::Ready(self.body_bytes.take().map(|body_bytes| Ok(ReplyChunk { body_bytes, offset: 0, })))
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<Option<header::HeaderMap>, Infallible>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body_bytes.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.body_bytes.as_ref().map_or(0, |body_bytes| body_bytes.len() as u64))
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    fmt,
+    io::{
+        self,
+        Read,
+        Seek,
+    },
+};
+
+use crate::block;
+
+/// Storage backend for a wheel, plugged in with `InterpreterParams::Custom`.
+///
+/// Wheel is a flat area of fixed size addressed by byte offsets, all of its layout is managed by the
+/// crate: a backend only stores and returns bytes. Methods are called from a dedicated interpreter thread,
+/// requests are served strictly one by one.
+///
+/// An instance in `Params` is a template: it is cloned with `clone_box` every time the wheel is (re)started.
+pub trait Interpreter: fmt::Debug + Send + Sync {
+    /// Size of a wheel created from scratch.
+    fn init_wheel_size_bytes(&self) -> usize;
+
+    /// Creates a blank wheel of `init_wheel_size_bytes` bytes with `wheel_init` stored at offset zero,
+    /// contents of the rest of the wheel are not significant.
+    fn create(&mut self, init_wheel_size_bytes: usize, wheel_init: &[u8]) -> io::Result<()>;
+
+    /// Opens an existing wheel for the initial scan, `None` is returned if there is no wheel yet.
+    ///
+    /// The whole wheel should be available through returned reader, its size is taken from its end.
+    fn open(&mut self) -> io::Result<Option<Box<dyn WheelRead + '_>>>;
+
+    /// Invoked once in the interpreter thread after a successful create or open, before any request.
+    fn run(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Executes the task synchronously, any error terminates the interpreter.
+    fn push_request(&mut self, task: Task<'_>) -> io::Result<()>;
+
+    /// Makes all previously written bytes durable.
+    fn device_sync(&mut self) -> io::Result<()>;
+
+    fn clone_box(&self) -> Box<dyn Interpreter>;
+}
+
+impl Clone for Box<dyn Interpreter> {
+    fn clone(&self) -> Box<dyn Interpreter> {
+        self.clone_box()
+    }
+}
+
+pub trait WheelRead: Read + Seek {}
+
+impl<T> WheelRead for T where T: Read + Seek {}
+
+#[derive(Debug)]
+pub struct Task<'a> {
+    pub offset: u64,
+    // block the task is issued for, terminator writes carry id of the block being committed
+    pub block_id: &'a block::Id,
+    pub kind: TaskKind<'a>,
+}
+
+#[derive(Debug)]
+pub enum TaskKind<'a> {
+    Write { bytes: &'a [u8], },
+    Read { bytes: &'a mut [u8], },
+}
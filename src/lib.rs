@@ -1,4 +1,6 @@
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+// `mmap` feature needs a single audited module with unsafe file mapping, see `wheel::interpret::mmap::region`
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
 
 use std::{
     path::PathBuf,
@@ -19,6 +21,11 @@ use edeltraud::{
     Edeltraud,
 };
 
+use serde_derive::{
+    Serialize,
+    Deserialize,
+};
+
 use alloc_pool::bytes::{
     Bytes,
     BytesPool,
@@ -32,15 +39,36 @@ use ero::{
 
 pub mod job;
 pub mod block;
+pub mod archive;
+pub mod upgrade;
+pub mod replication;
+pub mod net;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub mod interpreter;
+pub mod stripe;
+pub mod sharded;
+#[cfg(feature = "erasure")]
+pub mod erasure;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 
 mod wheel;
 mod proto;
 mod storage;
 mod context;
+mod fanout;
 
 #[cfg(test)]
 mod tests;
 
+pub use sharded::{
+    GenServer as ShardedGenServer,
+    Pid as ShardedPid,
+};
+
 #[derive(Clone, Debug)]
 pub struct Params {
     pub interpreter: InterpreterParams,
@@ -48,23 +76,149 @@ pub struct Params {
     pub work_block_size_bytes: usize,
     pub lru_cache_size_bytes: usize,
     pub defrag_parallel_tasks_limit: usize,
+    // overwrite contents of every deleted or defrag moved block, `Pid::delete_block_secure` applies per request
+    pub secure_delete: Option<SecureDelete>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SecureDelete {
+    Zeros,
+    Random,
 }
 
 #[derive(Clone, Debug)]
 pub enum InterpreterParams {
     FixedFile(FixedFileInterpreterParams),
+    #[cfg(unix)]
+    Positional(PositionalInterpreterParams),
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    IoUring(IoUringInterpreterParams),
+    #[cfg(feature = "mmap")]
+    Mmap(MmapInterpreterParams),
     Ram(RamInterpreterParams),
+    Faulty(FaultyInterpreterParams),
+    Mirror(MirrorInterpreterParams),
+    // user provided storage backend, see `interpreter::Interpreter`
+    Custom(Box<dyn interpreter::Interpreter>),
 }
 
 #[derive(Clone, Debug)]
 pub struct FixedFileInterpreterParams {
     pub wheel_filename: PathBuf,
     pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+    // `O_DIRECT` access (linux only), wheel size should be a multiple of 4096 bytes
+    pub direct_io: bool,
+    // deallocate (linux only) free areas left by deletes once merged to at least this size, `None` disables
+    pub punch_holes_min_bytes: Option<usize>,
+    // open existing wheel with shared lock and without write permission, writes and deletes are rejected
+    pub read_only: bool,
+}
+
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct PositionalInterpreterParams {
+    pub wheel_filename: PathBuf,
+    pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+    // also the number of read tasks kept in flight by the performer
+    pub reader_threads: usize,
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[derive(Clone, Debug)]
+pub struct IoUringInterpreterParams {
+    pub wheel_filename: PathBuf,
+    pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+    // ring size and the number of read tasks kept in flight, writes are still serialized
+    pub queue_depth: u32,
+}
+
+// `fixed_file` wheel accessed through a memory mapping, reads are copied once into `blocks_pool` bytes
+#[cfg(feature = "mmap")]
+#[derive(Clone, Debug)]
+pub struct MmapInterpreterParams {
+    pub wheel_filename: PathBuf,
+    pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+}
+
+// `fixed_file` wheel with scripted I/O faults, for testing failure and recovery paths
+#[derive(Clone, Debug)]
+pub struct FaultyInterpreterParams {
+    pub wheel_filename: PathBuf,
+    pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+    pub faults: Faults,
+}
+
+// the same wheel kept in every file: writes go to all of them, reads are served by the fastest one,
+// generation of every copy is kept in "<wheel_filename>.generation" and the freshest copy is taken on start
+#[derive(Clone, Debug)]
+pub struct MirrorInterpreterParams {
+    pub wheel_filenames: Vec<PathBuf>,
+    pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+    // also compare the copies of the same generation with the freshest one on start and rewrite differing areas
+    // (reads every copy in full), missing, replaced or stale files are resynced anyway; copies which diverged
+    // on a crash in the middle of a write are otherwise fixed by read repair
+    pub resync_on_open: bool,
+}
+
+// writes and reads are counted from 1 since the wheel is started,
+// after power cut all writes and syncs are silently dropped
+#[derive(Clone, Default, Debug)]
+pub struct Faults {
+    // fail this write with EIO, the interpreter terminates
+    pub fail_write_nth: Option<usize>,
+    // write only first bytes of this write, then cut the power
+    pub tear_write: Option<TearWrite>,
+    // cut the power once this many bytes are written
+    pub power_cut_after_write_bytes: Option<u64>,
+    // fail device syncs with EIO after power cut instead of dropping them, so flush reports the loss
+    pub report_power_cut_on_sync: bool,
+    // power cut also reverts writes which have not been followed by a device sync yet (volatile disk cache)
+    pub drop_unsynced_on_power_cut: bool,
+    // flip a bit in contents of this read
+    pub flip_bit_on_read: Option<FlipBit>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TearWrite {
+    pub nth_write: usize,
+    pub keep_bytes: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FlipBit {
+    pub nth_read: usize,
+    pub bit: usize,
+}
+
+// how the space of a new wheel file is allocated
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WheelCreateMode {
+    // write zeroes over the whole wheel
+    ZeroFill,
+    // reserve space with `fallocate` (linux only)
+    Fallocate,
+    // only set file length, space is allocated on write
+    Sparse,
+}
+
+impl Default for WheelCreateMode {
+    fn default() -> WheelCreateMode {
+        WheelCreateMode::ZeroFill
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct RamInterpreterParams {
     pub init_wheel_size_bytes: usize,
+    // wheel image is loaded from here on start and dumped back on flush and shutdown,
+    // every flush writes the whole `init_wheel_size_bytes` image, so flush rarely with large wheels
+    pub snapshot_filename: Option<PathBuf>,
 }
 
 impl Default for Params {
@@ -75,6 +229,7 @@ impl Default for Params {
             work_block_size_bytes: 8 * 1024 * 1024,
             lru_cache_size_bytes: 16 * 1024 * 1024,
             defrag_parallel_tasks_limit: 1,
+            secure_delete: None,
         }
     }
 }
@@ -90,6 +245,67 @@ impl Default for FixedFileInterpreterParams {
         FixedFileInterpreterParams {
             wheel_filename: "wheel".to_string().into(),
             init_wheel_size_bytes: 64 * 1024 * 1024,
+            create_mode: WheelCreateMode::default(),
+            direct_io: false,
+            punch_holes_min_bytes: None,
+            read_only: false,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Default for PositionalInterpreterParams {
+    fn default() -> PositionalInterpreterParams {
+        PositionalInterpreterParams {
+            wheel_filename: "wheel".to_string().into(),
+            init_wheel_size_bytes: 64 * 1024 * 1024,
+            create_mode: WheelCreateMode::default(),
+            reader_threads: 4,
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl Default for IoUringInterpreterParams {
+    fn default() -> IoUringInterpreterParams {
+        IoUringInterpreterParams {
+            wheel_filename: "wheel".to_string().into(),
+            init_wheel_size_bytes: 64 * 1024 * 1024,
+            create_mode: WheelCreateMode::default(),
+            queue_depth: 128,
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Default for MmapInterpreterParams {
+    fn default() -> MmapInterpreterParams {
+        MmapInterpreterParams {
+            wheel_filename: "wheel".to_string().into(),
+            init_wheel_size_bytes: 64 * 1024 * 1024,
+            create_mode: WheelCreateMode::default(),
+        }
+    }
+}
+
+impl Default for FaultyInterpreterParams {
+    fn default() -> FaultyInterpreterParams {
+        FaultyInterpreterParams {
+            wheel_filename: "wheel".to_string().into(),
+            init_wheel_size_bytes: 64 * 1024 * 1024,
+            create_mode: WheelCreateMode::default(),
+            faults: Faults::default(),
+        }
+    }
+}
+
+impl Default for MirrorInterpreterParams {
+    fn default() -> MirrorInterpreterParams {
+        MirrorInterpreterParams {
+            wheel_filenames: vec!["wheel.0".to_string().into(), "wheel.1".to_string().into()],
+            init_wheel_size_bytes: 64 * 1024 * 1024,
+            create_mode: WheelCreateMode::default(),
+            resync_on_open: false,
         }
     }
 }
@@ -98,6 +314,7 @@ impl Default for RamInterpreterParams {
     fn default() -> RamInterpreterParams {
         RamInterpreterParams {
             init_wheel_size_bytes: 64 * 1024 * 1024,
+            snapshot_filename: None,
         }
     }
 }
@@ -107,6 +324,7 @@ type Request = proto::Request<blockwheel_context::Context>;
 pub struct GenServer {
     request_tx: mpsc::Sender<Request>,
     fused_request_rx: stream::Fuse<mpsc::Receiver<Request>>,
+    commits_txs: Vec<mpsc::UnboundedSender<Committed>>,
 }
 
 #[derive(Clone)]
@@ -120,6 +338,7 @@ impl GenServer {
         GenServer {
             request_tx,
             fused_request_rx: request_rx.fuse(),
+            commits_txs: Vec::new(),
         }
     }
 
@@ -129,6 +348,12 @@ impl GenServer {
         }
     }
 
+    pub fn commits(&mut self) -> mpsc::UnboundedReceiver<Committed> {
+        let (commits_tx, commits_rx) = mpsc::unbounded();
+        self.commits_txs.push(commits_tx);
+        commits_rx
+    }
+
     pub async fn run<J>(
         self,
         parent_supervisor: SupervisorPid,
@@ -147,8 +372,23 @@ impl GenServer {
                     match params.interpreter {
                         InterpreterParams::FixedFile(ref interpreter_params) =>
                             format!("fixed file: {:?}", interpreter_params.wheel_filename),
+                        #[cfg(unix)]
+                        InterpreterParams::Positional(ref interpreter_params) =>
+                            format!("positional file: {:?}", interpreter_params.wheel_filename),
+                        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+                        InterpreterParams::IoUring(ref interpreter_params) =>
+                            format!("io_uring file: {:?}", interpreter_params.wheel_filename),
+                        #[cfg(feature = "mmap")]
+                        InterpreterParams::Mmap(ref interpreter_params) =>
+                            format!("mmap file: {:?}", interpreter_params.wheel_filename),
                         InterpreterParams::Ram(ref interpreter_params) =>
                             format!("ram file of {} bytes", interpreter_params.init_wheel_size_bytes),
+                        InterpreterParams::Faulty(ref interpreter_params) =>
+                            format!("faulty file: {:?}", interpreter_params.wheel_filename),
+                        InterpreterParams::Mirror(ref interpreter_params) =>
+                            format!("mirror files: {:?}", interpreter_params.wheel_filenames),
+                        InterpreterParams::Custom(ref interpreter) =>
+                            format!("custom: {:?}", interpreter),
                     },
                 ),
                 restart_strategy: RestartStrategy::Delay {
@@ -160,6 +400,7 @@ impl GenServer {
                 thread_pool,
                 blocks_pool,
                 fused_request_rx: self.fused_request_rx,
+                commits_txs: self.commits_txs,
                 params,
             },
             |mut state| async move {
@@ -181,18 +422,23 @@ impl GenServer {
 pub enum WriteBlockError {
     GenServer(ero::NoProcError),
     NoSpaceLeft,
+    BlockIdTaken,
+    ReadOnly,
 }
 
 #[derive(Debug)]
 pub enum ReadBlockError {
     GenServer(ero::NoProcError),
     NotFound,
+    // block is stored but its contents have been damaged
+    Corrupted,
 }
 
 #[derive(Debug)]
 pub enum DeleteBlockError {
     GenServer(ero::NoProcError),
     NotFound,
+    ReadOnly,
 }
 
 #[derive(Debug)]
@@ -200,14 +446,28 @@ pub enum IterBlocksError {
     GenServer(ero::NoProcError),
 }
 
+#[derive(Debug)]
+pub enum ChangesSinceError {
+    GenServer(ero::NoProcError),
+    ChangesTooOld { change_seq_min: u64, },
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct Deleted;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct Flushed;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Committed {
+    WriteBlock { block_id: block::Id, },
+    DeleteBlock { block_id: block::Id, },
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize, Debug)]
 pub struct Info {
+    // persistent identity of the wheel, generated once on create
+    pub wheel_id: u64,
     pub blocks_count: usize,
     pub wheel_size_bytes: usize,
     pub service_bytes_used: usize,
@@ -217,17 +477,24 @@ pub struct Info {
     pub interpret_stats: InterpretStats,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize, Debug)]
 pub struct InterpretStats {
     pub count_total: usize,
     pub count_no_seek: usize,
     pub count_seek_forward: usize,
     pub count_seek_backward: usize,
+    pub count_writer: usize,
+    pub count_readers: usize,
+    pub readers_busy_max: usize,
+    // reads served by every reader thread, indexed by reader, empty for interpreters without reader threads
+    pub count_per_reader: Vec<usize>,
 }
 
 pub struct IterBlocks {
     pub blocks_total_count: usize,
     pub blocks_total_size: usize,
+    pub change_seq: u64,
+    pub deleted_block_ids: Vec<block::Id>,
     pub blocks_rx: mpsc::Receiver<IterBlocksItem>,
 }
 
@@ -266,11 +533,26 @@ impl Pid {
     }
 
     pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        self.write_block_request(None, block_bytes).await
+    }
+
+    pub async fn write_block_with_id(&mut self, block_id: block::Id, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        self.write_block_request(Some(block_id), block_bytes).await
+    }
+
+    async fn write_block_request(
+        &mut self,
+        maybe_block_id: Option<block::Id>,
+        block_bytes: Bytes,
+    )
+        -> Result<block::Id, WriteBlockError>
+    {
         loop {
             let (reply_tx, reply_rx) = oneshot::channel();
             self.request_tx
                 .send(proto::Request::WriteBlock(proto::RequestWriteBlock {
                     block_bytes: block_bytes.clone(),
+                    block_id: maybe_block_id.clone(),
                     context: reply_tx,
                 }))
                 .await
@@ -281,6 +563,10 @@ impl Pid {
                     return Ok(block_id),
                 Ok(Err(blockwheel_context::RequestWriteBlockError::NoSpaceLeft)) =>
                     return Err(WriteBlockError::NoSpaceLeft),
+                Ok(Err(blockwheel_context::RequestWriteBlockError::BlockIdTaken)) =>
+                    return Err(WriteBlockError::BlockIdTaken),
+                Ok(Err(blockwheel_context::RequestWriteBlockError::ReadOnly)) =>
+                    return Err(WriteBlockError::ReadOnly),
                 Err(oneshot::Canceled) =>
                     (),
             }
@@ -303,6 +589,8 @@ impl Pid {
                     return Ok(block_bytes),
                 Ok(Err(blockwheel_context::RequestReadBlockError::NotFound)) =>
                     return Err(ReadBlockError::NotFound),
+                Ok(Err(blockwheel_context::RequestReadBlockError::Corrupted)) =>
+                    return Err(ReadBlockError::Corrupted),
                 Err(oneshot::Canceled) =>
                     (),
             }
@@ -310,11 +598,27 @@ impl Pid {
     }
 
     pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        self.delete_block_with(block_id, None).await
+    }
+
+    /// Deletes block overwriting its contents, returns only after the overwrite is synced to the device.
+    ///
+    /// Overwrites made because of `Params::secure_delete` (for plain deletes and defrag moves) are not synced
+    /// on their own: call `flush` when they must reach the device.
+    pub async fn delete_block_secure(&mut self, block_id: block::Id, secure_delete: SecureDelete) -> Result<Deleted, DeleteBlockError> {
+        let Deleted = self.delete_block_with(block_id, Some(secure_delete)).await?;
+        let Flushed = self.flush().await
+            .map_err(DeleteBlockError::GenServer)?;
+        Ok(Deleted)
+    }
+
+    async fn delete_block_with(&mut self, block_id: block::Id, secure_delete: Option<SecureDelete>) -> Result<Deleted, DeleteBlockError> {
         loop {
             let (reply_tx, reply_rx) = oneshot::channel();
             self.request_tx
                 .send(proto::Request::DeleteBlock(proto::RequestDeleteBlock {
                     block_id: block_id.clone(),
+                    secure_delete,
                     context: reply_tx,
                 }))
                 .await
@@ -325,22 +629,70 @@ impl Pid {
                     return Ok(Deleted),
                 Ok(Err(blockwheel_context::RequestDeleteBlockError::NotFound)) =>
                     return Err(DeleteBlockError::NotFound),
+                Ok(Err(blockwheel_context::RequestDeleteBlockError::ReadOnly)) =>
+                    return Err(DeleteBlockError::ReadOnly),
                 Err(oneshot::Canceled) =>
                     (),
             }
         }
     }
 
+    pub async fn export<W>(&mut self, writer: W) -> Result<archive::Exported, archive::ExportError>
+    where W: tokio::io::AsyncWrite + Unpin,
+    {
+        archive::export(self, writer).await
+    }
+
+    pub async fn import<R>(
+        &mut self,
+        reader: R,
+        blocks_pool: &BytesPool,
+        params: archive::ImportParams,
+    )
+        -> Result<archive::Imported, archive::ImportError>
+    where R: tokio::io::AsyncRead + Unpin,
+    {
+        archive::import(self, reader, blocks_pool, params).await
+    }
+
     pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
         loop {
             let (reply_tx, reply_rx) = oneshot::channel();
             self.request_tx
-                .send(proto::Request::IterBlocks(proto::RequestIterBlocks { context: reply_tx, })).await
+                .send(proto::Request::IterBlocks(proto::RequestIterBlocks { changed_since: None, context: reply_tx, })).await
                 .map_err(|_send_error| IterBlocksError::GenServer(ero::NoProcError))?;
 
             match reply_rx.await {
-                Ok(iter_blocks) =>
+                Ok(Ok(iter_blocks)) =>
                     return Ok(iter_blocks),
+                Ok(Err(blockwheel_context::RequestIterBlocksError::ChangesTooOld { .. })) =>
+                    unreachable!("full iteration is never too old"),
+                Err(oneshot::Canceled) =>
+                    (),
+            }
+        }
+    }
+
+    /// Streams blocks written after `change_seq` and reports ids of blocks deleted after it.
+    ///
+    /// Returned `IterBlocks::change_seq` should be passed here next time to get the following changes.
+    /// Deletions are tracked with tombstones on disk, so deletion of a block could be lost after wheel
+    /// reopen if its tombstone has been overwritten by some other block.
+    ///
+    /// Only a bounded number of recent tombstones is kept in memory: `ChangesSinceError::ChangesTooOld` is
+    /// returned when deletions after `change_seq` are not known anymore, full `iter_blocks` should be used then.
+    pub async fn changes_since(&mut self, change_seq: u64) -> Result<IterBlocks, ChangesSinceError> {
+        loop {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            self.request_tx
+                .send(proto::Request::IterBlocks(proto::RequestIterBlocks { changed_since: Some(change_seq), context: reply_tx, })).await
+                .map_err(|_send_error| ChangesSinceError::GenServer(ero::NoProcError))?;
+
+            match reply_rx.await {
+                Ok(Ok(iter_blocks)) =>
+                    return Ok(iter_blocks),
+                Ok(Err(blockwheel_context::RequestIterBlocksError::ChangesTooOld { change_seq_min, })) =>
+                    return Err(ChangesSinceError::ChangesTooOld { change_seq_min, }),
                 Err(oneshot::Canceled) =>
                     (),
             }
@@ -354,7 +706,7 @@ mod blockwheel_context {
             mpsc,
             oneshot,
         },
-        future,
+        stream,
     };
 
     use alloc_pool::bytes::Bytes;
@@ -380,23 +732,33 @@ mod blockwheel_context {
         type WriteBlock = oneshot::Sender<Result<block::Id, RequestWriteBlockError>>;
         type ReadBlock = oneshot::Sender<Result<Bytes, RequestReadBlockError>>;
         type DeleteBlock = oneshot::Sender<Result<Deleted, RequestDeleteBlockError>>;
-        type IterBlocks = oneshot::Sender<IterBlocks>;
+        type IterBlocks = oneshot::Sender<Result<IterBlocks, RequestIterBlocksError>>;
         type IterBlocksStream = mpsc::Sender<IterBlocksItem>;
-        type Interpreter = future::Fuse<interpret::RequestReplyRx<Self>>;
+        // replies for all tasks in progress
+        type Interpreter = stream::FuturesUnordered<interpret::RequestReplyRx<Self>>;
     }
 
     #[derive(Clone, PartialEq, Eq, Debug)]
     pub enum RequestWriteBlockError {
         NoSpaceLeft,
+        BlockIdTaken,
+        ReadOnly,
     }
 
     #[derive(Clone, PartialEq, Eq, Debug)]
     pub enum RequestReadBlockError {
         NotFound,
+        Corrupted,
     }
 
     #[derive(Clone, PartialEq, Eq, Debug)]
     pub enum RequestDeleteBlockError {
         NotFound,
+        ReadOnly,
+    }
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum RequestIterBlocksError {
+        ChangesTooOld { change_seq_min: u64, },
     }
 }
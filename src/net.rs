@@ -0,0 +1,223 @@
+//! Framed binary protocol exposing wheel `Pid` over TCP and Unix sockets.
+//!
+//! Every frame consists of:
+//!
+//! ```text
+//! FramePrefix { request_id, header_size, payload_size }
+//! header_size bytes of `RequestHeader` (client to server) or `ReplyHeader` (server to client)
+//! payload_size bytes of block contents (write requests, read replies and iter items, empty otherwise)
+//! ```
+//!
+//! All structures are encoded with the same bincode options as wheel storage itself. Replies carry
+//! `request_id` of their request, so any number of requests could be in flight over one connection.
+//! `IterBlocks` reply is followed by `IterBlocksItem` frames and finally `IterBlocksFinish` with the same id.
+//!
+//! Frames with header larger than `FRAME_HEADER_SIZE_MAX` or payload larger than wheel work block are rejected
+//! before anything is allocated for them.
+
+use std::io;
+
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+};
+
+use serde_derive::{
+    Serialize,
+    Deserialize,
+};
+
+use bincode::Options;
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use crate::{
+    block,
+    storage,
+    Info,
+};
+
+pub mod server;
+pub mod client;
+
+pub use self::client::{
+    RemotePid,
+    ClientParams,
+};
+pub use self::server::ServerParams;
+
+// fits `ReplyHeader::IterBlocks` with the maximum of tombstones kept by a wheel
+pub const FRAME_HEADER_SIZE_MAX: usize = 1024 * 1024;
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Default, Debug)]
+pub struct FramePrefix {
+    pub request_id: u64,
+    pub header_size: usize,
+    pub payload_size: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum RequestHeader {
+    Info,
+    Flush,
+    WriteBlock { block_id: Option<block::Id>, },
+    ReadBlock { block_id: block::Id, },
+    DeleteBlock { block_id: block::Id, },
+    IterBlocks { changed_since: Option<u64>, },
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum ReplyHeader {
+    Info(Info),
+    Flushed,
+    WriteBlock(Result<block::Id, WriteBlockFailure>),
+    ReadBlock(Result<(), ReadBlockFailure>),
+    DeleteBlock(Result<(), DeleteBlockFailure>),
+    IterBlocks {
+        blocks_total_count: usize,
+        blocks_total_size: usize,
+        change_seq: u64,
+        deleted_block_ids: Vec<block::Id>,
+    },
+    IterBlocksItem { block_id: block::Id, },
+    IterBlocksFinish,
+    ChangesTooOld { change_seq_min: u64, },
+    WheelGone,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum WriteBlockFailure {
+    NoSpaceLeft,
+    BlockIdTaken,
+    ReadOnly,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum ReadBlockFailure {
+    NotFound,
+    Corrupted,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum DeleteBlockFailure {
+    NotFound,
+    ReadOnly,
+}
+
+pub struct Frame<H> {
+    pub request_id: u64,
+    pub header: H,
+    pub payload: Bytes,
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    LayoutCalculate(bincode::Error),
+    PrefixRead(io::Error),
+    PrefixDeserialize(bincode::Error),
+    HeaderTooLarge {
+        header_size: usize,
+        header_size_max: usize,
+    },
+    PayloadTooLarge {
+        payload_size: usize,
+        payload_size_max: usize,
+    },
+    HeaderRead(io::Error),
+    HeaderDeserialize(bincode::Error),
+    PayloadRead(io::Error),
+    PrefixSerialize(bincode::Error),
+    HeaderSerialize(bincode::Error),
+    Write(io::Error),
+    Flush(io::Error),
+}
+
+pub async fn read_frame<R, H>(
+    reader: &mut R,
+    work_block: &mut Vec<u8>,
+    blocks_pool: &BytesPool,
+    payload_size_max: usize,
+)
+    -> Result<Frame<H>, FrameError>
+where R: AsyncRead + Unpin,
+      H: serde::de::DeserializeOwned,
+{
+    let frame_prefix_size = storage::bincode_options()
+        .serialized_size(&FramePrefix::default())
+        .map_err(FrameError::LayoutCalculate)? as usize;
+    work_block.resize(frame_prefix_size, 0);
+    reader.read_exact(work_block).await
+        .map_err(FrameError::PrefixRead)?;
+    let frame_prefix: FramePrefix = storage::bincode_options()
+        .deserialize_from(&work_block[..])
+        .map_err(FrameError::PrefixDeserialize)?;
+    if frame_prefix.header_size > FRAME_HEADER_SIZE_MAX {
+        return Err(FrameError::HeaderTooLarge {
+            header_size: frame_prefix.header_size,
+            header_size_max: FRAME_HEADER_SIZE_MAX,
+        });
+    }
+    if frame_prefix.payload_size > payload_size_max {
+        return Err(FrameError::PayloadTooLarge {
+            payload_size: frame_prefix.payload_size,
+            payload_size_max,
+        });
+    }
+
+    work_block.resize(frame_prefix.header_size, 0);
+    reader.read_exact(work_block).await
+        .map_err(FrameError::HeaderRead)?;
+    let header = storage::bincode_options()
+        .deserialize_from(&work_block[..])
+        .map_err(FrameError::HeaderDeserialize)?;
+
+    let mut payload = blocks_pool.lend();
+    payload.resize(frame_prefix.payload_size, 0);
+    reader.read_exact(&mut payload[..]).await
+        .map_err(FrameError::PayloadRead)?;
+
+    Ok(Frame {
+        request_id: frame_prefix.request_id,
+        header,
+        payload: payload.freeze(),
+    })
+}
+
+pub async fn write_frame<W, H>(
+    writer: &mut W,
+    work_block: &mut Vec<u8>,
+    request_id: u64,
+    header: &H,
+    payload: &[u8],
+)
+    -> Result<(), FrameError>
+where W: AsyncWrite + Unpin,
+      H: serde::Serialize,
+{
+    let header_size = storage::bincode_options()
+        .serialized_size(header)
+        .map_err(FrameError::LayoutCalculate)? as usize;
+    let frame_prefix = FramePrefix {
+        request_id,
+        header_size,
+        payload_size: payload.len(),
+    };
+    work_block.clear();
+    storage::bincode_options()
+        .serialize_into(&mut *work_block, &frame_prefix)
+        .map_err(FrameError::PrefixSerialize)?;
+    storage::bincode_options()
+        .serialize_into(&mut *work_block, header)
+        .map_err(FrameError::HeaderSerialize)?;
+    writer.write_all(work_block).await
+        .map_err(FrameError::Write)?;
+    writer.write_all(payload).await
+        .map_err(FrameError::Write)?;
+    writer.flush().await
+        .map_err(FrameError::Flush)
+}
@@ -0,0 +1,414 @@
+use std::{
+    io,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    net::SocketAddr,
+    collections::HashMap,
+};
+
+#[cfg(unix)]
+use std::path::Path;
+
+use futures::{
+    channel::{
+        mpsc,
+        oneshot,
+    },
+    SinkExt,
+    StreamExt,
+};
+
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncWrite,
+        BufReader,
+        BufWriter,
+    },
+    net::TcpStream,
+};
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use crate::{
+    block,
+    Info,
+    Deleted,
+    Flushed,
+    IterBlocks,
+    IterBlocksItem,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+    IterBlocksError,
+    ChangesSinceError,
+};
+
+use super::{
+    read_frame,
+    write_frame,
+    Frame,
+    FrameError,
+    RequestHeader,
+    ReplyHeader,
+    WriteBlockFailure,
+    ReadBlockFailure,
+    DeleteBlockFailure,
+};
+
+// iteration announcing more blocks than this fails once the buffer is full (untrusted count from server)
+const ITER_BLOCKS_BUFFER_MAX: usize = 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct ClientParams {
+    // should be `Params::work_block_size_bytes` of the remote wheel
+    pub payload_size_max: usize,
+}
+
+impl Default for ClientParams {
+    fn default() -> ClientParams {
+        ClientParams {
+            payload_size_max: crate::Params::default().work_block_size_bytes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConnectError {
+    Connect(io::Error),
+}
+
+/// Client for a wheel served with `net::server`.
+///
+/// Methods mirror those of `Pid`; `GenServer` error variants mean that connection
+/// to the server (or the wheel behind it) is gone.
+#[derive(Clone)]
+pub struct RemotePid {
+    request_tx: mpsc::Sender<Command>,
+}
+
+struct Command {
+    header: RequestHeader,
+    payload: Option<Bytes>,
+    pending: Pending,
+}
+
+enum Pending {
+    Reply(oneshot::Sender<Frame<ReplyHeader>>),
+    IterBlocks(oneshot::Sender<Result<IterBlocks, ChangesSinceError>>),
+    IterBlocksStream(mpsc::Sender<IterBlocksItem>),
+    IterBlocksDiscard,
+}
+
+#[derive(Default)]
+struct Inflight {
+    closed: bool,
+    next_request_id: u64,
+    pending: HashMap<u64, Pending>,
+}
+
+impl RemotePid {
+    pub async fn connect_tcp(addr: SocketAddr, blocks_pool: BytesPool, params: ClientParams) -> Result<RemotePid, ConnectError> {
+        let stream = TcpStream::connect(addr).await
+            .map_err(ConnectError::Connect)?;
+        stream.set_nodelay(true)
+            .map_err(ConnectError::Connect)?;
+        Ok(RemotePid::spawn(stream, blocks_pool, params))
+    }
+
+    #[cfg(unix)]
+    pub async fn connect_unix<P>(path: P, blocks_pool: BytesPool, params: ClientParams) -> Result<RemotePid, ConnectError> where P: AsRef<Path> {
+        let stream = UnixStream::connect(path).await
+            .map_err(ConnectError::Connect)?;
+        Ok(RemotePid::spawn(stream, blocks_pool, params))
+    }
+
+    fn spawn<S>(stream: S, blocks_pool: BytesPool, params: ClientParams) -> RemotePid where S: AsyncRead + AsyncWrite + Send + 'static {
+        let (reader, writer) = tokio::io::split(stream);
+        let (request_tx, request_rx) = mpsc::channel(0);
+        let inflight = Arc::new(Mutex::new(Inflight::default()));
+        tokio::spawn(write_requests(BufWriter::new(writer), request_rx, inflight.clone()));
+        tokio::spawn(read_replies(BufReader::new(reader), blocks_pool, params, inflight));
+        RemotePid { request_tx, }
+    }
+
+    pub async fn info(&mut self) -> Result<Info, ero::NoProcError> {
+        match self.request(RequestHeader::Info, None).await?.header {
+            ReplyHeader::Info(info) =>
+                Ok(info),
+            other =>
+                Err(unexpected_reply("info", other)),
+        }
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, ero::NoProcError> {
+        match self.request(RequestHeader::Flush, None).await?.header {
+            ReplyHeader::Flushed =>
+                Ok(Flushed),
+            other =>
+                Err(unexpected_reply("flush", other)),
+        }
+    }
+
+    pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        self.write_block_request(None, block_bytes).await
+    }
+
+    pub async fn write_block_with_id(&mut self, block_id: block::Id, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        self.write_block_request(Some(block_id), block_bytes).await
+    }
+
+    async fn write_block_request(
+        &mut self,
+        maybe_block_id: Option<block::Id>,
+        block_bytes: Bytes,
+    )
+        -> Result<block::Id, WriteBlockError>
+    {
+        let reply = self.request(RequestHeader::WriteBlock { block_id: maybe_block_id, }, Some(block_bytes)).await
+            .map_err(WriteBlockError::GenServer)?;
+        match reply.header {
+            ReplyHeader::WriteBlock(Ok(block_id)) =>
+                Ok(block_id),
+            ReplyHeader::WriteBlock(Err(WriteBlockFailure::NoSpaceLeft)) =>
+                Err(WriteBlockError::NoSpaceLeft),
+            ReplyHeader::WriteBlock(Err(WriteBlockFailure::BlockIdTaken)) =>
+                Err(WriteBlockError::BlockIdTaken),
+            ReplyHeader::WriteBlock(Err(WriteBlockFailure::ReadOnly)) =>
+                Err(WriteBlockError::ReadOnly),
+            other =>
+                Err(WriteBlockError::GenServer(unexpected_reply("write_block", other))),
+        }
+    }
+
+    pub async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        let reply = self.request(RequestHeader::ReadBlock { block_id, }, None).await
+            .map_err(ReadBlockError::GenServer)?;
+        match reply.header {
+            ReplyHeader::ReadBlock(Ok(())) =>
+                Ok(reply.payload),
+            ReplyHeader::ReadBlock(Err(ReadBlockFailure::NotFound)) =>
+                Err(ReadBlockError::NotFound),
+            ReplyHeader::ReadBlock(Err(ReadBlockFailure::Corrupted)) =>
+                Err(ReadBlockError::Corrupted),
+            other =>
+                Err(ReadBlockError::GenServer(unexpected_reply("read_block", other))),
+        }
+    }
+
+    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let reply = self.request(RequestHeader::DeleteBlock { block_id, }, None).await
+            .map_err(DeleteBlockError::GenServer)?;
+        match reply.header {
+            ReplyHeader::DeleteBlock(Ok(())) =>
+                Ok(Deleted),
+            ReplyHeader::DeleteBlock(Err(DeleteBlockFailure::NotFound)) =>
+                Err(DeleteBlockError::NotFound),
+            ReplyHeader::DeleteBlock(Err(DeleteBlockFailure::ReadOnly)) =>
+                Err(DeleteBlockError::ReadOnly),
+            other =>
+                Err(DeleteBlockError::GenServer(unexpected_reply("delete_block", other))),
+        }
+    }
+
+    pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
+        match self.iter_blocks_request(None).await {
+            Ok(iter_blocks) =>
+                Ok(iter_blocks),
+            Err(ChangesSinceError::GenServer(ero::NoProcError)) =>
+                Err(IterBlocksError::GenServer(ero::NoProcError)),
+            Err(ChangesSinceError::ChangesTooOld { change_seq_min, }) => {
+                log::error!("unexpected server reply for iter_blocks request: changes too old, min = {}", change_seq_min);
+                Err(IterBlocksError::GenServer(ero::NoProcError))
+            },
+        }
+    }
+
+    pub async fn changes_since(&mut self, change_seq: u64) -> Result<IterBlocks, ChangesSinceError> {
+        self.iter_blocks_request(Some(change_seq)).await
+    }
+
+    async fn iter_blocks_request(&mut self, changed_since: Option<u64>) -> Result<IterBlocks, ChangesSinceError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx
+            .send(Command {
+                header: RequestHeader::IterBlocks { changed_since, },
+                payload: None,
+                pending: Pending::IterBlocks(reply_tx),
+            })
+            .await
+            .map_err(|_send_error| ChangesSinceError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ChangesSinceError::GenServer(ero::NoProcError))?
+    }
+
+    async fn request(&mut self, header: RequestHeader, payload: Option<Bytes>) -> Result<Frame<ReplyHeader>, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx
+            .send(Command {
+                header,
+                payload,
+                pending: Pending::Reply(reply_tx),
+            })
+            .await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+}
+
+fn unexpected_reply(request: &str, reply: ReplyHeader) -> ero::NoProcError {
+    match reply {
+        ReplyHeader::WheelGone =>
+            log::debug!("wheel is gone on server during {} request", request),
+        other =>
+            log::error!("unexpected server reply for {} request: {:?}", request, other),
+    }
+    ero::NoProcError
+}
+
+async fn write_requests<W>(
+    mut writer: W,
+    mut request_rx: mpsc::Receiver<Command>,
+    inflight: Arc<Mutex<Inflight>>,
+)
+where W: AsyncWrite + Unpin,
+{
+    let mut work_block = Vec::new();
+    while let Some(Command { header, payload, pending, }) = request_rx.next().await {
+        let request_id = {
+            let mut inflight = inflight.lock().unwrap();
+            if inflight.closed {
+                // dropping `pending` notifies requester
+                continue;
+            }
+            let request_id = inflight.next_request_id;
+            inflight.next_request_id += 1;
+            inflight.pending.insert(request_id, pending);
+            request_id
+        };
+        let payload_slice: &[u8] = match payload {
+            Some(ref block_bytes) =>
+                block_bytes,
+            None =>
+                &[],
+        };
+        if let Err(error) = write_frame(&mut writer, &mut work_block, request_id, &header, payload_slice).await {
+            log::warn!("connection to server lost while sending request: {:?}", error);
+            close(&inflight);
+            break;
+        }
+    }
+}
+
+async fn read_replies<R>(
+    mut reader: R,
+    blocks_pool: BytesPool,
+    params: ClientParams,
+    inflight: Arc<Mutex<Inflight>>,
+)
+where R: AsyncRead + Unpin,
+{
+    let mut work_block = Vec::new();
+    loop {
+        let reply: Frame<ReplyHeader> = match read_frame(&mut reader, &mut work_block, &blocks_pool, params.payload_size_max).await {
+            Ok(reply) =>
+                reply,
+            Err(FrameError::PrefixRead(ref error)) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                log::debug!("server closed connection");
+                break;
+            },
+            Err(error) => {
+                log::warn!("connection to server lost while receiving reply: {:?}", error);
+                break;
+            },
+        };
+
+        let request_id = reply.request_id;
+        let maybe_pending = inflight.lock().unwrap().pending.remove(&request_id);
+        match (maybe_pending, reply.header) {
+            (None, header) =>
+                log::warn!("received reply {:?} for unknown request {}", header, request_id),
+            (Some(Pending::Reply(reply_tx)), header) => {
+                let frame = Frame { request_id, header, payload: reply.payload, };
+                if let Err(_send_error) = reply_tx.send(frame) {
+                    log::debug!("requester is gone before reply {} received", request_id);
+                }
+            },
+            (
+                Some(Pending::IterBlocks(reply_tx)),
+                ReplyHeader::IterBlocks { blocks_total_count, blocks_total_size, change_seq, deleted_block_ids, },
+            ) => {
+                // the whole iteration fits into the channel, so a slow consumer never stalls other replies
+                let buffer = blocks_total_count.min(ITER_BLOCKS_BUFFER_MAX) + 1;
+                let (blocks_tx, blocks_rx) = mpsc::channel(buffer);
+                let iter_blocks = IterBlocks {
+                    blocks_total_count,
+                    blocks_total_size,
+                    change_seq,
+                    deleted_block_ids,
+                    blocks_rx,
+                };
+                if let Err(_send_error) = reply_tx.send(Ok(iter_blocks)) {
+                    log::debug!("requester is gone before iter blocks reply {} received", request_id);
+                }
+                // keep routing items even if requester is gone: server continues to stream them
+                inflight.lock().unwrap().pending.insert(request_id, Pending::IterBlocksStream(blocks_tx));
+            },
+            (Some(Pending::IterBlocksStream(mut blocks_tx)), ReplyHeader::IterBlocksItem { block_id, }) => {
+                let item = IterBlocksItem::Block { block_id, block_bytes: reply.payload, };
+                let pending = match blocks_tx.try_send(item) {
+                    Ok(()) =>
+                        Pending::IterBlocksStream(blocks_tx),
+                    Err(ref error) if error.is_disconnected() => {
+                        log::debug!("iter blocks receiver is gone for request {}", request_id);
+                        Pending::IterBlocksStream(blocks_tx)
+                    },
+                    Err(..) => {
+                        // server sent more than announced: fail this iteration only, receiver sees the stream dropped
+                        log::error!("iter blocks request {} overflow, terminating iteration", request_id);
+                        Pending::IterBlocksDiscard
+                    },
+                };
+                inflight.lock().unwrap().pending.insert(request_id, pending);
+            },
+            (Some(Pending::IterBlocksStream(mut blocks_tx)), ReplyHeader::IterBlocksFinish) => {
+                if let Err(_send_error) = blocks_tx.try_send(IterBlocksItem::NoMoreBlocks) {
+                    log::debug!("iter blocks receiver is gone or overflown for request {}", request_id);
+                }
+            },
+            (Some(Pending::IterBlocks(reply_tx)), ReplyHeader::ChangesTooOld { change_seq_min, }) => {
+                if let Err(_send_error) = reply_tx.send(Err(ChangesSinceError::ChangesTooOld { change_seq_min, })) {
+                    log::debug!("requester is gone before iter blocks reply {} received", request_id);
+                }
+            },
+            (Some(Pending::IterBlocksDiscard), ReplyHeader::IterBlocksItem { .. }) => {
+                inflight.lock().unwrap().pending.insert(request_id, Pending::IterBlocksDiscard);
+            },
+            (Some(Pending::IterBlocksDiscard), ReplyHeader::IterBlocksFinish) |
+            (Some(Pending::IterBlocksDiscard), ReplyHeader::WheelGone) =>
+                (),
+            (Some(Pending::IterBlocks(..)), ReplyHeader::WheelGone) |
+            (Some(Pending::IterBlocksStream(..)), ReplyHeader::WheelGone) =>
+                log::debug!("wheel is gone on server during iter blocks request {}", request_id),
+            (Some(..), header) =>
+                log::error!("unexpected server reply for iter blocks request {}: {:?}", request_id, header),
+        }
+    }
+    close(&inflight);
+}
+
+fn close(inflight: &Mutex<Inflight>) {
+    let mut inflight = inflight.lock().unwrap();
+    inflight.closed = true;
+    // dropping pending senders notifies all requesters
+    inflight.pending.clear();
+}
@@ -0,0 +1,301 @@
+use std::{
+    io,
+    fmt::Debug,
+    sync::Arc,
+};
+
+use futures::{
+    channel::mpsc,
+    SinkExt,
+    StreamExt,
+};
+
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncWrite,
+        BufReader,
+        BufWriter,
+    },
+    net::TcpListener,
+    sync::Semaphore,
+};
+
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use crate::{
+    Pid,
+    Deleted,
+    Flushed,
+    IterBlocksItem,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+    IterBlocksError,
+    ChangesSinceError,
+};
+
+use super::{
+    read_frame,
+    write_frame,
+    Frame,
+    FrameError,
+    RequestHeader,
+    ReplyHeader,
+    WriteBlockFailure,
+    ReadBlockFailure,
+    DeleteBlockFailure,
+};
+
+#[derive(Clone, Debug)]
+pub struct ServerParams {
+    // should be `Params::work_block_size_bytes` of the served wheel
+    pub payload_size_max: usize,
+    // connection stops reading requests while this many are being processed
+    pub inflight_requests_max: usize,
+}
+
+impl Default for ServerParams {
+    fn default() -> ServerParams {
+        ServerParams {
+            payload_size_max: crate::Params::default().work_block_size_bytes,
+            inflight_requests_max: 64,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ServerError {
+    Accept(io::Error),
+}
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    RequestFrame(FrameError),
+    ReplyFrame(FrameError),
+}
+
+pub async fn serve_tcp(pid: Pid, blocks_pool: BytesPool, listener: TcpListener, params: ServerParams) -> Result<(), ServerError> {
+    loop {
+        let (stream, peer_addr) = listener.accept().await
+            .map_err(ServerError::Accept)?;
+        if let Err(error) = stream.set_nodelay(true) {
+            log::warn!("failed to set TCP_NODELAY for {}: {:?}", peer_addr, error);
+        }
+        spawn_connection(pid.clone(), blocks_pool.clone(), stream, peer_addr, params.clone());
+    }
+}
+
+#[cfg(unix)]
+pub async fn serve_unix(pid: Pid, blocks_pool: BytesPool, listener: UnixListener, params: ServerParams) -> Result<(), ServerError> {
+    loop {
+        let (stream, peer_addr) = listener.accept().await
+            .map_err(ServerError::Accept)?;
+        spawn_connection(pid.clone(), blocks_pool.clone(), stream, peer_addr, params.clone());
+    }
+}
+
+fn spawn_connection<S, A>(pid: Pid, blocks_pool: BytesPool, stream: S, peer_addr: A, params: ServerParams)
+where S: AsyncRead + AsyncWrite + Send + 'static,
+      A: Debug + Send + 'static,
+{
+    log::debug!("client {:?} connected", peer_addr);
+    tokio::spawn(async move {
+        match connection(pid, blocks_pool, stream, params).await {
+            Ok(()) =>
+                log::debug!("client {:?} disconnected", peer_addr),
+            Err(error) =>
+                log::warn!("client {:?} connection terminated: {:?}", peer_addr, error),
+        }
+    });
+}
+
+struct Reply {
+    request_id: u64,
+    header: ReplyHeader,
+    payload: Option<Bytes>,
+}
+
+async fn connection<S>(pid: Pid, blocks_pool: BytesPool, stream: S, params: ServerParams) -> Result<(), ConnectionError>
+where S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let (replies_tx, replies_rx) = mpsc::channel(0);
+    let writer_task = tokio::spawn(write_replies(BufWriter::new(writer), replies_rx));
+
+    let inflight = Arc::new(Semaphore::new(params.inflight_requests_max));
+    let mut work_block = Vec::new();
+    loop {
+        let permit = match inflight.clone().acquire_owned().await {
+            Ok(permit) =>
+                permit,
+            Err(_acquire_error) =>
+                unreachable!("semaphore is never closed"),
+        };
+        match read_frame(&mut reader, &mut work_block, &blocks_pool, params.payload_size_max).await {
+            Ok(request) => {
+                let process = process_request(pid.clone(), replies_tx.clone(), request);
+                tokio::spawn(async move {
+                    process.await;
+                    drop(permit);
+                })
+            },
+            Err(FrameError::PrefixRead(ref error)) if error.kind() == io::ErrorKind::UnexpectedEof =>
+                break,
+            Err(error) =>
+                return Err(ConnectionError::RequestFrame(error)),
+        };
+    }
+
+    // let pending requests finish and flush their replies
+    drop(replies_tx);
+    match writer_task.await {
+        Ok(result) =>
+            result,
+        Err(join_error) => {
+            log::error!("connection writer task failed: {:?}", join_error);
+            Ok(())
+        },
+    }
+}
+
+async fn write_replies<W>(mut writer: W, mut replies_rx: mpsc::Receiver<Reply>) -> Result<(), ConnectionError> where W: AsyncWrite + Unpin {
+    let mut work_block = Vec::new();
+    while let Some(Reply { request_id, header, payload, }) = replies_rx.next().await {
+        let payload_slice: &[u8] = match payload {
+            Some(ref block_bytes) =>
+                block_bytes,
+            None =>
+                &[],
+        };
+        write_frame(&mut writer, &mut work_block, request_id, &header, payload_slice).await
+            .map_err(ConnectionError::ReplyFrame)?;
+    }
+    Ok(())
+}
+
+async fn process_request(mut pid: Pid, mut replies_tx: mpsc::Sender<Reply>, request: Frame<RequestHeader>) {
+    let request_id = request.request_id;
+    let (header, payload) = match request.header {
+        RequestHeader::Info =>
+            match pid.info().await {
+                Ok(info) =>
+                    (ReplyHeader::Info(info), None),
+                Err(ero::NoProcError) =>
+                    (ReplyHeader::WheelGone, None),
+            },
+        RequestHeader::Flush =>
+            match pid.flush().await {
+                Ok(Flushed) =>
+                    (ReplyHeader::Flushed, None),
+                Err(ero::NoProcError) =>
+                    (ReplyHeader::WheelGone, None),
+            },
+        RequestHeader::WriteBlock { block_id: maybe_block_id, } => {
+            let result = match maybe_block_id {
+                None =>
+                    pid.write_block(request.payload).await,
+                Some(block_id) =>
+                    pid.write_block_with_id(block_id, request.payload).await,
+            };
+            match result {
+                Ok(block_id) =>
+                    (ReplyHeader::WriteBlock(Ok(block_id)), None),
+                Err(WriteBlockError::NoSpaceLeft) =>
+                    (ReplyHeader::WriteBlock(Err(WriteBlockFailure::NoSpaceLeft)), None),
+                Err(WriteBlockError::BlockIdTaken) =>
+                    (ReplyHeader::WriteBlock(Err(WriteBlockFailure::BlockIdTaken)), None),
+                Err(WriteBlockError::ReadOnly) =>
+                    (ReplyHeader::WriteBlock(Err(WriteBlockFailure::ReadOnly)), None),
+                Err(WriteBlockError::GenServer(ero::NoProcError)) =>
+                    (ReplyHeader::WheelGone, None),
+            }
+        },
+        RequestHeader::ReadBlock { block_id, } =>
+            match pid.read_block(block_id).await {
+                Ok(block_bytes) =>
+                    (ReplyHeader::ReadBlock(Ok(())), Some(block_bytes)),
+                Err(ReadBlockError::NotFound) =>
+                    (ReplyHeader::ReadBlock(Err(ReadBlockFailure::NotFound)), None),
+                Err(ReadBlockError::Corrupted) =>
+                    (ReplyHeader::ReadBlock(Err(ReadBlockFailure::Corrupted)), None),
+                Err(ReadBlockError::GenServer(ero::NoProcError)) =>
+                    (ReplyHeader::WheelGone, None),
+            },
+        RequestHeader::DeleteBlock { block_id, } =>
+            match pid.delete_block(block_id).await {
+                Ok(Deleted) =>
+                    (ReplyHeader::DeleteBlock(Ok(())), None),
+                Err(DeleteBlockError::NotFound) =>
+                    (ReplyHeader::DeleteBlock(Err(DeleteBlockFailure::NotFound)), None),
+                Err(DeleteBlockError::ReadOnly) =>
+                    (ReplyHeader::DeleteBlock(Err(DeleteBlockFailure::ReadOnly)), None),
+                Err(DeleteBlockError::GenServer(ero::NoProcError)) =>
+                    (ReplyHeader::WheelGone, None),
+            },
+        RequestHeader::IterBlocks { changed_since, } => {
+            let result = match changed_since {
+                None =>
+                    pid.iter_blocks().await
+                    .map_err(|IterBlocksError::GenServer(ero::NoProcError)| ReplyHeader::WheelGone),
+                Some(change_seq) =>
+                    pid.changes_since(change_seq).await
+                    .map_err(|error| match error {
+                        ChangesSinceError::GenServer(ero::NoProcError) =>
+                            ReplyHeader::WheelGone,
+                        ChangesSinceError::ChangesTooOld { change_seq_min, } =>
+                            ReplyHeader::ChangesTooOld { change_seq_min, },
+                    }),
+            };
+            let mut iter_blocks = match result {
+                Ok(iter_blocks) =>
+                    iter_blocks,
+                Err(header) => {
+                    send_reply(&mut replies_tx, request_id, header, None).await;
+                    return;
+                },
+            };
+            let header = ReplyHeader::IterBlocks {
+                blocks_total_count: iter_blocks.blocks_total_count,
+                blocks_total_size: iter_blocks.blocks_total_size,
+                change_seq: iter_blocks.change_seq,
+                deleted_block_ids: iter_blocks.deleted_block_ids,
+            };
+            if !send_reply(&mut replies_tx, request_id, header, None).await {
+                return;
+            }
+            loop {
+                let (header, payload) = match iter_blocks.blocks_rx.next().await {
+                    Some(IterBlocksItem::Block { block_id, block_bytes, }) =>
+                        (ReplyHeader::IterBlocksItem { block_id, }, Some(block_bytes)),
+                    Some(IterBlocksItem::NoMoreBlocks) =>
+                        break (ReplyHeader::IterBlocksFinish, None),
+                    None =>
+                        break (ReplyHeader::WheelGone, None),
+                };
+                if !send_reply(&mut replies_tx, request_id, header, payload).await {
+                    return;
+                }
+            }
+        },
+    };
+    send_reply(&mut replies_tx, request_id, header, payload).await;
+}
+
+async fn send_reply(replies_tx: &mut mpsc::Sender<Reply>, request_id: u64, header: ReplyHeader, payload: Option<Bytes>) -> bool {
+    match replies_tx.send(Reply { request_id, header, payload, }).await {
+        Ok(()) =>
+            true,
+        Err(_send_error) => {
+            log::debug!("connection writer is gone, dropping reply for request {}", request_id);
+            false
+        },
+    }
+}
@@ -3,6 +3,7 @@ use alloc_pool::bytes::Bytes;
 use super::{
     block,
     context::Context,
+    SecureDelete,
 };
 
 #[derive(Debug)]
@@ -28,6 +29,7 @@ pub struct RequestFlush<C> {
 #[derive(Debug)]
 pub struct RequestWriteBlock<C> {
     pub block_bytes: Bytes,
+    pub block_id: Option<block::Id>,
     pub context: C,
 }
 
@@ -40,10 +42,12 @@ pub struct RequestReadBlock<C> {
 #[derive(Debug)]
 pub struct RequestDeleteBlock<C> {
     pub block_id: block::Id,
+    pub secure_delete: Option<SecureDelete>,
     pub context: C,
 }
 
 #[derive(Debug)]
 pub struct RequestIterBlocks<C> {
+    pub changed_since: Option<u64>,
     pub context: C,
 }
@@ -0,0 +1,831 @@
+//! Leader/follower replication of wheel contents over TCP.
+//!
+//! Follower connects to leader and reports its last acknowledged position. Leader replies with
+//! batches of changes, every batch is finished with a commit record which follower acknowledges
+//! after the batch is applied to its own wheel:
+//!
+//! ```text
+//! follower: HelloRecord { magic, version, leader_id, change_seq }
+//! leader:   [SnapshotRecord { magic }]
+//!           DeleteRecord { magic, block_id } ...
+//!           archive::BlockRecord { magic, block_id, block_size, crc } block_size bytes of block contents ...
+//!           CommitRecord { magic, leader_id, change_seq }
+//! follower: AckRecord { magic, change_seq }
+//! ...
+//! ```
+//!
+//! Batches are built with `Pid::changes_since` so a follower which falls behind receives everything it
+//! missed in one batch. If follower position belongs to another leader (or it has never been synced)
+//! a snapshot of all leader blocks is sent instead, and follower removes every local block missing from it.
+//!
+//! `leader_id` is the persistent `Info::wheel_id` of the leader wheel, so followers keep their position
+//! across leader restarts and resync with a snapshot only after switching to another leader.
+//!
+//! Follower flushes its wheel before every acknowledgement, so an acknowledged batch survives a follower
+//! crash. With `FollowerParams::position_filename` set the acknowledged position survives it as well and
+//! catch-up after restart continues from there.
+
+use std::{
+    io,
+    time::{
+        Duration,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    net::SocketAddr,
+    collections::HashSet,
+};
+
+use futures::{
+    select,
+    pin_mut,
+    channel::{
+        mpsc,
+        oneshot,
+    },
+    FutureExt,
+    StreamExt,
+};
+
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncReadExt,
+        AsyncWrite,
+        AsyncWriteExt,
+        BufReader,
+        BufWriter,
+    },
+    net::{
+        TcpStream,
+        TcpListener,
+    },
+    sync::watch,
+};
+
+use serde_derive::{
+    Serialize,
+    Deserialize,
+};
+
+use bincode::Options;
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use crate::{
+    block,
+    storage,
+    archive::{
+        BlockRecord,
+        BLOCK_RECORD_MAGIC,
+    },
+    Pid,
+    Deleted,
+    Flushed,
+    Committed,
+    IterBlocks,
+    IterBlocksItem,
+    IterBlocksError,
+    ChangesSinceError,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+};
+
+pub const REPLICATION_VERSION: usize = 1;
+
+pub const HELLO_RECORD_MAGIC: u64 = 0x4c2b9e0d17a63f58;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HelloRecord {
+    pub magic: u64,
+    pub version: usize,
+    pub leader_id: u64,
+    pub change_seq: u64,
+}
+
+impl Default for HelloRecord {
+    fn default() -> HelloRecord {
+        HelloRecord {
+            magic: HELLO_RECORD_MAGIC,
+            version: REPLICATION_VERSION,
+            leader_id: 0,
+            change_seq: 0,
+        }
+    }
+}
+
+pub const SNAPSHOT_RECORD_MAGIC: u64 = 0x93d0f1a6e25b7c04;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SnapshotRecord {
+    pub magic: u64,
+}
+
+impl Default for SnapshotRecord {
+    fn default() -> SnapshotRecord {
+        SnapshotRecord {
+            magic: SNAPSHOT_RECORD_MAGIC,
+        }
+    }
+}
+
+pub const DELETE_RECORD_MAGIC: u64 = 0x2e75c8b04fd1a963;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteRecord {
+    pub magic: u64,
+    pub block_id: block::Id,
+}
+
+impl Default for DeleteRecord {
+    fn default() -> DeleteRecord {
+        DeleteRecord {
+            magic: DELETE_RECORD_MAGIC,
+            block_id: block::Id::default(),
+        }
+    }
+}
+
+pub const COMMIT_RECORD_MAGIC: u64 = 0xd6a1473e9b08c25f;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommitRecord {
+    pub magic: u64,
+    pub leader_id: u64,
+    pub change_seq: u64,
+}
+
+impl Default for CommitRecord {
+    fn default() -> CommitRecord {
+        CommitRecord {
+            magic: COMMIT_RECORD_MAGIC,
+            leader_id: 0,
+            change_seq: 0,
+        }
+    }
+}
+
+pub const ACK_RECORD_MAGIC: u64 = 0x718fe25ac09d346b;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AckRecord {
+    pub magic: u64,
+    pub change_seq: u64,
+}
+
+impl Default for AckRecord {
+    fn default() -> AckRecord {
+        AckRecord {
+            magic: ACK_RECORD_MAGIC,
+            change_seq: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, Debug)]
+pub struct Position {
+    pub leader_id: u64,
+    pub change_seq: u64,
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    LayoutCalculate(bincode::Error),
+    Serialize(bincode::Error),
+    Write(io::Error),
+    Read(io::Error),
+    Deserialize(bincode::Error),
+    InvalidMagic {
+        provided: u64,
+        expected: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum PositionFileError {
+    Read(io::Error),
+    Deserialize(bincode::Error),
+    Serialize(bincode::Error),
+    Write(io::Error),
+}
+
+#[derive(Debug)]
+pub enum LeaderError {
+    WheelGone,
+    Accept(io::Error),
+}
+
+#[derive(Debug)]
+pub enum LeaderSessionError {
+    HelloRecord(RecordError),
+    VersionMismatch {
+        provided: usize,
+        expected: usize,
+    },
+    IterBlocks(IterBlocksError),
+    ChangesSince(ChangesSinceError),
+    IterBlocksRxDropped,
+    SnapshotRecord(RecordError),
+    DeleteRecord(RecordError),
+    BlockRecord(RecordError),
+    BlockWrite(io::Error),
+    CommitRecord(RecordError),
+    Flush(io::Error),
+    AckRecord(RecordError),
+    AckChangeSeqMismatch {
+        provided: u64,
+        expected: u64,
+    },
+}
+
+/// Serves followers connecting to `listener` until the wheel behind `commits_rx` is gone.
+///
+/// `commits_rx` should be taken with `GenServer::commits` of the same wheel `pid` belongs to.
+pub async fn lead(
+    mut pid: Pid,
+    mut commits_rx: mpsc::UnboundedReceiver<Committed>,
+    listener: TcpListener,
+)
+    -> Result<(), LeaderError>
+{
+    let info = pid.info().await
+        .map_err(|ero::NoProcError| LeaderError::WheelGone)?;
+    let leader_id = info.wheel_id;
+    log::info!("replication leader {:016x} started", leader_id);
+
+    let (commits_counter_tx, commits_counter_rx) = watch::channel(0);
+    let mut commits_counter: usize = 0;
+    loop {
+        select! {
+            result = commits_rx.next() =>
+                match result {
+                    Some(committed) => {
+                        log::debug!("replication leader {:016x}: {:?}", leader_id, committed);
+                        commits_counter += 1;
+                        if let Err(_send_error) = commits_counter_tx.send(commits_counter) {
+                            unreachable!("commits_counter_rx is held by leader itself");
+                        }
+                    },
+                    None => {
+                        log::debug!("wheel is gone, replication leader {:016x} is shutting down", leader_id);
+                        return Ok(());
+                    },
+                },
+            result = listener.accept().fuse() => {
+                let (stream, peer_addr) = result
+                    .map_err(LeaderError::Accept)?;
+                log::info!("replication leader {:016x}: follower {} connected", leader_id, peer_addr);
+                let session = leader_session(pid.clone(), leader_id, stream, commits_counter_rx.clone());
+                tokio::spawn(async move {
+                    if let Err(error) = session.await {
+                        log::warn!("follower {} session terminated: {:?}", peer_addr, error);
+                    }
+                });
+            },
+        }
+    }
+}
+
+async fn leader_session(
+    mut pid: Pid,
+    leader_id: u64,
+    stream: TcpStream,
+    mut commits_counter_rx: watch::Receiver<usize>,
+)
+    -> Result<(), LeaderSessionError>
+{
+    let peer_addr = stream.peer_addr().ok();
+    let (reader, writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    let mut work_block = Vec::new();
+
+    let hello: HelloRecord = read_record(&mut reader, &mut work_block, HELLO_RECORD_MAGIC).await
+        .map_err(LeaderSessionError::HelloRecord)?;
+    if hello.version != REPLICATION_VERSION {
+        return Err(LeaderSessionError::VersionMismatch {
+            provided: hello.version,
+            expected: REPLICATION_VERSION,
+        });
+    }
+    let mut position = Position {
+        leader_id: hello.leader_id,
+        change_seq: hello.change_seq,
+    };
+
+    loop {
+        let mut snapshot = position.leader_id != leader_id;
+        let result = if snapshot {
+            pid.iter_blocks().await
+                .map_err(LeaderSessionError::IterBlocks)
+        } else {
+            match pid.changes_since(position.change_seq).await {
+                Ok(iter_blocks) =>
+                    Ok(iter_blocks),
+                Err(ChangesSinceError::ChangesTooOld { change_seq_min, }) => {
+                    log::info!(
+                        "follower {:?} position {:?} is behind known deletions (since {}), sending a snapshot",
+                        peer_addr,
+                        position,
+                        change_seq_min,
+                    );
+                    snapshot = true;
+                    pid.iter_blocks().await
+                        .map_err(LeaderSessionError::IterBlocks)
+                },
+                Err(error @ ChangesSinceError::GenServer(..)) =>
+                    Err(LeaderSessionError::ChangesSince(error)),
+            }
+        };
+        let mut iter_blocks = result?;
+
+        let nothing_changed = !snapshot
+            && iter_blocks.blocks_total_count == 0
+            && iter_blocks.deleted_block_ids.is_empty()
+            && iter_blocks.change_seq == position.change_seq;
+        if !nothing_changed {
+            send_batch(&mut writer, &mut work_block, leader_id, snapshot, &mut iter_blocks).await?;
+            let ack: AckRecord = read_record(&mut reader, &mut work_block, ACK_RECORD_MAGIC).await
+                .map_err(LeaderSessionError::AckRecord)?;
+            if ack.change_seq != iter_blocks.change_seq {
+                return Err(LeaderSessionError::AckChangeSeqMismatch {
+                    provided: ack.change_seq,
+                    expected: iter_blocks.change_seq,
+                });
+            }
+            position = Position { leader_id, change_seq: ack.change_seq, };
+            log::debug!("follower {:?} acknowledged {:?}", peer_addr, position);
+        }
+
+        if let Err(_recv_error) = commits_counter_rx.changed().await {
+            log::debug!("replication leader is gone, terminating follower {:?} session", peer_addr);
+            return Ok(());
+        }
+    }
+}
+
+async fn send_batch<W>(
+    writer: &mut W,
+    work_block: &mut Vec<u8>,
+    leader_id: u64,
+    snapshot: bool,
+    iter_blocks: &mut IterBlocks,
+)
+    -> Result<(), LeaderSessionError>
+where W: AsyncWrite + Unpin,
+{
+    if snapshot {
+        write_record(writer, work_block, &SnapshotRecord::default()).await
+            .map_err(LeaderSessionError::SnapshotRecord)?;
+    }
+
+    for block_id in iter_blocks.deleted_block_ids.drain(..) {
+        write_record(writer, work_block, &DeleteRecord { block_id, ..Default::default() }).await
+            .map_err(LeaderSessionError::DeleteRecord)?;
+    }
+
+    loop {
+        match iter_blocks.blocks_rx.next().await {
+            None =>
+                return Err(LeaderSessionError::IterBlocksRxDropped),
+            Some(IterBlocksItem::Block { block_id, block_bytes, }) => {
+                let block_record = BlockRecord {
+                    block_id,
+                    block_size: block_bytes.len(),
+                    crc: block::crc(&block_bytes),
+                    ..Default::default()
+                };
+                write_record(writer, work_block, &block_record).await
+                    .map_err(LeaderSessionError::BlockRecord)?;
+                writer.write_all(&block_bytes).await
+                    .map_err(LeaderSessionError::BlockWrite)?;
+            },
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                break,
+        }
+    }
+
+    let commit_record = CommitRecord {
+        leader_id,
+        change_seq: iter_blocks.change_seq,
+        ..Default::default()
+    };
+    write_record(writer, work_block, &commit_record).await
+        .map_err(LeaderSessionError::CommitRecord)?;
+    writer.flush().await
+        .map_err(LeaderSessionError::Flush)
+}
+
+#[derive(Clone, Debug)]
+pub struct FollowerParams {
+    pub leader_addr: SocketAddr,
+    pub reconnect_timeout: Duration,
+    // should be `Params::work_block_size_bytes` of the follower wheel
+    pub block_size_max: usize,
+    // acknowledged position is stored here and followed from after restart,
+    // it is kept only in memory when not set
+    pub position_filename: Option<PathBuf>,
+}
+
+impl Default for FollowerParams {
+    fn default() -> FollowerParams {
+        FollowerParams {
+            leader_addr: ([127, 0, 0, 1], 9717).into(),
+            reconnect_timeout: Duration::from_secs(4),
+            block_size_max: crate::Params::default().work_block_size_bytes,
+            position_filename: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Promote;
+
+#[derive(Debug)]
+pub enum FollowerError {
+    WheelGone,
+    PositionLoad(PositionFileError),
+}
+
+#[derive(Debug)]
+pub enum FollowerSessionError {
+    Connect(io::Error),
+    HelloRecord(RecordError),
+    Flush(io::Error),
+    RecordMagic(RecordError),
+    RecordInvalidMagic {
+        provided: u64,
+    },
+    SnapshotRecord(RecordError),
+    DeleteRecord(RecordError),
+    BlockRecord(RecordError),
+    BlockTooLarge {
+        block_id: block::Id,
+        block_size: usize,
+        block_size_max: usize,
+    },
+    BlockRead(io::Error),
+    BlockCrcMismatch {
+        block_id: block::Id,
+        record_crc: u64,
+        block_crc: u64,
+    },
+    CommitRecord(RecordError),
+    WheelFlush(ero::NoProcError),
+    PositionStore(PositionFileError),
+    AckRecord(RecordError),
+    ReadBlock(ReadBlockError),
+    DeleteBlock(DeleteBlockError),
+    WriteBlock(WriteBlockError),
+    IterBlocks(IterBlocksError),
+    IterBlocksRxDropped,
+}
+
+/// Applies changes streamed by leader at `params.leader_addr` to the wheel behind `pid`.
+///
+/// Follower starts from the position stored in `params.position_filename` if there is one, or from the
+/// position currently stored in `position_tx` otherwise, and publishes there every acknowledged position. Connection failures are retried after `params.reconnect_timeout`.
+/// When `promote_rx` fires (or its sender is dropped) following stops and the last acknowledged position
+/// is returned: the wheel could be served with `lead` from now on.
+pub async fn follow(
+    mut pid: Pid,
+    blocks_pool: BytesPool,
+    params: FollowerParams,
+    position_tx: watch::Sender<Position>,
+    promote_rx: oneshot::Receiver<Promote>,
+)
+    -> Result<Position, FollowerError>
+{
+    let maybe_stored_position = match params.position_filename {
+        Some(ref position_filename) =>
+            load_position(position_filename).await
+            .map_err(FollowerError::PositionLoad)?,
+        None =>
+            None,
+    };
+    let mut position = match maybe_stored_position {
+        Some(position) => {
+            log::info!("follower continues from stored position {:?}", position);
+            if let Err(_send_error) = position_tx.send(position) {
+                log::debug!("position receiver is gone, continuing replication");
+            }
+            position
+        },
+        None =>
+            *position_tx.borrow(),
+    };
+    let mut fused_promote_rx = promote_rx.fuse();
+    loop {
+        let maybe_session_result = {
+            let session = follower_session(&mut pid, &blocks_pool, &params, &mut position, &position_tx).fuse();
+            pin_mut!(session);
+            select! {
+                result = session =>
+                    Some(result),
+                _ = fused_promote_rx =>
+                    None,
+            }
+        };
+        match maybe_session_result {
+            None => {
+                log::info!("follower promoted at {:?}", position);
+                return Ok(position);
+            },
+            Some(Ok(())) =>
+                unreachable!("follower session never terminates successfully"),
+            Some(Err(FollowerSessionError::ReadBlock(ReadBlockError::GenServer(ero::NoProcError)))) |
+            Some(Err(FollowerSessionError::DeleteBlock(DeleteBlockError::GenServer(ero::NoProcError)))) |
+            Some(Err(FollowerSessionError::WriteBlock(WriteBlockError::GenServer(ero::NoProcError)))) |
+            Some(Err(FollowerSessionError::WheelFlush(ero::NoProcError))) |
+            Some(Err(FollowerSessionError::IterBlocks(IterBlocksError::GenServer(ero::NoProcError)))) =>
+                return Err(FollowerError::WheelGone),
+            Some(Err(error)) =>
+                log::warn!("replication from {} failed: {:?}, reconnecting", params.leader_addr, error),
+        }
+
+        select! {
+            () = tokio::time::sleep(params.reconnect_timeout).fuse() =>
+                (),
+            _ = fused_promote_rx => {
+                log::info!("follower promoted at {:?}", position);
+                return Ok(position);
+            },
+        }
+    }
+}
+
+async fn follower_session(
+    pid: &mut Pid,
+    blocks_pool: &BytesPool,
+    params: &FollowerParams,
+    position: &mut Position,
+    position_tx: &watch::Sender<Position>,
+)
+    -> Result<(), FollowerSessionError>
+{
+    let stream = TcpStream::connect(params.leader_addr).await
+        .map_err(FollowerSessionError::Connect)?;
+    let (reader, writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    let mut work_block = Vec::new();
+
+    let hello = HelloRecord {
+        leader_id: position.leader_id,
+        change_seq: position.change_seq,
+        ..Default::default()
+    };
+    write_record(&mut writer, &mut work_block, &hello).await
+        .map_err(FollowerSessionError::HelloRecord)?;
+    writer.flush().await
+        .map_err(FollowerSessionError::Flush)?;
+
+    let mut snapshot_block_ids: Option<HashSet<block::Id>> = None;
+    loop {
+        let magic = read_record_magic(&mut reader, &mut work_block).await
+            .map_err(FollowerSessionError::RecordMagic)?;
+        match magic {
+            SNAPSHOT_RECORD_MAGIC => {
+                let _snapshot: SnapshotRecord = read_record_tail(&mut reader, &mut work_block).await
+                    .map_err(FollowerSessionError::SnapshotRecord)?;
+                snapshot_block_ids = Some(HashSet::new());
+            },
+            DELETE_RECORD_MAGIC => {
+                let delete_record: DeleteRecord = read_record_tail(&mut reader, &mut work_block).await
+                    .map_err(FollowerSessionError::DeleteRecord)?;
+                match pid.delete_block(delete_record.block_id).await {
+                    Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                        (),
+                    Err(error) =>
+                        return Err(FollowerSessionError::DeleteBlock(error)),
+                }
+            },
+            BLOCK_RECORD_MAGIC => {
+                let block_record: BlockRecord = read_record_tail(&mut reader, &mut work_block).await
+                    .map_err(FollowerSessionError::BlockRecord)?;
+                if block_record.block_size > params.block_size_max {
+                    return Err(FollowerSessionError::BlockTooLarge {
+                        block_id: block_record.block_id,
+                        block_size: block_record.block_size,
+                        block_size_max: params.block_size_max,
+                    });
+                }
+                let mut block_bytes = blocks_pool.lend();
+                block_bytes.resize(block_record.block_size, 0);
+                reader.read_exact(&mut block_bytes[..]).await
+                    .map_err(FollowerSessionError::BlockRead)?;
+                let block_crc = block::crc(&block_bytes);
+                if block_crc != block_record.crc {
+                    return Err(FollowerSessionError::BlockCrcMismatch {
+                        block_id: block_record.block_id,
+                        record_crc: block_record.crc,
+                        block_crc,
+                    });
+                }
+                replace_block(pid, block_record.block_id.clone(), block_bytes.freeze()).await?;
+                if let Some(block_ids) = snapshot_block_ids.as_mut() {
+                    block_ids.insert(block_record.block_id);
+                }
+            },
+            COMMIT_RECORD_MAGIC => {
+                let commit_record: CommitRecord = read_record_tail(&mut reader, &mut work_block).await
+                    .map_err(FollowerSessionError::CommitRecord)?;
+                if let Some(block_ids) = snapshot_block_ids.take() {
+                    remove_blocks_except(pid, &block_ids).await?;
+                }
+                // batch is made durable before it is acknowledged, position is stored after it
+                let Flushed = pid.flush().await
+                    .map_err(FollowerSessionError::WheelFlush)?;
+                let acked_position = Position {
+                    leader_id: commit_record.leader_id,
+                    change_seq: commit_record.change_seq,
+                };
+                if let Some(ref position_filename) = params.position_filename {
+                    store_position(position_filename, &acked_position).await
+                        .map_err(FollowerSessionError::PositionStore)?;
+                }
+                let ack_record = AckRecord {
+                    change_seq: commit_record.change_seq,
+                    ..Default::default()
+                };
+                write_record(&mut writer, &mut work_block, &ack_record).await
+                    .map_err(FollowerSessionError::AckRecord)?;
+                writer.flush().await
+                    .map_err(FollowerSessionError::Flush)?;
+
+                *position = acked_position;
+                log::debug!("follower acknowledged {:?}", position);
+                if let Err(_send_error) = position_tx.send(*position) {
+                    log::debug!("position receiver is gone, continuing replication");
+                }
+            },
+            provided =>
+                return Err(FollowerSessionError::RecordInvalidMagic { provided, }),
+        }
+    }
+}
+
+async fn replace_block(pid: &mut Pid, block_id: block::Id, block_bytes: Bytes) -> Result<(), FollowerSessionError> {
+    match pid.write_block_with_id(block_id.clone(), block_bytes.clone()).await {
+        Ok(_block_id) =>
+            return Ok(()),
+        Err(WriteBlockError::BlockIdTaken) =>
+            (),
+        Err(error) =>
+            return Err(FollowerSessionError::WriteBlock(error)),
+    }
+    // snapshot resends every block, most of them are usually unchanged
+    match pid.read_block(block_id.clone()).await {
+        Ok(current_block_bytes) if current_block_bytes == block_bytes =>
+            return Ok(()),
+        Ok(..) | Err(ReadBlockError::NotFound) | Err(ReadBlockError::Corrupted) =>
+            (),
+        Err(error) =>
+            return Err(FollowerSessionError::ReadBlock(error)),
+    }
+    match pid.delete_block(block_id.clone()).await {
+        Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+            (),
+        Err(error) =>
+            return Err(FollowerSessionError::DeleteBlock(error)),
+    }
+    pid.write_block_with_id(block_id, block_bytes).await
+        .map_err(FollowerSessionError::WriteBlock)?;
+    Ok(())
+}
+
+async fn remove_blocks_except(pid: &mut Pid, keep_block_ids: &HashSet<block::Id>) -> Result<(), FollowerSessionError> {
+    let mut iter_blocks = pid.iter_blocks().await
+        .map_err(FollowerSessionError::IterBlocks)?;
+    let mut stale_block_ids = Vec::new();
+    loop {
+        match iter_blocks.blocks_rx.next().await {
+            None =>
+                return Err(FollowerSessionError::IterBlocksRxDropped),
+            Some(IterBlocksItem::Block { block_id, .. }) =>
+                if !keep_block_ids.contains(&block_id) {
+                    stale_block_ids.push(block_id);
+                },
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                break,
+        }
+    }
+    for block_id in stale_block_ids {
+        match pid.delete_block(block_id).await {
+            Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                (),
+            Err(error) =>
+                return Err(FollowerSessionError::DeleteBlock(error)),
+        }
+    }
+    Ok(())
+}
+
+async fn load_position(position_filename: &Path) -> Result<Option<Position>, PositionFileError> {
+    let position_bytes = match tokio::fs::read(position_filename).await {
+        Ok(position_bytes) =>
+            position_bytes,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound =>
+            return Ok(None),
+        Err(error) =>
+            return Err(PositionFileError::Read(error)),
+    };
+    storage::bincode_options()
+        .deserialize(&position_bytes)
+        .map(Some)
+        .map_err(PositionFileError::Deserialize)
+}
+
+// written aside and renamed over, so a crash keeps either the previous position or the new one
+async fn store_position(position_filename: &Path, position: &Position) -> Result<(), PositionFileError> {
+    let position_bytes = storage::bincode_options()
+        .serialize(position)
+        .map_err(PositionFileError::Serialize)?;
+    let mut tmp_filename = position_filename.as_os_str().to_owned();
+    tmp_filename.push(".tmp");
+    let mut tmp_file = tokio::fs::File::create(&tmp_filename).await
+        .map_err(PositionFileError::Write)?;
+    tmp_file.write_all(&position_bytes).await
+        .map_err(PositionFileError::Write)?;
+    tmp_file.sync_all().await
+        .map_err(PositionFileError::Write)?;
+    tokio::fs::rename(&tmp_filename, position_filename).await
+        .map_err(PositionFileError::Write)?;
+    sync_parent_dir(position_filename).await
+        .map_err(PositionFileError::Write)
+}
+
+// rename is durable only after its directory entry is synced
+#[cfg(unix)]
+async fn sync_parent_dir(filename: &Path) -> Result<(), io::Error> {
+    let parent_dir = match filename.parent() {
+        Some(parent_dir) if !parent_dir.as_os_str().is_empty() =>
+            parent_dir,
+        Some(..) | None =>
+            Path::new("."),
+    };
+    tokio::fs::File::open(parent_dir).await?.sync_all().await
+}
+
+#[cfg(not(unix))]
+async fn sync_parent_dir(_filename: &Path) -> Result<(), io::Error> {
+    Ok(())
+}
+
+async fn write_record<W, T>(writer: &mut W, work_block: &mut Vec<u8>, record: &T) -> Result<(), RecordError>
+where W: AsyncWrite + Unpin,
+      T: serde::Serialize,
+{
+    work_block.clear();
+    storage::bincode_options()
+        .serialize_into(&mut *work_block, record)
+        .map_err(RecordError::Serialize)?;
+    writer.write_all(work_block).await
+        .map_err(RecordError::Write)
+}
+
+async fn read_record<R, T>(reader: &mut R, work_block: &mut Vec<u8>, expected: u64) -> Result<T, RecordError>
+where R: AsyncRead + Unpin,
+      T: Default + serde::Serialize + serde::de::DeserializeOwned,
+{
+    let magic = read_record_magic(reader, work_block).await?;
+    if magic != expected {
+        return Err(RecordError::InvalidMagic { provided: magic, expected, });
+    }
+    read_record_tail(reader, work_block).await
+}
+
+async fn read_record_magic<R>(reader: &mut R, work_block: &mut Vec<u8>) -> Result<u64, RecordError> where R: AsyncRead + Unpin {
+    let record_magic_size = storage::bincode_options()
+        .serialized_size(&0u64)
+        .map_err(RecordError::LayoutCalculate)? as usize;
+    work_block.resize(record_magic_size, 0);
+    reader.read_exact(work_block).await
+        .map_err(RecordError::Read)?;
+    storage::bincode_options()
+        .deserialize_from(&work_block[..])
+        .map_err(RecordError::Deserialize)
+}
+
+// expects record magic to be already read into `work_block`
+async fn read_record_tail<R, T>(reader: &mut R, work_block: &mut Vec<u8>) -> Result<T, RecordError>
+where R: AsyncRead + Unpin,
+      T: Default + serde::Serialize + serde::de::DeserializeOwned,
+{
+    let record_magic_size = work_block.len();
+    let record_size = storage::bincode_options()
+        .serialized_size(&T::default())
+        .map_err(RecordError::LayoutCalculate)? as usize;
+    work_block.resize(record_size, 0);
+    reader.read_exact(&mut work_block[record_magic_size ..]).await
+        .map_err(RecordError::Read)?;
+    storage::bincode_options()
+        .deserialize_from(&work_block[..])
+        .map_err(RecordError::Deserialize)
+}
@@ -0,0 +1,582 @@
+//! Sharded wheel: several independent wheels behind a single `Pid`.
+//!
+//! Every block is stored whole on one shard chosen with `Routing`, a shard which reports `NoSpaceLeft` is skipped
+//! in favor of the next one. Shard number is encoded into the block id returned (see `block::Id::with_shard`),
+//! so no location of blocks is kept in memory and reads and deletes go straight to the shard.
+//!
+//! Requests to different shards are executed in parallel, `info`, `iter_blocks` and `changes_since` fan out
+//! to every shard and combine the results.
+
+use std::{
+    cmp,
+};
+
+use futures::{
+    future::BoxFuture,
+    stream::FuturesUnordered,
+    channel::{
+        mpsc,
+        oneshot,
+    },
+    FutureExt,
+    SinkExt,
+    StreamExt,
+};
+
+use edeltraud::{
+    Edeltraud,
+};
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use ero::{
+    supervisor::SupervisorPid,
+};
+
+use crate::{
+    job,
+    block,
+    fanout::{
+        self,
+        Source,
+        Member,
+    },
+    Deleted,
+    Flushed,
+    SecureDelete,
+    IterBlocks,
+    InterpretStats,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+    IterBlocksError,
+};
+
+pub use crate::fanout::WriteBlockWithIdError;
+
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub shards: Vec<crate::Params>,
+    pub routing: Routing,
+}
+
+// how a shard is chosen for a new block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Routing {
+    // shard with most free space according to the last known `Info`
+    FreeSpace,
+    // shard by crc of block contents, so the same contents always go to the same shard while it has space
+    Hash,
+}
+
+impl Default for Routing {
+    fn default() -> Routing {
+        Routing::FreeSpace
+    }
+}
+
+
+/// Blocks changed since given change sequences of all shards.
+pub struct Changes {
+    // change sequence of every shard to pass to the next `changes_since`, in the same order as `Params::shards`
+    pub change_seqs: Vec<u64>,
+    // `change_seq` is always zero here, `change_seqs` should be used instead
+    pub iter_blocks: IterBlocks,
+}
+
+#[derive(Debug)]
+pub enum ChangesSinceError {
+    GenServer(ero::NoProcError),
+    ShardsCountMismatch {
+        shards: usize,
+        change_seqs: usize,
+    },
+    ChangesTooOld {
+        shard: usize,
+        change_seq_min: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoShards,
+    TooManyShards {
+        shards: usize,
+    },
+    ShardInfo {
+        shard: usize,
+        error: ero::NoProcError,
+    },
+}
+
+pub struct GenServer {
+    request_tx: mpsc::Sender<Request>,
+    request_rx: mpsc::Receiver<Request>,
+}
+
+#[derive(Clone)]
+pub struct Pid {
+    request_tx: mpsc::Sender<Request>,
+}
+
+enum Request {
+    Info {
+        reply_tx: oneshot::Sender<Vec<crate::Info>>,
+    },
+    Flush {
+        reply_tx: oneshot::Sender<Flushed>,
+    },
+    WriteBlock {
+        block_bytes: Bytes,
+        reply_tx: oneshot::Sender<Result<block::Id, WriteBlockError>>,
+    },
+    WriteBlockWithId {
+        block_id: block::Id,
+        block_bytes: Bytes,
+        reply_tx: oneshot::Sender<Result<block::Id, WriteBlockWithIdError>>,
+    },
+    ReadBlock {
+        block_id: block::Id,
+        reply_tx: oneshot::Sender<Result<Bytes, ReadBlockError>>,
+    },
+    DeleteBlock {
+        block_id: block::Id,
+        secure_delete: Option<SecureDelete>,
+        reply_tx: oneshot::Sender<Result<Deleted, DeleteBlockError>>,
+    },
+    IterBlocks {
+        reply_tx: oneshot::Sender<IterBlocks>,
+    },
+    ChangesSince {
+        change_seqs: Vec<u64>,
+        reply_tx: oneshot::Sender<Result<Changes, ChangesSinceError>>,
+    },
+}
+
+impl GenServer {
+    pub fn new() -> GenServer {
+        let (request_tx, request_rx) = mpsc::channel(0);
+        GenServer { request_tx, request_rx, }
+    }
+
+    pub fn pid(&self) -> Pid {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub async fn run<J>(
+        self,
+        parent_supervisor: SupervisorPid,
+        thread_pool: Edeltraud<J>,
+        blocks_pool: BytesPool,
+        params: Params,
+    )
+    where J: edeltraud::Job + From<job::Job>,
+          J::Output: From<job::JobOutput>,
+          job::JobOutput: From<J::Output>,
+    {
+        let GenServer { request_rx, .. } = self;
+        let routing = params.routing;
+        fanout::run(
+            parent_supervisor,
+            thread_pool,
+            blocks_pool,
+            params.shards,
+            |shards_pids| busyloop(request_rx, shards_pids, routing),
+        ).await
+    }
+}
+
+impl Pid {
+    /// Totals over all shards, `wheel_id` is derived from ids of the shards.
+    pub async fn info(&mut self) -> Result<crate::Info, ero::NoProcError> {
+        let infos = self.shards_info().await?;
+        Ok(aggregate_info(&infos))
+    }
+
+    /// Info of every shard in the same order as `Params::shards`.
+    pub async fn shards_info(&mut self) -> Result<Vec<crate::Info>, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Info { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Flush { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+
+    pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::WriteBlock { block_bytes, reply_tx, }).await
+            .map_err(|_send_error| WriteBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| WriteBlockError::GenServer(ero::NoProcError))?
+    }
+
+    /// Block is stored on the shard encoded in `block_id`.
+    pub async fn write_block_with_id(&mut self, block_id: block::Id, block_bytes: Bytes) -> Result<block::Id, WriteBlockWithIdError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::WriteBlockWithId { block_id, block_bytes, reply_tx, }).await
+            .map_err(|_send_error| WriteBlockWithIdError::WriteBlock(WriteBlockError::GenServer(ero::NoProcError)))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| WriteBlockWithIdError::WriteBlock(WriteBlockError::GenServer(ero::NoProcError)))?
+    }
+
+    pub async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::ReadBlock { block_id, reply_tx, }).await
+            .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ReadBlockError::GenServer(ero::NoProcError))?
+    }
+
+    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        self.delete_block_with(block_id, None).await
+    }
+
+    pub async fn delete_block_secure(&mut self, block_id: block::Id, secure_delete: SecureDelete) -> Result<Deleted, DeleteBlockError> {
+        self.delete_block_with(block_id, Some(secure_delete)).await
+    }
+
+    async fn delete_block_with(&mut self, block_id: block::Id, secure_delete: Option<SecureDelete>) -> Result<Deleted, DeleteBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::DeleteBlock { block_id, secure_delete, reply_tx, }).await
+            .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| DeleteBlockError::GenServer(ero::NoProcError))?
+    }
+
+    /// Streams blocks of all shards one shard after another.
+    ///
+    /// Shards have independent change sequences, so returned `IterBlocks::change_seq` is always zero:
+    /// `changes_since` with empty `change_seqs` should be used to start tracking changes.
+    pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::IterBlocks { reply_tx, }).await
+            .map_err(|_send_error| IterBlocksError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| IterBlocksError::GenServer(ero::NoProcError))
+    }
+
+    /// Same as `crate::Pid::changes_since` with a change sequence for every shard taken from previous `Changes`.
+    ///
+    /// Empty `change_seqs` streams all blocks and returns the change sequences to start from.
+    pub async fn changes_since(&mut self, change_seqs: Vec<u64>) -> Result<Changes, ChangesSinceError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::ChangesSince { change_seqs, reply_tx, }).await
+            .map_err(|_send_error| ChangesSinceError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ChangesSinceError::GenServer(ero::NoProcError))?
+    }
+}
+
+fn aggregate_info(infos: &[crate::Info]) -> crate::Info {
+    let mut wheel_ids_bytes = Vec::with_capacity(infos.len() * 8);
+    for info in infos {
+        wheel_ids_bytes.extend(info.wheel_id.to_le_bytes().iter().cloned());
+    }
+    let mut aggregate = crate::Info {
+        wheel_id: block::crc(&wheel_ids_bytes),
+        ..Default::default()
+    };
+    for info in infos {
+        aggregate.blocks_count += info.blocks_count;
+        aggregate.wheel_size_bytes += info.wheel_size_bytes;
+        aggregate.service_bytes_used += info.service_bytes_used;
+        aggregate.data_bytes_used += info.data_bytes_used;
+        aggregate.defrag_write_pending_bytes += info.defrag_write_pending_bytes;
+        aggregate.bytes_free += info.bytes_free;
+        aggregate.interpret_stats = InterpretStats {
+            count_total: aggregate.interpret_stats.count_total + info.interpret_stats.count_total,
+            count_no_seek: aggregate.interpret_stats.count_no_seek + info.interpret_stats.count_no_seek,
+            count_seek_forward: aggregate.interpret_stats.count_seek_forward + info.interpret_stats.count_seek_forward,
+            count_seek_backward: aggregate.interpret_stats.count_seek_backward + info.interpret_stats.count_seek_backward,
+            count_writer: aggregate.interpret_stats.count_writer + info.interpret_stats.count_writer,
+            count_readers: aggregate.interpret_stats.count_readers + info.interpret_stats.count_readers,
+            readers_busy_max: cmp::max(aggregate.interpret_stats.readers_busy_max, info.interpret_stats.readers_busy_max),
+            // reader threads of every shard are listed one after another
+            count_per_reader: aggregate.interpret_stats.count_per_reader.iter()
+                .chain(info.interpret_stats.count_per_reader.iter())
+                .cloned()
+                .collect(),
+        };
+    }
+    aggregate
+}
+
+enum Done {
+    Info {
+        infos: Vec<crate::Info>,
+    },
+    ShardInfo {
+        shard: usize,
+        info: crate::Info,
+    },
+    ShardsFull {
+        shards_full: Vec<usize>,
+    },
+    Nothing,
+}
+
+async fn busyloop(
+    request_rx: mpsc::Receiver<Request>,
+    shards_pids: Vec<crate::Pid>,
+    routing: Routing,
+)
+    -> Result<(), Error>
+{
+    if shards_pids.is_empty() {
+        return Err(Error::NoShards);
+    }
+    if block::Id::init().with_shard(shards_pids.len() - 1).is_none() {
+        return Err(Error::TooManyShards { shards: shards_pids.len(), });
+    }
+
+    let mut shards = Vec::with_capacity(shards_pids.len());
+    for (shard, mut pid) in shards_pids.into_iter().enumerate() {
+        let info = pid.info().await
+            .map_err(|error| Error::ShardInfo { shard, error, })?;
+        shards.push(Member { pid, bytes_free: info.bytes_free, });
+    }
+    log::debug!("sharded wheel of {} shards started", shards.len());
+
+    let mut fused_request_rx = request_rx.fuse();
+    let mut pending: FuturesUnordered<BoxFuture<'static, Done>> = FuturesUnordered::new();
+
+    loop {
+        match fanout::next_source(&mut fused_request_rx, &mut pending).await {
+
+            Source::Pid(None) => {
+                log::debug!("all Pid frontends have been terminated");
+                return Ok(());
+            },
+
+            Source::Pid(Some(Request::Info { reply_tx, })) => {
+                let pids: Vec<_> = shards.iter().map(|shard| shard.pid.clone()).collect();
+                pending.push(async move {
+                    let infos = match fanout::infos(pids).await {
+                        Ok(infos) =>
+                            infos,
+                        Err(ero::NoProcError) =>
+                            return Done::Nothing,
+                    };
+                    if let Err(_send_error) = reply_tx.send(infos.clone()) {
+                        log::warn!("reply channel has been closed during Info result send");
+                    }
+                    Done::Info { infos, }
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::Flush { reply_tx, })) => {
+                let pids: Vec<_> = shards.iter().map(|shard| shard.pid.clone()).collect();
+                pending.push(async move {
+                    if let Ok(Flushed) = fanout::flush(pids).await {
+                        if let Err(_send_error) = reply_tx.send(Flushed) {
+                            log::warn!("reply channel has been closed during Flush result send");
+                        }
+                    }
+                    Done::Nothing
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::WriteBlockWithId { block_id, block_bytes, reply_tx, })) =>
+                match block_id.shard() {
+                    (shard, shard_block_id) if shard < shards.len() => {
+                        shards[shard].bytes_free = shards[shard].bytes_free.saturating_sub(block_bytes.len());
+                        let mut pid = shards[shard].pid.clone();
+                        pending.push(async move {
+                            let result = pid.write_block_with_id(shard_block_id, block_bytes).await
+                                .map(|_shard_block_id| block_id);
+                            let done = match result {
+                                Err(WriteBlockError::NoSpaceLeft) =>
+                                    Done::ShardsFull { shards_full: vec![shard], },
+                                _ =>
+                                    Done::Nothing,
+                            };
+                            if let Err(_send_error) = reply_tx.send(result.map_err(WriteBlockWithIdError::WriteBlock)) {
+                                log::warn!("reply channel has been closed during WriteBlock result send");
+                            }
+                            done
+                        }.boxed());
+                    },
+                    _ =>
+                        if let Err(_send_error) = reply_tx.send(Err(WriteBlockWithIdError::InvalidBlockId)) {
+                            log::warn!("reply channel has been closed during WriteBlock result send");
+                        },
+                },
+
+            Source::Pid(Some(Request::WriteBlock { block_bytes, reply_tx, })) => {
+                let mut order: Vec<usize> = (0 .. shards.len()).collect();
+                match routing {
+                    Routing::FreeSpace =>
+                        order.sort_by_key(|&shard| cmp::Reverse(shards[shard].bytes_free)),
+                    Routing::Hash =>
+                        order.rotate_left((block::crc(&block_bytes) % shards.len() as u64) as usize),
+                }
+                let shard = order[0];
+                shards[shard].bytes_free = shards[shard].bytes_free.saturating_sub(block_bytes.len());
+
+                let candidates: Vec<_> = order.into_iter()
+                    .map(|shard| (shard, shards[shard].pid.clone()))
+                    .collect();
+                pending.push(async move {
+                    let (result, shards_full) = fanout::write_block(block_bytes, candidates).await;
+                    if let Err(_send_error) = reply_tx.send(result) {
+                        log::warn!("reply channel has been closed during WriteBlock result send");
+                    }
+                    Done::ShardsFull { shards_full, }
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::ReadBlock { block_id, reply_tx, })) =>
+                match block_id.shard() {
+                    (shard, shard_block_id) if shard < shards.len() => {
+                        let mut pid = shards[shard].pid.clone();
+                        pending.push(async move {
+                            let result = pid.read_block(shard_block_id).await;
+                            if let Err(_send_error) = reply_tx.send(result) {
+                                log::warn!("reply channel has been closed during ReadBlock result send");
+                            }
+                            Done::Nothing
+                        }.boxed());
+                    },
+                    _ =>
+                        if let Err(_send_error) = reply_tx.send(Err(ReadBlockError::NotFound)) {
+                            log::warn!("reply channel has been closed during ReadBlock result send");
+                        },
+                },
+
+            Source::Pid(Some(Request::DeleteBlock { block_id, secure_delete, reply_tx, })) =>
+                match block_id.shard() {
+                    (shard, shard_block_id) if shard < shards.len() => {
+                        let mut pid = shards[shard].pid.clone();
+                        pending.push(async move {
+                            let result = match secure_delete {
+                                None =>
+                                    pid.delete_block(shard_block_id).await,
+                                Some(secure_delete) =>
+                                    pid.delete_block_secure(shard_block_id, secure_delete).await,
+                            };
+                            let deleted = matches!(result, Ok(Deleted));
+                            if let Err(_send_error) = reply_tx.send(result) {
+                                log::warn!("reply channel has been closed during DeleteBlock result send");
+                            }
+                            // space of the block is credited back with the actual shard free space
+                            if deleted {
+                                if let Ok(info) = pid.info().await {
+                                    return Done::ShardInfo { shard, info, };
+                                }
+                            }
+                            Done::Nothing
+                        }.boxed());
+                    },
+                    _ =>
+                        if let Err(_send_error) = reply_tx.send(Err(DeleteBlockError::NotFound)) {
+                            log::warn!("reply channel has been closed during DeleteBlock result send");
+                        },
+                },
+
+            Source::Pid(Some(Request::IterBlocks { reply_tx, })) => {
+                let pids: Vec<_> = shards.iter().map(|shard| shard.pid.clone()).collect();
+                pending.push(async move {
+                    let mut shards_iter_blocks = Vec::with_capacity(pids.len());
+                    for mut pid in pids {
+                        match pid.iter_blocks().await {
+                            Ok(iter_blocks) =>
+                                shards_iter_blocks.push(iter_blocks),
+                            Err(IterBlocksError::GenServer(ero::NoProcError)) =>
+                                return Done::Nothing,
+                        }
+                    }
+                    let (iter_blocks, forward) = fanout::concat_iter_blocks(shards_iter_blocks);
+                    if let Err(_send_error) = reply_tx.send(iter_blocks) {
+                        log::warn!("reply channel has been closed during IterBlocks result send");
+                        return Done::Nothing;
+                    }
+                    forward.await;
+                    Done::Nothing
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::ChangesSince { change_seqs, reply_tx, })) =>
+                if !change_seqs.is_empty() && change_seqs.len() != shards.len() {
+                    let error = ChangesSinceError::ShardsCountMismatch { shards: shards.len(), change_seqs: change_seqs.len(), };
+                    if let Err(_send_error) = reply_tx.send(Err(error)) {
+                        log::warn!("reply channel has been closed during ChangesSince result send");
+                    }
+                } else {
+                    let pids: Vec<_> = shards.iter().map(|shard| shard.pid.clone()).collect();
+                    pending.push(changes_since(pids, change_seqs, reply_tx).boxed());
+                },
+
+            Source::Pending(Done::Info { infos, }) =>
+                for (shard, info) in shards.iter_mut().zip(infos) {
+                    shard.bytes_free = info.bytes_free;
+                },
+
+            Source::Pending(Done::ShardInfo { shard, info, }) =>
+                shards[shard].bytes_free = info.bytes_free,
+
+            Source::Pending(Done::ShardsFull { shards_full, }) =>
+                for full in shards_full {
+                    shards[full].bytes_free = 0;
+                },
+
+            Source::Pending(Done::Nothing) =>
+                (),
+
+        }
+    }
+}
+
+async fn changes_since(
+    pids: Vec<crate::Pid>,
+    change_seqs: Vec<u64>,
+    reply_tx: oneshot::Sender<Result<Changes, ChangesSinceError>>,
+)
+    -> Done
+{
+    let mut shards_iter_blocks = Vec::with_capacity(pids.len());
+    for (shard, mut pid) in pids.into_iter().enumerate() {
+        let result = match change_seqs.get(shard) {
+            None =>
+                pid.iter_blocks().await
+                .map_err(|IterBlocksError::GenServer(error)| crate::ChangesSinceError::GenServer(error)),
+            Some(&change_seq) =>
+                pid.changes_since(change_seq).await,
+        };
+        match result {
+            Ok(iter_blocks) =>
+                shards_iter_blocks.push(iter_blocks),
+            Err(crate::ChangesSinceError::GenServer(ero::NoProcError)) =>
+                return Done::Nothing,
+            Err(crate::ChangesSinceError::ChangesTooOld { change_seq_min, }) => {
+                if let Err(_send_error) = reply_tx.send(Err(ChangesSinceError::ChangesTooOld { shard, change_seq_min, })) {
+                    log::warn!("reply channel has been closed during ChangesSince result send");
+                }
+                return Done::Nothing;
+            },
+        }
+    }
+
+    let change_seqs = shards_iter_blocks.iter()
+        .map(|iter_blocks| iter_blocks.change_seq)
+        .collect();
+    let (iter_blocks, forward) = fanout::concat_iter_blocks(shards_iter_blocks);
+    if let Err(_send_error) = reply_tx.send(Ok(Changes { change_seqs, iter_blocks, })) {
+        log::warn!("reply channel has been closed during ChangesSince result send");
+        return Done::Nothing;
+    }
+    forward.await;
+    Done::Nothing
+}
@@ -0,0 +1,854 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+};
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesMut,
+    BytesPool,
+};
+
+use rand::{
+    Rng,
+    SeedableRng,
+    rngs::StdRng,
+};
+
+use crate::{
+    block,
+    proto,
+    context,
+    storage,
+    wheel::{
+        lru,
+        core::{
+            task,
+            performer,
+        },
+        interpret::{
+            self,
+            ram,
+        },
+    },
+};
+
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub seed: u64,
+    pub actions: usize,
+    pub active_tasks: usize,
+    pub block_size_bytes: usize,
+    pub init_wheel_size_bytes: usize,
+    pub work_block_size_bytes: usize,
+    pub lru_cache_size_bytes: usize,
+    pub defrag_parallel_tasks_limit: usize,
+}
+
+impl Default for Params {
+    fn default() -> Params {
+        Params {
+            seed: 0,
+            actions: 65536,
+            active_tasks: 64,
+            block_size_bytes: 4096,
+            init_wheel_size_bytes: 1024 * 1024,
+            work_block_size_bytes: 16 * 1024,
+            lru_cache_size_bytes: 16 * 1024,
+            defrag_parallel_tasks_limit: 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Stats {
+    pub steps: usize,
+    pub writes: usize,
+    pub reads: usize,
+    pub deletes: usize,
+    pub flushes: usize,
+    pub infos: usize,
+    pub iterations: usize,
+    pub no_space_left: usize,
+    pub not_found: usize,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    PerformerInit(performer::BuilderError),
+    RamCreate(ram::WheelCreateError),
+    RamLoad(ram::WheelOpenError),
+    RamInit(ram::Error),
+    BlockPrepareWrite(interpret::BlockPrepareWriteJobError),
+    BlockProcessRead(interpret::BlockProcessReadJobError),
+    BlockPrepareDelete(interpret::BlockPrepareDeleteJobError),
+    Diverged {
+        seed: u64,
+        step: usize,
+        divergence: Divergence,
+    },
+}
+
+// a difference between the wheel and the reference model
+#[derive(Debug)]
+pub enum Divergence {
+    WriteBlockIdReused { block_id: block::Id, },
+    WriteBlockIdTaken,
+    ReadBlockContents { block_id: block::Id, },
+    ReadBlockLost { block_id: block::Id, },
+    ReadBlockResurrected { block_id: block::Id, },
+    DeleteBlockLost { block_id: block::Id, },
+    DeleteBlockResurrected { block_id: block::Id, },
+    InfoBlocksCount { expected: usize, actual: usize, },
+    IterBlocksUnknown { block_id: block::Id, },
+    IterBlocksContents { block_id: block::Id, },
+    IterBlocksDuplicate { block_id: block::Id, },
+    IterBlocksCount { expected: usize, actual: usize, },
+    UnexpectedReply { action_id: ActionId, },
+    // nothing is ready to run while actions are still in flight
+    Stalled { in_flight: usize, },
+}
+
+pub type ActionId = usize;
+
+struct Context;
+
+impl context::Context for Context {
+    type Info = ActionId;
+    type Flush = ActionId;
+    type WriteBlock = ActionId;
+    type ReadBlock = ActionId;
+    type DeleteBlock = ActionId;
+    type IterBlocks = ActionId;
+    type IterBlocksStream = ActionId;
+    // single interpreter task is kept by the scheduler itself
+    type Interpreter = ();
+}
+
+enum Action {
+    Info { expected_blocks_count: usize, },
+    Flush,
+    WriteBlock { block_bytes: Bytes, },
+    ReadBlock { block_id: block::Id, expect_found: bool, },
+    DeleteBlock { block_id: block::Id, expect_found: bool, },
+    IterBlocks { exact: Option<HashMap<block::Id, Bytes>>, seen: HashSet<block::Id>, },
+    // initial iteration over a loaded image filling the reference model
+    Adopt,
+}
+
+enum Job {
+    BlockPrepareWrite {
+        block_id: block::Id,
+        block_bytes: Bytes,
+        change_seq: u64,
+        total_chunk_size: usize,
+        context: task::WriteBlockContext<ActionId>,
+    },
+    BlockProcessRead {
+        storage_layout: storage::Layout,
+        block_header: storage::BlockHeader,
+        block_bytes: Bytes,
+        pending_contexts: task::queue::PendingReadContextBag,
+    },
+    BlockPrepareDelete {
+        block_id: block::Id,
+        change_seq: u64,
+        total_chunk_size: usize,
+        context: task::DeleteBlockContext<ActionId>,
+    },
+}
+
+// all sources of events the real wheel is waiting for with `select!`, resolved one at a time in a seeded random order
+pub fn run(params: Params) -> Result<Stats, Error> {
+    let performer_builder = make_performer_builder(&params)?;
+    let wheel_data = ram::SyncGenServer::create(
+        ram::CreateParams {
+            init_wheel_size_bytes: params.init_wheel_size_bytes,
+            snapshot_filename: None,
+        },
+        performer_builder,
+    )
+        .map_err(Error::RamCreate)?;
+    run_wheel(params, wheel_data, false)
+}
+
+// same as `run` but over an existing wheel image: its blocks are adopted into the model first
+pub fn run_image(params: Params, image: Vec<u8>) -> Result<Stats, Error> {
+    let performer_builder = make_performer_builder(&params)?;
+    let wheel_data = ram::SyncGenServer::load(image, None, performer_builder)
+        .map_err(Error::RamLoad)?;
+    run_wheel(params, wheel_data, true)
+}
+
+fn make_performer_builder(params: &Params) -> Result<performer::PerformerBuilderInit<Context>, Error> {
+    performer::PerformerBuilderInit::new(
+        lru::Cache::new(params.lru_cache_size_bytes),
+        if params.defrag_parallel_tasks_limit == 0 {
+            None
+        } else {
+            Some(performer::DefragConfig::new(params.defrag_parallel_tasks_limit))
+        },
+        params.work_block_size_bytes,
+    )
+        .map_err(Error::PerformerInit)
+}
+
+fn run_wheel(params: Params, wheel_data: ram::WheelData<Context>, adopt: bool) -> Result<Stats, Error> {
+    let blocks_pool = BytesPool::new();
+    let ram::WheelData { sync_gen_server, performer, } = wheel_data;
+    let memory = sync_gen_server.into_memory(blocks_pool.clone())
+        .map_err(Error::RamInit)?;
+
+    let mut sim = Sim {
+        rng: StdRng::seed_from_u64(params.seed),
+        params,
+        blocks_pool,
+        memory,
+        stats: Stats::default(),
+        actions_left: 0,
+        next_action_id: 0,
+        final_check_done: false,
+        in_flight: HashMap::new(),
+        mutations_in_flight: 0,
+        write_bytes_in_flight: 0,
+        interpreter_task: None,
+        jobs: Vec::new(),
+        iter_items: Vec::new(),
+        model: HashMap::new(),
+        model_bytes: 0,
+        live_ids: Vec::new(),
+        deleted_ids: Vec::new(),
+        pending_deletes: HashSet::new(),
+        written: HashMap::new(),
+        adopt_pending: adopt,
+        adopting: false,
+    };
+    sim.actions_left = sim.params.actions;
+    sim.busyloop(performer)?;
+    Ok(sim.stats)
+}
+
+struct Sim {
+    rng: StdRng,
+    params: Params,
+    blocks_pool: BytesPool,
+    memory: ram::Memory,
+    stats: Stats,
+    actions_left: usize,
+    next_action_id: ActionId,
+    final_check_done: bool,
+    in_flight: HashMap<ActionId, Action>,
+    mutations_in_flight: usize,
+    write_bytes_in_flight: usize,
+    interpreter_task: Option<(u64, task::Task<Context>)>,
+    jobs: Vec<Job>,
+    iter_items: Vec<performer::IterBlocksState<ActionId>>,
+    // reference model: blocks acknowledged as written and not yet acknowledged as deleted
+    model: HashMap<block::Id, Bytes>,
+    model_bytes: usize,
+    live_ids: Vec<block::Id>,
+    deleted_ids: Vec<block::Id>,
+    pending_deletes: HashSet<block::Id>,
+    written: HashMap<block::Id, Bytes>,
+    adopt_pending: bool,
+    adopting: bool,
+}
+
+enum Poll {
+    RequestAndInterpreter(performer::PollRequestAndInterpreterNext<Context>),
+    Request(performer::PollRequestNext<Context>),
+}
+
+enum Source {
+    Request(proto::Request<Context>),
+    InterpreterDone,
+    Job(Job),
+    IterItem(performer::IterBlocksState<ActionId>),
+}
+
+impl Sim {
+    fn busyloop(&mut self, performer: performer::Performer<Context>) -> Result<(), Error> {
+        let mut op = performer.next();
+        loop {
+            self.stats.steps += 1;
+            op = match op {
+
+                performer::Op::Idle(performer) =>
+                    performer.next(),
+
+                performer::Op::Query(performer::QueryOp::PollRequestAndInterpreter(poll)) =>
+                    match self.poll(true)? {
+                        None =>
+                            return Ok(()),
+                        Some(source) =>
+                            self.process(Poll::RequestAndInterpreter(poll.next), source)?,
+                    },
+
+                performer::Op::Query(performer::QueryOp::PollRequest(poll)) =>
+                    match self.poll(false)? {
+                        None =>
+                            return Ok(()),
+                        Some(source) =>
+                            self.process(Poll::Request(poll.next), source)?,
+                    },
+
+                performer::Op::Query(performer::QueryOp::InterpretTask(performer::InterpretTask { offset, task, next, .. })) => {
+                    assert!(self.interpreter_task.is_none());
+                    self.interpreter_task = Some((offset, task));
+                    let performer = next.task_accepted(());
+                    performer.next()
+                },
+
+                performer::Op::Query(performer::QueryOp::MakeIterBlocksStream(performer::MakeIterBlocksStream {
+                    blocks_total_count,
+                    iter_blocks_context: action_id,
+                    next,
+                    ..
+                })) => {
+                    if let Some(Action::IterBlocks { exact: Some(ref expected), .. }) = self.in_flight.get(&action_id) {
+                        if blocks_total_count != expected.len() {
+                            return Err(self.diverged(Divergence::IterBlocksCount {
+                                expected: expected.len(),
+                                actual: blocks_total_count,
+                            }));
+                        }
+                    }
+                    next.stream_ready(action_id)
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::Info(
+                        performer::TaskDoneOp { context: action_id, op: performer::InfoOp::Success { info, }, },
+                    ),
+                    performer,
+                }) => {
+                    match self.in_flight.remove(&action_id) {
+                        Some(Action::Info { expected_blocks_count, }) if expected_blocks_count == info.blocks_count =>
+                            self.stats.infos += 1,
+                        Some(Action::Info { expected_blocks_count, }) =>
+                            return Err(self.diverged(Divergence::InfoBlocksCount {
+                                expected: expected_blocks_count,
+                                actual: info.blocks_count,
+                            })),
+                        _ =>
+                            return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+                    }
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::Flush(
+                        performer::TaskDoneOp { context: action_id, op: performer::FlushOp::Flushed, },
+                    ),
+                    performer,
+                }) => {
+                    // device sync is a no-op for a ram wheel without snapshot
+                    match self.in_flight.remove(&action_id) {
+                        Some(Action::Flush) =>
+                            self.stats.flushes += 1,
+                        _ =>
+                            return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+                    }
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::WriteBlock(performer::TaskDoneOp { context: action_id, op, }),
+                    performer,
+                }) => {
+                    let block_bytes = match self.in_flight.remove(&action_id) {
+                        Some(Action::WriteBlock { block_bytes, }) =>
+                            block_bytes,
+                        _ =>
+                            return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+                    };
+                    self.mutations_in_flight -= 1;
+                    self.write_bytes_in_flight -= block_bytes.len();
+                    match op {
+                        performer::WriteBlockOp::NoSpaceLeft =>
+                            self.stats.no_space_left += 1,
+                        performer::WriteBlockOp::BlockIdTaken =>
+                            return Err(self.diverged(Divergence::WriteBlockIdTaken)),
+                        performer::WriteBlockOp::Done { block_id, } => {
+                            if self.written.insert(block_id.clone(), block_bytes.clone()).is_some() {
+                                return Err(self.diverged(Divergence::WriteBlockIdReused { block_id, }));
+                            }
+                            self.model_bytes += block_bytes.len();
+                            self.model.insert(block_id.clone(), block_bytes);
+                            self.live_ids.push(block_id);
+                            self.stats.writes += 1;
+                        },
+                    }
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::ReadBlock(performer::TaskDoneOp { context: action_id, op, }),
+                    performer,
+                }) => {
+                    let (block_id, expect_found) = match self.in_flight.remove(&action_id) {
+                        Some(Action::ReadBlock { block_id, expect_found, }) =>
+                            (block_id, expect_found),
+                        _ =>
+                            return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+                    };
+                    match op {
+                        performer::ReadBlockOp::Done { block_bytes, } => {
+                            if !expect_found {
+                                return Err(self.diverged(Divergence::ReadBlockResurrected { block_id, }));
+                            }
+                            if self.written.get(&block_id) != Some(&block_bytes) {
+                                return Err(self.diverged(Divergence::ReadBlockContents { block_id, }));
+                            }
+                            self.stats.reads += 1;
+                        },
+                        performer::ReadBlockOp::NotFound => {
+                            // found block may only disappear due to a delete issued after the read
+                            if self.model.contains_key(&block_id) && !self.pending_deletes.contains(&block_id) {
+                                return Err(self.diverged(Divergence::ReadBlockLost { block_id, }));
+                            }
+                            self.stats.not_found += 1;
+                        },
+                        performer::ReadBlockOp::Corrupted =>
+                            unreachable!("failed read jobs are reported as Error::BlockProcessRead"),
+                    }
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::DeleteBlock(performer::TaskDoneOp { context: action_id, op, }),
+                    performer,
+                }) => {
+                    let (block_id, expect_found) = match self.in_flight.remove(&action_id) {
+                        Some(Action::DeleteBlock { block_id, expect_found, }) =>
+                            (block_id, expect_found),
+                        _ =>
+                            return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+                    };
+                    self.mutations_in_flight -= 1;
+                    match op {
+                        performer::DeleteBlockOp::Done { .. } if expect_found => {
+                            self.pending_deletes.remove(&block_id);
+                            let block_bytes = self.model.remove(&block_id).unwrap();
+                            self.model_bytes -= block_bytes.len();
+                            let index = self.live_ids.iter().position(|live_id| live_id == &block_id).unwrap();
+                            self.live_ids.swap_remove(index);
+                            self.deleted_ids.push(block_id);
+                            self.stats.deletes += 1;
+                        },
+                        performer::DeleteBlockOp::Done { .. } =>
+                            return Err(self.diverged(Divergence::DeleteBlockResurrected { block_id, })),
+                        performer::DeleteBlockOp::NotFound if expect_found =>
+                            return Err(self.diverged(Divergence::DeleteBlockLost { block_id, })),
+                        performer::DeleteBlockOp::NotFound =>
+                            self.stats.not_found += 1,
+                    }
+                    performer.next()
+                },
+
+                // simulation only runs full iterations, which are never too old
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::IterBlocks(performer::TaskDoneOp { context: action_id, .. }),
+                    ..
+                }) =>
+                    return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::IterBlocksItem(performer::IterBlocksItemOp { block_id, block_bytes, iter_blocks_state, }),
+                    performer,
+                }) => {
+                    if let Some(Action::Adopt) = self.in_flight.get(&iter_blocks_state.iter_blocks_stream_context) {
+                        if self.written.insert(block_id.clone(), block_bytes.clone()).is_some() {
+                            return Err(self.diverged(Divergence::IterBlocksDuplicate { block_id, }));
+                        }
+                        self.model_bytes += block_bytes.len();
+                        self.model.insert(block_id.clone(), block_bytes);
+                        self.live_ids.push(block_id);
+                    } else {
+                        if self.written.get(&block_id).map_or(true, |written_bytes| written_bytes != &block_bytes) {
+                            return Err(self.diverged(if self.written.contains_key(&block_id) {
+                                Divergence::IterBlocksContents { block_id, }
+                            } else {
+                                Divergence::IterBlocksUnknown { block_id, }
+                            }));
+                        }
+                        match self.in_flight.get_mut(&iter_blocks_state.iter_blocks_stream_context) {
+                            Some(Action::IterBlocks { exact, seen, }) => {
+                                if !seen.insert(block_id.clone()) {
+                                    return Err(self.diverged(Divergence::IterBlocksDuplicate { block_id, }));
+                                }
+                                if let Some(expected) = exact {
+                                    if !expected.contains_key(&block_id) {
+                                        return Err(self.diverged(Divergence::IterBlocksUnknown { block_id, }));
+                                    }
+                                }
+                            },
+                            _ =>
+                                return Err(self.diverged(Divergence::UnexpectedReply {
+                                    action_id: iter_blocks_state.iter_blocks_stream_context,
+                                })),
+                        }
+                    }
+                    self.iter_items.push(iter_blocks_state);
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::IterBlocksFinish(performer::IterBlocksFinishOp { iter_blocks_stream_context: action_id, }),
+                    performer,
+                }) => {
+                    match self.in_flight.remove(&action_id) {
+                        Some(Action::IterBlocks { exact: Some(expected), seen, }) if expected.len() != seen.len() =>
+                            return Err(self.diverged(Divergence::IterBlocksCount {
+                                expected: expected.len(),
+                                actual: seen.len(),
+                            })),
+                        Some(Action::IterBlocks { .. }) =>
+                            self.stats.iterations += 1,
+                        Some(Action::Adopt) =>
+                            self.adopting = false,
+                        _ =>
+                            return Err(self.diverged(Divergence::UnexpectedReply { action_id, })),
+                    }
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::PrepareInterpretTask(performer::PrepareInterpretTaskOp {
+                        block_id,
+                        task: performer::PrepareInterpretTaskKind::WriteBlock(performer::PrepareInterpretTaskWriteBlock {
+                            block_bytes,
+                            change_seq,
+                            total_chunk_size,
+                            context,
+                        }),
+                    }),
+                    performer,
+                }) => {
+                    self.jobs.push(Job::BlockPrepareWrite { block_id, block_bytes, change_seq, total_chunk_size, context, });
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::PrepareInterpretTask(performer::PrepareInterpretTaskOp {
+                        block_id,
+                        task: performer::PrepareInterpretTaskKind::DeleteBlock(performer::PrepareInterpretTaskDeleteBlock {
+                            change_seq,
+                            total_chunk_size,
+                            context,
+                            ..
+                        }),
+                    }),
+                    performer,
+                }) => {
+                    self.jobs.push(Job::BlockPrepareDelete { block_id, change_seq, total_chunk_size, context, });
+                    performer.next()
+                },
+
+                performer::Op::Event(performer::Event {
+                    op: performer::EventOp::ProcessReadBlockTaskDone(performer::ProcessReadBlockTaskDoneOp {
+                        storage_layout,
+                        block_header,
+                        block_bytes,
+                        pending_contexts,
+                    }),
+                    performer,
+                }) => {
+                    self.jobs.push(Job::BlockProcessRead { storage_layout, block_header, block_bytes, pending_contexts, });
+                    performer.next()
+                },
+
+            };
+        }
+    }
+
+    // `None` means the run is complete: all actions are done and the final check passed
+    fn poll(&mut self, interpreter_busy: bool) -> Result<Option<Source>, Error> {
+        if self.adopt_pending {
+            self.adopt_pending = false;
+            self.adopting = true;
+            let action_id = self.register(Action::Adopt);
+            return Ok(Some(Source::Request(proto::Request::IterBlocks(proto::RequestIterBlocks {
+                changed_since: None,
+                context: action_id,
+            }))));
+        }
+        // random actions start only after the model knows all blocks of a loaded image
+        let can_submit = !self.adopting
+            && self.actions_left > 0
+            && self.in_flight.len() < self.params.active_tasks;
+        let choices = [
+            can_submit,
+            interpreter_busy && self.interpreter_task.is_some(),
+            !self.jobs.is_empty(),
+            !self.iter_items.is_empty(),
+        ];
+        let ready: Vec<usize> = choices.iter()
+            .enumerate()
+            .filter(|(_, ready)| **ready)
+            .map(|(index, _)| index)
+            .collect();
+        if ready.is_empty() {
+            if !self.in_flight.is_empty() {
+                return Err(self.diverged(Divergence::Stalled { in_flight: self.in_flight.len(), }));
+            }
+            if self.final_check_done {
+                return Ok(None);
+            }
+            // quiescent wheel should iterate exactly over the model
+            self.final_check_done = true;
+            let action_id = self.register(Action::IterBlocks { exact: Some(self.model.clone()), seen: HashSet::new(), });
+            return Ok(Some(Source::Request(proto::Request::IterBlocks(proto::RequestIterBlocks {
+                changed_since: None,
+                context: action_id,
+            }))));
+        }
+
+        let source = match ready[self.rng.gen_range(0 .. ready.len())] {
+            0 => {
+                self.actions_left -= 1;
+                Source::Request(self.make_request())
+            },
+            1 =>
+                Source::InterpreterDone,
+            2 => {
+                let index = self.rng.gen_range(0 .. self.jobs.len());
+                Source::Job(self.jobs.swap_remove(index))
+            },
+            3 => {
+                let index = self.rng.gen_range(0 .. self.iter_items.len());
+                Source::IterItem(self.iter_items.swap_remove(index))
+            },
+            _ =>
+                unreachable!(),
+        };
+        Ok(Some(source))
+    }
+
+    fn process(&mut self, poll: Poll, source: Source) -> Result<performer::Op<Context>, Error> {
+        let op = match source {
+            Source::Request(request) =>
+                poll.incoming_request(request),
+            Source::InterpreterDone => {
+                let (offset, task) = self.interpreter_task.take().unwrap();
+                let interpret::DoneTask { task_done, stats, } = self.memory.interpret(offset, task);
+                match poll {
+                    Poll::RequestAndInterpreter(next) =>
+                        next.incoming_task_done_stats(task_done, stats, ()),
+                    Poll::Request(..) =>
+                        unreachable!(),
+                }
+            },
+            Source::Job(Job::BlockPrepareWrite { block_id, block_bytes, change_seq, total_chunk_size, context, }) => {
+                let done = interpret::block_prepare_write_job(interpret::BlockPrepareWriteJobArgs {
+                    block_id: block_id.clone(),
+                    block_bytes,
+                    change_seq,
+                    total_chunk_size,
+                    blocks_pool: self.blocks_pool.clone(),
+                })
+                    .map_err(Error::BlockPrepareWrite)?;
+                poll.prepared_write_block_done(block_id, done.write_block_bytes, context)
+            },
+            Source::Job(Job::BlockProcessRead { storage_layout, block_header, block_bytes, pending_contexts, }) => {
+                let done = interpret::block_process_read_job(interpret::BlockProcessReadJobArgs {
+                    storage_layout,
+                    block_header,
+                    block_bytes,
+                })
+                    .map_err(Error::BlockProcessRead)?;
+                poll.process_read_block_done(done.block_id, done.block_bytes, pending_contexts)
+            },
+            Source::Job(Job::BlockPrepareDelete { block_id, change_seq, total_chunk_size, context, }) => {
+                let done = interpret::block_prepare_delete_job(interpret::BlockPrepareDeleteJobArgs {
+                    block_id: block_id.clone(),
+                    change_seq,
+                    secure_delete: None,
+                    total_chunk_size,
+                    blocks_pool: self.blocks_pool.clone(),
+                })
+                    .map_err(Error::BlockPrepareDelete)?;
+                poll.prepared_delete_block_done(block_id, done.delete_block_bytes, context)
+            },
+            Source::IterItem(iter_blocks_state) =>
+                poll.incoming_iter_blocks(iter_blocks_state),
+        };
+        Ok(op)
+    }
+
+    fn make_request(&mut self) -> proto::Request<Context> {
+        loop {
+            match self.rng.gen_range(0 .. 100) {
+                0 ..= 39 => {
+                    let block_size = self.rng.gen_range(1 ..= self.params.block_size_bytes);
+                    // keep the wheel at most half full, so pending defrag writes are always satisfied eventually
+                    if (self.model_bytes + self.write_bytes_in_flight + block_size) * 2 > self.params.init_wheel_size_bytes {
+                        continue;
+                    }
+                    let mut block = self.blocks_pool.lend();
+                    block.extend((0 .. block_size).map(|_| 0));
+                    self.rng.fill(&mut block[..]);
+                    let block_bytes = block.freeze();
+                    self.mutations_in_flight += 1;
+                    self.write_bytes_in_flight += block_size;
+                    let action_id = self.register(Action::WriteBlock { block_bytes: block_bytes.clone(), });
+                    return proto::Request::WriteBlock(proto::RequestWriteBlock {
+                        block_bytes,
+                        block_id: None,
+                        context: action_id,
+                    });
+                },
+                40 ..= 69 => {
+                    let (block_id, expect_found) = match self.pick_block_id() {
+                        Some(picked) =>
+                            picked,
+                        None =>
+                            continue,
+                    };
+                    let action_id = self.register(Action::ReadBlock { block_id: block_id.clone(), expect_found, });
+                    return proto::Request::ReadBlock(proto::RequestReadBlock {
+                        block_id,
+                        context: action_id,
+                    });
+                },
+                70 ..= 89 => {
+                    let (block_id, expect_found) = match self.pick_block_id() {
+                        Some((block_id, true)) if self.pending_deletes.contains(&block_id) =>
+                            continue,
+                        Some(picked) =>
+                            picked,
+                        None =>
+                            continue,
+                    };
+                    if expect_found {
+                        self.pending_deletes.insert(block_id.clone());
+                    }
+                    self.mutations_in_flight += 1;
+                    let action_id = self.register(Action::DeleteBlock { block_id: block_id.clone(), expect_found, });
+                    return proto::Request::DeleteBlock(proto::RequestDeleteBlock {
+                        block_id,
+                        secure_delete: None,
+                        context: action_id,
+                    });
+                },
+                90 ..= 93 => {
+                    let action_id = self.register(Action::Flush);
+                    return proto::Request::Flush(proto::RequestFlush { context: action_id, });
+                },
+                94 ..= 96 => {
+                    // blocks count is exact only when no writes or deletes are in flight
+                    if self.mutations_in_flight > 0 {
+                        continue;
+                    }
+                    let action_id = self.register(Action::Info { expected_blocks_count: self.model.len(), });
+                    return proto::Request::Info(proto::RequestInfo { context: action_id, });
+                },
+                _ => {
+                    let action_id = self.register(Action::IterBlocks { exact: None, seen: HashSet::new(), });
+                    return proto::Request::IterBlocks(proto::RequestIterBlocks {
+                        changed_since: None,
+                        context: action_id,
+                    });
+                },
+            }
+        }
+    }
+
+    // mostly live blocks, sometimes already deleted ones
+    fn pick_block_id(&mut self) -> Option<(block::Id, bool)> {
+        if !self.deleted_ids.is_empty() && (self.live_ids.is_empty() || self.rng.gen_range(0 .. 8) == 0) {
+            let index = self.rng.gen_range(0 .. self.deleted_ids.len());
+            Some((self.deleted_ids[index].clone(), false))
+        } else if !self.live_ids.is_empty() {
+            let index = self.rng.gen_range(0 .. self.live_ids.len());
+            Some((self.live_ids[index].clone(), true))
+        } else {
+            None
+        }
+    }
+
+    fn register(&mut self, action: Action) -> ActionId {
+        let action_id = self.next_action_id;
+        self.next_action_id += 1;
+        self.in_flight.insert(action_id, action);
+        action_id
+    }
+
+    fn diverged(&self, divergence: Divergence) -> Error {
+        Error::Diverged {
+            seed: self.params.seed,
+            step: self.stats.steps,
+            divergence,
+        }
+    }
+}
+
+impl Poll {
+    fn incoming_request(self, request: proto::Request<Context>) -> performer::Op<Context> {
+        match self {
+            Poll::RequestAndInterpreter(next) =>
+                next.incoming_request(request, ()),
+            Poll::Request(next) =>
+                next.incoming_request(request),
+        }
+    }
+
+    fn incoming_iter_blocks(self, iter_blocks_state: performer::IterBlocksState<ActionId>) -> performer::Op<Context> {
+        match self {
+            Poll::RequestAndInterpreter(next) =>
+                next.incoming_iter_blocks(iter_blocks_state, ()),
+            Poll::Request(next) =>
+                next.incoming_iter_blocks(iter_blocks_state),
+        }
+    }
+
+    fn prepared_write_block_done(
+        self,
+        block_id: block::Id,
+        write_block_bytes: BytesMut,
+        context: task::WriteBlockContext<ActionId>,
+    )
+        -> performer::Op<Context>
+    {
+        match self {
+            Poll::RequestAndInterpreter(next) =>
+                next.prepared_write_block_done(block_id, write_block_bytes, context, ()),
+            Poll::Request(next) =>
+                next.prepared_write_block_done(block_id, write_block_bytes, context),
+        }
+    }
+
+    fn prepared_delete_block_done(
+        self,
+        block_id: block::Id,
+        delete_block_bytes: BytesMut,
+        context: task::DeleteBlockContext<ActionId>,
+    )
+        -> performer::Op<Context>
+    {
+        match self {
+            Poll::RequestAndInterpreter(next) =>
+                next.prepared_delete_block_done(block_id, delete_block_bytes, context, ()),
+            Poll::Request(next) =>
+                next.prepared_delete_block_done(block_id, delete_block_bytes, context),
+        }
+    }
+
+    fn process_read_block_done(
+        self,
+        block_id: block::Id,
+        block_bytes: Bytes,
+        pending_contexts: task::queue::PendingReadContextBag,
+    )
+        -> performer::Op<Context>
+    {
+        match self {
+            Poll::RequestAndInterpreter(next) =>
+                next.process_read_block_done(block_id, block_bytes, pending_contexts, ()),
+            Poll::Request(next) =>
+                next.process_read_block_done(block_id, block_bytes, pending_contexts),
+        }
+    }
+}
@@ -10,13 +10,16 @@ use super::{
 };
 
 pub const WHEEL_MAGIC: u64 = 0xc0f124c9f1ba71d5;
-pub const WHEEL_VERSION: usize = 1;
+// 2: change sequences in commit tags and tombstones, 3: wheel id in header, 4: block align in header
+pub const WHEEL_VERSION: usize = 4;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WheelHeader {
     pub magic: u64,
     pub version: usize,
+    pub wheel_id: u64,
     pub size_bytes: u64,
+    pub block_align: usize,
 }
 
 impl Default for WheelHeader {
@@ -24,11 +27,18 @@ impl Default for WheelHeader {
         WheelHeader {
             magic: WHEEL_MAGIC,
             version: WHEEL_VERSION,
+            wheel_id: 0,
             size_bytes: 0,
+            block_align: 1,
         }
     }
 }
 
+// zero wheel id is never generated
+pub fn generate_wheel_id() -> u64 {
+    rand::random::<u64>() | 1
+}
+
 pub const BLOCK_MAGIC: u64 = 0x1af107518a38d0cf;
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
@@ -53,12 +63,16 @@ pub const TOMBSTONE_TAG_MAGIC: u64 = 0xce1063910922bdd5;
 #[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub struct TombstoneTag {
     pub magic: u64,
+    pub block_id: block::Id,
+    pub change_seq: u64,
 }
 
 impl Default for TombstoneTag {
     fn default() -> TombstoneTag {
         TombstoneTag {
             magic: TOMBSTONE_TAG_MAGIC,
+            block_id: block::Id::default(),
+            change_seq: 0,
         }
     }
 }
@@ -70,6 +84,7 @@ pub struct CommitTag {
     pub magic: u64,
     pub block_id: block::Id,
     pub crc: u64,
+    pub change_seq: u64,
 }
 
 impl Default for CommitTag {
@@ -78,6 +93,7 @@ impl Default for CommitTag {
             magic: COMMIT_TAG_MAGIC,
             block_id: block::Id::default(),
             crc: 0,
+            change_seq: 0,
         }
     }
 }
@@ -103,6 +119,8 @@ pub struct Layout {
     pub block_header_size: usize,
     pub commit_tag_size: usize,
     pub terminator_tag_size: usize,
+    // blocks are placed at offsets and padded to sizes which are multiples of this, 1 means no alignment
+    pub block_align: usize,
 }
 
 #[derive(Debug)]
@@ -146,11 +164,12 @@ impl Layout {
             block_header_size,
             commit_tag_size,
             terminator_tag_size,
+            block_align: 1,
         })
     }
 
     pub fn service_size_min(&self) -> usize {
-        self.wheel_header_size
+        self.data_offset_start()
             + self.terminator_tag_size
     }
 
@@ -158,6 +177,24 @@ impl Layout {
         self.block_header_size
             + self.commit_tag_size
     }
+
+    // offset of the first block
+    pub fn data_offset_start(&self) -> usize {
+        align_up(self.wheel_header_size, self.block_align)
+    }
+
+    // space occupied by a block with contents of `block_size` bytes, including alignment padding
+    pub fn block_chunk_size(&self, block_size: usize) -> usize {
+        align_up(self.data_size_block_min() + block_size, self.block_align)
+    }
+}
+
+fn align_up(size: usize, align: usize) -> usize {
+    if align <= 1 {
+        size
+    } else {
+        (size + align - 1) / align * align
+    }
 }
 
 pub fn bincode_options() -> impl Options {
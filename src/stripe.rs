@@ -0,0 +1,442 @@
+//! Striped wheel: one logical wheel over several member wheels, each one usually on its own device.
+//!
+//! Every block is stored whole on a single member chosen with `Placement`, a member which reports
+//! `NoSpaceLeft` is skipped in favor of the next one. Member number is encoded into the block id returned
+//! (see `block::Id::with_shard`), so nothing has to be read from members on start and reads and deletes go
+//! straight to the member.
+//!
+//! Requests to different members are executed in parallel.
+
+use std::{
+    cmp,
+};
+
+use futures::{
+    future::BoxFuture,
+    stream::FuturesUnordered,
+    channel::{
+        mpsc,
+        oneshot,
+    },
+    FutureExt,
+    SinkExt,
+    StreamExt,
+};
+
+use edeltraud::{
+    Edeltraud,
+};
+
+use alloc_pool::bytes::{
+    Bytes,
+    BytesPool,
+};
+
+use ero::{
+    supervisor::SupervisorPid,
+};
+
+use crate::{
+    job,
+    block,
+    fanout::{
+        self,
+        Source,
+        Member,
+    },
+    Deleted,
+    Flushed,
+    IterBlocks,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+    IterBlocksError,
+};
+
+pub use crate::fanout::WriteBlockWithIdError;
+
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub members: Vec<crate::Params>,
+    pub placement: Placement,
+}
+
+// how a member is chosen for a new block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Placement {
+    RoundRobin,
+    // member with most free space according to the last known `Info`
+    FreeSpace,
+}
+
+impl Default for Placement {
+    fn default() -> Placement {
+        Placement::RoundRobin
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Info {
+    // in the same order as `Params::members`
+    pub members: Vec<crate::Info>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoMembers,
+    TooManyMembers {
+        members: usize,
+    },
+    MemberInfo {
+        member: usize,
+        error: ero::NoProcError,
+    },
+}
+
+pub struct GenServer {
+    request_tx: mpsc::Sender<Request>,
+    request_rx: mpsc::Receiver<Request>,
+}
+
+#[derive(Clone)]
+pub struct Pid {
+    request_tx: mpsc::Sender<Request>,
+}
+
+enum Request {
+    Info {
+        reply_tx: oneshot::Sender<Info>,
+    },
+    Flush {
+        reply_tx: oneshot::Sender<Flushed>,
+    },
+    WriteBlock {
+        block_bytes: Bytes,
+        reply_tx: oneshot::Sender<Result<block::Id, WriteBlockError>>,
+    },
+    WriteBlockWithId {
+        block_id: block::Id,
+        block_bytes: Bytes,
+        reply_tx: oneshot::Sender<Result<block::Id, WriteBlockWithIdError>>,
+    },
+    ReadBlock {
+        block_id: block::Id,
+        reply_tx: oneshot::Sender<Result<Bytes, ReadBlockError>>,
+    },
+    DeleteBlock {
+        block_id: block::Id,
+        reply_tx: oneshot::Sender<Result<Deleted, DeleteBlockError>>,
+    },
+    IterBlocks {
+        reply_tx: oneshot::Sender<IterBlocks>,
+    },
+}
+
+impl GenServer {
+    pub fn new() -> GenServer {
+        let (request_tx, request_rx) = mpsc::channel(0);
+        GenServer { request_tx, request_rx, }
+    }
+
+    pub fn pid(&self) -> Pid {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub async fn run<J>(
+        self,
+        parent_supervisor: SupervisorPid,
+        thread_pool: Edeltraud<J>,
+        blocks_pool: BytesPool,
+        params: Params,
+    )
+    where J: edeltraud::Job + From<job::Job>,
+          J::Output: From<job::JobOutput>,
+          job::JobOutput: From<J::Output>,
+    {
+        let GenServer { request_rx, .. } = self;
+        let placement = params.placement;
+        fanout::run(
+            parent_supervisor,
+            thread_pool,
+            blocks_pool,
+            params.members,
+            |members_pids| busyloop(request_rx, members_pids, placement),
+        ).await
+    }
+}
+
+impl Pid {
+    pub async fn info(&mut self) -> Result<Info, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Info { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, ero::NoProcError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::Flush { reply_tx, }).await
+            .map_err(|_send_error| ero::NoProcError)?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ero::NoProcError)
+    }
+
+    pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::WriteBlock { block_bytes, reply_tx, }).await
+            .map_err(|_send_error| WriteBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| WriteBlockError::GenServer(ero::NoProcError))?
+    }
+
+    /// Block is stored on the member encoded in `block_id`.
+    pub async fn write_block_with_id(&mut self, block_id: block::Id, block_bytes: Bytes) -> Result<block::Id, WriteBlockWithIdError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::WriteBlockWithId { block_id, block_bytes, reply_tx, }).await
+            .map_err(|_send_error| WriteBlockWithIdError::WriteBlock(WriteBlockError::GenServer(ero::NoProcError)))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| WriteBlockWithIdError::WriteBlock(WriteBlockError::GenServer(ero::NoProcError)))?
+    }
+
+    pub async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::ReadBlock { block_id, reply_tx, }).await
+            .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ReadBlockError::GenServer(ero::NoProcError))?
+    }
+
+    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::DeleteBlock { block_id, reply_tx, }).await
+            .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| DeleteBlockError::GenServer(ero::NoProcError))?
+    }
+
+    /// Streams blocks of all members one member after another.
+    ///
+    /// Members have independent change sequences, so returned `IterBlocks::change_seq` is always zero.
+    pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request_tx.send(Request::IterBlocks { reply_tx, }).await
+            .map_err(|_send_error| IterBlocksError::GenServer(ero::NoProcError))?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| IterBlocksError::GenServer(ero::NoProcError))
+    }
+}
+
+enum Done {
+    Info {
+        infos: Vec<crate::Info>,
+    },
+    MemberInfo {
+        member: usize,
+        info: crate::Info,
+    },
+    MembersFull {
+        members_full: Vec<usize>,
+    },
+    Nothing,
+}
+
+async fn busyloop(
+    request_rx: mpsc::Receiver<Request>,
+    members_pids: Vec<crate::Pid>,
+    placement: Placement,
+)
+    -> Result<(), Error>
+{
+    if members_pids.is_empty() {
+        return Err(Error::NoMembers);
+    }
+    if block::Id::init().with_shard(members_pids.len() - 1).is_none() {
+        return Err(Error::TooManyMembers { members: members_pids.len(), });
+    }
+
+    let mut members = Vec::with_capacity(members_pids.len());
+    for (member, mut pid) in members_pids.into_iter().enumerate() {
+        let info = pid.info().await
+            .map_err(|error| Error::MemberInfo { member, error, })?;
+        members.push(Member { pid, bytes_free: info.bytes_free, });
+    }
+    log::debug!("stripe of {} members started", members.len());
+
+    let mut fused_request_rx = request_rx.fuse();
+    let mut pending: FuturesUnordered<BoxFuture<'static, Done>> = FuturesUnordered::new();
+    let mut round_robin = 0;
+
+    loop {
+        match fanout::next_source(&mut fused_request_rx, &mut pending).await {
+
+            Source::Pid(None) => {
+                log::debug!("all Pid frontends have been terminated");
+                return Ok(());
+            },
+
+            Source::Pid(Some(Request::Info { reply_tx, })) => {
+                let pids: Vec<_> = members.iter().map(|member| member.pid.clone()).collect();
+                pending.push(async move {
+                    let infos = match fanout::infos(pids).await {
+                        Ok(infos) =>
+                            infos,
+                        Err(ero::NoProcError) =>
+                            return Done::Nothing,
+                    };
+                    if let Err(_send_error) = reply_tx.send(Info { members: infos.clone(), }) {
+                        log::warn!("reply channel has been closed during Info result send");
+                    }
+                    Done::Info { infos, }
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::Flush { reply_tx, })) => {
+                let pids: Vec<_> = members.iter().map(|member| member.pid.clone()).collect();
+                pending.push(async move {
+                    if let Ok(Flushed) = fanout::flush(pids).await {
+                        if let Err(_send_error) = reply_tx.send(Flushed) {
+                            log::warn!("reply channel has been closed during Flush result send");
+                        }
+                    }
+                    Done::Nothing
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::WriteBlockWithId { block_id, block_bytes, reply_tx, })) =>
+                match block_id.shard() {
+                    (member, member_block_id) if member < members.len() => {
+                        members[member].bytes_free = members[member].bytes_free.saturating_sub(block_bytes.len());
+                        let mut pid = members[member].pid.clone();
+                        pending.push(async move {
+                            let result = pid.write_block_with_id(member_block_id, block_bytes).await
+                                .map(|_member_block_id| block_id);
+                            let done = match result {
+                                Err(WriteBlockError::NoSpaceLeft) =>
+                                    Done::MembersFull { members_full: vec![member], },
+                                _ =>
+                                    Done::Nothing,
+                            };
+                            if let Err(_send_error) = reply_tx.send(result.map_err(WriteBlockWithIdError::WriteBlock)) {
+                                log::warn!("reply channel has been closed during WriteBlock result send");
+                            }
+                            done
+                        }.boxed());
+                    },
+                    _ =>
+                        if let Err(_send_error) = reply_tx.send(Err(WriteBlockWithIdError::InvalidBlockId)) {
+                            log::warn!("reply channel has been closed during WriteBlock result send");
+                        },
+                },
+
+            Source::Pid(Some(Request::WriteBlock { block_bytes, reply_tx, })) => {
+                let mut order: Vec<usize> = (0 .. members.len()).collect();
+                match placement {
+                    Placement::RoundRobin => {
+                        order.rotate_left(round_robin % members.len());
+                        round_robin += 1;
+                    },
+                    Placement::FreeSpace =>
+                        order.sort_by_key(|&member| cmp::Reverse(members[member].bytes_free)),
+                }
+                let member = order[0];
+                members[member].bytes_free = members[member].bytes_free.saturating_sub(block_bytes.len());
+
+                let candidates: Vec<_> = order.into_iter()
+                    .map(|member| (member, members[member].pid.clone()))
+                    .collect();
+                pending.push(async move {
+                    let (result, members_full) = fanout::write_block(block_bytes, candidates).await;
+                    if let Err(_send_error) = reply_tx.send(result) {
+                        log::warn!("reply channel has been closed during WriteBlock result send");
+                    }
+                    Done::MembersFull { members_full, }
+                }.boxed());
+            },
+
+            Source::Pid(Some(Request::ReadBlock { block_id, reply_tx, })) =>
+                match block_id.shard() {
+                    (member, member_block_id) if member < members.len() => {
+                        let mut pid = members[member].pid.clone();
+                        pending.push(async move {
+                            let result = pid.read_block(member_block_id).await;
+                            if let Err(_send_error) = reply_tx.send(result) {
+                                log::warn!("reply channel has been closed during ReadBlock result send");
+                            }
+                            Done::Nothing
+                        }.boxed());
+                    },
+                    _ =>
+                        if let Err(_send_error) = reply_tx.send(Err(ReadBlockError::NotFound)) {
+                            log::warn!("reply channel has been closed during ReadBlock result send");
+                        },
+                },
+
+            Source::Pid(Some(Request::DeleteBlock { block_id, reply_tx, })) =>
+                match block_id.shard() {
+                    (member, member_block_id) if member < members.len() => {
+                        let mut pid = members[member].pid.clone();
+                        pending.push(async move {
+                            let result = pid.delete_block(member_block_id).await;
+                            let deleted = matches!(result, Ok(Deleted));
+                            if let Err(_send_error) = reply_tx.send(result) {
+                                log::warn!("reply channel has been closed during DeleteBlock result send");
+                            }
+                            // space of the block is credited back with the actual member free space
+                            if deleted {
+                                if let Ok(info) = pid.info().await {
+                                    return Done::MemberInfo { member, info, };
+                                }
+                            }
+                            Done::Nothing
+                        }.boxed());
+                    },
+                    _ =>
+                        if let Err(_send_error) = reply_tx.send(Err(DeleteBlockError::NotFound)) {
+                            log::warn!("reply channel has been closed during DeleteBlock result send");
+                        },
+                },
+
+            Source::Pid(Some(Request::IterBlocks { reply_tx, })) => {
+                let pids: Vec<_> = members.iter().map(|member| member.pid.clone()).collect();
+                pending.push(async move {
+                    let mut members_iter_blocks = Vec::with_capacity(pids.len());
+                    for mut pid in pids {
+                        match pid.iter_blocks().await {
+                            Ok(iter_blocks) =>
+                                members_iter_blocks.push(iter_blocks),
+                            Err(IterBlocksError::GenServer(ero::NoProcError)) =>
+                                return Done::Nothing,
+                        }
+                    }
+                    let (iter_blocks, forward) = fanout::concat_iter_blocks(members_iter_blocks);
+                    if let Err(_send_error) = reply_tx.send(iter_blocks) {
+                        log::warn!("reply channel has been closed during IterBlocks result send");
+                        return Done::Nothing;
+                    }
+                    forward.await;
+                    Done::Nothing
+                }.boxed());
+            },
+
+            Source::Pending(Done::Info { infos, }) =>
+                for (member, info) in members.iter_mut().zip(infos) {
+                    member.bytes_free = info.bytes_free;
+                },
+
+            Source::Pending(Done::MemberInfo { member, info, }) =>
+                members[member].bytes_free = info.bytes_free,
+
+            Source::Pending(Done::MembersFull { members_full, }) =>
+                for full in members_full {
+                    members[full].bytes_free = 0;
+                },
+
+            Source::Pending(Done::Nothing) =>
+                (),
+
+        }
+    }
+}
@@ -1,5 +1,14 @@
 use std::{
     fs,
+    io,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    collections::{
+        BTreeMap,
+        BTreeSet,
+    },
 };
 
 use futures::{
@@ -23,21 +32,55 @@ use ero::{
     },
 };
 
-use rand::Rng;
+use rand::{
+    Rng,
+    SeedableRng,
+    rngs::StdRng,
+};
 
 use super::{
     job,
+    sim,
     block,
+    net,
+    archive,
+    upgrade,
+    storage,
+    replication,
+    interpreter,
+    stripe,
+    sharded,
+    Pid,
     Params,
     GenServer,
     Flushed,
     Deleted,
+    SecureDelete,
+    IterBlocks,
     IterBlocksItem,
+    InterpretStats,
     InterpreterParams,
+    WheelCreateMode,
     RamInterpreterParams,
     FixedFileInterpreterParams,
+    FaultyInterpreterParams,
+    MirrorInterpreterParams,
+    Faults,
+    TearWrite,
 };
 
+#[cfg(unix)]
+use super::PositionalInterpreterParams;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use super::IoUringInterpreterParams;
+
+#[cfg(feature = "mmap")]
+use super::MmapInterpreterParams;
+
+#[cfg(feature = "erasure")]
+use super::erasure;
+
 #[test]
 fn stress_fixed_file() {
     env_logger::init();
@@ -53,6 +96,207 @@ fn stress_fixed_file() {
         interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
             wheel_filename: wheel_filename.into(),
             init_wheel_size_bytes,
+            create_mode: WheelCreateMode::ZeroFill,
+            direct_io: false,
+            punch_holes_min_bytes: None,
+            read_only: false,
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 8,
+        ..Default::default()
+    };
+
+    let limits = Limits {
+        active_tasks: 128,
+        actions: 1024,
+        block_size_bytes: work_block_size_bytes - 256,
+    };
+
+    let mut counter = Counter::default();
+    let mut blocks = Vec::new();
+
+    // first fill wheel from scratch
+    fs::remove_file(wheel_filename).ok();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    // next load existing wheel and repeat stress with blocks
+    counter.clear();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn stress_fixed_file_direct() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let wheel_filename = "/tmp/blockwheel_stress_direct";
+    let work_block_size_bytes = 16 * 1024;
+    let init_wheel_size_bytes = 1 * 1024 * 1024;
+
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes,
+            direct_io: true,
+            ..Default::default()
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 8,
+        ..Default::default()
+    };
+
+    let limits = Limits {
+        active_tasks: 128,
+        actions: 1024,
+        block_size_bytes: work_block_size_bytes - 256,
+    };
+
+    let mut counter = Counter::default();
+    let mut blocks = Vec::new();
+
+    // first fill wheel from scratch
+    fs::remove_file(wheel_filename).ok();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    // next load existing wheel and repeat stress with blocks
+    counter.clear();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn stress_positional() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let wheel_filename = "/tmp/blockwheel_stress_positional";
+    let work_block_size_bytes = 16 * 1024;
+    let init_wheel_size_bytes = 1 * 1024 * 1024;
+
+    let params = Params {
+        interpreter: InterpreterParams::Positional(PositionalInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes,
+            create_mode: WheelCreateMode::ZeroFill,
+            reader_threads: 4,
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 8,
+        ..Default::default()
+    };
+
+    let limits = Limits {
+        active_tasks: 128,
+        actions: 1024,
+        block_size_bytes: work_block_size_bytes - 256,
+    };
+
+    let mut counter = Counter::default();
+    let mut blocks = Vec::new();
+
+    // first fill wheel from scratch
+    fs::remove_file(wheel_filename).ok();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    // next load existing wheel and repeat stress with blocks
+    counter.clear();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    // finally read all blocks at once: reader threads should overlap
+    let interpret_stats = runtime.block_on(read_all_concurrently(params.clone(), &blocks)).unwrap();
+    assert!(interpret_stats.readers_busy_max > 1, "reads never overlapped: readers_busy_max = {}", interpret_stats.readers_busy_max);
+    assert_eq!(interpret_stats.count_per_reader.len(), 4);
+    assert!(interpret_stats.count_per_reader.iter().sum::<usize>() > 0);
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[test]
+fn stress_io_uring() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let wheel_filename = "/tmp/blockwheel_stress_io_uring";
+    let work_block_size_bytes = 16 * 1024;
+    let init_wheel_size_bytes = 1 * 1024 * 1024;
+
+    let params = Params {
+        interpreter: InterpreterParams::IoUring(IoUringInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes,
+            create_mode: WheelCreateMode::ZeroFill,
+            queue_depth: 32,
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 8,
+        ..Default::default()
+    };
+
+    let limits = Limits {
+        active_tasks: 128,
+        actions: 1024,
+        block_size_bytes: work_block_size_bytes - 256,
+    };
+
+    let mut counter = Counter::default();
+    let mut blocks = Vec::new();
+
+    // first fill wheel from scratch
+    fs::remove_file(wheel_filename).ok();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    // next load existing wheel and repeat stress with blocks
+    counter.clear();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    // finally read all blocks at once: ring reads should overlap
+    let interpret_stats = runtime.block_on(read_all_concurrently(params.clone(), &blocks)).unwrap();
+    assert!(interpret_stats.readers_busy_max > 1, "reads never overlapped: readers_busy_max = {}", interpret_stats.readers_busy_max);
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn stress_mmap() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let wheel_filename = "/tmp/blockwheel_stress_mmap";
+    let work_block_size_bytes = 16 * 1024;
+    let init_wheel_size_bytes = 1 * 1024 * 1024;
+
+    let params = Params {
+        interpreter: InterpreterParams::Mmap(MmapInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes,
+            create_mode: WheelCreateMode::ZeroFill,
         }),
         work_block_size_bytes,
         lru_cache_size_bytes: 0,
@@ -95,6 +339,7 @@ fn stress_ram() {
     let params = Params {
         interpreter: InterpreterParams::Ram(RamInterpreterParams {
             init_wheel_size_bytes,
+            snapshot_filename: None,
         }),
         work_block_size_bytes,
         lru_cache_size_bytes: 0,
@@ -117,6 +362,2118 @@ fn stress_ram() {
     assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
 }
 
+#[test]
+fn stress_ram_snapshot() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let snapshot_filename = "/tmp/blockwheel_stress_ram_snapshot";
+    let work_block_size_bytes = 16 * 1024;
+    let init_wheel_size_bytes = 1 * 1024 * 1024;
+
+    let params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes,
+            snapshot_filename: Some(snapshot_filename.into()),
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 8,
+        ..Default::default()
+    };
+
+    let limits = Limits {
+        active_tasks: 128,
+        actions: 1024,
+        block_size_bytes: work_block_size_bytes - 256,
+    };
+
+    let mut counter = Counter::default();
+    let mut blocks = Vec::new();
+
+    // first fill wheel from scratch
+    fs::remove_file(snapshot_filename).ok();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+    assert_eq!(fs::metadata(snapshot_filename).unwrap().len(), init_wheel_size_bytes as u64);
+
+    // next load wheel from snapshot and repeat stress with blocks
+    counter.clear();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+
+    fs::remove_file(snapshot_filename).ok();
+}
+
+#[test]
+fn stress_custom() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let work_block_size_bytes = 16 * 1024;
+    let init_wheel_size_bytes = 1 * 1024 * 1024;
+
+    let memory = Arc::new(Mutex::new(None));
+    let params = Params {
+        interpreter: InterpreterParams::Custom(Box::new(SharedMemoryInterpreter {
+            init_wheel_size_bytes,
+            memory: memory.clone(),
+        })),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 8,
+        ..Default::default()
+    };
+
+    let limits = Limits {
+        active_tasks: 128,
+        actions: 1024,
+        block_size_bytes: work_block_size_bytes - 256,
+    };
+
+    let mut counter = Counter::default();
+    let mut blocks = Vec::new();
+
+    // first fill wheel from scratch
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+    assert_eq!(memory.lock().unwrap().as_ref().map(Vec::len), Some(init_wheel_size_bytes));
+
+    // next open the same wheel through interpreter and repeat stress with blocks
+    counter.clear();
+    runtime.block_on(stress_loop(params.clone(), &mut blocks, &mut counter, &limits)).unwrap();
+
+    assert_eq!(counter.reads + counter.writes + counter.deletes, limits.actions);
+}
+
+// every clone works with the same memory, so a restarted wheel opens the image left by previous one
+#[derive(Clone, Debug)]
+struct SharedMemoryInterpreter {
+    init_wheel_size_bytes: usize,
+    memory: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl interpreter::Interpreter for SharedMemoryInterpreter {
+    fn init_wheel_size_bytes(&self) -> usize {
+        self.init_wheel_size_bytes
+    }
+
+    fn create(&mut self, init_wheel_size_bytes: usize, wheel_init: &[u8]) -> io::Result<()> {
+        let mut memory = vec![0; init_wheel_size_bytes];
+        memory[.. wheel_init.len()].copy_from_slice(wheel_init);
+        *self.memory.lock().unwrap() = Some(memory);
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<Option<Box<dyn interpreter::WheelRead + '_>>> {
+        let maybe_memory = self.memory.lock().unwrap().clone();
+        Ok(maybe_memory.map(|memory| Box::new(io::Cursor::new(memory)) as Box<dyn interpreter::WheelRead>))
+    }
+
+    fn push_request(&mut self, task: interpreter::Task<'_>) -> io::Result<()> {
+        let mut memory = self.memory.lock().unwrap();
+        let memory = memory.as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "wheel is not created"))?;
+        let start = task.offset as usize;
+        match task.kind {
+            interpreter::TaskKind::Write { bytes, } =>
+                memory[start .. start + bytes.len()].copy_from_slice(bytes),
+            interpreter::TaskKind::Read { bytes, } =>
+                bytes.copy_from_slice(&memory[start .. start + bytes.len()]),
+        }
+        Ok(())
+    }
+
+    fn device_sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn interpreter::Interpreter> {
+        Box::new(self.clone())
+    }
+}
+
+#[test]
+fn archive_export_import_ram() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 256 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    runtime.block_on(async {
+        let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+
+        let mut source_pid = spawn_wheel(&mut supervisor_pid, thread_pool.clone(), blocks_pool.clone(), params.clone());
+        let mut rng = rand::thread_rng();
+        let mut blocks = Vec::new();
+        for _ in 0 .. 16 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. rng.gen_range(1 .. 4096)).map(|_| 0));
+            rng.fill(&mut block[..]);
+            let block_bytes = block.freeze();
+            let block_id = source_pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+        let BlockTank { block_id, .. } = blocks.swap_remove(3);
+        let Deleted = source_pid.delete_block(block_id).await
+            .map_err(Error::DeleteBlock)?;
+
+        let mut archive_bytes = Vec::new();
+        let exported = source_pid.export(&mut archive_bytes).await
+            .map_err(Error::Export)?;
+        assert_eq!(exported.blocks_count, blocks.len());
+
+        let mut target_pid = spawn_wheel(&mut supervisor_pid, thread_pool.clone(), blocks_pool.clone(), params.clone());
+        let imported = target_pid.import(&archive_bytes[..], &blocks_pool, archive::ImportParams { preserve_block_ids: true, block_size_max: params.work_block_size_bytes, }).await
+            .map_err(Error::Import)?;
+        assert_eq!(imported.blocks_count, exported.blocks_count);
+        assert_eq!(imported.blocks_total_size, exported.blocks_total_size);
+
+        for tank in &blocks {
+            let block_bytes = target_pid.read_block(tank.block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+
+        // importing the same archive again with preserved ids must collide
+        match target_pid.import(&archive_bytes[..], &blocks_pool, archive::ImportParams { preserve_block_ids: true, block_size_max: params.work_block_size_bytes, }).await {
+            Err(archive::ImportError::WriteBlock(super::WriteBlockError::BlockIdTaken)) =>
+                (),
+            other =>
+                panic!("expected BlockIdTaken during second import, got {:?}", other),
+        }
+
+        // records larger than target work block are rejected
+        let import_params = archive::ImportParams { block_size_max: 1024, ..Default::default() };
+        match target_pid.import(&archive_bytes[..], &blocks_pool, import_params).await {
+            Err(archive::ImportError::BlockTooLarge { block_size_max: 1024, .. }) =>
+                (),
+            other =>
+                panic!("expected BlockTooLarge during import, got {:?}", other),
+        }
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn secure_delete_fixed_file() {
+    let wheel_filename = "/tmp/blockwheel_secure_delete";
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes: 64 * 1024,
+            ..Default::default()
+        }),
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+
+    fs::remove_file(wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params)?;
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 4096).map(|_| 0xa5));
+        let block_id = pid.write_block(block.freeze()).await
+            .map_err(Error::WriteBlock)?;
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        assert!(contains_pattern(&fs::read(wheel_filename).unwrap()));
+
+        let Deleted = pid.delete_block_secure(block_id, SecureDelete::Zeros).await
+            .map_err(Error::DeleteBlock)?;
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        assert!(!contains_pattern(&fs::read(wheel_filename).unwrap()));
+
+        Ok::<_, Error>(())
+    }).unwrap();
+    fs::remove_file(wheel_filename).ok();
+
+    fn contains_pattern(contents: &[u8]) -> bool {
+        contents.windows(64).any(|window| window.iter().all(|&byte| byte == 0xa5))
+    }
+}
+
+// secure delete is only done once its overwrite is synced: power cut reverts whatever has not reached the device
+#[test]
+fn secure_delete_faulty_power_cut() {
+    let wheel_filename = "/tmp/blockwheel_secure_delete_power_cut";
+    let params = Params {
+        // writes: block a, block b, terminator on flush, block a overwrite, block c (cut)
+        interpreter: InterpreterParams::Faulty(FaultyInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes: 64 * 1024,
+            create_mode: WheelCreateMode::ZeroFill,
+            faults: Faults {
+                tear_write: Some(TearWrite { nth_write: 5, keep_bytes: 0, }),
+                drop_unsynced_on_power_cut: true,
+                ..Default::default()
+            },
+        }),
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+
+    fs::remove_file(wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params)?;
+        let mut block_ids = Vec::new();
+        for byte in [0xa5_u8, 0x5a] {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 4096).map(|_| byte));
+            let block_id = pid.write_block(block.freeze()).await
+                .map_err(Error::WriteBlock)?;
+            block_ids.push(block_id);
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+
+        let Deleted = pid.delete_block_secure(block_ids[0].clone(), SecureDelete::Zeros).await
+            .map_err(Error::DeleteBlock)?;
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 4096).map(|_| 0x33));
+        pid.write_block(block.freeze()).await
+            .map_err(Error::WriteBlock)?;
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+
+        Ok::<_, Error>(())
+    }).unwrap();
+    drop(runtime);
+
+    let contents = fs::read(wheel_filename).unwrap();
+    assert!(!contains_pattern(&contents, 0xa5));
+    assert!(contains_pattern(&contents, 0x5a));
+    fs::remove_file(wheel_filename).ok();
+
+    fn contains_pattern(contents: &[u8], byte: u8) -> bool {
+        contents.windows(64).any(|window| window.iter().all(|&value| value == byte))
+    }
+}
+
+#[test]
+fn punch_holes_fixed_file() {
+    use std::os::unix::fs::MetadataExt;
+
+    let wheel_filename = "/tmp/blockwheel_punch_holes";
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes: 1024 * 1024,
+            punch_holes_min_bytes: Some(64 * 1024),
+            ..Default::default()
+        }),
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+
+    fs::remove_file(wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params)?;
+        // every block is well below `punch_holes_min_bytes`
+        let mut block_ids = Vec::new();
+        for _ in 0 .. 128 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 2000).map(|_| 0xa5));
+            let block_id = pid.write_block(block.freeze()).await
+                .map_err(Error::WriteBlock)?;
+            block_ids.push(block_id);
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        let blocks_before = fs::metadata(wheel_filename).unwrap().blocks();
+
+        for block_id in block_ids {
+            let Deleted = pid.delete_block(block_id).await
+                .map_err(Error::DeleteBlock)?;
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        let metadata = fs::metadata(wheel_filename).unwrap();
+        assert_eq!(metadata.len(), 1024 * 1024);
+        // merged gap of about 256 KiB is deallocated
+        assert!(
+            metadata.blocks() + 256 < blocks_before,
+            "st_blocks before deletes: {}, after: {}",
+            blocks_before,
+            metadata.blocks(),
+        );
+
+        Ok::<_, Error>(())
+    }).unwrap();
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[test]
+fn read_only_fixed_file() {
+    let wheel_filename = "/tmp/blockwheel_read_only";
+    let fixed_file_params = FixedFileInterpreterParams {
+        wheel_filename: wheel_filename.into(),
+        init_wheel_size_bytes: 64 * 1024,
+        ..Default::default()
+    };
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(fixed_file_params.clone()),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    fs::remove_file(wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let tank = runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params.clone())?;
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 1024).map(|_| 0x5a));
+        let block_bytes = block.freeze();
+        let block_id = pid.write_block(block_bytes.clone()).await
+            .map_err(Error::WriteBlock)?;
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        Ok::<_, Error>(BlockTank { block_id, block_bytes, })
+    }).unwrap();
+    drop(runtime);
+    let wheel_contents = fs::read(wheel_filename).unwrap();
+
+    let read_only_params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            read_only: true,
+            ..fixed_file_params
+        }),
+        ..params
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(read_only_params)?;
+        let block_bytes = pid.read_block(tank.block_id.clone()).await
+            .map_err(Error::ReadBlock)?;
+        assert_eq!(block_bytes, tank.block_bytes);
+        match pid.write_block(blocks_pool.lend().freeze()).await {
+            Err(super::WriteBlockError::ReadOnly) =>
+                (),
+            other =>
+                panic!("expected ReadOnly, got {:?}", other),
+        }
+        match pid.delete_block(tank.block_id.clone()).await {
+            Err(super::DeleteBlockError::ReadOnly) =>
+                (),
+            other =>
+                panic!("expected ReadOnly, got {:?}", other),
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.blocks_count, 1);
+        Ok::<_, Error>(())
+    }).unwrap();
+    drop(runtime);
+    assert_eq!(fs::read(wheel_filename).unwrap(), wheel_contents);
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[test]
+fn corrupted_block_fixed_file() {
+    let wheel_filename = "/tmp/blockwheel_corrupted_block";
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes: 64 * 1024,
+            ..Default::default()
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    fs::remove_file(wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let tanks = runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params.clone())?;
+        let mut tanks = Vec::new();
+        for byte in [0x5a_u8, 0xa5] {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 1024).map(|_| byte));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            tanks.push(BlockTank { block_id, block_bytes, });
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        Ok::<_, Error>(tanks)
+    }).unwrap();
+    drop(runtime);
+
+    let mut wheel_bytes = fs::read(wheel_filename).unwrap();
+    let offset = wheel_bytes.windows(1024).position(|window| window.iter().all(|&byte| byte == 0x5a)).unwrap();
+    wheel_bytes[offset + 512] ^= 0x10;
+    fs::write(wheel_filename, &wheel_bytes).unwrap();
+
+    // damaged block is reported to its reader while the wheel keeps serving the others
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, _blocks_pool) = start_wheel(params)?;
+        for _ in 0 .. 2 {
+            match pid.read_block(tanks[0].block_id.clone()).await {
+                Err(super::ReadBlockError::Corrupted) =>
+                    (),
+                other =>
+                    panic!("expected Corrupted, got {:?}", other),
+            }
+            let block_bytes = pid.read_block(tanks[1].block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tanks[1].block_bytes);
+        }
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.blocks_count, 2);
+        Ok::<_, Error>(())
+    }).unwrap();
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+// overwrites the block device from `BLOCKWHEEL_TEST_DEVICE`, e.g. a loop device made by `losetup -f --show image`
+#[test]
+#[ignore]
+fn block_device_fixed_file() {
+    let device_filename = match std::env::var("BLOCKWHEEL_TEST_DEVICE") {
+        Ok(device_filename) =>
+            device_filename,
+        Err(..) => {
+            log::warn!("BLOCKWHEEL_TEST_DEVICE is not set, skipping block device test");
+            return;
+        },
+    };
+
+    // blank the header area, so the wheel is created from scratch
+    {
+        use std::io::Write;
+        let mut device = fs::OpenOptions::new().write(true).open(&device_filename).unwrap();
+        device.write_all(&[0; 4096]).unwrap();
+        device.sync_all().unwrap();
+    }
+
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: device_filename.clone().into(),
+            init_wheel_size_bytes: 1024 * 1024,
+            ..Default::default()
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let tank = runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params.clone())?;
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 4096).map(|index| index as u8));
+        let block_bytes = block.freeze();
+        let block_id = pid.write_block(block_bytes.clone()).await
+            .map_err(Error::WriteBlock)?;
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        Ok::<_, Error>(BlockTank { block_id, block_bytes, })
+    }).unwrap();
+    drop(runtime);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, _blocks_pool) = start_wheel(params)?;
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        // the wheel takes only a part of the device
+        assert_eq!(info.wheel_size_bytes, 1024 * 1024);
+        assert_eq!(info.blocks_count, 1);
+        let block_bytes = pid.read_block(tank.block_id.clone()).await
+            .map_err(Error::ReadBlock)?;
+        assert_eq!(block_bytes, tank.block_bytes);
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn upgrade_v1_fixed_file() {
+    use bincode::Options;
+
+    let source_wheel_filename = "/tmp/blockwheel_upgrade_v1_source";
+    let target_wheel_filename = "/tmp/blockwheel_upgrade_v1_target";
+    let size_bytes: u64 = 64 * 1024;
+
+    // v1 layout: header without wheel id, commit tags without change seq, tombstones with magic only
+    let blocks: Vec<(block::Id, Vec<u8>)> = vec![
+        (block::Id::init(), (0 .. 1024).map(|_| 0x11).collect()),
+        (block::Id::init().next().next(), (0 .. 512).map(|_| 0x22).collect()),
+    ];
+    let mut image = Vec::new();
+    storage::bincode_options().serialize_into(&mut image, &(storage::WHEEL_MAGIC, 1usize, size_bytes)).unwrap();
+    image.extend((0 .. 13).map(|_| 0));
+    for (index, (block_id, block_bytes)) in blocks.iter().enumerate() {
+        let block_header = storage::BlockHeader { block_id: block_id.clone(), block_size: block_bytes.len(), ..Default::default() };
+        storage::bincode_options().serialize_into(&mut image, &block_header).unwrap();
+        image.extend(block_bytes);
+        storage::bincode_options()
+            .serialize_into(&mut image, &(storage::COMMIT_TAG_MAGIC, block_id.clone(), block::crc(block_bytes)))
+            .unwrap();
+        if index == 0 {
+            storage::bincode_options().serialize_into(&mut image, &storage::TOMBSTONE_TAG_MAGIC).unwrap();
+            image.extend((0 .. 40).map(|_| 0));
+        }
+    }
+    storage::bincode_options().serialize_into(&mut image, &storage::TerminatorTag::default()).unwrap();
+    image.resize(size_bytes as usize, 0);
+    fs::write(source_wheel_filename, &image).unwrap();
+    fs::remove_file(target_wheel_filename).ok();
+
+    let upgraded = upgrade::upgrade_v1(upgrade::UpgradeParams {
+        source_wheel_filename,
+        target_wheel_filename,
+        block_size_max: 16 * 1024,
+    }).unwrap();
+    assert_eq!(upgraded.blocks_count, 2);
+    assert_eq!(upgraded.blocks_total_size, 1536);
+    assert_eq!(upgraded.wheel_size_bytes, size_bytes);
+
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: target_wheel_filename.into(),
+            init_wheel_size_bytes: size_bytes as usize,
+            ..Default::default()
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, _blocks_pool) = start_wheel(params)?;
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.wheel_id, upgraded.wheel_id);
+        assert_eq!(info.blocks_count, 2);
+        for (block_id, block_bytes) in &blocks {
+            let read_bytes = pid.read_block(block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(&read_bytes[..], &block_bytes[..]);
+        }
+        // upgraded blocks are visible as changes since the very beginning
+        let iter_blocks = pid.changes_since(0).await
+            .map_err(Error::ChangesSince)?;
+        assert_eq!(iter_blocks.blocks_total_count, 2);
+        assert_eq!(iter_blocks.change_seq, 2);
+        Ok::<_, Error>(())
+    }).unwrap();
+    drop(runtime);
+
+    // target is never overwritten
+    match upgrade::upgrade_v1(upgrade::UpgradeParams { source_wheel_filename, target_wheel_filename, block_size_max: 16 * 1024, }) {
+        Err(upgrade::UpgradeError::TargetCreate { .. }) =>
+            (),
+        other =>
+            panic!("expected TargetCreate error, got {:?}", other),
+    }
+
+    fs::remove_file(source_wheel_filename).ok();
+    fs::remove_file(target_wheel_filename).ok();
+}
+
+#[test]
+fn faulty_torn_write_reopen() {
+    let wheel_filename = "/tmp/blockwheel_faulty_torn_write";
+    let fixed_file_params = FixedFileInterpreterParams {
+        wheel_filename: wheel_filename.into(),
+        init_wheel_size_bytes: 64 * 1024,
+        ..Default::default()
+    };
+    let params = Params {
+        // writes: block a, terminator on flush, block b (torn)
+        interpreter: InterpreterParams::Faulty(FaultyInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes: 64 * 1024,
+            create_mode: WheelCreateMode::ZeroFill,
+            faults: Faults {
+                tear_write: Some(TearWrite { nth_write: 3, keep_bytes: 10, }),
+                ..Default::default()
+            },
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+
+    fs::remove_file(wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let (tank_a, tank_b) = runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params)?;
+        let mut tanks = Vec::new();
+        for byte in [0xa1_u8, 0xb2] {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 1024).map(|_| byte));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            let Flushed = pid.flush().await
+                .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+            tanks.push(BlockTank { block_id, block_bytes, });
+        }
+        let tank_b = tanks.pop().unwrap();
+        let tank_a = tanks.pop().unwrap();
+        Ok::<_, Error>((tank_a, tank_b))
+    }).unwrap();
+    drop(runtime);
+
+    // block b was acknowledged, but has never reached the disk entirely
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(fixed_file_params),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, _blocks_pool) = start_wheel(params)?;
+        let block_bytes = pid.read_block(tank_a.block_id.clone()).await
+            .map_err(Error::ReadBlock)?;
+        assert_eq!(block_bytes, tank_a.block_bytes);
+        match pid.read_block(tank_b.block_id.clone()).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("expected NotFound, got {:?}", other),
+        }
+        Ok::<_, Error>(())
+    }).unwrap();
+    drop(runtime);
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[test]
+fn mirror_read_repair_resync() {
+    let wheel_filenames = ["/tmp/blockwheel_mirror.0", "/tmp/blockwheel_mirror.1"];
+    let mirror_params = |wheel_filenames: &[&str], resync_on_open| Params {
+        interpreter: InterpreterParams::Mirror(MirrorInterpreterParams {
+            wheel_filenames: wheel_filenames.iter().map(Into::into).collect(),
+            init_wheel_size_bytes: 64 * 1024,
+            create_mode: WheelCreateMode::ZeroFill,
+            resync_on_open,
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+    let fixed_file_params = |wheel_filename: &str| Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: wheel_filename.into(),
+            init_wheel_size_bytes: 64 * 1024,
+            read_only: true,
+            ..Default::default()
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+    let read_all = |params: Params, tanks: Vec<BlockTank>| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (mut pid, _blocks_pool) = start_wheel(params)?;
+            for tank in &tanks {
+                let block_bytes = pid.read_block(tank.block_id.clone()).await
+                    .map_err(Error::ReadBlock)?;
+                assert_eq!(block_bytes, tank.block_bytes);
+            }
+            Ok::<_, Error>(())
+        }).unwrap();
+    };
+
+    remove_mirror_files(&wheel_filenames);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let tanks = runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(mirror_params(&wheel_filenames[..], true))?;
+        let mut tanks = Vec::new();
+        for byte in [0xa1_u8, 0xb2] {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 1024).map(|_| byte));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            tanks.push(BlockTank { block_id, block_bytes, });
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        Ok::<_, Error>(tanks)
+    }).unwrap();
+    drop(runtime);
+    assert_eq!(fs::read(wheel_filenames[0]).unwrap(), fs::read(wheel_filenames[1]).unwrap());
+
+    // second copy of the first block goes bad, it is left as is without resync on open
+    let mut wheel_bytes = fs::read(wheel_filenames[1]).unwrap();
+    let offset = wheel_bytes.windows(1024).position(|window| window.iter().all(|&byte| byte == 0xa1)).unwrap();
+    wheel_bytes[offset + 512] ^= 0x10;
+    fs::write(wheel_filenames[1], &wheel_bytes).unwrap();
+
+    // damaged copy cannot be loaded, but it is listed first, so it serves reads and gets repaired
+    read_all(mirror_params(&[wheel_filenames[1], wheel_filenames[0]], false), tanks.clone());
+    assert_eq!(fs::read(wheel_filenames[0]).unwrap(), fs::read(wheel_filenames[1]).unwrap());
+    read_all(fixed_file_params(wheel_filenames[1]), tanks.clone());
+
+    // replaced member is copied from the surviving one
+    fs::remove_file(wheel_filenames[0]).unwrap();
+    read_all(mirror_params(&wheel_filenames[..], true), tanks.clone());
+    assert_eq!(fs::read(wheel_filenames[0]).unwrap(), fs::read(wheel_filenames[1]).unwrap());
+    read_all(fixed_file_params(wheel_filenames[0]), tanks);
+
+    remove_mirror_files(&wheel_filenames);
+}
+
+// member missing the writes of the previous run is listed first, but the wheel is still loaded from the freshest one
+#[test]
+fn mirror_stale_member_first() {
+    let wheel_filenames = ["/tmp/blockwheel_mirror_stale.0", "/tmp/blockwheel_mirror_stale.1"];
+    let mirror_params = |wheel_filenames: &[&str]| Params {
+        interpreter: InterpreterParams::Mirror(MirrorInterpreterParams {
+            wheel_filenames: wheel_filenames.iter().map(Into::into).collect(),
+            init_wheel_size_bytes: 64 * 1024,
+            create_mode: WheelCreateMode::ZeroFill,
+            resync_on_open: false,
+        }),
+        wheel_task_restart_sec: 1,
+        work_block_size_bytes: 16 * 1024,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+    let write_flush = |params: Params, byte: u8| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (mut pid, blocks_pool) = start_wheel(params)?;
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 1024).map(|_| byte));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            let Flushed = pid.flush().await
+                .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+            Ok::<_, Error>(BlockTank { block_id, block_bytes, })
+        }).unwrap()
+    };
+
+    remove_mirror_files(&wheel_filenames);
+    let tank_a = write_flush(mirror_params(&wheel_filenames[..]), 0xa1);
+    assert_eq!(fs::read(wheel_filenames[0]).unwrap(), fs::read(wheel_filenames[1]).unwrap());
+
+    // first member is away while the second one takes more writes
+    let tank_b = write_flush(mirror_params(&wheel_filenames[1 ..]), 0xb2);
+    assert_ne!(fs::read(wheel_filenames[0]).unwrap(), fs::read(wheel_filenames[1]).unwrap());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mut pid, _blocks_pool) = start_wheel(mirror_params(&wheel_filenames[..]))?;
+        for tank in [&tank_a, &tank_b] {
+            let block_bytes = pid.read_block(tank.block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+        Ok::<_, Error>(())
+    }).unwrap();
+    drop(runtime);
+    // stale member is resynced on open
+    assert_eq!(fs::read(wheel_filenames[0]).unwrap(), fs::read(wheel_filenames[1]).unwrap());
+
+    remove_mirror_files(&wheel_filenames);
+}
+
+fn remove_mirror_files(wheel_filenames: &[&str]) {
+    for wheel_filename in wheel_filenames {
+        fs::remove_file(wheel_filename).ok();
+        fs::remove_file(format!("{}.generation", wheel_filename)).ok();
+        fs::remove_file(format!("{}.generation.tmp", wheel_filename)).ok();
+    }
+}
+
+// random write/delete workload with defrag over a wheel losing power after a random amount of written bytes,
+// every block acknowledged before the last successful flush should survive reopening; writes not synced by then
+// are reverted by the power cut, so a flush which never reaches the device sync loses blocks here
+#[test]
+fn crash_consistency_fixed_file() {
+    let mut rng = StdRng::seed_from_u64(0x0b10c4ee1);
+    for round in 0 .. 16 {
+        let seed = rng.gen();
+        let cut_bytes = rng.gen_range(0 .. 512 * 1024);
+        crash_round(round, seed, cut_bytes);
+    }
+}
+
+fn crash_round(round: usize, seed: u64, cut_bytes: u64) {
+    let wheel_filename = format!("/tmp/blockwheel_crash_consistency_{}", round);
+    let init_wheel_size_bytes = 256 * 1024;
+    let work_block_size_bytes = 16 * 1024;
+    log::info!("crash round {}: seed = {}, cut_bytes = {}", round, seed, cut_bytes);
+
+    let faulty_params = Params {
+        interpreter: InterpreterParams::Faulty(FaultyInterpreterParams {
+            wheel_filename: wheel_filename.clone().into(),
+            init_wheel_size_bytes,
+            create_mode: WheelCreateMode::ZeroFill,
+            faults: Faults {
+                power_cut_after_write_bytes: Some(cut_bytes),
+                report_power_cut_on_sync: true,
+                drop_unsynced_on_power_cut: true,
+                ..Default::default()
+            },
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 4,
+        ..Default::default()
+    };
+    let params = Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename: wheel_filename.clone().into(),
+            init_wheel_size_bytes,
+            ..Default::default()
+        }),
+        work_block_size_bytes,
+        lru_cache_size_bytes: 0,
+        defrag_parallel_tasks_limit: 0,
+        ..Default::default()
+    };
+
+    fs::remove_file(&wheel_filename).ok();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let model = runtime.block_on(crash_workload(faulty_params, seed)).unwrap();
+    drop(runtime);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let label = format!("crash round {} (seed = {}, cut_bytes = {})", round, seed, cut_bytes);
+    runtime.block_on(crash_verify(params, &model, &label)).unwrap();
+    drop(runtime);
+
+    fs::remove_file(&wheel_filename).ok();
+}
+
+#[derive(Default)]
+struct CrashModel {
+    durable: BTreeMap<block::Id, Bytes>,
+    changed_since_flush: BTreeSet<block::Id>,
+    written: BTreeMap<block::Id, Bytes>,
+}
+
+async fn crash_workload(params: Params, seed: u64) -> Result<CrashModel, Error> {
+    let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+    // wheel is expected to fail on flush after power cut, so it is not linked permanently
+    let gen_server = GenServer::new();
+    let mut pid = gen_server.pid();
+    supervisor_pid.spawn_link_temporary(
+        gen_server.run(supervisor_pid.clone(), thread_pool, blocks_pool.clone(), params),
+    );
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut model = CrashModel::default();
+    let mut acked = BTreeMap::new();
+    for _ in 0 .. 256 {
+        match rng.gen_range(0 .. 10) {
+            0 =>
+                match pid.flush().await {
+                    Ok(Flushed) => {
+                        model.durable = acked.clone();
+                        model.changed_since_flush.clear();
+                    },
+                    Err(ero::NoProcError) =>
+                        return Ok(model),
+                },
+            1 | 2 | 3 if !acked.is_empty() => {
+                let block_id: block::Id = acked.keys().nth(rng.gen_range(0 .. acked.len())).unwrap().clone();
+                model.changed_since_flush.insert(block_id.clone());
+                match pid.delete_block(block_id.clone()).await {
+                    Ok(Deleted) => {
+                        acked.remove(&block_id);
+                    },
+                    Err(super::DeleteBlockError::GenServer(ero::NoProcError)) =>
+                        return Ok(model),
+                    Err(error) =>
+                        return Err(Error::DeleteBlock(error)),
+                }
+            },
+            _ => {
+                let mut block = blocks_pool.lend();
+                block.extend((0 .. rng.gen_range(1 .. 4096)).map(|_| 0));
+                rng.fill(&mut block[..]);
+                let block_bytes = block.freeze();
+                match pid.write_block(block_bytes.clone()).await {
+                    Ok(block_id) => {
+                        model.changed_since_flush.insert(block_id.clone());
+                        model.written.insert(block_id.clone(), block_bytes.clone());
+                        acked.insert(block_id, block_bytes);
+                    },
+                    Err(super::WriteBlockError::NoSpaceLeft) =>
+                        (),
+                    Err(super::WriteBlockError::GenServer(ero::NoProcError)) =>
+                        return Ok(model),
+                    Err(error) =>
+                        return Err(Error::WriteBlock(error)),
+                }
+            },
+        }
+    }
+    if let Ok(Flushed) = pid.flush().await {
+        model.durable = acked;
+        model.changed_since_flush.clear();
+    }
+    Ok(model)
+}
+
+async fn crash_verify(params: Params, model: &CrashModel, label: &str) -> Result<(), Error> {
+    let (mut pid, _blocks_pool) = start_wheel(params)?;
+
+    for (block_id, block_bytes) in &model.durable {
+        if model.changed_since_flush.contains(block_id) {
+            continue;
+        }
+        match pid.read_block(block_id.clone()).await {
+            Ok(block_bytes_read) =>
+                assert_eq!(&block_bytes_read, block_bytes, "{}: block {:?} contents mismatch", label, block_id),
+            Err(error) =>
+                panic!("{}: durable block {:?} is lost: {:?}", label, block_id, error),
+        }
+    }
+
+    let mut iter_blocks = pid.iter_blocks().await
+        .map_err(Error::IterBlocks)?;
+    loop {
+        match iter_blocks.blocks_rx.next().await {
+            None =>
+                return Err(Error::IterBlocksRxDropped),
+            Some(IterBlocksItem::Block { block_id, block_bytes, }) => {
+                match model.written.get(&block_id) {
+                    Some(written_bytes) =>
+                        assert_eq!(&block_bytes, written_bytes, "{}: block {:?} contents mismatch", label, block_id),
+                    None =>
+                        panic!("{}: block {:?} has never been written", label, block_id),
+                }
+                assert!(
+                    model.durable.contains_key(&block_id) || model.changed_since_flush.contains(&block_id),
+                    "{}: block {:?} deleted before last flush is back",
+                    label,
+                    block_id,
+                );
+            },
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                break,
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn deterministic_simulation() {
+    for seed in 0 .. 8 {
+        let params = sim::Params {
+            seed,
+            actions: 65536,
+            ..Default::default()
+        };
+        let stats = sim::run(params.clone()).unwrap();
+        assert!(stats.writes > 0 && stats.reads > 0 && stats.deletes > 0 && stats.iterations > 0);
+        // same seed replays exactly the same schedule
+        assert_eq!(sim::run(params).unwrap(), stats);
+    }
+}
+
+#[test]
+fn simulation_over_image_with_duplicate_ids() {
+    use bincode::Options;
+
+    let size_bytes: u64 = 64 * 1024;
+    let mut image = Vec::new();
+    let wheel_header = storage::WheelHeader { wheel_id: 1, size_bytes, ..Default::default() };
+    storage::bincode_options().serialize_into(&mut image, &wheel_header).unwrap();
+    // block 0 is stored twice, the later change should win
+    for (block_id, fill, change_seq) in vec![(block::Id::init(), 0x01, 1), (block::Id::init().next(), 0x02, 2), (block::Id::init(), 0x03, 3)] {
+        let block_bytes: Vec<u8> = (0 .. 100).map(|_| fill).collect();
+        let block_header = storage::BlockHeader { block_id: block_id.clone(), block_size: block_bytes.len(), ..Default::default() };
+        storage::bincode_options().serialize_into(&mut image, &block_header).unwrap();
+        image.extend(&block_bytes);
+        let commit_tag = storage::CommitTag { block_id, crc: block::crc(&block_bytes), change_seq, ..Default::default() };
+        storage::bincode_options().serialize_into(&mut image, &commit_tag).unwrap();
+    }
+    storage::bincode_options().serialize_into(&mut image, &storage::TerminatorTag::default()).unwrap();
+    image.resize(size_bytes as usize, 0);
+
+    let params = sim::Params {
+        actions: 4096,
+        init_wheel_size_bytes: size_bytes as usize,
+        block_size_bytes: 1024,
+        ..Default::default()
+    };
+    let stats = sim::run_image(params, image).unwrap();
+    assert!(stats.writes > 0 && stats.reads > 0 && stats.deletes > 0);
+}
+
+#[test]
+fn changes_since_ram() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 64 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params)?;
+        let mut block_ids = Vec::new();
+        for i in 0 .. 4 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 128).map(|_| i as u8));
+            let block_id = pid.write_block(block.freeze()).await
+                .map_err(Error::WriteBlock)?;
+            block_ids.push(block_id);
+        }
+
+        let full = pid.changes_since(0).await
+            .map_err(Error::ChangesSince)?;
+        assert_eq!(full.blocks_total_count, 4);
+        assert_eq!(full.change_seq, 4);
+        assert!(full.deleted_block_ids.is_empty());
+
+        let Deleted = pid.delete_block(block_ids[1].clone()).await
+            .map_err(Error::DeleteBlock)?;
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 64).map(|_| 4));
+        let block_id = pid.write_block(block.freeze()).await
+            .map_err(Error::WriteBlock)?;
+
+        let mut incremental = pid.changes_since(full.change_seq).await
+            .map_err(Error::ChangesSince)?;
+        assert_eq!(incremental.blocks_total_count, 1);
+        assert_eq!(incremental.blocks_total_size, 64);
+        assert_eq!(incremental.change_seq, 6);
+        assert_eq!(incremental.deleted_block_ids, vec![block_ids[1].clone()]);
+        match incremental.blocks_rx.next().await {
+            Some(IterBlocksItem::Block { block_id: changed_block_id, .. }) =>
+                assert_eq!(changed_block_id, block_id),
+            Some(IterBlocksItem::NoMoreBlocks) | None =>
+                return Err(Error::IterBlocksRxDropped),
+        }
+        match incremental.blocks_rx.next().await {
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                (),
+            Some(IterBlocksItem::Block { block_id, .. }) =>
+                return Err(Error::IterBlocksUnexpectedBlockReceived { block_id, }),
+            None =>
+                return Err(Error::IterBlocksRxDropped),
+        }
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn stripe_ram() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let member_params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 64 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+    let params = stripe::Params {
+        members: vec![member_params.clone(), member_params.clone(), member_params],
+        placement: stripe::Placement::RoundRobin,
+    };
+
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_stripe(params)?;
+
+        let mut blocks = Vec::new();
+        for i in 0 .. 9 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 1024).map(|_| i as u8));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            assert!(blocks.iter().all(|tank: &BlockTank| tank.block_id != block_id));
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.members.len(), 3);
+        for member_info in &info.members {
+            assert_eq!(member_info.blocks_count, 3);
+        }
+
+        for tank in &blocks {
+            let block_bytes = pid.read_block(tank.block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+
+        match pid.write_block_with_id(blocks[0].block_id.clone(), blocks[0].block_bytes.clone()).await {
+            Err(stripe::WriteBlockWithIdError::WriteBlock(super::WriteBlockError::BlockIdTaken)) =>
+                (),
+            other =>
+                panic!("unexpected write block result: {:?}", other),
+        }
+        match pid.write_block_with_id(block::Id::init().with_shard(3).unwrap(), blocks[0].block_bytes.clone()).await {
+            Err(stripe::WriteBlockWithIdError::InvalidBlockId) =>
+                (),
+            other =>
+                panic!("unexpected write block result: {:?}", other),
+        }
+
+        let BlockTank { block_id: deleted_block_id, .. } = blocks.remove(4);
+        let Deleted = pid.delete_block(deleted_block_id.clone()).await
+            .map_err(Error::DeleteBlock)?;
+        match pid.read_block(deleted_block_id).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("unexpected read block result: {:?}", other),
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+
+        let iter_blocks = pid.iter_blocks().await
+            .map_err(Error::IterBlocks)?;
+        check_iter_blocks(iter_blocks, &blocks).await?;
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+// member 1 has room for a single block only, so round robin falls back to the other members
+#[test]
+fn stripe_ram_member_full() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let member_params = |init_wheel_size_bytes| Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+    let params = stripe::Params {
+        members: vec![member_params(128 * 1024), member_params(12 * 1024), member_params(128 * 1024)],
+        placement: stripe::Placement::RoundRobin,
+    };
+
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_stripe(params)?;
+
+        let mut blocks = Vec::new();
+        for i in 0 .. 9 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 8192).map(|_| i as u8));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.members[1].blocks_count, 1);
+        assert_eq!(info.members.iter().map(|member_info| member_info.blocks_count).sum::<usize>(), blocks.len());
+        for (member, member_info) in info.members.iter().enumerate() {
+            assert_eq!(blocks.iter().filter(|tank| tank.block_id.shard().0 == member).count(), member_info.blocks_count);
+        }
+
+        for tank in &blocks {
+            let block_bytes = pid.read_block(tank.block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+
+        // space freed on the full member is used again
+        let tank = blocks.iter().find(|tank| tank.block_id.shard().0 == 1).unwrap();
+        let Deleted = pid.delete_block(tank.block_id.clone()).await
+            .map_err(Error::DeleteBlock)?;
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.members[1].blocks_count, 0);
+        let mut written_to_member = false;
+        for _ in 0 .. 3 {
+            let block_id = pid.write_block(tank.block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            written_to_member |= block_id.shard().0 == 1;
+        }
+        assert!(written_to_member);
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+// nothing is read from members on start: blocks written before restart are found by their ids
+#[test]
+fn stripe_fixed_file_restart() {
+    let wheel_filenames = ["/tmp/blockwheel_stripe.0", "/tmp/blockwheel_stripe.1"];
+    let params = stripe::Params {
+        members: wheel_filenames.iter()
+            .map(|wheel_filename| Params {
+                interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+                    wheel_filename: wheel_filename.into(),
+                    init_wheel_size_bytes: 64 * 1024,
+                    ..Default::default()
+                }),
+                wheel_task_restart_sec: 1,
+                work_block_size_bytes: 16 * 1024,
+                defrag_parallel_tasks_limit: 0,
+                ..Default::default()
+            })
+            .collect(),
+        placement: stripe::Placement::RoundRobin,
+    };
+    let run = |params: stripe::Params, blocks: Vec<BlockTank>| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (mut pid, blocks_pool) = start_stripe(params)?;
+
+            for tank in &blocks {
+                let block_bytes = pid.read_block(tank.block_id.clone()).await
+                    .map_err(Error::ReadBlock)?;
+                assert_eq!(block_bytes, tank.block_bytes);
+            }
+
+            let mut blocks = blocks;
+            for i in 0 .. 4 {
+                let mut block = blocks_pool.lend();
+                block.extend((0 .. 1024).map(|_| (blocks.len() + i) as u8));
+                let block_bytes = block.freeze();
+                let block_id = pid.write_block(block_bytes.clone()).await
+                    .map_err(Error::WriteBlock)?;
+                assert!(blocks.iter().all(|tank: &BlockTank| tank.block_id != block_id));
+                blocks.push(BlockTank { block_id, block_bytes, });
+            }
+            let Flushed = pid.flush().await
+                .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+
+            let iter_blocks = pid.iter_blocks().await
+                .map_err(Error::IterBlocks)?;
+            assert_eq!(iter_blocks.blocks_total_count, blocks.len());
+
+            Ok::<_, Error>(blocks)
+        }).unwrap()
+    };
+
+    for wheel_filename in &wheel_filenames {
+        fs::remove_file(wheel_filename).ok();
+    }
+    let blocks = run(params.clone(), Vec::new());
+    let blocks = run(params.clone(), blocks);
+    assert_eq!(blocks.len(), 8);
+
+    for wheel_filename in &wheel_filenames {
+        fs::remove_file(wheel_filename).ok();
+    }
+}
+
+// two data and one parity shards: every block survives losing a member file, either deleted or damaged, and is rebuilt on its replacement
+#[cfg(feature = "erasure")]
+#[test]
+fn erasure_fixed_file() {
+    let wheel_filenames = ["/tmp/blockwheel_erasure.0", "/tmp/blockwheel_erasure.1", "/tmp/blockwheel_erasure.2"];
+    let params = |read_only: [bool; 3]| erasure::Params {
+        members: wheel_filenames.iter()
+            .zip(read_only)
+            .map(|(wheel_filename, read_only)| Params {
+                interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+                    wheel_filename: wheel_filename.into(),
+                    init_wheel_size_bytes: 64 * 1024,
+                    read_only,
+                    ..Default::default()
+                }),
+                wheel_task_restart_sec: 1,
+                work_block_size_bytes: 16 * 1024,
+                defrag_parallel_tasks_limit: 0,
+                ..Default::default()
+            })
+            .collect(),
+        data_shards: 2,
+    };
+    let fill = |mut pid: erasure::Pid, blocks_pool: BytesPool| async move {
+        let mut blocks = Vec::new();
+        for (size, byte) in [(4096, 0xc3_u8), (1001, 0x5a), (1, 0x01), (0, 0x00)] {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. size).map(|_| byte));
+            let block_bytes = block.freeze();
+            let erasure::Written { block_id, shards_missing, } = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            assert_eq!(shards_missing, 0);
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        check_erasure_blocks(&mut pid, &blocks, 0).await?;
+        Ok::<_, Error>(blocks)
+    };
+
+    for damage_corrupt in [false, true] {
+        for wheel_filename in &wheel_filenames {
+            fs::remove_file(wheel_filename).ok();
+        }
+        let blocks = run_erasure(params([false; 3]), fill);
+
+        let blocks_degraded = if damage_corrupt {
+            let mut wheel_bytes = fs::read(wheel_filenames[0]).unwrap();
+            let offset = wheel_bytes.windows(256).position(|window| window.iter().all(|&byte| byte == 0xc3)).unwrap();
+            wheel_bytes[offset + 128] ^= 0x10;
+            fs::write(wheel_filenames[0], &wheel_bytes).unwrap();
+            1
+        } else {
+            fs::remove_file(wheel_filenames[0]).unwrap();
+            blocks.len()
+        };
+        let blocks = run_erasure(params([false; 3]), move |mut pid, _blocks_pool| async move {
+            check_erasure_blocks(&mut pid, &blocks, blocks_degraded).await?;
+            let rebuilt = pid.rebuild().await
+                .map_err(|ero::NoProcError| Error::WheelGoneDuringRebuild)?;
+            assert_eq!(rebuilt, erasure::Rebuilt { blocks_rebuilt: blocks_degraded, blocks_degraded: 0, });
+            let Flushed = pid.flush().await
+                .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+            check_erasure_blocks(&mut pid, &blocks, 0).await?;
+            Ok::<_, Error>(blocks)
+        });
+
+        // blocks can be restored now only with the rebuilt shards of the first member
+        fs::remove_file(wheel_filenames[1]).unwrap();
+        run_erasure(params([false; 3]), move |mut pid, _blocks_pool| async move {
+            let blocks_degraded = blocks.len();
+            check_erasure_blocks(&mut pid, &blocks, blocks_degraded).await
+        });
+    }
+
+    for wheel_filename in &wheel_filenames {
+        fs::remove_file(wheel_filename).ok();
+    }
+    let blocks = run_erasure(params([false; 3]), fill);
+    let deleted_block_id = blocks[0].block_id.clone();
+    let blocks = run_erasure(params([false, false, true]), move |mut pid, _blocks_pool| async move {
+        // a single shard is left on the read only member, which is not enough to bring the block back
+        let Deleted = pid.delete_block(blocks[0].block_id.clone()).await
+            .map_err(Error::ErasureDeleteBlock)?;
+        Ok::<_, Error>(blocks[1 ..].to_vec())
+    });
+    let blocks = run_erasure(params([false, true, true]), move |mut pid, _blocks_pool| async move {
+        // two shards are left on read only members, so the block is kept
+        match pid.delete_block(blocks[0].block_id.clone()).await {
+            Err(erasure::DeleteBlockError::TooManyShardsLeft { left: 2, allowed: 1, }) =>
+                (),
+            other =>
+                panic!("expected TooManyShardsLeft, got {:?}", other),
+        }
+        check_erasure_blocks(&mut pid, &blocks, 1).await?;
+        Ok::<_, Error>(blocks)
+    });
+    run_erasure(params([false; 3]), move |mut pid, _blocks_pool| async move {
+        match pid.read_block(deleted_block_id).await {
+            Err(erasure::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("expected NotFound, got {:?}", other),
+        }
+        // leftover shard of the deleted block is removed on start
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.members[2].as_ref().map(|member_info| member_info.blocks_count), Some(blocks.len()));
+        check_erasure_blocks(&mut pid, &blocks, 1).await?;
+        let rebuilt = pid.rebuild().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringRebuild)?;
+        assert_eq!(rebuilt, erasure::Rebuilt { blocks_rebuilt: 1, blocks_degraded: 0, });
+        Ok::<_, Error>(())
+    });
+
+    for wheel_filename in &wheel_filenames {
+        fs::remove_file(wheel_filename).ok();
+    }
+}
+
+#[test]
+fn sharded_ram() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let shard_params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 64 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+    let params = sharded::Params {
+        shards: vec![shard_params.clone(), shard_params.clone(), shard_params],
+        routing: sharded::Routing::FreeSpace,
+    };
+
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_sharded(params)?;
+
+        let mut blocks = Vec::new();
+        for i in 0 .. 6 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 1024).map(|_| i as u8));
+            let block_bytes = block.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            assert!(blocks.iter().all(|tank: &BlockTank| tank.block_id != block_id));
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+
+        // every shard is equally free, so blocks are spread evenly and the shard is seen in the id
+        let shards_info = pid.shards_info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(shards_info.len(), 3);
+        for (shard, shard_info) in shards_info.iter().enumerate() {
+            assert_eq!(shard_info.blocks_count, 2);
+            assert_eq!(blocks.iter().filter(|tank| tank.block_id.shard().0 == shard).count(), 2);
+        }
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(info.blocks_count, blocks.len());
+        assert_eq!(info.bytes_free, shards_info.iter().map(|shard_info| shard_info.bytes_free).sum::<usize>());
+
+        let changes = pid.changes_since(Vec::new()).await
+            .map_err(Error::ShardedChangesSince)?;
+        assert_eq!(changes.change_seqs.len(), 3);
+        check_iter_blocks(changes.iter_blocks, &blocks).await?;
+        match pid.changes_since(vec![0]).await {
+            Err(sharded::ChangesSinceError::ShardsCountMismatch { shards: 3, change_seqs: 1, }) =>
+                (),
+            Err(error) =>
+                panic!("unexpected changes since error: {:?}", error),
+            Ok(..) =>
+                panic!("unexpected changes since success"),
+        }
+
+        for tank in &blocks {
+            let block_bytes = pid.read_block(tank.block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+
+        match pid.write_block_with_id(blocks[0].block_id.clone(), blocks[0].block_bytes.clone()).await {
+            Err(sharded::WriteBlockWithIdError::WriteBlock(super::WriteBlockError::BlockIdTaken)) =>
+                (),
+            other =>
+                panic!("unexpected write block result: {:?}", other),
+        }
+        let missing_shard_block_id = block::Id::init().with_shard(7).unwrap();
+        match pid.write_block_with_id(missing_shard_block_id.clone(), blocks[0].block_bytes.clone()).await {
+            Err(sharded::WriteBlockWithIdError::InvalidBlockId) =>
+                (),
+            other =>
+                panic!("unexpected write block result: {:?}", other),
+        }
+        match pid.read_block(missing_shard_block_id).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("unexpected read block result: {:?}", other),
+        }
+
+        let BlockTank { block_id: deleted_block_id, .. } = blocks.remove(3);
+        let Deleted = pid.delete_block(deleted_block_id.clone()).await
+            .map_err(Error::DeleteBlock)?;
+        match pid.read_block(deleted_block_id.clone()).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("unexpected read block result: {:?}", other),
+        }
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 512).map(|_| 0xaa));
+        let block_bytes = block.freeze();
+        let block_id = pid.write_block(block_bytes.clone()).await
+            .map_err(Error::WriteBlock)?;
+        blocks.push(BlockTank { block_id: block_id.clone(), block_bytes: block_bytes.clone(), });
+        let Flushed = pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+
+        // composite ids are reported for changes of every shard
+        let changes = pid.changes_since(changes.change_seqs).await
+            .map_err(Error::ShardedChangesSince)?;
+        assert_eq!(changes.iter_blocks.deleted_block_ids, vec![deleted_block_id]);
+        check_iter_blocks(changes.iter_blocks, &[BlockTank { block_id, block_bytes, }]).await?;
+
+        let iter_blocks = pid.iter_blocks().await
+            .map_err(Error::IterBlocks)?;
+        check_iter_blocks(iter_blocks, &blocks).await?;
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[test]
+fn replication_loopback_ram() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 64 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    let position_filename = "/tmp/blockwheel_replication_position";
+    fs::remove_file(position_filename).ok();
+
+    runtime.block_on(async {
+        let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+
+        let mut leader_gen_server = GenServer::new();
+        let mut leader_pid = leader_gen_server.pid();
+        let commits_rx = leader_gen_server.commits();
+        supervisor_pid.spawn_link_permanent(
+            leader_gen_server.run(supervisor_pid.clone(), thread_pool.clone(), blocks_pool.clone(), params.clone()),
+        );
+        let mut follower_pid = spawn_wheel(&mut supervisor_pid, thread_pool.clone(), blocks_pool.clone(), params.clone());
+
+        let mut blocks = Vec::new();
+        for i in 0 .. 4 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 256).map(|_| i as u8));
+            let block_bytes = block.freeze();
+            let block_id = leader_pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+        let BlockTank { block_id: deleted_block_id, .. } = blocks.remove(1);
+        let Deleted = leader_pid.delete_block(deleted_block_id.clone()).await
+            .map_err(Error::DeleteBlock)?;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .map_err(Error::ReplicationBind)?;
+        let leader_addr = listener.local_addr()
+            .map_err(Error::ReplicationBind)?;
+        tokio::spawn(replication::lead(leader_pid.clone(), commits_rx, listener));
+
+        let (position_tx, mut position_rx) = tokio::sync::watch::channel(replication::Position::default());
+        let (promote_tx, promote_rx) = futures::channel::oneshot::channel();
+        let follower = tokio::spawn(replication::follow(
+            follower_pid.clone(),
+            blocks_pool.clone(),
+            replication::FollowerParams {
+                leader_addr,
+                reconnect_timeout: std::time::Duration::from_millis(100),
+                block_size_max: params.work_block_size_bytes,
+                position_filename: Some(position_filename.into()),
+            },
+            position_tx,
+            promote_rx,
+        ));
+
+        // initial snapshot
+        while position_rx.borrow().change_seq < 5 {
+            position_rx.changed().await.unwrap();
+        }
+        for tank in &blocks {
+            let block_bytes = follower_pid.read_block(tank.block_id.clone()).await
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+        match follower_pid.read_block(deleted_block_id).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("expected NotFound for deleted block on follower, got {:?}", other),
+        }
+
+        // live changes
+        let mut block = blocks_pool.lend();
+        block.extend((0 .. 128).map(|_| 4));
+        let block_bytes = block.freeze();
+        let block_id = leader_pid.write_block(block_bytes.clone()).await
+            .map_err(Error::WriteBlock)?;
+        let Deleted = leader_pid.delete_block(blocks[0].block_id.clone()).await
+            .map_err(Error::DeleteBlock)?;
+        while position_rx.borrow().change_seq < 7 {
+            position_rx.changed().await.unwrap();
+        }
+        assert_eq!(follower_pid.read_block(block_id).await.map_err(Error::ReadBlock)?, block_bytes);
+        match follower_pid.read_block(blocks[0].block_id.clone()).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("expected NotFound for deleted block on follower, got {:?}", other),
+        }
+
+        // promotion
+        promote_tx.send(replication::Promote).unwrap();
+        let position = follower.await.unwrap().unwrap();
+        assert_eq!(position.change_seq, 7);
+        let leader_info = leader_pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(position.leader_id, leader_info.wheel_id);
+        let block_id = follower_pid.write_block(block_bytes.clone()).await
+            .map_err(Error::WriteBlock)?;
+        assert_eq!(follower_pid.read_block(block_id).await.map_err(Error::ReadBlock)?, block_bytes);
+
+        // restarted follower continues from the stored position
+        let (position_tx, position_rx) = tokio::sync::watch::channel(replication::Position::default());
+        let (promote_tx, promote_rx) = futures::channel::oneshot::channel();
+        promote_tx.send(replication::Promote).unwrap();
+        let restarted_position = replication::follow(
+            follower_pid.clone(),
+            blocks_pool.clone(),
+            replication::FollowerParams {
+                leader_addr,
+                position_filename: Some(position_filename.into()),
+                ..Default::default()
+            },
+            position_tx,
+            promote_rx,
+        ).await.unwrap();
+        assert_eq!(restarted_position, position);
+        assert_eq!(*position_rx.borrow(), position);
+
+        Ok::<_, Error>(())
+    }).unwrap();
+
+    fs::remove_file(position_filename).ok();
+}
+
+#[test]
+fn net_remote_pid_tcp_ram() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 64 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    runtime.block_on(async {
+        let (mut pid, blocks_pool) = start_wheel(params)?;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await
+            .map_err(Error::NetBind)?;
+        let server_addr = listener.local_addr()
+            .map_err(Error::NetBind)?;
+        let server_params = net::ServerParams {
+            payload_size_max: 16 * 1024,
+            inflight_requests_max: 2,
+        };
+        tokio::spawn(net::server::serve_tcp(pid.clone(), blocks_pool.clone(), listener, server_params));
+
+        let client_params = net::ClientParams {
+            payload_size_max: 16 * 1024,
+        };
+        let mut remote_pid = net::RemotePid::connect_tcp(server_addr, blocks_pool.clone(), client_params).await
+            .map_err(Error::NetConnect)?;
+
+        let mut blocks = Vec::new();
+        for i in 0 .. 4 {
+            let mut block = blocks_pool.lend();
+            block.extend((0 .. 512).map(|_| i as u8));
+            let block_bytes = block.freeze();
+            let block_id = remote_pid.write_block(block_bytes.clone()).await
+                .map_err(Error::WriteBlock)?;
+            blocks.push(BlockTank { block_id, block_bytes, });
+        }
+        match remote_pid.write_block_with_id(blocks[0].block_id.clone(), blocks[0].block_bytes.clone()).await {
+            Err(super::WriteBlockError::BlockIdTaken) =>
+                (),
+            other =>
+                panic!("expected BlockIdTaken, got {:?}", other),
+        }
+
+        // concurrent requests from cloned remote pids
+        let mut reads = Vec::new();
+        for tank in &blocks {
+            let mut remote_pid = remote_pid.clone();
+            let block_id = tank.block_id.clone();
+            reads.push(tokio::spawn(async move { remote_pid.read_block(block_id).await }));
+        }
+        for (read, tank) in reads.into_iter().zip(blocks.iter()) {
+            let block_bytes = read.await.unwrap()
+                .map_err(Error::ReadBlock)?;
+            assert_eq!(block_bytes, tank.block_bytes);
+        }
+
+        let BlockTank { block_id, .. } = blocks.remove(2);
+        let Deleted = remote_pid.delete_block(block_id.clone()).await
+            .map_err(Error::DeleteBlock)?;
+        match remote_pid.delete_block(block_id.clone()).await {
+            Err(super::DeleteBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("expected NotFound, got {:?}", other),
+        }
+        match remote_pid.read_block(block_id).await {
+            Err(super::ReadBlockError::NotFound) =>
+                (),
+            other =>
+                panic!("expected NotFound, got {:?}", other),
+        }
+
+        let Flushed = remote_pid.flush().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringFlush)?;
+        let remote_info = remote_pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        assert_eq!(remote_info.blocks_count, blocks.len());
+        assert_eq!(remote_info.data_bytes_used, info.data_bytes_used);
+
+        let mut iter_blocks = remote_pid.iter_blocks().await
+            .map_err(Error::IterBlocks)?;
+        assert_eq!(iter_blocks.blocks_total_count, blocks.len());
+        // unconsumed iteration does not stall other replies on the same connection
+        let block_bytes = remote_pid.read_block(blocks[0].block_id.clone()).await
+            .map_err(Error::ReadBlock)?;
+        assert_eq!(block_bytes, blocks[0].block_bytes);
+        let mut iterated = 0;
+        loop {
+            match iter_blocks.blocks_rx.next().await {
+                None =>
+                    return Err(Error::IterBlocksRxDropped),
+                Some(IterBlocksItem::Block { block_id, block_bytes, }) => {
+                    let tank = blocks.iter().find(|tank| tank.block_id == block_id)
+                        .ok_or(Error::IterBlocksUnexpectedBlockReceived { block_id, })?;
+                    assert_eq!(block_bytes, tank.block_bytes);
+                    iterated += 1;
+                },
+                Some(IterBlocksItem::NoMoreBlocks) =>
+                    break,
+            }
+        }
+        assert_eq!(iterated, blocks.len());
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_gateway_ram() {
+    use hyper::{
+        Body,
+        Method,
+        Request,
+        StatusCode,
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let params = Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: 16 * 1024,
+            snapshot_filename: None,
+        }),
+        work_block_size_bytes: 16 * 1024,
+        ..Default::default()
+    };
+
+    runtime.block_on(async {
+        let (pid, blocks_pool) = start_wheel(params.clone())?;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .map_err(Error::NetBind)?;
+        let addr = listener.local_addr()
+            .map_err(Error::NetBind)?;
+        drop(listener);
+        let serve_params = super::http::ServeParams {
+            body_size_max: params.work_block_size_bytes,
+        };
+        tokio::spawn(super::http::serve(pid, blocks_pool, addr, serve_params));
+        tokio::task::yield_now().await;
+
+        let client = hyper::Client::new();
+        let request = |method: Method, path: &str, body: Body| {
+            Request::builder()
+                .method(method)
+                .uri(format!("http://{}{}", addr, path))
+                .body(body)
+                .unwrap()
+        };
+
+        let response = client.request(request(Method::POST, "/blocks", Body::from("hello, world!"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let block_id = String::from_utf8(hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap();
+
+        let response = client.request(request(Method::GET, &format!("/blocks/{}", block_id), Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(&hyper::body::to_bytes(response.into_body()).await.unwrap()[..], b"hello, world!");
+
+        let response = client.request(request(Method::GET, "/info", Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let info: serde_json::Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(info["blocks_count"], 1);
+        assert_eq!(info["data_bytes_used"], 13);
+
+        let response = client.request(request(Method::POST, "/flush", Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = client.request(request(Method::DELETE, &format!("/blocks/{}", block_id), Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = client.request(request(Method::GET, &format!("/blocks/{}", block_id), Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = client.request(request(Method::GET, "/blocks/garbage", Body::empty())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client.request(request(Method::POST, "/blocks", Body::from(vec![0; 12 * 1024]))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = client.request(request(Method::POST, "/blocks", Body::from(vec![0; 12 * 1024]))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        let response = client.request(request(Method::POST, "/blocks", Body::from(vec![0; 17 * 1024]))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        Ok::<_, Error>(())
+    }).unwrap();
+}
+
+fn start_supervisor() -> Result<(SupervisorPid, edeltraud::Edeltraud<job::Job>, BytesPool), Error> {
+    let supervisor_gen_server = SupervisorGenServer::new();
+    let supervisor_pid = supervisor_gen_server.pid();
+    tokio::spawn(supervisor_gen_server.run());
+
+    let thread_pool = edeltraud::Builder::new()
+        .build()
+        .map_err(Error::ThreadPool)?;
+    Ok((supervisor_pid, thread_pool, BytesPool::new()))
+}
+
+fn start_wheel(params: Params) -> Result<(Pid, BytesPool), Error> {
+    let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+    let pid = spawn_wheel(&mut supervisor_pid, thread_pool, blocks_pool.clone(), params);
+    Ok((pid, blocks_pool))
+}
+
+fn start_stripe(params: stripe::Params) -> Result<(stripe::Pid, BytesPool), Error> {
+    let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+    let gen_server = stripe::GenServer::new();
+    let pid = gen_server.pid();
+    supervisor_pid.spawn_link_temporary(
+        gen_server.run(supervisor_pid.clone(), thread_pool, blocks_pool.clone(), params),
+    );
+    Ok((pid, blocks_pool))
+}
+
+fn start_sharded(params: sharded::Params) -> Result<(sharded::Pid, BytesPool), Error> {
+    let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+    let gen_server = sharded::GenServer::new();
+    let pid = gen_server.pid();
+    supervisor_pid.spawn_link_temporary(
+        gen_server.run(supervisor_pid.clone(), thread_pool, blocks_pool.clone(), params),
+    );
+    Ok((pid, blocks_pool))
+}
+
+#[cfg(feature = "erasure")]
+fn start_erasure(params: erasure::Params) -> Result<(erasure::Pid, BytesPool), Error> {
+    let (mut supervisor_pid, thread_pool, blocks_pool) = start_supervisor()?;
+    let gen_server = erasure::GenServer::new();
+    let pid = gen_server.pid();
+    supervisor_pid.spawn_link_temporary(
+        gen_server.run(supervisor_pid.clone(), thread_pool, blocks_pool.clone(), params),
+    );
+    Ok((pid, blocks_pool))
+}
+
+#[cfg(feature = "erasure")]
+fn run_erasure<F, B, T>(params: erasure::Params, body: F) -> T
+where F: FnOnce(erasure::Pid, BytesPool) -> B,
+      B: Future<Output = Result<T, Error>>,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (pid, blocks_pool) = start_erasure(params)?;
+        body(pid, blocks_pool).await
+    }).unwrap()
+}
+
+// every block should be read back both directly and with iteration
+#[cfg(feature = "erasure")]
+async fn check_erasure_blocks(pid: &mut erasure::Pid, blocks: &[BlockTank], blocks_degraded: usize) -> Result<(), Error> {
+    for tank in blocks {
+        let block_bytes = pid.read_block(tank.block_id.clone()).await
+            .map_err(Error::ErasureReadBlock)?;
+        assert_eq!(block_bytes, tank.block_bytes);
+    }
+    let iter_blocks = pid.iter_blocks().await
+        .map_err(Error::IterBlocks)?;
+    check_iter_blocks(iter_blocks, blocks).await?;
+    let info = pid.info().await
+        .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+    assert_eq!(info.blocks_degraded, blocks_degraded);
+    Ok(())
+}
+
+// iteration should stream exactly `blocks` with their contents
+async fn check_iter_blocks(mut iter_blocks: IterBlocks, blocks: &[BlockTank]) -> Result<(), Error> {
+    assert_eq!(iter_blocks.blocks_total_count, blocks.len());
+    let mut actual_count = 0;
+    loop {
+        match iter_blocks.blocks_rx.next().await {
+            None =>
+                return Err(Error::IterBlocksRxDropped),
+            Some(IterBlocksItem::Block { block_id, block_bytes, }) =>
+                match blocks.iter().find(|tank| tank.block_id == block_id) {
+                    None =>
+                        return Err(Error::IterBlocksUnexpectedBlockReceived { block_id, }),
+                    Some(tank) => {
+                        assert_eq!(block_bytes, tank.block_bytes);
+                        actual_count += 1;
+                    },
+                },
+            Some(IterBlocksItem::NoMoreBlocks) =>
+                break,
+        }
+    }
+    assert_eq!(actual_count, blocks.len());
+    Ok(())
+}
+
+// reads all `blocks` concurrently a few times until reads overlap and returns interpreter stats
+async fn read_all_concurrently(params: Params, blocks: &[BlockTank]) -> Result<InterpretStats, Error> {
+    assert!(!blocks.is_empty());
+    let (mut pid, _blocks_pool) = start_wheel(params)?;
+    let mut interpret_stats = InterpretStats::default();
+    for _ in 0 .. 16 {
+        let reads = blocks.iter().map(|tank| {
+            let mut pid = pid.clone();
+            async move {
+                let block_bytes = pid.read_block(tank.block_id.clone()).await
+                    .map_err(Error::ReadBlock)?;
+                assert_eq!(block_bytes, tank.block_bytes);
+                Ok::<_, Error>(())
+            }
+        });
+        for result in futures::future::join_all(reads).await {
+            result?;
+        }
+        let info = pid.info().await
+            .map_err(|ero::NoProcError| Error::WheelGoneDuringInfo)?;
+        interpret_stats = info.interpret_stats;
+        if interpret_stats.readers_busy_max > 1 {
+            break;
+        }
+    }
+    Ok(interpret_stats)
+}
+
+fn spawn_wheel(
+    supervisor_pid: &mut SupervisorPid,
+    thread_pool: edeltraud::Edeltraud<job::Job>,
+    blocks_pool: BytesPool,
+    params: Params,
+)
+    -> Pid
+{
+    let gen_server = GenServer::new();
+    let pid = gen_server.pid();
+    supervisor_pid.spawn_link_permanent(
+        gen_server.run(supervisor_pid.clone(), thread_pool, blocks_pool, params),
+    );
+    pid
+}
+
 #[derive(Clone, Copy, Default, Debug)]
 struct Limits {
     actions: usize,
@@ -382,12 +2739,20 @@ enum Error {
     WriteBlock(super::WriteBlockError),
     DeleteBlock(super::DeleteBlockError),
     ReadBlock(super::ReadBlockError),
+    #[cfg(feature = "erasure")]
+    ErasureReadBlock(erasure::ReadBlockError),
+    #[cfg(feature = "erasure")]
+    ErasureDeleteBlock(erasure::DeleteBlockError),
+    #[cfg(feature = "erasure")]
+    WheelGoneDuringRebuild,
     ReadBlockCrcMismarch {
         block_id: block::Id,
         expected_crc: u64,
         provided_crc: u64,
     },
     IterBlocks(super::IterBlocksError),
+    ChangesSince(super::ChangesSinceError),
+    ShardedChangesSince(sharded::ChangesSinceError),
     BlocksCountMismatch {
         blocks_count_iter: usize,
         blocks_count_info: usize,
@@ -400,4 +2765,9 @@ enum Error {
     IterBlocksUnexpectedBlockReceived {
         block_id: block::Id,
     },
+    Export(archive::ExportError),
+    ReplicationBind(std::io::Error),
+    NetBind(std::io::Error),
+    NetConnect(net::client::ConnectError),
+    Import(archive::ImportError),
 }
@@ -0,0 +1,311 @@
+//! Offline conversion of wheel files written by older versions of the crate.
+//!
+//! Version 1 wheels have no change sequence in commit tags and no block ids in tombstones, so they cannot be
+//! opened in place. `upgrade_v1` copies every committed block into a fresh current version wheel file keeping
+//! block ids. Copied blocks receive change sequences in the order they are stored, v1 tombstones are dropped
+//! (a new wheel id makes replication followers start from a snapshot anyway).
+
+use std::{
+    fs,
+    io::{
+        self,
+        Read,
+        Seek,
+        Write,
+        BufReader,
+        BufWriter,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde_derive::{
+    Serialize,
+    Deserialize,
+};
+
+use bincode::Options;
+
+use crate::{
+    block,
+    storage,
+};
+
+pub const WHEEL_VERSION_V1: usize = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WheelHeaderV1 {
+    magic: u64,
+    version: usize,
+    size_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CommitTagV1 {
+    magic: u64,
+    block_id: block::Id,
+    crc: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpgradeParams<P, Q> {
+    pub source_wheel_filename: P,
+    pub target_wheel_filename: Q,
+    // should be `Params::work_block_size_bytes` the source wheel has been used with
+    pub block_size_max: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Upgraded {
+    pub blocks_count: usize,
+    pub blocks_total_size: usize,
+    pub wheel_id: u64,
+    pub wheel_size_bytes: u64,
+}
+
+#[derive(Debug)]
+pub enum UpgradeError {
+    SourceOpen {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+    TargetCreate {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+    StorageLayoutCalculate(storage::LayoutError),
+    SourceHeaderRead(io::Error),
+    SourceHeaderDeserialize(bincode::Error),
+    SourceHeaderInvalidMagic {
+        provided: u64,
+        expected: u64,
+    },
+    SourceVersionMismatch {
+        provided: usize,
+        expected: usize,
+    },
+    SourceRead(io::Error),
+    SourceSeek(io::Error),
+    SourceBlockSizeTooLarge {
+        block_id: block::Id,
+        block_size: usize,
+        block_size_max: usize,
+    },
+    SourceBlockCrcMismatch {
+        block_id: block::Id,
+        commit_tag_crc: u64,
+        block_crc: u64,
+    },
+    TargetSerialize(bincode::Error),
+    TargetWrite(io::Error),
+    TargetSetLen(io::Error),
+    TargetSync(io::Error),
+}
+
+pub fn upgrade_v1<P, Q>(params: UpgradeParams<P, Q>) -> Result<Upgraded, UpgradeError> where P: AsRef<Path>, Q: AsRef<Path> {
+    let source_file = fs::File::open(params.source_wheel_filename.as_ref())
+        .map_err(|error| UpgradeError::SourceOpen {
+            wheel_filename: params.source_wheel_filename.as_ref().to_owned(),
+            error,
+        })?;
+    let mut source = BufReader::new(source_file);
+
+    let storage_layout = storage::Layout::calculate(&mut Vec::new())
+        .map_err(UpgradeError::StorageLayoutCalculate)?;
+    let mut work_block = Vec::new();
+
+    let header_v1_size = bincode_size(&mut work_block, &WheelHeaderV1 { magic: 0, version: 0, size_bytes: 0, })?;
+    let commit_tag_v1_size = bincode_size(&mut work_block, &CommitTagV1 { magic: 0, block_id: block::Id::default(), crc: 0, })?;
+
+    work_block.resize(header_v1_size, 0);
+    source.read_exact(&mut work_block)
+        .map_err(UpgradeError::SourceHeaderRead)?;
+    let header_v1: WheelHeaderV1 = storage::bincode_options()
+        .deserialize_from(&work_block[..])
+        .map_err(UpgradeError::SourceHeaderDeserialize)?;
+    if header_v1.magic != storage::WHEEL_MAGIC {
+        return Err(UpgradeError::SourceHeaderInvalidMagic {
+            provided: header_v1.magic,
+            expected: storage::WHEEL_MAGIC,
+        });
+    }
+    if header_v1.version != WHEEL_VERSION_V1 {
+        return Err(UpgradeError::SourceVersionMismatch {
+            provided: header_v1.version,
+            expected: WHEEL_VERSION_V1,
+        });
+    }
+
+    let target_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(params.target_wheel_filename.as_ref())
+        .map_err(|error| UpgradeError::TargetCreate {
+            wheel_filename: params.target_wheel_filename.as_ref().to_owned(),
+            error,
+        })?;
+    let mut target = BufWriter::new(target_file);
+    // header is written last, when the target size is known
+    target.seek(io::SeekFrom::Start(storage_layout.wheel_header_size as u64))
+        .map_err(UpgradeError::TargetWrite)?;
+    let mut target_cursor = storage_layout.wheel_header_size as u64;
+
+    let mut upgraded = Upgraded {
+        blocks_count: 0,
+        blocks_total_size: 0,
+        wheel_id: storage::generate_wheel_id(),
+        wheel_size_bytes: 0,
+    };
+
+    // same byte by byte scan as wheel open performs, only with v1 layout
+    let mut cursor = header_v1_size as u64;
+    let mut window = vec![0; storage_layout.block_header_size];
+    let mut window_len = 0;
+    while cursor + (storage_layout.block_header_size as u64) <= header_v1.size_bytes {
+        while window_len < window.len() {
+            let bytes_read = source.read(&mut window[window_len ..])
+                .map_err(UpgradeError::SourceRead)?;
+            if bytes_read == 0 {
+                break;
+            }
+            window_len += bytes_read;
+        }
+        if window_len < window.len() {
+            break;
+        }
+
+        if let Ok(terminator_tag) = storage::bincode_options().deserialize_from::<_, storage::TerminatorTag>(&window[..]) {
+            if terminator_tag.magic == storage::TERMINATOR_TAG_MAGIC {
+                break;
+            }
+        }
+        match storage::bincode_options().deserialize_from::<_, storage::BlockHeader>(&window[..]) {
+            Ok(block_header) if block_header.magic == storage::BLOCK_MAGIC => {
+                let block_end = cursor
+                    .checked_add((storage_layout.block_header_size + commit_tag_v1_size) as u64)
+                    .and_then(|block_end| block_end.checked_add(block_header.block_size as u64));
+                if let Some(block_end) = block_end.filter(|&block_end| block_end <= header_v1.size_bytes) {
+                    // check commit tag first: block header magic could be a part of some block contents
+                    source.seek(io::SeekFrom::Start(block_end - commit_tag_v1_size as u64))
+                        .map_err(UpgradeError::SourceSeek)?;
+                    work_block.resize(commit_tag_v1_size, 0);
+                    source.read_exact(&mut work_block)
+                        .map_err(UpgradeError::SourceRead)?;
+                    let maybe_commit_tag: Option<CommitTagV1> = storage::bincode_options()
+                        .deserialize_from(&work_block[..])
+                        .ok();
+                    match maybe_commit_tag {
+                        Some(commit_tag) if commit_tag.magic == storage::COMMIT_TAG_MAGIC && commit_tag.block_id == block_header.block_id => {
+                            if block_header.block_size > params.block_size_max {
+                                return Err(UpgradeError::SourceBlockSizeTooLarge {
+                                    block_id: block_header.block_id,
+                                    block_size: block_header.block_size,
+                                    block_size_max: params.block_size_max,
+                                });
+                            }
+                            source.seek(io::SeekFrom::Start(cursor + window.len() as u64))
+                                .map_err(UpgradeError::SourceSeek)?;
+                            work_block.resize(block_header.block_size, 0);
+                            source.read_exact(&mut work_block)
+                                .map_err(UpgradeError::SourceRead)?;
+                            let crc = block::crc(&work_block);
+                            if crc != commit_tag.crc {
+                                return Err(UpgradeError::SourceBlockCrcMismatch {
+                                    block_id: block_header.block_id,
+                                    commit_tag_crc: commit_tag.crc,
+                                    block_crc: crc,
+                                });
+                            }
+                            upgraded.blocks_count += 1;
+                            upgraded.blocks_total_size += work_block.len();
+                            write_block(&mut target, &block_header, &work_block, crc, upgraded.blocks_count as u64)?;
+                            target_cursor += (storage_layout.data_size_block_min() + work_block.len()) as u64;
+
+                            source.seek(io::SeekFrom::Start(block_end))
+                                .map_err(UpgradeError::SourceSeek)?;
+                            cursor = block_end;
+                            window_len = 0;
+                            continue;
+                        },
+                        Some(..) | None =>
+                            // not a block: rewind right after the window
+                            source.seek(io::SeekFrom::Start(cursor + window.len() as u64))
+                                .map(|_| ())
+                                .map_err(UpgradeError::SourceSeek)?,
+                    }
+                }
+            },
+            Ok(..) | Err(..) =>
+                (),
+        }
+
+        window.copy_within(1 .., 0);
+        window_len -= 1;
+        cursor += 1;
+    }
+
+    storage::bincode_options()
+        .serialize_into(&mut target, &storage::TerminatorTag::default())
+        .map_err(UpgradeError::TargetSerialize)?;
+    target_cursor += storage_layout.terminator_tag_size as u64;
+
+    // v2 commit tags are larger, so a full v1 wheel grows a bit
+    upgraded.wheel_size_bytes = header_v1.size_bytes.max(target_cursor);
+    target.seek(io::SeekFrom::Start(0))
+        .map_err(UpgradeError::TargetWrite)?;
+    let wheel_header = storage::WheelHeader {
+        wheel_id: upgraded.wheel_id,
+        size_bytes: upgraded.wheel_size_bytes,
+        ..Default::default()
+    };
+    storage::bincode_options()
+        .serialize_into(&mut target, &wheel_header)
+        .map_err(UpgradeError::TargetSerialize)?;
+
+    target.flush()
+        .map_err(UpgradeError::TargetWrite)?;
+    let target_file = target.get_ref();
+    target_file.set_len(upgraded.wheel_size_bytes)
+        .map_err(UpgradeError::TargetSetLen)?;
+    target_file.sync_all()
+        .map_err(UpgradeError::TargetSync)?;
+
+    Ok(upgraded)
+}
+
+fn write_block<W>(
+    target: &mut W,
+    block_header: &storage::BlockHeader,
+    block_bytes: &[u8],
+    crc: u64,
+    change_seq: u64,
+)
+    -> Result<(), UpgradeError>
+where W: Write,
+{
+    storage::bincode_options()
+        .serialize_into(&mut *target, block_header)
+        .map_err(UpgradeError::TargetSerialize)?;
+    target.write_all(block_bytes)
+        .map_err(UpgradeError::TargetWrite)?;
+    let commit_tag = storage::CommitTag {
+        block_id: block_header.block_id.clone(),
+        crc,
+        change_seq,
+        ..Default::default()
+    };
+    storage::bincode_options()
+        .serialize_into(&mut *target, &commit_tag)
+        .map_err(UpgradeError::TargetSerialize)?;
+    Ok(())
+}
+
+fn bincode_size<T>(work_block: &mut Vec<u8>, value: &T) -> Result<usize, UpgradeError> where T: serde::Serialize {
+    work_block.clear();
+    storage::bincode_options()
+        .serialize_into(&mut *work_block, value)
+        .map_err(UpgradeError::TargetSerialize)?;
+    Ok(work_block.len())
+}
@@ -1,3 +1,7 @@
+use std::{
+    path::PathBuf,
+};
+
 use futures::{
     future,
     select,
@@ -37,10 +41,16 @@ use super::{
     Params,
     Flushed,
     Deleted,
+    Committed,
+    SecureDelete,
     IterBlocks,
     IterBlocksItem,
     InterpreterParams,
+    RamInterpreterParams,
+    MirrorInterpreterParams,
+    WheelCreateMode,
     blockwheel_context::Context,
+    interpreter::Interpreter,
 };
 
 pub mod core;
@@ -51,7 +61,7 @@ use self::core::{
 
 pub mod interpret;
 
-mod lru;
+pub(crate) mod lru;
 
 #[derive(Debug)]
 pub enum Error {
@@ -63,7 +73,6 @@ pub enum Error {
     InterpreterCrash,
     ThreadPoolGone,
     BlockPrepareWrite(interpret::BlockPrepareWriteJobError),
-    BlockProcessRead(interpret::BlockProcessReadJobError),
     BlockPrepareDelete(interpret::BlockPrepareDeleteJobError),
 }
 
@@ -74,6 +83,7 @@ pub struct State<J> where J: edeltraud::Job {
     pub thread_pool: Edeltraud<J>,
     pub blocks_pool: BytesPool,
     pub fused_request_rx: stream::Fuse<mpsc::Receiver<Request>>,
+    pub commits_txs: Vec<mpsc::UnboundedSender<Committed>>,
     pub params: Params,
 }
 
@@ -82,79 +92,35 @@ where J: edeltraud::Job + From<job::Job>,
       J::Output: From<job::JobOutput>,
       job::JobOutput: From<J::Output>,
 {
-    let performer_builder = performer::PerformerBuilderInit::new(
-        lru::Cache::new(state.params.lru_cache_size_bytes),
-        if state.params.defrag_parallel_tasks_limit == 0 {
-            None
-        } else {
-            Some(performer::DefragConfig::new(state.params.defrag_parallel_tasks_limit))
-        },
-        state.params.work_block_size_bytes,
-    )
+    let performer_builder = make_performer_builder(&state.params)
         .map_err(Error::InterpreterInit)
         .map_err(ErrorSeverity::Fatal)?;
 
     let (interpreter_pid, performer, interpret_error_rx) = match state.params.interpreter {
 
         InterpreterParams::FixedFile(ref interpreter_params) => {
-            let cloned_interpreter_params = interpreter_params.clone();
-            let open_async = tokio::task::spawn_blocking(move || {
-                interpret::fixed_file::SyncGenServer::open(
-                    interpret::fixed_file::OpenParams {
-                        wheel_filename: &cloned_interpreter_params.wheel_filename,
-                    },
-                    performer_builder,
-                )
-            });
-            let interpret::fixed_file::WheelData { sync_gen_server: interpreter_gen_server, performer, } = match open_async.await {
-                Ok(Ok(interpret::fixed_file::WheelOpenStatus::Success(wheel_data))) =>
+            let maybe_wheel_data = fixed_file_open_or_create(
+                interpreter_params.wheel_filename.clone(),
+                interpreter_params.init_wheel_size_bytes,
+                interpreter_params.create_mode,
+                interpreter_params.read_only,
+                performer_builder,
+            ).await.map_err(ErrorSeverity::Fatal)?;
+            let interpret::fixed_file::WheelData { sync_gen_server: interpreter_gen_server, performer, } = match maybe_wheel_data {
+                Some(wheel_data) =>
                     wheel_data,
-                Ok(Ok(interpret::fixed_file::WheelOpenStatus::FileNotFound { performer_builder, })) => {
-                    let cloned_interpreter_params = interpreter_params.clone();
-                    let create_async = tokio::task::spawn_blocking(move || {
-                        interpret::fixed_file::SyncGenServer::create(
-                            interpret::fixed_file::CreateParams {
-                                wheel_filename: &cloned_interpreter_params.wheel_filename,
-                                init_wheel_size_bytes: cloned_interpreter_params.init_wheel_size_bytes,
-                            },
-                            performer_builder,
-                        )
-                    });
-                    match create_async.await {
-                        Ok(Ok(data)) =>
-                            data,
-                        Ok(Err(error)) =>
-                            return Err(ErrorSeverity::Fatal(Error::InterpreterCreate(interpret::CreateError::FixedFile(error)))),
-                        Err(error) =>
-                            return Err(ErrorSeverity::Fatal(
-                                Error::InterpreterTaskJoin(
-                                    interpret::TaskJoinError::FixedFile(
-                                        interpret::fixed_file::TaskJoinError::Create(error),
-                                    ),
-                                ),
-                            )),
-                    }
-                },
-                Ok(Err(interpret::fixed_file::WheelOpenError::FileWrongType)) => {
-                    log::error!("[ {:?} ] is not a file", interpreter_params.wheel_filename);
-                    return Err(ErrorSeverity::Recoverable { state, });
-                },
-                Ok(Err(error)) =>
-                    return Err(ErrorSeverity::Fatal(Error::InterpreterOpen(interpret::OpenError::FixedFile(error)))),
-                Err(error) =>
-                    return Err(ErrorSeverity::Fatal(
-                        Error::InterpreterTaskJoin(
-                            interpret::TaskJoinError::FixedFile(
-                                interpret::fixed_file::TaskJoinError::Open(error),
-                            ),
-                        ),
-                    )),
+                None =>
+                    return Err(ErrorSeverity::Recoverable { state, }),
             };
 
             let interpreter_pid = interpreter_gen_server.pid();
             let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
             interpreter_gen_server
                 .run(
+                    interpret::fixed_file::RunParams {
+                        direct_io: interpreter_params.direct_io,
+                        punch_holes_min_bytes: interpreter_params.punch_holes_min_bytes,
+                    },
                     state.blocks_pool.clone(),
                     interpret_error_tx,
                     |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::FixedFile(error))),
@@ -166,30 +132,145 @@ where J: edeltraud::Job + From<job::Job>,
             (interpreter_pid, performer, interpret_error_rx)
         },
 
-        InterpreterParams::Ram(ref interpreter_params) => {
-            let cloned_interpreter_params = interpreter_params.clone();
-            let create_async = tokio::task::spawn_blocking(move || {
-                interpret::ram::SyncGenServer::create(
-                    interpret::ram::CreateParams {
-                        init_wheel_size_bytes: cloned_interpreter_params.init_wheel_size_bytes,
+        #[cfg(unix)]
+        InterpreterParams::Positional(ref interpreter_params) => {
+            let maybe_wheel_data = fixed_file_open_or_create(
+                interpreter_params.wheel_filename.clone(),
+                interpreter_params.init_wheel_size_bytes,
+                interpreter_params.create_mode,
+                false,
+                performer_builder,
+            ).await.map_err(ErrorSeverity::Fatal)?;
+            let interpret::fixed_file::WheelData { sync_gen_server, performer, } = match maybe_wheel_data {
+                Some(wheel_data) =>
+                    wheel_data,
+                None =>
+                    return Err(ErrorSeverity::Recoverable { state, }),
+            };
+            let interpreter_gen_server = interpret::positional::SyncGenServer::new(sync_gen_server);
+
+            let interpreter_pid = interpreter_gen_server.pid();
+            let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
+            interpreter_gen_server
+                .run(
+                    interpret::positional::RunParams {
+                        reader_threads: interpreter_params.reader_threads,
                     },
-                    performer_builder,
+                    state.blocks_pool.clone(),
+                    interpret_error_tx,
+                    |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::Positional(error))),
                 )
-            });
-            let interpret::ram::WheelData { sync_gen_server: interpreter_gen_server, performer, } = match create_async.await {
-                Ok(Ok(data)) =>
-                    data,
-                Ok(Err(error)) =>
-                    return Err(ErrorSeverity::Fatal(Error::InterpreterCreate(interpret::CreateError::Ram(error)))),
-                Err(error) =>
-                    return Err(ErrorSeverity::Fatal(
-                        Error::InterpreterTaskJoin(
-                            interpret::TaskJoinError::Ram(
-                                interpret::ram::TaskJoinError::Create(error),
-                            ),
-                        ),
-                    )),
+                .map_err(interpret::RunError::Positional)
+                .map_err(Error::InterpreterRun)
+                .map_err(ErrorSeverity::Fatal)?;
+
+            (interpreter_pid, performer, interpret_error_rx)
+        },
+
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        InterpreterParams::IoUring(ref interpreter_params) => {
+            let maybe_wheel_data = fixed_file_open_or_create(
+                interpreter_params.wheel_filename.clone(),
+                interpreter_params.init_wheel_size_bytes,
+                interpreter_params.create_mode,
+                false,
+                performer_builder,
+            ).await.map_err(ErrorSeverity::Fatal)?;
+            let interpret::fixed_file::WheelData { sync_gen_server, performer, } = match maybe_wheel_data {
+                Some(wheel_data) =>
+                    wheel_data,
+                None =>
+                    return Err(ErrorSeverity::Recoverable { state, }),
+            };
+            let interpreter_gen_server = interpret::io_uring::SyncGenServer::new(sync_gen_server);
+
+            let interpreter_pid = interpreter_gen_server.pid();
+            let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
+            interpreter_gen_server
+                .run(
+                    interpret::io_uring::RunParams {
+                        queue_depth: interpreter_params.queue_depth,
+                    },
+                    state.blocks_pool.clone(),
+                    interpret_error_tx,
+                    |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::IoUring(error))),
+                )
+                .map_err(interpret::RunError::IoUring)
+                .map_err(Error::InterpreterRun)
+                .map_err(ErrorSeverity::Fatal)?;
+
+            (interpreter_pid, performer, interpret_error_rx)
+        },
+
+        #[cfg(feature = "mmap")]
+        InterpreterParams::Mmap(ref interpreter_params) => {
+            let maybe_wheel_data = fixed_file_open_or_create(
+                interpreter_params.wheel_filename.clone(),
+                interpreter_params.init_wheel_size_bytes,
+                interpreter_params.create_mode,
+                false,
+                performer_builder,
+            ).await.map_err(ErrorSeverity::Fatal)?;
+            let interpret::fixed_file::WheelData { sync_gen_server, performer, } = match maybe_wheel_data {
+                Some(wheel_data) =>
+                    wheel_data,
+                None =>
+                    return Err(ErrorSeverity::Recoverable { state, }),
             };
+            let interpreter_gen_server = interpret::mmap::SyncGenServer::new(sync_gen_server);
+
+            let interpreter_pid = interpreter_gen_server.pid();
+            let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
+            interpreter_gen_server
+                .run(
+                    state.blocks_pool.clone(),
+                    interpret_error_tx,
+                    |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::Mmap(error))),
+                )
+                .map_err(interpret::RunError::Mmap)
+                .map_err(Error::InterpreterRun)
+                .map_err(ErrorSeverity::Fatal)?;
+
+            (interpreter_pid, performer, interpret_error_rx)
+        },
+
+        InterpreterParams::Faulty(ref interpreter_params) => {
+            let maybe_wheel_data = fixed_file_open_or_create(
+                interpreter_params.wheel_filename.clone(),
+                interpreter_params.init_wheel_size_bytes,
+                interpreter_params.create_mode,
+                false,
+                performer_builder,
+            ).await.map_err(ErrorSeverity::Fatal)?;
+            let interpret::fixed_file::WheelData { sync_gen_server, performer, } = match maybe_wheel_data {
+                Some(wheel_data) =>
+                    wheel_data,
+                None =>
+                    return Err(ErrorSeverity::Recoverable { state, }),
+            };
+            let interpreter_gen_server = interpret::faulty::SyncGenServer::new(sync_gen_server);
+
+            let interpreter_pid = interpreter_gen_server.pid();
+            let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
+            interpreter_gen_server
+                .run(
+                    interpreter_params.faults.clone(),
+                    state.blocks_pool.clone(),
+                    interpret_error_tx,
+                    |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::Faulty(error))),
+                )
+                .map_err(interpret::RunError::Faulty)
+                .map_err(Error::InterpreterRun)
+                .map_err(ErrorSeverity::Fatal)?;
+
+            (interpreter_pid, performer, interpret_error_rx)
+        },
+
+        InterpreterParams::Ram(ref interpreter_params) => {
+            let interpret::ram::WheelData { sync_gen_server: interpreter_gen_server, performer, } =
+                ram_open_or_create(interpreter_params.clone(), performer_builder)
+                .await
+                .map_err(ErrorSeverity::Fatal)?;
 
             let interpreter_pid = interpreter_gen_server.pid();
             let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
@@ -206,11 +287,288 @@ where J: edeltraud::Job + From<job::Job>,
             (interpreter_pid, performer, interpret_error_rx)
         },
 
+        InterpreterParams::Mirror(ref interpreter_params) => {
+            let interpret::mirror::WheelData { sync_gen_server: interpreter_gen_server, performer, } =
+                mirror_open_or_create(interpreter_params.clone(), state.params.clone())
+                .await
+                .map_err(ErrorSeverity::Fatal)?;
+
+            let interpreter_pid = interpreter_gen_server.pid();
+            let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
+            interpreter_gen_server
+                .run(
+                    state.blocks_pool.clone(),
+                    interpret_error_tx,
+                    |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::Mirror(error))),
+                )
+                .map_err(interpret::RunError::Mirror)
+                .map_err(Error::InterpreterRun)
+                .map_err(ErrorSeverity::Fatal)?;
+
+            (interpreter_pid, performer, interpret_error_rx)
+        },
+
+        InterpreterParams::Custom(ref interpreter) => {
+            let interpret::custom::WheelData { sync_gen_server: interpreter_gen_server, performer, } =
+                custom_open_or_create(interpreter.clone(), performer_builder)
+                .await
+                .map_err(ErrorSeverity::Fatal)?;
+
+            let interpreter_pid = interpreter_gen_server.pid();
+            let (interpret_error_tx, interpret_error_rx) = oneshot::channel();
+            interpreter_gen_server
+                .run(
+                    state.blocks_pool.clone(),
+                    interpret_error_tx,
+                    |error| ErrorSeverity::Fatal(Error::InterpreterRun(interpret::RunError::Custom(error))),
+                )
+                .map_err(interpret::RunError::Custom)
+                .map_err(Error::InterpreterRun)
+                .map_err(ErrorSeverity::Fatal)?;
+
+            (interpreter_pid, performer, interpret_error_rx)
+        },
+
     };
 
     busyloop(supervisor_pid, interpreter_pid, interpret_error_rx.fuse(), state, performer).await
 }
 
+fn make_performer_builder(params: &Params) -> Result<performer::PerformerBuilderInit<Context>, performer::BuilderError> {
+    let performer_builder = performer::PerformerBuilderInit::new(
+        lru::Cache::new(params.lru_cache_size_bytes),
+        if params.defrag_parallel_tasks_limit == 0 || is_read_only(params) {
+            None
+        } else {
+            Some(performer::DefragConfig::new(params.defrag_parallel_tasks_limit))
+        },
+        params.work_block_size_bytes,
+    )?;
+    Ok(match params.interpreter {
+        InterpreterParams::FixedFile(ref interpreter_params) if interpreter_params.direct_io =>
+            performer_builder.block_align(interpret::fixed_file::SECTOR_ALIGN),
+        // these interpreters serve reads concurrently, others run tasks one by one
+        #[cfg(unix)]
+        InterpreterParams::Positional(ref interpreter_params) =>
+            performer_builder.read_tasks_limit(interpreter_params.reader_threads),
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        InterpreterParams::IoUring(ref interpreter_params) =>
+            performer_builder.read_tasks_limit(interpreter_params.queue_depth as usize),
+        _ =>
+            performer_builder,
+    })
+}
+
+// `None` is returned for a wheel file which cannot be used right now (wrong type), worth retrying later,
+// a lock held by another wheel is fatal: it is not going to be released while that wheel is running
+async fn fixed_file_open_or_create(
+    wheel_filename: PathBuf,
+    init_wheel_size_bytes: usize,
+    create_mode: WheelCreateMode,
+    read_only: bool,
+    performer_builder: performer::PerformerBuilderInit<Context>,
+)
+    -> Result<Option<interpret::fixed_file::WheelData<Context>>, Error>
+{
+    let cloned_wheel_filename = wheel_filename.clone();
+    let open_async = tokio::task::spawn_blocking(move || {
+        interpret::fixed_file::SyncGenServer::open(
+            interpret::fixed_file::OpenParams {
+                wheel_filename: &cloned_wheel_filename,
+                lock_mode: if read_only {
+                    interpret::fixed_file::LockMode::Shared
+                } else {
+                    interpret::fixed_file::LockMode::Exclusive
+                },
+                read_only,
+            },
+            performer_builder,
+        )
+    });
+    match open_async.await {
+        Ok(Ok(interpret::fixed_file::WheelOpenStatus::Success(wheel_data))) =>
+            Ok(Some(wheel_data)),
+        Ok(Ok(interpret::fixed_file::WheelOpenStatus::FileNotFound { .. })) |
+        Ok(Ok(interpret::fixed_file::WheelOpenStatus::DeviceBlank { .. })) if read_only => {
+            log::error!("[ {:?} ] has no wheel to open in read-only mode", wheel_filename);
+            Ok(None)
+        },
+        Ok(Ok(interpret::fixed_file::WheelOpenStatus::FileNotFound { performer_builder, })) |
+        Ok(Ok(interpret::fixed_file::WheelOpenStatus::DeviceBlank { performer_builder, })) => {
+            let create_async = tokio::task::spawn_blocking(move || {
+                interpret::fixed_file::SyncGenServer::create(
+                    interpret::fixed_file::CreateParams {
+                        wheel_filename: &wheel_filename,
+                        init_wheel_size_bytes,
+                        create_mode,
+                    },
+                    performer_builder,
+                )
+            });
+            match create_async.await {
+                Ok(Ok(wheel_data)) =>
+                    Ok(Some(wheel_data)),
+                Ok(Err(error)) =>
+                    Err(Error::InterpreterCreate(interpret::CreateError::FixedFile(error))),
+                Err(error) =>
+                    Err(Error::InterpreterTaskJoin(
+                        interpret::TaskJoinError::FixedFile(
+                            interpret::fixed_file::TaskJoinError::Create(error),
+                        ),
+                    )),
+            }
+        },
+        Ok(Err(interpret::fixed_file::WheelOpenError::FileWrongType)) => {
+            log::error!("[ {:?} ] is not a file", wheel_filename);
+            Ok(None)
+        },
+        Ok(Err(error)) =>
+            Err(Error::InterpreterOpen(interpret::OpenError::FixedFile(error))),
+        Err(error) =>
+            Err(Error::InterpreterTaskJoin(
+                interpret::TaskJoinError::FixedFile(
+                    interpret::fixed_file::TaskJoinError::Open(error),
+                ),
+            )),
+    }
+}
+
+async fn ram_open_or_create(
+    interpreter_params: RamInterpreterParams,
+    performer_builder: performer::PerformerBuilderInit<Context>,
+)
+    -> Result<interpret::ram::WheelData<Context>, Error>
+{
+    let performer_builder = match interpreter_params.snapshot_filename {
+        None =>
+            performer_builder,
+        Some(ref snapshot_filename) => {
+            let cloned_snapshot_filename = snapshot_filename.clone();
+            let open_async = tokio::task::spawn_blocking(move || {
+                interpret::ram::SyncGenServer::open(
+                    interpret::ram::OpenParams {
+                        snapshot_filename: &cloned_snapshot_filename,
+                    },
+                    performer_builder,
+                )
+            });
+            match open_async.await {
+                Ok(Ok(interpret::ram::WheelOpenStatus::Success(wheel_data))) =>
+                    return Ok(wheel_data),
+                Ok(Ok(interpret::ram::WheelOpenStatus::FileNotFound { performer_builder, })) =>
+                    performer_builder,
+                Ok(Err(error)) =>
+                    return Err(Error::InterpreterOpen(interpret::OpenError::Ram(error))),
+                Err(error) =>
+                    return Err(Error::InterpreterTaskJoin(
+                        interpret::TaskJoinError::Ram(
+                            interpret::ram::TaskJoinError::Open(error),
+                        ),
+                    )),
+            }
+        },
+    };
+
+    let create_async = tokio::task::spawn_blocking(move || {
+        interpret::ram::SyncGenServer::create(
+            interpret::ram::CreateParams {
+                init_wheel_size_bytes: interpreter_params.init_wheel_size_bytes,
+                snapshot_filename: interpreter_params.snapshot_filename,
+            },
+            performer_builder,
+        )
+    });
+    match create_async.await {
+        Ok(Ok(wheel_data)) =>
+            Ok(wheel_data),
+        Ok(Err(error)) =>
+            Err(Error::InterpreterCreate(interpret::CreateError::Ram(error))),
+        Err(error) =>
+            Err(Error::InterpreterTaskJoin(
+                interpret::TaskJoinError::Ram(
+                    interpret::ram::TaskJoinError::Create(error),
+                ),
+            )),
+    }
+}
+
+async fn mirror_open_or_create(
+    interpreter_params: MirrorInterpreterParams,
+    params: Params,
+)
+    -> Result<interpret::mirror::WheelData<Context>, Error>
+{
+    let open_async = tokio::task::spawn_blocking(move || {
+        interpret::mirror::SyncGenServer::open(
+            interpret::mirror::OpenParams {
+                wheel_filenames: interpreter_params.wheel_filenames,
+                init_wheel_size_bytes: interpreter_params.init_wheel_size_bytes,
+                create_mode: interpreter_params.create_mode,
+                resync_on_open: interpreter_params.resync_on_open,
+            },
+            || make_performer_builder(&params),
+        )
+    });
+    match open_async.await {
+        Ok(Ok(wheel_data)) =>
+            Ok(wheel_data),
+        Ok(Err(error)) =>
+            Err(Error::InterpreterOpen(interpret::OpenError::Mirror(error))),
+        Err(error) =>
+            Err(Error::InterpreterTaskJoin(
+                interpret::TaskJoinError::Mirror(
+                    interpret::mirror::TaskJoinError::Open(error),
+                ),
+            )),
+    }
+}
+
+async fn custom_open_or_create(
+    interpreter: Box<dyn Interpreter>,
+    performer_builder: performer::PerformerBuilderInit<Context>,
+)
+    -> Result<interpret::custom::WheelData<Context>, Error>
+{
+    let open_async = tokio::task::spawn_blocking(move || {
+        interpret::custom::SyncGenServer::open(interpreter, performer_builder)
+    });
+    let (interpreter, performer_builder) = match open_async.await {
+        Ok(Ok(interpret::custom::WheelOpenStatus::Success(wheel_data))) =>
+            return Ok(wheel_data),
+        Ok(Ok(interpret::custom::WheelOpenStatus::WheelNotFound { interpreter, performer_builder, })) =>
+            (interpreter, performer_builder),
+        Ok(Err(error)) =>
+            return Err(Error::InterpreterOpen(interpret::OpenError::Custom(error))),
+        Err(error) =>
+            return Err(Error::InterpreterTaskJoin(
+                interpret::TaskJoinError::Custom(
+                    interpret::custom::TaskJoinError::Open(error),
+                ),
+            )),
+    };
+
+    let create_async = tokio::task::spawn_blocking(move || {
+        let init_wheel_size_bytes = interpreter.init_wheel_size_bytes();
+        interpret::custom::SyncGenServer::create(
+            interpreter,
+            interpret::custom::CreateParams { init_wheel_size_bytes, },
+            performer_builder,
+        )
+    });
+    match create_async.await {
+        Ok(Ok(wheel_data)) =>
+            Ok(wheel_data),
+        Ok(Err(error)) =>
+            Err(Error::InterpreterCreate(interpret::CreateError::Custom(error))),
+        Err(error) =>
+            Err(Error::InterpreterTaskJoin(
+                interpret::TaskJoinError::Custom(
+                    interpret::custom::TaskJoinError::Create(error),
+                ),
+            )),
+    }
+}
+
 async fn busyloop<J>(
     _supervisor_pid: SupervisorPid,
     mut interpreter_pid: interpret::Pid<Context>,
@@ -226,6 +584,7 @@ where J: edeltraud::Job + From<job::Job>,
     let mut job_tasks = FuturesUnordered::new();
     let mut iter_tasks = FuturesUnordered::new();
 
+    let read_only = is_read_only(&state.params);
     let mut op = performer.next();
     loop {
         op = match op {
@@ -242,14 +601,14 @@ where J: edeltraud::Job + From<job::Job>,
                     JobTask(E),
                 }
 
-                let mut fused_interpret_result_rx = poll.interpreter_context;
+                let mut interpreter_replies = poll.interpreter_context;
                 loop {
                     let source = match (iter_tasks.is_empty(), job_tasks.is_empty()) {
                         (true, true) =>
                             select! {
                                 result = state.fused_request_rx.next() =>
                                     Source::Pid(result),
-                                result = fused_interpret_result_rx =>
+                                result = interpreter_replies.next() =>
                                     Source::InterpreterDone(result),
                                 result = fused_interpret_error_rx =>
                                     Source::InterpreterError(result),
@@ -258,7 +617,7 @@ where J: edeltraud::Job + From<job::Job>,
                             select! {
                                 result = state.fused_request_rx.next() =>
                                     Source::Pid(result),
-                                result = fused_interpret_result_rx =>
+                                result = interpreter_replies.next() =>
                                     Source::InterpreterDone(result),
                                 result = fused_interpret_error_rx =>
                                     Source::InterpreterError(result),
@@ -273,7 +632,7 @@ where J: edeltraud::Job + From<job::Job>,
                             select! {
                                 result = state.fused_request_rx.next() =>
                                     Source::Pid(result),
-                                result = fused_interpret_result_rx =>
+                                result = interpreter_replies.next() =>
                                     Source::InterpreterDone(result),
                                 result = fused_interpret_error_rx =>
                                     Source::InterpreterError(result),
@@ -288,7 +647,7 @@ where J: edeltraud::Job + From<job::Job>,
                             select! {
                                 result = state.fused_request_rx.next() =>
                                     Source::Pid(result),
-                                result = fused_interpret_result_rx =>
+                                result = interpreter_replies.next() =>
                                     Source::InterpreterDone(result),
                                 result = fused_interpret_error_rx =>
                                     Source::InterpreterError(result),
@@ -308,14 +667,21 @@ where J: edeltraud::Job + From<job::Job>,
                     };
                     break match source {
                         Source::Pid(Some(request)) =>
-                            poll.next.incoming_request(request, fused_interpret_result_rx),
-                        Source::InterpreterDone(Ok(interpret::DoneTask { task_done, stats, })) =>
-                            poll.next.incoming_task_done_stats(task_done, stats),
+                            match accept_request(read_only, request) {
+                                Some(request) =>
+                                    poll.next.incoming_request(request, interpreter_replies),
+                                None =>
+                                    continue,
+                            },
+                        Source::InterpreterDone(Some(Ok(interpret::DoneTask { task_done, stats, }))) =>
+                            poll.next.incoming_task_done_stats(task_done, stats, interpreter_replies),
+                        Source::InterpreterDone(None) =>
+                            unreachable!(),
                         Source::Pid(None) => {
                             log::debug!("all Pid frontends have been terminated");
                             return Ok(());
                         },
-                        Source::InterpreterDone(Err(oneshot::Canceled)) => {
+                        Source::InterpreterDone(Some(Err(oneshot::Canceled))) => {
                             log::debug!("interpreter reply channel closed: shutting down");
                             return Ok(());
                         },
@@ -332,7 +698,7 @@ where J: edeltraud::Job + From<job::Job>,
                             continue;
                         },
                         Source::IterTask(IterTaskDone::ItemSent(iter_block_state)) =>
-                            poll.next.incoming_iter_blocks(iter_block_state, fused_interpret_result_rx),
+                            poll.next.incoming_iter_blocks(iter_block_state, interpreter_replies),
                         Source::IterTask(IterTaskDone::Finished) => {
                             log::debug!("iteration finished");
                             continue;
@@ -342,21 +708,25 @@ where J: edeltraud::Job + From<job::Job>,
                                 block_id,
                                 done.write_block_bytes,
                                 context,
-                                fused_interpret_result_rx,
+                                interpreter_replies,
                             ),
                         Source::JobTask(Ok(JobDone::BlockProcessRead { pending_contexts, done, })) =>
                             poll.next.process_read_block_done(
                                 done.block_id,
                                 done.block_bytes,
                                 pending_contexts,
-                                fused_interpret_result_rx,
+                                interpreter_replies,
                             ),
+                        Source::JobTask(Ok(JobDone::BlockProcessReadCorrupted { block_id, pending_contexts, error, })) => {
+                            log::error!("block {} is corrupted: {:?}", block_id, error);
+                            poll.next.process_read_block_corrupted(block_id, pending_contexts, interpreter_replies)
+                        },
                         Source::JobTask(Ok(JobDone::BlockPrepareDelete { block_id, context, done, })) =>
                             poll.next.prepared_delete_block_done(
                                 block_id,
                                 done.delete_block_bytes,
                                 context,
-                                fused_interpret_result_rx,
+                                interpreter_replies,
                             ),
                         Source::JobTask(Err(error)) =>
                             return Err(ErrorSeverity::Fatal(error)),
@@ -429,7 +799,12 @@ where J: edeltraud::Job + From<job::Job>,
                     };
                     break match source {
                         Source::Pid(Some(request)) =>
-                            poll.next.incoming_request(request),
+                            match accept_request(read_only, request) {
+                                Some(request) =>
+                                    poll.next.incoming_request(request),
+                                None =>
+                                    continue,
+                            },
                         Source::Pid(None) => {
                             log::debug!("all Pid frontends have been terminated");
                             return Ok(());
@@ -464,6 +839,10 @@ where J: edeltraud::Job + From<job::Job>,
                                 done.block_bytes,
                                 pending_contexts,
                             ),
+                        Source::JobTask(Ok(JobDone::BlockProcessReadCorrupted { block_id, pending_contexts, error, })) => {
+                            log::error!("block {} is corrupted: {:?}", block_id, error);
+                            poll.next.process_read_block_corrupted(block_id, pending_contexts)
+                        },
                         Source::JobTask(Ok(JobDone::BlockPrepareDelete { block_id, context, done, })) =>
                             poll.next.prepared_delete_block_done(
                                 block_id,
@@ -476,16 +855,20 @@ where J: edeltraud::Job + From<job::Job>,
                 }
             },
 
-            performer::Op::Query(performer::QueryOp::InterpretTask(performer::InterpretTask { offset, task, next, })) => {
+            performer::Op::Query(performer::QueryOp::InterpretTask(performer::InterpretTask { offset, task, interpreter_context, next, })) => {
                 let reply_rx = interpreter_pid.push_request(offset, task)
                     .map_err(|ero::NoProcError| ErrorSeverity::Fatal(Error::InterpreterCrash))?;
-                let performer = next.task_accepted(reply_rx.fuse());
+                let mut interpreter_replies = interpreter_context.unwrap_or_default();
+                interpreter_replies.push(reply_rx);
+                let performer = next.task_accepted(interpreter_replies);
                 performer.next()
             },
 
             performer::Op::Query(performer::QueryOp::MakeIterBlocksStream(performer::MakeIterBlocksStream {
                 blocks_total_count,
                 blocks_total_size,
+                change_seq,
+                deleted_block_ids,
                 iter_blocks_context: reply_tx,
                 next,
             })) => {
@@ -493,14 +876,29 @@ where J: edeltraud::Job + From<job::Job>,
                 let iter_blocks = IterBlocks {
                     blocks_total_count,
                     blocks_total_size,
+                    change_seq,
+                    deleted_block_ids,
                     blocks_rx: iter_blocks_rx,
                 };
-                if let Err(_send_error) = reply_tx.send(iter_blocks) {
+                if let Err(_send_error) = reply_tx.send(Ok(iter_blocks)) {
                     log::warn!("Pid is gone during IterBlocks query result send");
                 }
                 next.stream_ready(iter_blocks_tx)
             },
 
+            performer::Op::Event(performer::Event {
+                op: performer::EventOp::IterBlocks(
+                    performer::TaskDoneOp { context: reply_tx, op: performer::IterBlocksOp::ChangesTooOld { change_seq_min, }, },
+                ),
+                performer,
+            }) => {
+                let error = super::blockwheel_context::RequestIterBlocksError::ChangesTooOld { change_seq_min, };
+                if let Err(_send_error) = reply_tx.send(Err(error)) {
+                    log::warn!("Pid is gone during IterBlocks query result send");
+                }
+                performer.next()
+            },
+
             performer::Op::Event(performer::Event {
                 op: performer::EventOp::Info(
                     performer::TaskDoneOp { context: reply_tx, op: performer::InfoOp::Success { info, }, },
@@ -539,12 +937,25 @@ where J: edeltraud::Job + From<job::Job>,
                 performer.next()
             },
 
+            performer::Op::Event(performer::Event {
+                op: performer::EventOp::WriteBlock(
+                    performer::TaskDoneOp { context: reply_tx, op: performer::WriteBlockOp::BlockIdTaken, },
+                ),
+                performer,
+            }) => {
+                if let Err(_send_error) = reply_tx.send(Err(super::blockwheel_context::RequestWriteBlockError::BlockIdTaken)) {
+                    log::warn!("reply channel has been closed during WriteBlock result send");
+                }
+                performer.next()
+            },
+
             performer::Op::Event(performer::Event {
                 op: performer::EventOp::WriteBlock(
                     performer::TaskDoneOp { context: reply_tx, op: performer::WriteBlockOp::Done { block_id, }, },
                 ),
                 performer,
             }) => {
+                notify_committed(&mut state.commits_txs, Committed::WriteBlock { block_id: block_id.clone(), });
                 if let Err(_send_error) = reply_tx.send(Ok(block_id)) {
                     log::warn!("client channel was closed before a block is actually written");
                 }
@@ -563,6 +974,18 @@ where J: edeltraud::Job + From<job::Job>,
                 performer.next()
             },
 
+            performer::Op::Event(performer::Event {
+                op: performer::EventOp::ReadBlock(
+                    performer::TaskDoneOp { context: reply_tx, op: performer::ReadBlockOp::Corrupted, },
+                ),
+                performer,
+            }) => {
+                if let Err(_send_error) = reply_tx.send(Err(super::blockwheel_context::RequestReadBlockError::Corrupted)) {
+                    log::warn!("reply channel has been closed during ReadBlock result send");
+                }
+                performer.next()
+            },
+
             performer::Op::Event(performer::Event {
                 op: performer::EventOp::ReadBlock(
                     performer::TaskDoneOp { context: reply_tx, op: performer::ReadBlockOp::Done { block_bytes, }, },
@@ -589,10 +1012,11 @@ where J: edeltraud::Job + From<job::Job>,
 
             performer::Op::Event(performer::Event {
                 op: performer::EventOp::DeleteBlock(
-                    performer::TaskDoneOp { context: reply_tx, op: performer::DeleteBlockOp::Done { .. }, },
+                    performer::TaskDoneOp { context: reply_tx, op: performer::DeleteBlockOp::Done { block_id, }, },
                 ),
                 performer,
             }) => {
+                notify_committed(&mut state.commits_txs, Committed::DeleteBlock { block_id, });
                 if let Err(_send_error) = reply_tx.send(Ok(Deleted)) {
                     log::warn!("client channel was closed before a block is actually deleted");
                 }
@@ -641,6 +1065,8 @@ where J: edeltraud::Job + From<job::Job>,
                         block_id,
                         task: performer::PrepareInterpretTaskKind::WriteBlock(performer::PrepareInterpretTaskWriteBlock {
                             block_bytes,
+                            change_seq,
+                            total_chunk_size,
                             context,
                         }),
                     },
@@ -651,6 +1077,8 @@ where J: edeltraud::Job + From<job::Job>,
                     JobTask::BlockPrepareWrite {
                         block_id,
                         block_bytes,
+                        change_seq,
+                        total_chunk_size,
                         blocks_pool: state.blocks_pool.clone(),
                         context,
                     },
@@ -664,6 +1092,9 @@ where J: edeltraud::Job + From<job::Job>,
                     performer::PrepareInterpretTaskOp {
                         block_id,
                         task: performer::PrepareInterpretTaskKind::DeleteBlock(performer::PrepareInterpretTaskDeleteBlock {
+                            change_seq,
+                            secure_delete,
+                            total_chunk_size,
                             context,
                         }),
                     },
@@ -673,6 +1104,9 @@ where J: edeltraud::Job + From<job::Job>,
                 job_tasks.push(make_job_task(
                     JobTask::BlockPrepareDelete {
                         block_id,
+                        change_seq,
+                        secure_delete: secure_delete.or(state.params.secure_delete),
+                        total_chunk_size,
                         blocks_pool: state.blocks_pool.clone(),
                         context,
                     },
@@ -708,6 +1142,39 @@ where J: edeltraud::Job + From<job::Job>,
     }
 }
 
+fn is_read_only(params: &Params) -> bool {
+    match params.interpreter {
+        InterpreterParams::FixedFile(ref interpreter_params) =>
+            interpreter_params.read_only,
+        _ =>
+            false,
+    }
+}
+
+// read-only wheel rejects writes and deletes before they reach the performer
+fn accept_request(read_only: bool, request: Request) -> Option<Request> {
+    match request {
+        proto::Request::WriteBlock(proto::RequestWriteBlock { context: reply_tx, .. }) if read_only => {
+            if let Err(_send_error) = reply_tx.send(Err(super::blockwheel_context::RequestWriteBlockError::ReadOnly)) {
+                log::warn!("reply channel has been closed during WriteBlock result send");
+            }
+            None
+        },
+        proto::Request::DeleteBlock(proto::RequestDeleteBlock { context: reply_tx, .. }) if read_only => {
+            if let Err(_send_error) = reply_tx.send(Err(super::blockwheel_context::RequestDeleteBlockError::ReadOnly)) {
+                log::warn!("reply channel has been closed during DeleteBlock result send");
+            }
+            None
+        },
+        request =>
+            Some(request),
+    }
+}
+
+fn notify_committed(commits_txs: &mut Vec<mpsc::UnboundedSender<Committed>>, committed: Committed) {
+    commits_txs.retain(|commits_tx| commits_tx.unbounded_send(committed.clone()).is_ok());
+}
+
 enum IterTask {
     Item {
         block_id: block::Id,
@@ -751,6 +1218,8 @@ enum JobTask<C> where C: context::Context {
     BlockPrepareWrite {
         block_id: block::Id,
         block_bytes: Bytes,
+        change_seq: u64,
+        total_chunk_size: usize,
         blocks_pool: BytesPool,
         context: task::WriteBlockContext<C::WriteBlock>,
     },
@@ -762,6 +1231,9 @@ enum JobTask<C> where C: context::Context {
     },
     BlockPrepareDelete {
         block_id: block::Id,
+        change_seq: u64,
+        secure_delete: Option<SecureDelete>,
+        total_chunk_size: usize,
         blocks_pool: BytesPool,
         context: task::DeleteBlockContext<C::DeleteBlock>,
     },
@@ -777,6 +1249,12 @@ enum JobDone<C> where C: context::Context {
         pending_contexts: task::queue::PendingReadContextBag,
         done: interpret::BlockProcessReadJobDone,
     },
+    // damaged block is reported to its readers only, the wheel keeps serving the others
+    BlockProcessReadCorrupted {
+        block_id: block::Id,
+        pending_contexts: task::queue::PendingReadContextBag,
+        error: interpret::BlockProcessReadJobError,
+    },
     BlockPrepareDelete {
         block_id: block::Id,
         context: task::DeleteBlockContext<C::DeleteBlock>,
@@ -796,8 +1274,14 @@ where C: context::Context + Send,
 {
     match job_task {
 
-        JobTask::BlockPrepareWrite { block_id, block_bytes, blocks_pool, context, } => {
-            let job = job::Job::BlockPrepareWrite(interpret::BlockPrepareWriteJobArgs { block_id: block_id.clone(), block_bytes, blocks_pool, });
+        JobTask::BlockPrepareWrite { block_id, block_bytes, change_seq, total_chunk_size, blocks_pool, context, } => {
+            let job = job::Job::BlockPrepareWrite(interpret::BlockPrepareWriteJobArgs {
+                block_id: block_id.clone(),
+                block_bytes,
+                change_seq,
+                total_chunk_size,
+                blocks_pool,
+            });
             let job_output = thread_pool.spawn(job).await
                 .map_err(|edeltraud::SpawnError::ThreadPoolGone| Error::ThreadPoolGone)?;
             let job_output: job::JobOutput = job_output.into();
@@ -813,18 +1297,28 @@ where C: context::Context + Send,
             block_bytes,
             pending_contexts,
         } => {
+            let block_id = block_header.block_id.clone();
             let job = job::Job::BlockProcessRead(interpret::BlockProcessReadJobArgs { storage_layout, block_header, block_bytes, });
             let job_output = thread_pool.spawn(job).await
                 .map_err(|edeltraud::SpawnError::ThreadPoolGone| Error::ThreadPoolGone)?;
             let job_output: job::JobOutput = job_output.into();
             let job::BlockProcessReadDone(block_process_read_result) = job_output.into();
-            let done = block_process_read_result
-                .map_err(Error::BlockProcessRead)?;
-            Ok(JobDone::BlockProcessRead { pending_contexts, done, })
+            match block_process_read_result {
+                Ok(done) =>
+                    Ok(JobDone::BlockProcessRead { pending_contexts, done, }),
+                Err(error) =>
+                    Ok(JobDone::BlockProcessReadCorrupted { block_id, pending_contexts, error, }),
+            }
         },
 
-        JobTask::BlockPrepareDelete { block_id, blocks_pool, context, } => {
-            let job = job::Job::BlockPrepareDelete(interpret::BlockPrepareDeleteJobArgs { blocks_pool, });
+        JobTask::BlockPrepareDelete { block_id, change_seq, secure_delete, total_chunk_size, blocks_pool, context, } => {
+            let job = job::Job::BlockPrepareDelete(interpret::BlockPrepareDeleteJobArgs {
+                block_id: block_id.clone(),
+                change_seq,
+                secure_delete,
+                total_chunk_size,
+                blocks_pool,
+            });
             let job_output = thread_pool.spawn(job).await
                 .map_err(|edeltraud::SpawnError::ThreadPoolGone| Error::ThreadPoolGone)?;
             let job_output: job::JobOutput = job_output.into();
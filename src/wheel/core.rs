@@ -17,6 +17,7 @@ pub struct BlockEntry {
     pub offset: u64,
     pub header: storage::BlockHeader,
     pub environs: Environs,
+    pub change_seq: u64,
     pub tasks_head: task::queue::TasksHead,
 }
 
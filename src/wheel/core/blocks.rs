@@ -33,11 +33,17 @@ impl Index {
         self.blocks_total_size
     }
 
-    pub fn next_block_id_from(&self, offset: block::Id) -> Option<block::Id> {
-        self.index.range(offset ..).next()
+    pub fn next_block_id_changed_since(&self, offset: block::Id, change_seq: u64) -> Option<block::Id> {
+        self.index.range(offset ..)
+            .find(|kv| kv.1.change_seq > change_seq)
             .map(|kv| kv.0.clone())
     }
 
+    pub fn changed_since(&self, change_seq: u64) -> impl Iterator<Item = &BlockEntry> {
+        self.index.values()
+            .filter(move |block_entry| block_entry.change_seq > change_seq)
+    }
+
     pub fn insert(&mut self, block_id: block::Id, block_entry: BlockEntry) {
         self.blocks_total_size += block_entry.header.block_size;
         self.index.insert(block_id, block_entry);
@@ -243,6 +243,7 @@ mod tests {
                                 left: LeftEnvirons::Start,
                                 right: RightEnvirons::Space { space_key: SpaceKey { space_available: 4, serial: 1, }, },
                             },
+                            change_seq: 0,
                             tasks_head: Default::default(),
                         },
                     },
@@ -259,6 +260,7 @@ mod tests {
                                 left: LeftEnvirons::Space { space_key: SpaceKey { space_available: 4, serial: 1, }, },
                                 right: RightEnvirons::Space { space_key: SpaceKey { space_available: 60, serial: 2, }, },
                             },
+                            change_seq: 0,
                             tasks_head: Default::default(),
                         },
                     },
@@ -289,6 +291,7 @@ mod tests {
                                 left: LeftEnvirons::Space { space_key: SpaceKey { space_available: 4, serial: 1, }, },
                                 right: RightEnvirons::Space { space_key: SpaceKey { space_available: 60, serial: 2, }, },
                             },
+                            change_seq: 0,
                             tasks_head: Default::default(),
                         },
                     },
@@ -339,6 +342,7 @@ mod tests {
                                 left: LeftEnvirons::Start,
                                 right: RightEnvirons::Space { space_key: SpaceKey { space_available: 4, serial: 1, }, },
                             },
+                            change_seq: 0,
                             tasks_head: Default::default(),
                         },
                     },
@@ -355,6 +359,7 @@ mod tests {
                                 left: LeftEnvirons::Space { space_key: SpaceKey { space_available: 4, serial: 1, }, },
                                 right: RightEnvirons::Space { space_key: SpaceKey { space_available: 60, serial: 2, }, },
                             },
+                            change_seq: 0,
                             tasks_head: Default::default(),
                         },
                     },
@@ -379,6 +384,7 @@ mod tests {
                                 left: LeftEnvirons::Space { space_key: SpaceKey { space_available: 4, serial: 1, }, },
                                 right: RightEnvirons::Space { space_key: SpaceKey { space_available: 60, serial: 2, }, },
                             },
+                            change_seq: 0,
                             tasks_head: Default::default(),
                         },
                     },
@@ -424,6 +430,7 @@ mod tests {
                     left: LeftEnvirons::Start,
                     right: RightEnvirons::Space { space_key: space_key_a, },
                 },
+                change_seq: 0,
                 tasks_head: Default::default(),
             });
             blocks_index.insert(block_b_id.clone(), BlockEntry {
@@ -437,6 +444,7 @@ mod tests {
                     left: LeftEnvirons::Space { space_key: space_key_a, },
                     right: RightEnvirons::Space { space_key: space_key_b, },
                 },
+                change_seq: 0,
                 tasks_head: Default::default(),
             });
 
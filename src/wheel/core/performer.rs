@@ -11,6 +11,7 @@ use alloc_pool::bytes::{
 use crate::{
     Info,
     InterpretStats,
+    SecureDelete,
     proto,
     storage,
     context::Context,
@@ -33,12 +34,14 @@ use crate::{
 mod tests;
 
 struct Inner<C> where C: Context {
+    wheel_id: u64,
     schema: schema::Schema,
     lru_cache: lru::Cache,
     pending_write_external: HashSet<block::Id>,
     defrag: Option<Defrag<C::WriteBlock>>,
     freed_space_key: Option<SpaceKey>,
     bg_task: BackgroundTask<C::Interpreter>,
+    read_tasks_limit: usize,
     tasks_queue: task::queue::Queue<C>,
     done_task: DoneTask<C>,
     interpret_stats: InterpretStats,
@@ -60,7 +63,7 @@ enum DoneTask<C> where C: Context {
     },
     ReadBlockProcessed {
         block_id: block::Id,
-        maybe_block_bytes: Option<Bytes>,
+        read_block_op: ReadBlockOp,
         pending_contexts: task::queue::PendingReadContextBag,
     },
     DeleteBlockRegular {
@@ -90,6 +93,8 @@ pub enum QueryOp<C> where C: Context {
 pub struct MakeIterBlocksStream<C> where C: Context {
     pub blocks_total_count: usize,
     pub blocks_total_size: usize,
+    pub change_seq: u64,
+    pub deleted_block_ids: Vec<block::Id>,
     pub iter_blocks_context: C::IterBlocks,
     pub next: MakeIterBlocksStreamNext<C>,
 }
@@ -114,6 +119,7 @@ pub enum EventOp<C> where C: Context {
     WriteBlock(TaskDoneOp<C::WriteBlock, WriteBlockOp>),
     ReadBlock(TaskDoneOp<C::ReadBlock, ReadBlockOp>),
     DeleteBlock(TaskDoneOp<C::DeleteBlock, DeleteBlockOp>),
+    IterBlocks(TaskDoneOp<C::IterBlocks, IterBlocksOp>),
     IterBlocksItem(IterBlocksItemOp<C::IterBlocksStream>),
     IterBlocksFinish(IterBlocksFinishOp<C::IterBlocksStream>),
     PrepareInterpretTask(PrepareInterpretTaskOp<C>),
@@ -135,11 +141,15 @@ pub enum FlushOp {
 
 pub enum WriteBlockOp {
     NoSpaceLeft,
+    BlockIdTaken,
     Done { block_id: block::Id, },
 }
 
+#[derive(Clone)]
 pub enum ReadBlockOp {
     NotFound,
+    // stored block has not passed integrity checks
+    Corrupted,
     Done { block_bytes: Bytes, },
 }
 
@@ -148,6 +158,10 @@ pub enum DeleteBlockOp {
     Done { block_id: block::Id, },
 }
 
+pub enum IterBlocksOp {
+    ChangesTooOld { change_seq_min: u64, },
+}
+
 pub struct IterBlocksItemOp<C> {
     pub block_id: block::Id,
     pub block_bytes: Bytes,
@@ -166,10 +180,15 @@ pub enum PrepareInterpretTaskKind<C> where C: Context {
 
 pub struct PrepareInterpretTaskWriteBlock<C> {
     pub block_bytes: Bytes,
+    pub change_seq: u64,
+    pub total_chunk_size: usize,
     pub context: task::WriteBlockContext<C>,
 }
 
 pub struct PrepareInterpretTaskDeleteBlock<C> {
+    pub change_seq: u64,
+    pub secure_delete: Option<SecureDelete>,
+    pub total_chunk_size: usize,
     pub context: task::DeleteBlockContext<C>,
 }
 
@@ -189,6 +208,7 @@ pub struct IterBlocksState<C> {
 #[derive(Debug)]
 pub struct IterBlocksCursor {
     block_id: block::Id,
+    changed_since: u64,
 }
 
 pub struct IterBlocksFinishOp<C> {
@@ -198,6 +218,8 @@ pub struct IterBlocksFinishOp<C> {
 pub struct InterpretTask<C> where C: Context {
     pub offset: u64,
     pub task: task::Task<C>,
+    // context of read tasks still in progress, it should be merged with the one for this task in `task_accepted`
+    pub interpreter_context: Option<C::Interpreter>,
     pub next: InterpretTaskNext<C>,
 }
 
@@ -215,6 +237,7 @@ pub struct PollRequestNext<C> where C: Context {
 
 pub struct MakeIterBlocksStreamNext<C> where C: Context {
     inner: Inner<C>,
+    changed_since: u64,
 }
 
 pub struct DefragConfig<C> {
@@ -240,6 +263,7 @@ pub struct PerformerBuilderInit<C> where C: Context {
     lru_cache: lru::Cache,
     defrag: Option<Defrag<C::WriteBlock>>,
     storage_layout: storage::Layout,
+    read_tasks_limit: usize,
     work_block: Vec<u8>,
 }
 
@@ -264,10 +288,23 @@ impl<C> PerformerBuilderInit<C> where C: Context {
                     in_progress_tasks_limit: config.in_progress_tasks_limit,
                 }),
             storage_layout,
+            read_tasks_limit: 1,
             work_block,
         })
     }
 
+    // blocks are placed at offsets and padded to sizes which are multiples of `block_align`
+    pub fn block_align(mut self, block_align: usize) -> PerformerBuilderInit<C> {
+        self.storage_layout.block_align = block_align;
+        self
+    }
+
+    // up to `read_tasks_limit` read tasks are handed to interpreter at once, writes and deletes always run alone
+    pub fn read_tasks_limit(mut self, read_tasks_limit: usize) -> PerformerBuilderInit<C> {
+        self.read_tasks_limit = read_tasks_limit.max(1);
+        self
+    }
+
     pub fn storage_layout(&self) -> &storage::Layout {
         &self.storage_layout
     }
@@ -288,6 +325,7 @@ impl<C> PerformerBuilderInit<C> where C: Context {
                 schema_builder,
                 lru_cache: self.lru_cache,
                 defrag: self.defrag,
+                read_tasks_limit: self.read_tasks_limit,
             },
             self.work_block,
         )
@@ -298,11 +336,12 @@ pub struct PerformerBuilder<C> where C: Context {
     schema_builder: schema::Builder,
     lru_cache: lru::Cache,
     defrag: Option<Defrag<C::WriteBlock>>,
+    read_tasks_limit: usize,
 }
 
 impl<C> PerformerBuilder<C> where C: Context {
-    pub fn push_block(&mut self, offset: u64, block_header: storage::BlockHeader) {
-        let defrag_op = self.schema_builder.push_block(offset, block_header);
+    pub fn push_block(&mut self, offset: u64, block_header: storage::BlockHeader, change_seq: u64) {
+        let defrag_op = self.schema_builder.push_block(offset, block_header, change_seq);
         if let Some(Defrag { queues: defrag::Queues { tasks, .. }, .. }) = self.defrag.as_mut() {
             match defrag_op {
                 schema::DefragOp::Queue { defrag_gaps, moving_block_id, } =>
@@ -313,11 +352,15 @@ impl<C> PerformerBuilder<C> where C: Context {
         }
     }
 
+    pub fn push_tombstone(&mut self, block_id: block::Id, change_seq: u64) {
+        self.schema_builder.push_tombstone(block_id, change_seq);
+    }
+
     pub fn storage_layout(&self) -> &storage::Layout {
         self.schema_builder.storage_layout()
     }
 
-    pub fn finish(mut self, size_bytes_total: usize) -> Performer<C> {
+    pub fn finish(mut self, wheel_id: u64, size_bytes_total: usize) -> Performer<C> {
         let (defrag_op, schema) = self.schema_builder.finish(size_bytes_total);
         if let Some(Defrag { queues: defrag::Queues { tasks, .. }, .. }) = self.defrag.as_mut() {
             match defrag_op {
@@ -330,9 +373,11 @@ impl<C> PerformerBuilder<C> where C: Context {
 
         Performer {
             inner: Inner::new(
+                wheel_id,
                 schema,
                 self.lru_cache,
                 self.defrag,
+                self.read_tasks_limit,
             ),
         }
     }
@@ -356,13 +401,21 @@ impl<C> PollRequestAndInterpreterNext<C> where C: Context {
     }
 
     #[cfg(test)]
-    pub fn incoming_task_done(self, task_done: task::Done<C>) -> Op<C> {
-        self.inner.incoming_interpreter(task_done)
+    pub fn incoming_task_done(self, task_done: task::Done<C>, interpreter_context: C::Interpreter) -> Op<C> {
+        self.inner.incoming_interpreter(task_done, interpreter_context)
     }
 
-    pub fn incoming_task_done_stats(mut self, task_done: task::Done<C>, stats: InterpretStats) -> Op<C> {
+    // `interpreter_context` is the one from `PollRequestAndInterpreter` without the task just done
+    pub fn incoming_task_done_stats(
+        mut self,
+        task_done: task::Done<C>,
+        stats: InterpretStats,
+        interpreter_context: C::Interpreter,
+    )
+        -> Op<C>
+    {
         self.inner.interpret_stats = stats;
-        self.inner.incoming_interpreter(task_done)
+        self.inner.incoming_interpreter(task_done, interpreter_context)
     }
 
     pub fn prepared_write_block_done(
@@ -401,7 +454,19 @@ impl<C> PollRequestAndInterpreterNext<C> where C: Context {
         -> Op<C>
     {
         self.inner.rollback_bg_task_state(interpreter_context);
-        self.inner.process_read_block_done(block_id, block_bytes, pending_contexts)
+        self.inner.process_read_block_done(block_id, ReadBlockOp::Done { block_bytes, }, pending_contexts)
+    }
+
+    pub fn process_read_block_corrupted(
+        mut self,
+        block_id: block::Id,
+        pending_contexts: task::queue::PendingReadContextBag,
+        interpreter_context: C::Interpreter,
+    )
+        -> Op<C>
+    {
+        self.inner.rollback_bg_task_state(interpreter_context);
+        self.inner.process_read_block_done(block_id, ReadBlockOp::Corrupted, pending_contexts)
     }
 
     pub fn incoming_iter_blocks(
@@ -413,7 +478,7 @@ impl<C> PollRequestAndInterpreterNext<C> where C: Context {
     {
         self.inner.rollback_bg_task_state(interpreter_context);
         self.inner.iter_blocks_stream_next(
-            iter_blocks_state.iter_blocks_cursor.block_id,
+            iter_blocks_state.iter_blocks_cursor,
             iter_blocks_state.iter_blocks_stream_context,
         )
     }
@@ -426,7 +491,7 @@ impl<C> PollRequestNext<C> where C: Context {
 
     pub fn incoming_iter_blocks(self, iter_blocks_state: IterBlocksState<C::IterBlocksStream>) -> Op<C> {
         self.inner.iter_blocks_stream_next(
-            iter_blocks_state.iter_blocks_cursor.block_id,
+            iter_blocks_state.iter_blocks_cursor,
             iter_blocks_state.iter_blocks_stream_context,
         )
     }
@@ -461,15 +526,25 @@ impl<C> PollRequestNext<C> where C: Context {
     )
         -> Op<C>
     {
-        self.inner.process_read_block_done(block_id, block_bytes, pending_contexts)
+        self.inner.process_read_block_done(block_id, ReadBlockOp::Done { block_bytes, }, pending_contexts)
+    }
+
+    pub fn process_read_block_corrupted(
+        self,
+        block_id: block::Id,
+        pending_contexts: task::queue::PendingReadContextBag,
+    )
+        -> Op<C>
+    {
+        self.inner.process_read_block_done(block_id, ReadBlockOp::Corrupted, pending_contexts)
     }
 }
 
 impl<C> InterpretTaskNext<C> where C: Context {
     pub fn task_accepted(mut self, interpreter_context: C::Interpreter) -> Performer<C> {
         self.inner.bg_task.state = match self.inner.bg_task.state {
-            BackgroundTaskState::Await { block_id, } =>
-                BackgroundTaskState::InProgress { block_id, interpreter_context, },
+            BackgroundTaskState::Await =>
+                BackgroundTaskState::InProgress { interpreter_context, },
             BackgroundTaskState::Idle | BackgroundTaskState::InProgress { .. } =>
                 unreachable!(),
         };
@@ -479,36 +554,40 @@ impl<C> InterpretTaskNext<C> where C: Context {
 
 impl<C> MakeIterBlocksStreamNext<C> where C: Context {
     pub fn stream_ready(self, iter_blocks_stream_context: C::IterBlocksStream) -> Op<C> {
-        self.inner.iter_blocks_stream_ready(iter_blocks_stream_context)
+        self.inner.iter_blocks_stream_ready(self.changed_since, iter_blocks_stream_context)
     }
 }
 
 
 struct BackgroundTask<C> {
     current_offset: u64,
+    // tasks handed to interpreter and not done yet
+    tasks_count: usize,
+    // several tasks are in progress only when all of them are reads
+    reads_only: bool,
     state: BackgroundTaskState<C>,
 }
 
 enum BackgroundTaskState<C> {
     Idle,
     InProgress {
-        block_id: block::Id,
         interpreter_context: C,
     },
-    Await {
-        block_id: block::Id,
-    }
+    Await,
 }
 
 impl<C> Inner<C> where C: Context {
     fn new(
+        wheel_id: u64,
         schema: schema::Schema,
         lru_cache: lru::Cache,
         defrag: Option<Defrag<C::WriteBlock>>,
+        read_tasks_limit: usize,
     )
         -> Inner<C>
     {
         Inner {
+            wheel_id,
             schema,
             lru_cache,
             pending_write_external: HashSet::new(),
@@ -517,8 +596,11 @@ impl<C> Inner<C> where C: Context {
             freed_space_key: None,
             bg_task: BackgroundTask {
                 current_offset: 0,
+                tasks_count: 0,
+                reads_only: false,
                 state: BackgroundTaskState::Idle,
             },
+            read_tasks_limit,
             done_task: DoneTask::None,
             interpret_stats: InterpretStats::default(),
         }
@@ -526,13 +608,34 @@ impl<C> Inner<C> where C: Context {
 
     fn rollback_bg_task_state(&mut self, interpreter_context: C::Interpreter) {
         self.bg_task.state = match mem::replace(&mut self.bg_task.state, BackgroundTaskState::Idle) {
-            BackgroundTaskState::Await { block_id, } =>
-                BackgroundTaskState::InProgress { block_id, interpreter_context, },
+            BackgroundTaskState::Await =>
+                BackgroundTaskState::InProgress { interpreter_context, },
             BackgroundTaskState::Idle | BackgroundTaskState::InProgress { .. } =>
                 unreachable!(),
         };
     }
 
+    fn finish_bg_task(&mut self, current_offset: u64, interpreter_context: C::Interpreter) {
+        assert!(self.bg_task.tasks_count > 0);
+        self.bg_task.current_offset = current_offset;
+        self.bg_task.tasks_count -= 1;
+        self.bg_task.state = if self.bg_task.tasks_count == 0 {
+            BackgroundTaskState::Idle
+        } else {
+            BackgroundTaskState::InProgress { interpreter_context, }
+        };
+    }
+
+    fn poll_request_and_interpreter(mut self, interpreter_context: C::Interpreter) -> Op<C> {
+        self.bg_task.state = BackgroundTaskState::Await;
+        Op::Query(QueryOp::PollRequestAndInterpreter(PollRequestAndInterpreter {
+            interpreter_context,
+            next: PollRequestAndInterpreterNext {
+                inner: self,
+            },
+        }))
+    }
+
     fn incoming_poke(mut self) -> Op<C> {
         match mem::replace(&mut self.done_task, DoneTask::None) {
 
@@ -576,9 +679,19 @@ impl<C> Inner<C> where C: Context {
 
                             task::ReadBlockContext::Defrag(task::ReadBlockDefragContext { defrag_gaps, }) =>
                                 if defrag_gaps.is_still_relevant(&block_header.block_id, self.schema.block_get()) {
+                                    // block is moved, not deleted: tombstone keeps its own change sequence
+                                    let change_seq = self.schema.block_get()
+                                        .by_id(&block_header.block_id)
+                                        .unwrap()
+                                        .change_seq;
+                                    let total_chunk_size = self.schema.storage_layout()
+                                        .block_chunk_size(block_header.block_size);
                                     Some(EventOp::PrepareInterpretTask(PrepareInterpretTaskOp {
                                         block_id: block_header.block_id.clone(),
                                         task: PrepareInterpretTaskKind::DeleteBlock(PrepareInterpretTaskDeleteBlock {
+                                            change_seq,
+                                            secure_delete: None,
+                                            total_chunk_size,
                                             context: task::DeleteBlockContext::Defrag {
                                                 defrag_gaps,
                                                 block_bytes: block_bytes.clone(),
@@ -611,29 +724,29 @@ impl<C> Inner<C> where C: Context {
                 }
             },
 
-            DoneTask::ReadBlockProcessed { block_id, maybe_block_bytes, mut pending_contexts, } => {
+            DoneTask::ReadBlockProcessed { block_id, read_block_op, mut pending_contexts, } => {
                 if let Some(read_block) = self.tasks_queue.pop_pending_read_context(&mut pending_contexts) {
-                    let maybe_op = match (maybe_block_bytes.clone(), read_block.context) {
-                        (None, task::ReadBlockContext::Process(task::ReadBlockProcessContext::External(context))) =>
-                            Some(EventOp::ReadBlock(TaskDoneOp { context, op: ReadBlockOp::NotFound, })),
-
-                        (Some(block_bytes), task::ReadBlockContext::Process(task::ReadBlockProcessContext::External(context))) =>
-                            Some(EventOp::ReadBlock(TaskDoneOp { context, op: ReadBlockOp::Done { block_bytes, }, }, )),
+                    let maybe_op = match (read_block_op.clone(), read_block.context) {
+                        (op, task::ReadBlockContext::Process(task::ReadBlockProcessContext::External(context))) =>
+                            Some(EventOp::ReadBlock(TaskDoneOp { context, op, })),
 
                         (
-                            None,
+                            ReadBlockOp::NotFound | ReadBlockOp::Corrupted,
                             task::ReadBlockContext::Process(
-                                task::ReadBlockProcessContext::IterBlocks { iter_blocks_stream_context, next_block_id, },
+                                task::ReadBlockProcessContext::IterBlocks { iter_blocks_stream_context, next_block_id, changed_since, },
                             ),
                         ) => {
                             // skip this block, proceed with the next one
-                            self.iter_blocks_stream_next_op(next_block_id, iter_blocks_stream_context)
+                            self.iter_blocks_stream_next_op(
+                                IterBlocksCursor { block_id: next_block_id, changed_since, },
+                                iter_blocks_stream_context,
+                            )
                         },
 
                         (
-                            Some(block_bytes),
+                            ReadBlockOp::Done { block_bytes, },
                             task::ReadBlockContext::Process(
-                                task::ReadBlockProcessContext::IterBlocks { iter_blocks_stream_context, next_block_id, },
+                                task::ReadBlockProcessContext::IterBlocks { iter_blocks_stream_context, next_block_id, changed_since, },
                             ),
                         ) =>
                             Some(EventOp::IterBlocksItem(IterBlocksItemOp {
@@ -643,6 +756,7 @@ impl<C> Inner<C> where C: Context {
                                     iter_blocks_stream_context,
                                     iter_blocks_cursor: IterBlocksCursor {
                                         block_id: next_block_id,
+                                        changed_since,
                                     },
                                 },
                             })),
@@ -653,7 +767,7 @@ impl<C> Inner<C> where C: Context {
 
                     self.done_task = DoneTask::ReadBlockProcessed {
                         block_id,
-                        maybe_block_bytes,
+                        read_block_op,
                         pending_contexts,
                     };
 
@@ -697,9 +811,13 @@ impl<C> Inner<C> where C: Context {
                         task::ReadBlockContext::Process(task::ReadBlockProcessContext::IterBlocks {
                             iter_blocks_stream_context,
                             next_block_id,
+                            changed_since,
                         }) => {
                             // skip this block, proceed with the next one
-                            return self.iter_blocks_stream_next(next_block_id, iter_blocks_stream_context);
+                            return self.iter_blocks_stream_next(
+                                IterBlocksCursor { block_id: next_block_id, changed_since, },
+                                iter_blocks_stream_context,
+                            );
                         },
                         task::ReadBlockContext::Defrag { .. } => {
                             // cancel defrag read task
@@ -737,7 +855,8 @@ impl<C> Inner<C> where C: Context {
             if let Some(defrag) = self.defrag.as_mut() {
                 if let Some(request_write_block) = defrag.queues.pending.pop_at_most(space_key.space_available()) {
 
-                    match self.schema.process_write_block_request(&request_write_block.block_bytes, Some(defrag.queues.pending.pending_bytes())) {
+                    let defrag_pending_bytes = Some(defrag.queues.pending.pending_bytes());
+                    match schema_write_block_request(&mut self.schema, &request_write_block, defrag_pending_bytes) {
                         schema::WriteBlockOp::Perform(write_block_perform) => {
                             self.freed_space_key = write_block_perform.right_space_key;
 
@@ -748,12 +867,16 @@ impl<C> Inner<C> where C: Context {
                                     (),
                             }
                             self.pending_write_external.insert(write_block_perform.task_op.block_id.clone());
+                            let total_chunk_size = self.schema.storage_layout()
+                                .block_chunk_size(request_write_block.block_bytes.len());
 
                             return Op::Event(Event {
                                 op: EventOp::PrepareInterpretTask(PrepareInterpretTaskOp {
                                     block_id: write_block_perform.task_op.block_id,
                                     task: PrepareInterpretTaskKind::WriteBlock(PrepareInterpretTaskWriteBlock {
                                         block_bytes: request_write_block.block_bytes,
+                                        change_seq: write_block_perform.task_op.change_seq,
+                                        total_chunk_size,
                                         context: task::WriteBlockContext::External(
                                             request_write_block.context,
                                         ),
@@ -767,6 +890,16 @@ impl<C> Inner<C> where C: Context {
                         },
                         schema::WriteBlockOp::ReplyNoSpaceLeft =>
                             unreachable!(),
+                        schema::WriteBlockOp::ReplyBlockIdTaken => {
+                            self.freed_space_key = Some(space_key);
+                            return Op::Event(Event {
+                                op: EventOp::WriteBlock(TaskDoneOp {
+                                    context: request_write_block.context,
+                                    op: WriteBlockOp::BlockIdTaken,
+                                }),
+                                performer: Performer { inner: self, },
+                            });
+                        },
                     }
 
                 }
@@ -818,17 +951,14 @@ impl<C> Inner<C> where C: Context {
 
         match mem::replace(&mut self.bg_task.state, BackgroundTaskState::Idle) {
             BackgroundTaskState::Idle =>
-                self.maybe_run_background_task(),
-            BackgroundTaskState::InProgress { block_id, interpreter_context, } => {
-                self.bg_task.state = BackgroundTaskState::Await { block_id, };
-                Op::Query(QueryOp::PollRequestAndInterpreter(PollRequestAndInterpreter {
-                    interpreter_context,
-                    next: PollRequestAndInterpreterNext {
-                        inner: self,
-                    },
-                }))
-            },
-            BackgroundTaskState::Await { .. } =>
+                self.maybe_run_background_task(None),
+            BackgroundTaskState::InProgress { interpreter_context, } =>
+                if self.bg_task.reads_only && self.bg_task.tasks_count < self.read_tasks_limit {
+                    self.maybe_run_background_task(Some(interpreter_context))
+                } else {
+                    self.poll_request_and_interpreter(interpreter_context)
+                },
+            BackgroundTaskState::Await =>
                 unreachable!(),
         }
     }
@@ -852,7 +982,8 @@ impl<C> Inner<C> where C: Context {
 
     fn incoming_request_info(self, proto::RequestInfo { context, }: proto::RequestInfo<C::Info>) -> Op<C> {
         let mut info = self.schema.info();
-        info.interpret_stats = self.interpret_stats;
+        info.wheel_id = self.wheel_id;
+        info.interpret_stats = self.interpret_stats.clone();
         if let Some(defrag) = self.defrag.as_ref() {
             info.defrag_write_pending_bytes = defrag.queues.pending.pending_bytes();
             assert!(
@@ -879,7 +1010,7 @@ impl<C> Inner<C> where C: Context {
         let defrag_pending_bytes = self.defrag
             .as_ref()
             .map(|defrag| defrag.queues.pending.pending_bytes());
-        match self.schema.process_write_block_request(&request_write_block.block_bytes, defrag_pending_bytes) {
+        match schema_write_block_request(&mut self.schema, &request_write_block, defrag_pending_bytes) {
 
             schema::WriteBlockOp::Perform(schema::WriteBlockPerform { defrag_op, task_op, .. }) => {
                 if let Some(Defrag { queues: defrag::Queues { tasks, .. }, .. }) = self.defrag.as_mut() {
@@ -891,12 +1022,16 @@ impl<C> Inner<C> where C: Context {
                     }
                 }
                 self.pending_write_external.insert(task_op.block_id.clone());
+                let total_chunk_size = self.schema.storage_layout()
+                    .block_chunk_size(request_write_block.block_bytes.len());
 
                 Op::Event(Event {
                     op: EventOp::PrepareInterpretTask(PrepareInterpretTaskOp {
                         block_id: task_op.block_id,
                         task: PrepareInterpretTaskKind::WriteBlock(PrepareInterpretTaskWriteBlock {
                             block_bytes: request_write_block.block_bytes,
+                            change_seq: task_op.change_seq,
+                            total_chunk_size,
                             context: task::WriteBlockContext::External(
                                 request_write_block.context,
                             ),
@@ -935,6 +1070,15 @@ impl<C> Inner<C> where C: Context {
                     performer: Performer { inner: self, },
                 }),
 
+            schema::WriteBlockOp::ReplyBlockIdTaken =>
+                Op::Event(Event {
+                    op: EventOp::WriteBlock(TaskDoneOp {
+                        context: request_write_block.context,
+                        op: WriteBlockOp::BlockIdTaken,
+                    }),
+                    performer: Performer { inner: self, },
+                }),
+
         }
     }
 
@@ -989,18 +1133,28 @@ impl<C> Inner<C> where C: Context {
     fn incoming_request_delete_block(mut self, request_delete_block: proto::RequestDeleteBlock<C::DeleteBlock>) -> Op<C> {
         match self.schema.process_delete_block_request(&request_delete_block.block_id) {
 
-            schema::DeleteBlockOp::Perform(schema::DeleteBlockPerform) =>
+            schema::DeleteBlockOp::Perform(schema::DeleteBlockPerform { change_seq, }) => {
+                let storage_layout = self.schema.storage_layout().clone();
+                let block_size = self.schema.block_get()
+                    .by_id(&request_delete_block.block_id)
+                    .unwrap()
+                    .header
+                    .block_size;
                 Op::Event(Event {
                     op: EventOp::PrepareInterpretTask(PrepareInterpretTaskOp {
                         block_id: request_delete_block.block_id,
                         task: PrepareInterpretTaskKind::DeleteBlock(PrepareInterpretTaskDeleteBlock {
+                            change_seq,
+                            secure_delete: request_delete_block.secure_delete,
+                            total_chunk_size: storage_layout.block_chunk_size(block_size),
                             context: task::DeleteBlockContext::External(
                                 request_delete_block.context,
                             ),
                         }),
                     }),
                     performer: Performer { inner: self, },
-                }),
+                })
+            },
 
             schema::DeleteBlockOp::NotFound =>
                 Op::Event(Event {
@@ -1015,13 +1169,34 @@ impl<C> Inner<C> where C: Context {
     }
 
     fn incoming_request_iter_blocks(self, request_iter_blocks: proto::RequestIterBlocks<C::IterBlocks>) -> Op<C> {
-        let info = self.schema.info();
+        if let Some(changed_since) = request_iter_blocks.changed_since {
+            let change_seq_min = self.schema.changes_horizon();
+            if changed_since < change_seq_min {
+                return Op::Event(Event {
+                    op: EventOp::IterBlocks(TaskDoneOp {
+                        context: request_iter_blocks.context,
+                        op: IterBlocksOp::ChangesTooOld { change_seq_min, },
+                    }),
+                    performer: Performer { inner: self, },
+                });
+            }
+        }
+        let changes = self.schema.changes_since(request_iter_blocks.changed_since.unwrap_or(0));
+        let deleted_block_ids = match request_iter_blocks.changed_since {
+            None =>
+                Vec::new(),
+            Some(..) =>
+                changes.deleted_block_ids,
+        };
         Op::Query(QueryOp::MakeIterBlocksStream(MakeIterBlocksStream {
-            blocks_total_count: info.blocks_count,
-            blocks_total_size: info.data_bytes_used,
+            blocks_total_count: changes.blocks_count,
+            blocks_total_size: changes.blocks_total_size,
+            change_seq: changes.change_seq,
+            deleted_block_ids,
             iter_blocks_context: request_iter_blocks.context,
             next: MakeIterBlocksStreamNext {
                 inner: self,
+                changed_since: request_iter_blocks.changed_since.unwrap_or(0),
             },
         }))
     }
@@ -1059,6 +1234,7 @@ impl<C> Inner<C> where C: Context {
     )
         -> Op<C>
     {
+        let storage_layout = self.schema.storage_layout().clone();
         let mut block_get = self.schema.block_get();
         match (block_get.by_id(&block_id), context) {
             (None, task::DeleteBlockContext::Defrag { .. }) => {
@@ -1086,12 +1262,14 @@ impl<C> Inner<C> where C: Context {
                     }
                 }
 
+                let total_chunk_size = storage_layout.block_chunk_size(block_entry.header.block_size);
                 let mut lens = self.tasks_queue.focus_block_id(block_id.clone());
                 lens.push_task(
                     task::Task {
                         block_id,
                         kind: task::TaskKind::DeleteBlock(task::DeleteBlock {
                             delete_block_bytes: delete_block_bytes.freeze(),
+                            total_chunk_size,
                             commit: task::Commit::None,
                             context,
                         }),
@@ -1107,30 +1285,30 @@ impl<C> Inner<C> where C: Context {
     fn process_read_block_done(
         mut self,
         block_id: block::Id,
-        block_bytes: Bytes,
+        read_block_op: ReadBlockOp,
         pending_contexts: task::queue::PendingReadContextBag,
     )
         -> Op<C>
     {
-        match self.schema.process_read_block_task_done(&block_id) {
+        let read_block_op = match self.schema.process_read_block_task_done(&block_id) {
             schema::ReadBlockTaskDoneOp::NotFound =>
-                self.done_task = DoneTask::ReadBlockProcessed { block_id, maybe_block_bytes: None, pending_contexts, },
+                ReadBlockOp::NotFound,
             schema::ReadBlockTaskDoneOp::Perform(schema::ReadBlockTaskDonePerform) => {
-                self.lru_cache.insert(block_id.clone(), block_bytes.clone());
-                self.done_task = DoneTask::ReadBlockProcessed {
-                    block_id, maybe_block_bytes: Some(block_bytes),
-                    pending_contexts,
-                };
+                if let ReadBlockOp::Done { ref block_bytes, } = read_block_op {
+                    self.lru_cache.insert(block_id.clone(), block_bytes.clone());
+                }
+                read_block_op
             },
-        }
+        };
+        self.done_task = DoneTask::ReadBlockProcessed { block_id, read_block_op, pending_contexts, };
         Op::Idle(Performer { inner: self, })
     }
 
-    fn incoming_interpreter(mut self, incoming: task::Done<C>) -> Op<C> {
+    fn incoming_interpreter(mut self, incoming: task::Done<C>, interpreter_context: C::Interpreter) -> Op<C> {
         match incoming {
 
             task::Done { current_offset, task: task::TaskDone { block_id, kind: task::TaskDoneKind::WriteBlock(write_block), }, } => {
-                self.bg_task = BackgroundTask { current_offset, state: BackgroundTaskState::Idle, };
+                self.finish_bg_task(current_offset, interpreter_context);
                 let mut lens = self.tasks_queue.focus_block_id(block_id.clone());
                 lens.finish(self.schema.block_get());
                 lens.enqueue(self.schema.block_get());
@@ -1156,7 +1334,7 @@ impl<C> Inner<C> where C: Context {
             },
 
             task::Done { current_offset, task: task::TaskDone { block_id, kind: task::TaskDoneKind::ReadBlock(read_block), }, } => {
-                self.bg_task = BackgroundTask { current_offset, state: BackgroundTaskState::Idle, };
+                self.finish_bg_task(current_offset, interpreter_context);
                 let mut block_get = self.schema.block_get();
                 let block_header = block_get.by_id(&block_id)
                     .unwrap()
@@ -1174,7 +1352,7 @@ impl<C> Inner<C> where C: Context {
             },
 
             task::Done { current_offset, task: task::TaskDone { block_id, kind: task::TaskDoneKind::DeleteBlock(delete_block), }, } => {
-                self.bg_task = BackgroundTask { current_offset, state: BackgroundTaskState::Idle, };
+                self.finish_bg_task(current_offset, interpreter_context);
                 self.tasks_queue.focus_block_id(block_id.clone())
                     .finish(self.schema.block_get());
                 match delete_block.context {
@@ -1249,20 +1427,30 @@ impl<C> Inner<C> where C: Context {
         }
     }
 
-    fn iter_blocks_stream_ready(self, iter_blocks_stream_context: C::IterBlocksStream) -> Op<C> {
-        self.iter_blocks_stream_next(block::Id::init(), iter_blocks_stream_context)
+    fn iter_blocks_stream_ready(self, changed_since: u64, iter_blocks_stream_context: C::IterBlocksStream) -> Op<C> {
+        let iter_blocks_cursor = IterBlocksCursor {
+            block_id: block::Id::init(),
+            changed_since,
+        };
+        self.iter_blocks_stream_next(iter_blocks_cursor, iter_blocks_stream_context)
     }
 
-    fn iter_blocks_stream_next(mut self, block_id_from: block::Id, iter_blocks_stream_context: C::IterBlocksStream) -> Op<C> {
-        if let Some(op) = self.iter_blocks_stream_next_op(block_id_from, iter_blocks_stream_context) {
+    fn iter_blocks_stream_next(mut self, iter_blocks_cursor: IterBlocksCursor, iter_blocks_stream_context: C::IterBlocksStream) -> Op<C> {
+        if let Some(op) = self.iter_blocks_stream_next_op(iter_blocks_cursor, iter_blocks_stream_context) {
             Op::Event(Event { op, performer: Performer { inner: self, }, })
         } else {
             Op::Idle(Performer { inner: self, })
         }
     }
 
-    fn iter_blocks_stream_next_op(&mut self, block_id_from: block::Id, iter_blocks_stream_context: C::IterBlocksStream) -> Option<EventOp<C>> {
-        match self.schema.next_block_id_from(block_id_from) {
+    fn iter_blocks_stream_next_op(
+        &mut self,
+        IterBlocksCursor { block_id: block_id_from, changed_since, }: IterBlocksCursor,
+        iter_blocks_stream_context: C::IterBlocksStream,
+    )
+        -> Option<EventOp<C>>
+    {
+        match self.schema.next_block_id_changed_since(block_id_from, changed_since) {
             None =>
                 Some(EventOp::IterBlocksFinish(IterBlocksFinishOp {
                     iter_blocks_stream_context,
@@ -1280,6 +1468,7 @@ impl<C> Inner<C> where C: Context {
                                     iter_blocks_stream_context,
                                     iter_blocks_cursor: IterBlocksCursor {
                                         block_id: block_id.next(),
+                                        changed_since,
                                     },
                                 },
                             }))
@@ -1293,6 +1482,7 @@ impl<C> Inner<C> where C: Context {
                                         context: task::ReadBlockContext::Process(task::ReadBlockProcessContext::IterBlocks {
                                             iter_blocks_stream_context,
                                             next_block_id: block_id.next(),
+                                            changed_since,
                                         }),
                                     }),
                                 },
@@ -1309,7 +1499,7 @@ impl<C> Inner<C> where C: Context {
         }
     }
 
-    fn maybe_run_background_task(mut self) -> Op<C> {
+    fn maybe_run_background_task(mut self, interpreter_context: Option<C::Interpreter>) -> Op<C> {
         loop {
             if let Some((offset, mut lens)) = self.tasks_queue.next_trigger(self.bg_task.current_offset, self.schema.block_get()) {
                 let mut task_kind = match lens.pop_task(self.schema.block_get()) {
@@ -1336,6 +1526,23 @@ impl<C> Inner<C> where C: Context {
                         },
                 }
 
+                let is_read = match &task_kind {
+                    task::TaskKind::ReadBlock(..) =>
+                        true,
+                    task::TaskKind::WriteBlock(..) | task::TaskKind::DeleteBlock(..) =>
+                        false,
+                };
+                if !is_read {
+                    if let Some(interpreter_context) = interpreter_context {
+                        // writes and deletes wait until reads in progress are done
+                        let block_id = lens.block_id().clone();
+                        lens.push_task(task::Task { block_id, kind: task_kind, }, self.schema.block_get());
+                        lens.finish(self.schema.block_get());
+                        lens.enqueue(self.schema.block_get());
+                        return self.poll_request_and_interpreter(interpreter_context);
+                    }
+                }
+
                 match &mut task_kind {
                     task::TaskKind::WriteBlock(task::WriteBlock { commit, .. }) |
                     task::TaskKind::DeleteBlock(task::DeleteBlock { commit, .. }) =>
@@ -1346,20 +1553,24 @@ impl<C> Inner<C> where C: Context {
                         (),
                 };
 
-                self.bg_task.state = BackgroundTaskState::Await {
-                    block_id: lens.block_id().clone(),
-                };
+                let block_id = lens.block_id().clone();
+                self.bg_task.tasks_count += 1;
+                self.bg_task.reads_only = is_read;
+                self.bg_task.state = BackgroundTaskState::Await;
 
                 return Op::Query(QueryOp::InterpretTask(InterpretTask {
                     offset,
                     task: task::Task {
-                        block_id: lens.block_id().clone(),
+                        block_id,
                         kind: task_kind,
                     },
+                    interpreter_context,
                     next: InterpretTaskNext {
                         inner: self,
                     },
                 }));
+            } else if let Some(interpreter_context) = interpreter_context {
+                return self.poll_request_and_interpreter(interpreter_context);
             } else {
                 return Op::Query(QueryOp::PollRequest(PollRequest {
                     next: PollRequestNext {
@@ -1375,3 +1586,18 @@ fn cancel_defrag_task<C>(defrag: &mut Defrag<C>) {
     assert!(defrag.in_progress_tasks_count > 0);
     defrag.in_progress_tasks_count -= 1;
 }
+
+fn schema_write_block_request<C>(
+    schema: &mut schema::Schema,
+    request_write_block: &proto::RequestWriteBlock<C>,
+    defrag_pending_bytes: Option<usize>,
+)
+    -> schema::WriteBlockOp
+{
+    match request_write_block.block_id {
+        None =>
+            schema.process_write_block_request(&request_write_block.block_bytes, defrag_pending_bytes),
+        Some(ref block_id) =>
+            schema.process_write_block_with_id_request(block_id.clone(), &request_write_block.block_bytes, defrag_pending_bytes),
+    }
+}
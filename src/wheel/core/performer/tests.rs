@@ -21,6 +21,7 @@ use super::{
     ReadBlockOp,
     WriteBlockOp,
     DeleteBlockOp,
+    IterBlocksOp,
     IterBlocksItemOp,
     IterBlocksFinishOp,
     IterBlocksState,
@@ -72,12 +73,18 @@ fn with_defrag_config(defrag_config: Option<DefragConfig<C>>) -> Performer<Conte
     )
         .unwrap()
         .start_fill();
-    performer_builder.finish(160)
+    performer_builder.finish(1, data_offset(152) as usize)
+}
+
+// offsets in scripts (and in their comments) are counted from the first block, so they do not depend on wheel header size
+fn data_offset(offset: u64) -> u64 {
+    let storage_layout = storage::Layout::calculate(&mut Vec::new()).unwrap();
+    storage_layout.data_offset_start() as u64 + offset
 }
 
 fn hello_world_write_req(context: C) -> proto::RequestWriteBlock<C> {
     let block_bytes = hello_world_bytes().freeze();
-    proto::RequestWriteBlock { block_bytes, context, }
+    proto::RequestWriteBlock { block_bytes, block_id: None, context, }
 }
 
 fn hello_world_read_done(block_id: block::Id, context: C) -> task::TaskDone<Context> {
@@ -119,6 +126,7 @@ enum ExpectOp {
     InfoSuccess { expect_info: Info, expect_context: C, },
     FlushSuccess { expect_context: C, },
     WriteBlockNoSpaceLeft { expect_context: C, },
+    WriteBlockIdTaken { expect_context: C, },
     WriteBlockDone { expect_block_id: block::Id, expect_context: C, },
     ReadBlockNotFound { expect_context: C, },
     ReadBlockDone { expect_block_bytes: Bytes, expect_context: C, },
@@ -236,7 +244,7 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                             Some(ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest { request, interpreter_context, })) =>
                                 poll.next.incoming_request(request, interpreter_context),
                             Some(ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone { task_done, })) =>
-                                poll.next.incoming_task_done(task_done),
+                                poll.next.incoming_task_done(task_done, poll.interpreter_context),
                             Some(ScriptOp::Do(DoOp::RequestAndInterpreterIncomingIterBlocks { iter_blocks_state, interpreter_context, })) =>
                                 poll.next.incoming_iter_blocks(iter_blocks_state, interpreter_context),
                             Some(ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedWriteBlockDone {
@@ -329,7 +337,7 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                         ),
                 },
 
-            Op::Query(QueryOp::InterpretTask(InterpretTask { offset, task, next, })) =>
+            Op::Query(QueryOp::InterpretTask(InterpretTask { offset, task, next, .. })) =>
                 match script.pop() {
                     None =>
                         panic!(
@@ -401,6 +409,22 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                         ),
                 },
 
+            Op::Event(Event { op: EventOp::WriteBlock(TaskDoneOp { context, op: WriteBlockOp::BlockIdTaken, }), performer, }) =>
+                match script.pop() {
+                    None =>
+                        panic!(
+                            "unexpected script end on WriteBlockOp::BlockIdTaken, expecting ExpectOp::WriteBlockIdTaken @ {}",
+                            script_len - script.len(),
+                        ),
+                    Some(ScriptOp::Expect(ExpectOp::WriteBlockIdTaken { expect_context, })) if expect_context == context =>
+                        performer.next(),
+                    Some(other_op) =>
+                        panic!(
+                            "expecting exact ExpectOp::WriteBlockIdTaken for WriteBlockOp::BlockIdTaken but got {:?} @ {}",
+                            other_op, script_len - script.len(),
+                        ),
+                },
+
             Op::Event(Event { op: EventOp::WriteBlock(TaskDoneOp { context, op: WriteBlockOp::Done { block_id, }, }), performer,}) =>
                 match script.pop() {
                     None =>
@@ -459,6 +483,9 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                         ),
                 },
 
+            Op::Event(Event { op: EventOp::ReadBlock(TaskDoneOp { op: ReadBlockOp::Corrupted, .. }), .. }) =>
+                panic!("unexpected ReadBlockOp::Corrupted @ {}", script_len - script.len()),
+
             Op::Event(Event { op: EventOp::DeleteBlock(TaskDoneOp { context, op: DeleteBlockOp::NotFound, }), performer, }) =>
                 match script.pop() {
                     None =>
@@ -492,6 +519,12 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                         ),
                 },
 
+            Op::Event(Event { op: EventOp::IterBlocks(TaskDoneOp { op: IterBlocksOp::ChangesTooOld { change_seq_min, }, .. }), .. }) =>
+                panic!(
+                    "unexpected IterBlocksOp::ChangesTooOld {{ change_seq_min: {} }} @ {}",
+                    change_seq_min, script_len - script.len(),
+                ),
+
             Op::Event(Event {
                 op: EventOp::IterBlocksItem(IterBlocksItemOp {
                     block_id,
@@ -538,6 +571,7 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                     task: PrepareInterpretTaskKind::WriteBlock(PrepareInterpretTaskWriteBlock {
                         block_bytes,
                         context,
+                        ..
                     }),
                 }),
                 performer,
@@ -563,6 +597,7 @@ fn interpret(performer: Performer<Context>, mut script: Vec<ScriptOp>) {
                     block_id,
                     task: PrepareInterpretTaskKind::DeleteBlock(PrepareInterpretTaskDeleteBlock {
                         context,
+                        ..
                     }),
                 }),
                 performer,
@@ -1,4 +1,5 @@
 use super::{
+    data_offset,
     task,
     proto,
     block,
@@ -62,9 +63,9 @@ fn script_basic() {
             context: task::WriteBlockContext::External("ectx02"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: write task in progress @ 24 }
+        // { 0: write task in progress @ 0 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -78,7 +79,7 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 0: read req }
+        // { 0: write task in progress @ 0, 0: read req }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::ReadBlock(proto::RequestReadBlock { block_id: block::Id::init(), context: "ectx03", }),
             interpreter_context: "ictx01",
@@ -87,12 +88,12 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx01",
         }),
-        // { 0: write task in progress @ 24, 0: read req, 0: delete req }
+        // { 0: write task in progress @ 0, 0: read req, 0: delete req }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
-            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), context: "ectx04", }),
+            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), secure_delete: None, context: "ectx04", }),
             interpreter_context: "ictx02",
         }),
-        // { 0: write task in progress @ 24, 0: read req, 0: prep delete }
+        // { 0: write task in progress @ 0, 0: read req, 0: prep delete }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskDeleteBlock {
             expect_block_id: block::Id::init(),
             expect_context: task::DeleteBlockContext::External("ectx04"),
@@ -100,12 +101,12 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx02",
         }),
-        // { 0: write task in progress @ 24, 0: read req, 0: prep delete, 1: write req }
+        // { 0: write task in progress @ 0, 0: read req, 0: prep delete, 1: write req }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::WriteBlock(hello_world_write_req("ectx05")),
             interpreter_context: "ictx03",
         }),
-        // { 0: write task in progress @ 24, 0: read req, 0: prep delete, 1: prep write }
+        // { 0: write task in progress @ 0, 0: read req, 0: prep delete, 1: prep write }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
             expect_block_id: block::Id::init().next(),
             expect_block_bytes: hello_world_bytes().freeze(),
@@ -114,7 +115,7 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx03",
         }),
-        // { 0: write task in progress @ 24, 0: read req, 0: prep delete done, 1: prep write }
+        // { 0: write task in progress @ 0, 0: read req, 0: prep delete done, 1: prep write }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedDeleteBlockDone {
             block_id: block::Id::init(),
             delete_block_bytes: hello_world_bytes(),
@@ -125,7 +126,7 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx03",
         }),
-        // { 0: write task in progress @ 24, 0: read req, 0: prep delete done, 1: prep write done }
+        // { 0: write task in progress @ 0, 0: read req, 0: prep delete done, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedWriteBlockDone {
             block_id: block::Id::init().next(),
             write_block_bytes: hello_world_bytes(),
@@ -136,10 +137,10 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx03",
         }),
-        // { 0: write task done @ 24 .. 85, 0: read req, 0: prep delete done, 1: prep write done }
+        // { 0: write task done @ 0 .. 69, 0: read req, 0: prep delete done, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -152,9 +153,9 @@ fn script_basic() {
             expect_block_id: block::Id::init(),
             expect_context: "ectx02",
         }),
-        // { 0: ready @ 24 .. 85, 0: read req, 0: prep delete done, 1: write task in progress @ 85 }
+        // { 0: ready @ 0 .. 69, 0: read req, 0: prep delete done, 1: write task in progress @ 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -168,10 +169,10 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx04",
         }),
-        // { 0: ready @ 24 .. 85, 0: read req, 0: prep delete done, 1: write task done @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read req, 0: prep delete done, 1: write task done @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 130,
+                current_offset: data_offset(122),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -184,9 +185,9 @@ fn script_basic() {
             expect_block_id: block::Id::init().next(),
             expect_context: "ectx05",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task in progress @ 24, 0: prep delete done, 1: write task done @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task in progress @ 0, 0: prep delete done, 1: write task done @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::ReadBlock(ExpectTaskReadBlock {
@@ -203,24 +204,24 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx05",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done @ 24 .. 85, 0: prep delete done, 1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done @ 0 .. 69, 0: prep delete done, 1: ready @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: hello_world_read_done(block::Id::init(), "ectx03"),
             },
         }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: prep delete done, 1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: prep delete done, 1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::ProcessReadBlockTaskDone {
             expect_block_id: block::Id::init(),
             expect_block_bytes: hello_world_bytes().freeze(),
             expect_pending_contexts_key: "pk0",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task in progress @ 24, 1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task in progress @ 0, 1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::DeleteBlock(ExpectTaskDeleteBlock {
@@ -234,7 +235,7 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx05",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task in progress @ 24, 0: read req, 1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task in progress @ 0, 0: read req, 1: ready @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::ReadBlock(proto::RequestReadBlock { block_id: block::Id::init(), context: "ectx06", }),
             interpreter_context: "ictx06",
@@ -243,14 +244,14 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx06",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task in progress @ 24, 0: read req, 0: delete req,
-        //   1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task in progress @ 0, 0: read req, 0: delete req,
+        //   1: ready @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
-            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), context: "ectx07", }),
+            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), secure_delete: None, context: "ectx07", }),
             interpreter_context: "ictx07",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task in progress @ 24, 0: read req, 0: prep delete,
-        //   1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task in progress @ 0, 0: read req, 0: prep delete,
+        //   1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskDeleteBlock {
             expect_block_id: block::Id::init(),
             expect_context: task::DeleteBlockContext::External("ectx07"),
@@ -258,8 +259,8 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx07",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task in progress @ 24, 0: read req, 0: prep delete done,
-        //   1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task in progress @ 0, 0: read req, 0: prep delete done,
+        //   1: ready @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedDeleteBlockDone {
             block_id: block::Id::init(),
             delete_block_bytes: hello_world_bytes(),
@@ -270,11 +271,11 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx07",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task done @ 24, 0: read req, 0: prep delete done,
-        //   1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task done @ 0, 0: read req, 0: prep delete done,
+        //   1: ready @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 24,
+                current_offset: data_offset(0),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
@@ -287,50 +288,50 @@ fn script_basic() {
             expect_block_id: block::Id::init(),
             expect_context: "ectx04",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task done @ 24, 0: prep delete done,
-        //   1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task done @ 0, 0: prep delete done,
+        //   1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::ReadBlockNotFound {
             expect_context: "ectx06",
         }),
-        // { 0: ready @ 24 .. 85, 0: read task done process @ 24 .. 85, 0: delete task done @ 24, 1: ready @ 85 .. 130 }
+        // { 0: ready @ 0 .. 69, 0: read task done process @ 0 .. 69, 0: delete task done @ 0, 1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::DeleteBlockNotFound {
             expect_context: "ectx07",
         }),
-        // { 0: read task done process @ 24 .. 85, 1: ready @ 85 .. 130 }
+        // { 0: read task done process @ 0 .. 69, 1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::PollRequest),
-        // { 0: read task done process done @ 24 .. 85, 1: ready @ 85 .. 130 }
+        // { 0: read task done process done @ 0 .. 69, 1: ready @ 69 .. 122 }
         ScriptOp::Do(DoOp::RequestIncomingProcessReadBlockDone {
             block_id: block::Id::init(),
             block_bytes: hello_world_bytes().freeze(),
             pending_contexts_key: "pk0",
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 1: ready @ 85 .. 130 }
+        // { 1: ready @ 69 .. 122 }
         ScriptOp::Expect(ExpectOp::ReadBlockNotFound {
             expect_context: "ectx03",
         }),
         ScriptOp::Expect(ExpectOp::PollRequest),
-        // { 1: ready @ 85 .. 130, 2: write req }
+        // { 1: ready @ 69 .. 122, 2: write req }
         ScriptOp::Do(DoOp::RequestIncomingRequest {
             request: proto::Request::WriteBlock(hello_world_write_req("ectx08")),
         }),
-        // { 1: ready @ 85 .. 130, 2: prep write }
+        // { 1: ready @ 69 .. 122, 2: prep write }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
             expect_block_id: block::Id::init().next().next(),
             expect_block_bytes: hello_world_bytes().freeze(),
             expect_context: task::WriteBlockContext::External("ectx08"),
         }),
         ScriptOp::Expect(ExpectOp::PollRequest),
-        // { 1: ready @ 85 .. 130, 2: prep write done }
+        // { 1: ready @ 69 .. 122, 2: prep write done }
         ScriptOp::Do(DoOp::RequestIncomingPreparedWriteBlockDone {
             block_id: block::Id::init().next().next(),
             write_block_bytes: hello_world_bytes(),
             context: task::WriteBlockContext::External("ectx08"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 1: ready @ 85 .. 130, 2: write task in progress @ 24 }
+        // { 1: ready @ 69 .. 122, 2: write task in progress @ 0 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -354,7 +355,7 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx09",
         }),
-        // { 1: ready @ 85 .. 130, 1: read req, 2: write task in progress @ 24 }
+        // { 1: ready @ 69 .. 122, 1: read req, 2: write task in progress @ 0 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::ReadBlock(proto::RequestReadBlock { block_id: block::Id::init().next(), context: "ectx0a", }),
             interpreter_context: "ictx0a",
@@ -363,10 +364,10 @@ fn script_basic() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx0a",
         }),
-        // { 1: ready @ 85 .. 130, 1: read req, 2: write task done @ 24 .. 85 }
+        // { 1: ready @ 69 .. 122, 1: read req, 2: write task done @ 0 .. 69 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init().next().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -379,9 +380,9 @@ fn script_basic() {
             expect_block_id: block::Id::init().next().next(),
             expect_context: "ectx08",
         }),
-        // { 1: ready @ 85 .. 130, 1: read task in progress, 2: ready @ 24 .. 85 }
+        // { 1: ready @ 69 .. 122, 1: read task in progress, 2: ready @ 0 .. 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::ReadBlock(ExpectTaskReadBlock {
@@ -400,20 +401,20 @@ fn script_basic() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: hello_world_read_done(block::Id::init().next(), "ectx0a"),
             },
         }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 1: ready @ 85 .. 130, 1: read task done process, 2: ready @ 24 .. 85 }
+        // { 1: ready @ 69 .. 122, 1: read task done process, 2: ready @ 0 .. 69 }
         ScriptOp::Expect(ExpectOp::ProcessReadBlockTaskDone {
             expect_block_id: block::Id::init().next(),
             expect_block_bytes: hello_world_bytes().freeze(),
             expect_pending_contexts_key: "pk1",
         }),
         ScriptOp::Expect(ExpectOp::PollRequest),
-        // { 1: ready @ 85 .. 130, 1: read task done process done, 2: ready @ 24 .. 85 }
+        // { 1: ready @ 69 .. 122, 1: read task done process done, 2: ready @ 0 .. 69 }
         ScriptOp::Do(DoOp::RequestIncomingProcessReadBlockDone {
             block_id: block::Id::init().next(),
             block_bytes: hello_world_bytes().freeze(),
@@ -430,9 +431,10 @@ fn script_basic() {
         }),
         ScriptOp::Expect(ExpectOp::InfoSuccess {
             expect_info: Info {
+                wheel_id: 1,
                 blocks_count: 2,
-                wheel_size_bytes: 160,
-                service_bytes_used: 128,
+                wheel_size_bytes: data_offset(152) as usize,
+                service_bytes_used: data_offset(120) as usize,
                 data_bytes_used: 26,
                 defrag_write_pending_bytes: 0,
                 bytes_free: 6,
@@ -441,6 +443,10 @@ fn script_basic() {
                     count_no_seek: 0,
                     count_seek_forward: 0,
                     count_seek_backward: 0,
+                    count_writer: 0,
+                    count_readers: 0,
+                    readers_busy_max: 0,
+                    count_per_reader: Vec::new(),
                 },
             },
             expect_context: "ectx0b",
@@ -457,6 +463,54 @@ fn script_basic() {
     interpret(performer, script)
 }
 
+#[test]
+fn script_write_with_id() {
+    let performer = init();
+    let block_id = block::Id::init().next().next();
+    let script = vec![
+        // { }
+        ScriptOp::Expect(ExpectOp::PollRequest),
+        // { 2: write req }
+        ScriptOp::Do(DoOp::RequestIncomingRequest {
+            request: proto::Request::WriteBlock(proto::RequestWriteBlock {
+                block_id: Some(block_id.clone()),
+                ..hello_world_write_req("ectx01")
+            }),
+        }),
+        // { 2: prep write }
+        ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
+            expect_block_id: block_id.clone(),
+            expect_block_bytes: hello_world_bytes().freeze(),
+            expect_context: task::WriteBlockContext::External("ectx01"),
+        }),
+        ScriptOp::Expect(ExpectOp::PollRequest),
+        // { 2: prep write, 2: write req }
+        ScriptOp::Do(DoOp::RequestIncomingRequest {
+            request: proto::Request::WriteBlock(proto::RequestWriteBlock {
+                block_id: Some(block_id.clone()),
+                ..hello_world_write_req("ectx02")
+            }),
+        }),
+        ScriptOp::Expect(ExpectOp::WriteBlockIdTaken {
+            expect_context: "ectx02",
+        }),
+        ScriptOp::Expect(ExpectOp::PollRequest),
+        // { 2: prep write, 3: write req }
+        ScriptOp::Do(DoOp::RequestIncomingRequest {
+            request: proto::Request::WriteBlock(hello_world_write_req("ectx03")),
+        }),
+        // { 2: prep write, 3: prep write }
+        ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
+            expect_block_id: block_id.next(),
+            expect_block_bytes: hello_world_bytes().freeze(),
+            expect_context: task::WriteBlockContext::External("ectx03"),
+        }),
+        ScriptOp::Expect(ExpectOp::PollRequest),
+    ];
+
+    interpret(performer, script)
+}
+
 #[test]
 fn script_iter() {
     let performer = init();
@@ -481,9 +535,9 @@ fn script_iter() {
             context: task::WriteBlockContext::External("ectx02"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: write task in progress @ 24 }
+        // { 0: write task in progress @ 0 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -497,12 +551,12 @@ fn script_iter() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: write req }
+        // { 0: write task in progress @ 0, 1: write req }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::WriteBlock(hello_world_write_req("ectx03")),
             interpreter_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: prep write }
+        // { 0: write task in progress @ 0, 1: prep write }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
             expect_block_id: block::Id::init().next(),
             expect_block_bytes: hello_world_bytes().freeze(),
@@ -511,7 +565,7 @@ fn script_iter() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: prep write done }
+        // { 0: write task in progress @ 0, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedWriteBlockDone {
             block_id: block::Id::init().next(),
             write_block_bytes: hello_world_bytes(),
@@ -522,10 +576,10 @@ fn script_iter() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task done @ 24 .. 85, 1: prep write done }
+        // { 0: write task done @ 0 .. 69, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -538,9 +592,9 @@ fn script_iter() {
             expect_block_id: block::Id::init(),
             expect_context: "ectx02",
         }),
-        // { 0: ready @ 24 .. 85, 1: write task in progress @ 85 }
+        // { 0: ready @ 0 .. 69, 1: write task in progress @ 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -554,10 +608,10 @@ fn script_iter() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx01",
         }),
-        // { 0: ready @ 24 .. 85, 1: write task done @ 85 }
+        // { 0: ready @ 0 .. 69, 1: write task done @ 69 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 130,
+                current_offset: data_offset(122),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -574,13 +628,13 @@ fn script_iter() {
 
         // request iter
         ScriptOp::Do(DoOp::RequestIncomingRequest {
-            request: proto::Request::IterBlocks(proto::RequestIterBlocks { context: "ectx04", }),
+            request: proto::Request::IterBlocks(proto::RequestIterBlocks { changed_since: None, context: "ectx04", }),
         }),
         ScriptOp::Expect(ExpectOp::MakeIterBlocksStream),
         ScriptOp::Do(DoOp::StreamReady { iter_context: "sctx00", }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::ReadBlock(ExpectTaskReadBlock {
@@ -592,6 +646,7 @@ fn script_iter() {
                     context: task::ReadBlockContext::Process(task::ReadBlockProcessContext::IterBlocks {
                         iter_blocks_stream_context: "sctx00",
                         next_block_id: block::Id::init().next(),
+                        changed_since: 0,
                     }),
                 }),
             },
@@ -602,7 +657,7 @@ fn script_iter() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
@@ -610,6 +665,7 @@ fn script_iter() {
                         context: task::ReadBlockContext::Process(task::ReadBlockProcessContext::IterBlocks {
                             iter_blocks_stream_context: "sctx00",
                             next_block_id: block::Id::init().next(),
+                            changed_since: 0,
                         }),
                     }),
                 },
@@ -640,12 +696,13 @@ fn script_iter() {
                 iter_blocks_stream_context: "sctx00",
                 iter_blocks_cursor: IterBlocksCursor {
                     block_id: block::Id::init().next(),
+                    changed_since: 0,
                 },
             },
         }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::ReadBlock(ExpectTaskReadBlock {
@@ -657,6 +714,7 @@ fn script_iter() {
                     context: task::ReadBlockContext::Process(task::ReadBlockProcessContext::IterBlocks {
                         iter_blocks_stream_context: "sctx00",
                         next_block_id: block::Id::init().next().next(),
+                        changed_since: 0,
                     }),
                 }),
             },
@@ -667,7 +725,7 @@ fn script_iter() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 130,
+                current_offset: data_offset(122),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
@@ -675,6 +733,7 @@ fn script_iter() {
                         context: task::ReadBlockContext::Process(task::ReadBlockProcessContext::IterBlocks {
                             iter_blocks_stream_context: "sctx00",
                             next_block_id: block::Id::init().next().next(),
+                            changed_since: 0,
                         }),
                     }),
                 },
@@ -705,6 +764,7 @@ fn script_iter() {
                 iter_blocks_stream_context: "sctx00",
                 iter_blocks_cursor: IterBlocksCursor {
                     block_id: block::Id::init().next().next(),
+                    changed_since: 0,
                 },
             },
         }),
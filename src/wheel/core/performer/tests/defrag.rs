@@ -1,4 +1,5 @@
 use super::{
+    data_offset,
     task,
     proto,
     block,
@@ -47,9 +48,9 @@ fn script_simple_defrag() {
             context: task::WriteBlockContext::External("ectx00"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: write task in progress @ 24 }
+        // { 0: write task in progress @ 0 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -64,12 +65,12 @@ fn script_simple_defrag() {
             expect_context: "ictx00",
         }),
 
-        // { 0: write task in progress @ 24, 1: write req }
+        // { 0: write task in progress @ 0, 1: write req }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::WriteBlock(hello_world_write_req("ectx01")),
             interpreter_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: prep write }
+        // { 0: write task in progress @ 0, 1: prep write }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
             expect_block_id: block::Id::init().next(),
             expect_block_bytes: hello_world_bytes().freeze(),
@@ -78,7 +79,7 @@ fn script_simple_defrag() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: prep write done }
+        // { 0: write task in progress @ 0, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedWriteBlockDone {
             block_id: block::Id::init().next(),
             write_block_bytes: hello_world_bytes(),
@@ -89,10 +90,10 @@ fn script_simple_defrag() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task done @ 24 .. 85, 1: prep write done }
+        // { 0: write task done @ 0 .. 69, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -105,9 +106,9 @@ fn script_simple_defrag() {
             expect_block_id: block::Id::init(),
             expect_context: "ectx00",
         }),
-        // { 0: ready @ 24 .. 85, 1: write task in progress @ 85 }
+        // { 0: ready @ 0 .. 69, 1: write task in progress @ 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -121,10 +122,10 @@ fn script_simple_defrag() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx01",
         }),
-        // { 0: ready @ 24 .. 85, 1: write task done @ 85 }
+        // { 0: ready @ 0 .. 69, 1: write task done @ 69 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 130,
+                current_offset: data_offset(122),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -137,29 +138,29 @@ fn script_simple_defrag() {
             expect_block_id: block::Id::init().next(),
             expect_context: "ectx01",
         }),
-        // { 0: ready @ 24 .. 85, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 1: ready @ 69 }
         ScriptOp::Expect(ExpectOp::PollRequest),
 
-        // { 0: ready @ 24 .. 85, 0: delete req, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: delete req, 1: ready @ 69 }
         ScriptOp::Do(DoOp::RequestIncomingRequest {
-            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), context: "ectx02", }),
+            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), secure_delete: None, context: "ectx02", }),
         }),
-        // { 0: ready @ 24 .. 85, 0: prep delete, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: prep delete, 1: ready @ 69 }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskDeleteBlock {
             expect_block_id: block::Id::init(),
             expect_context: task::DeleteBlockContext::External("ectx02"),
         }),
         ScriptOp::Expect(ExpectOp::PollRequest),
-        // { 0: ready @ 24 .. 85, 0: prep delete done, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: prep delete done, 1: ready @ 69 }
         ScriptOp::Do(DoOp::RequestIncomingPreparedDeleteBlockDone {
             block_id: block::Id::init(),
             delete_block_bytes: hello_world_bytes(),
             context: task::DeleteBlockContext::External("ectx02"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: ready @ 24 .. 85, 0: delete task in progress @ 24, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: delete task in progress @ 0, 1: ready @ 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::DeleteBlock(ExpectTaskDeleteBlock {
@@ -173,10 +174,10 @@ fn script_simple_defrag() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx02",
         }),
-        // { 0: ready @ 24 .. 85, 0: delete task done @ 24, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: delete task done @ 0, 1: ready @ 69 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 24,
+                current_offset: data_offset(0),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
@@ -190,10 +191,10 @@ fn script_simple_defrag() {
             expect_context: "ectx02",
         }),
 
-        // { 1: ready @ 85 }
+        // { 1: ready @ 69 }
         // defragmentation has started
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::ReadBlock(ExpectTaskReadBlock {
@@ -204,7 +205,7 @@ fn script_simple_defrag() {
                     },
                     context: task::ReadBlockContext::Defrag(task::ReadBlockDefragContext {
                         defrag_gaps: DefragGaps::Both {
-                            space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                            space_key_left: SpaceKey { space_available: 69, serial: 4, },
                             space_key_right: SpaceKey { space_available: 6, serial: 3 },
                         },
                     }),
@@ -217,14 +218,14 @@ fn script_simple_defrag() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
                         block_bytes: hello_world_bytes(),
                         context: task::ReadBlockContext::Defrag(task::ReadBlockDefragContext {
                             defrag_gaps: DefragGaps::OnlyLeft {
-                                space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                                space_key_left: SpaceKey { space_available: 69, serial: 4, },
                             },
                         }),
                     }),
@@ -237,7 +238,7 @@ fn script_simple_defrag() {
             expect_context: task::DeleteBlockContext::Defrag {
                 block_bytes: hello_world_bytes().freeze(),
                 defrag_gaps: DefragGaps::OnlyLeft {
-                    space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                    space_key_left: SpaceKey { space_available: 69, serial: 4, },
                 },
             },
         }),
@@ -248,13 +249,13 @@ fn script_simple_defrag() {
             context: task::DeleteBlockContext::Defrag {
                 block_bytes: hello_world_bytes().freeze(),
                 defrag_gaps: DefragGaps::OnlyLeft {
-                    space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                    space_key_left: SpaceKey { space_available: 69, serial: 4, },
                 },
             },
         }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::DeleteBlock(ExpectTaskDeleteBlock {
@@ -262,7 +263,7 @@ fn script_simple_defrag() {
                     commit: task::Commit::WithTerminator,
                     context: task::DeleteBlockContext::Defrag {
                         defrag_gaps: DefragGaps::OnlyLeft {
-                            space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                            space_key_left: SpaceKey { space_available: 69, serial: 4, },
                         },
                         block_bytes: hello_world_bytes().freeze(),
                     },
@@ -275,13 +276,13 @@ fn script_simple_defrag() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
                         context: task::DeleteBlockContext::Defrag {
                             defrag_gaps: DefragGaps::OnlyLeft {
-                                space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                                space_key_left: SpaceKey { space_available: 69, serial: 4, },
                             },
                             block_bytes: hello_world_bytes().freeze(),
                         },
@@ -291,7 +292,7 @@ fn script_simple_defrag() {
         }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -307,7 +308,7 @@ fn script_simple_defrag() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -335,7 +336,7 @@ fn script_simple_defrag() {
         }),
         ScriptOp::Expect(ExpectOp::Idle),
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -351,7 +352,7 @@ fn script_simple_defrag() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 130,
+                current_offset: data_offset(122),
                 task: task::TaskDone {
                     block_id: block::Id::init().next().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -1,4 +1,5 @@
 use super::{
+    data_offset,
     task,
     proto,
     block,
@@ -48,9 +49,9 @@ fn script_defrag_disturb() {
             context: task::WriteBlockContext::External("ectx00"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: write task in progress @ 24 }
+        // { 0: write task in progress @ 0 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -65,12 +66,12 @@ fn script_defrag_disturb() {
             expect_context: "ictx00",
         }),
 
-        // { 0: write task in progress @ 24, 1: write req }
+        // { 0: write task in progress @ 0, 1: write req }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::WriteBlock(hello_world_write_req("ectx01")),
             interpreter_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: prep write }
+        // { 0: write task in progress @ 0, 1: prep write }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskWriteBlock {
             expect_block_id: block::Id::init().next(),
             expect_block_bytes: hello_world_bytes().freeze(),
@@ -79,7 +80,7 @@ fn script_defrag_disturb() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task in progress @ 24, 1: prep write done }
+        // { 0: write task in progress @ 0, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingPreparedWriteBlockDone {
             block_id: block::Id::init().next(),
             write_block_bytes: hello_world_bytes(),
@@ -90,10 +91,10 @@ fn script_defrag_disturb() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx00",
         }),
-        // { 0: write task done @ 24 .. 85, 1: prep write done }
+        // { 0: write task done @ 0 .. 69, 1: prep write done }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 85,
+                current_offset: data_offset(69),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -106,9 +107,9 @@ fn script_defrag_disturb() {
             expect_block_id: block::Id::init(),
             expect_context: "ectx00",
         }),
-        // { 0: ready @ 24 .. 85, 1: write task in progress @ 85 }
+        // { 0: ready @ 0 .. 69, 1: write task in progress @ 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -122,10 +123,10 @@ fn script_defrag_disturb() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx01",
         }),
-        // { 0: ready @ 24 .. 85, 1: write task done @ 85 }
+        // { 0: ready @ 0 .. 69, 1: write task done @ 69 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 130,
+                current_offset: data_offset(122),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -138,29 +139,29 @@ fn script_defrag_disturb() {
             expect_block_id: block::Id::init().next(),
             expect_context: "ectx01",
         }),
-        // { 0: ready @ 24 .. 85, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 1: ready @ 69 }
         ScriptOp::Expect(ExpectOp::PollRequest),
 
-        // { 0: ready @ 24 .. 85, 0: delete req, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: delete req, 1: ready @ 69 }
         ScriptOp::Do(DoOp::RequestIncomingRequest {
-            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), context: "ectx02", }),
+            request: proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id: block::Id::init(), secure_delete: None, context: "ectx02", }),
         }),
-        // { 0: ready @ 24 .. 85, 0: prep delete, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: prep delete, 1: ready @ 69 }
         ScriptOp::Expect(ExpectOp::PrepareInterpretTaskDeleteBlock {
             expect_block_id: block::Id::init(),
             expect_context: task::DeleteBlockContext::External("ectx02"),
         }),
         ScriptOp::Expect(ExpectOp::PollRequest),
-        // { 0: ready @ 24 .. 85, 0: prep delete done, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: prep delete done, 1: ready @ 69 }
         ScriptOp::Do(DoOp::RequestIncomingPreparedDeleteBlockDone {
             block_id: block::Id::init(),
             delete_block_bytes: hello_world_bytes(),
             context: task::DeleteBlockContext::External("ectx02"),
         }),
         ScriptOp::Expect(ExpectOp::Idle),
-        // { 0: ready @ 24 .. 85, 0: delete task in progress @ 24, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: delete task in progress @ 0, 1: ready @ 69 }
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init(),
                 kind: ExpectTaskKind::DeleteBlock(ExpectTaskDeleteBlock {
@@ -174,10 +175,10 @@ fn script_defrag_disturb() {
         ScriptOp::Expect(ExpectOp::PollRequestAndInterpreter {
             expect_context: "ictx02",
         }),
-        // { 0: ready @ 24 .. 85, 0: delete task done @ 24, 1: ready @ 85 }
+        // { 0: ready @ 0 .. 69, 0: delete task done @ 0, 1: ready @ 69 }
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 24,
+                current_offset: data_offset(0),
                 task: task::TaskDone {
                     block_id: block::Id::init(),
                     kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
@@ -191,10 +192,10 @@ fn script_defrag_disturb() {
             expect_context: "ectx02",
         }),
 
-        // { 1: ready @ 85 }
+        // { 1: ready @ 69 }
         // defragmentation #0 has started
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::ReadBlock(ExpectTaskReadBlock {
@@ -205,7 +206,7 @@ fn script_defrag_disturb() {
                     },
                     context: task::ReadBlockContext::Defrag(task::ReadBlockDefragContext {
                         defrag_gaps: DefragGaps::Both {
-                            space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                            space_key_left: SpaceKey { space_available: 69, serial: 4, },
                             space_key_right: SpaceKey { space_available: 6, serial: 3 },
                         },
                     }),
@@ -230,6 +231,7 @@ fn script_defrag_disturb() {
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingRequest {
             request: proto::Request::WriteBlock(proto::RequestWriteBlock {
                 block_bytes: hello_bytes().freeze(),
+                block_id: None,
                 context: "ectx04",
             }),
             interpreter_context: "ictx06",
@@ -255,14 +257,14 @@ fn script_defrag_disturb() {
         // defrag #0 read done
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 146,
+                current_offset: data_offset(138),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
                         block_bytes: hello_world_bytes(),
                         context: task::ReadBlockContext::Defrag(task::ReadBlockDefragContext {
                             defrag_gaps: DefragGaps::Both {
-                                space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                                space_key_left: SpaceKey { space_available: 69, serial: 4, },
                                 space_key_right: SpaceKey { space_available: 6, serial: 3 },
                             },
                         }),
@@ -277,7 +279,7 @@ fn script_defrag_disturb() {
             expect_context: task::DeleteBlockContext::Defrag {
                 block_bytes: hello_world_bytes().freeze(),
                 defrag_gaps: DefragGaps::Both {
-                    space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                    space_key_left: SpaceKey { space_available: 69, serial: 4, },
                     space_key_right: SpaceKey { space_available: 6, serial: 3 },
                 },
             },
@@ -291,7 +293,7 @@ fn script_defrag_disturb() {
         }),
         // proceed with user write
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 24,
+            expect_offset: data_offset(0),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -312,7 +314,7 @@ fn script_defrag_disturb() {
             context: task::DeleteBlockContext::Defrag {
                 block_bytes: hello_world_bytes().freeze(),
                 defrag_gaps: DefragGaps::Both {
-                    space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                    space_key_left: SpaceKey { space_available: 69, serial: 4, },
                     space_key_right: SpaceKey { space_available: 6, serial: 3 },
                 },
             },
@@ -325,7 +327,7 @@ fn script_defrag_disturb() {
         // user write block task done
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 78,
+                current_offset: data_offset(62),
                 task: task::TaskDone {
                     block_id: block::Id::init().next().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
@@ -340,7 +342,7 @@ fn script_defrag_disturb() {
         }),
         // defragmentation #0 continue (delete task)
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 85,
+            expect_offset: data_offset(69),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::DeleteBlock(ExpectTaskDeleteBlock {
@@ -348,7 +350,7 @@ fn script_defrag_disturb() {
                     commit: task::Commit::WithTerminator,
                     context: task::DeleteBlockContext::Defrag {
                         defrag_gaps: DefragGaps::Both {
-                            space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                            space_key_left: SpaceKey { space_available: 69, serial: 4, },
                             space_key_right: SpaceKey { space_available: 6, serial: 3 },
                         },
                         block_bytes: hello_world_bytes().freeze(),
@@ -363,13 +365,13 @@ fn script_defrag_disturb() {
         // defragmentation #0 continue (delete task ready)
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 93,
+                current_offset: data_offset(93),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
                         context: task::DeleteBlockContext::Defrag {
                             defrag_gaps: DefragGaps::Both {
-                                space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                                space_key_left: SpaceKey { space_available: 69, serial: 4, },
                                 space_key_right: SpaceKey { space_available: 6, serial: 3 },
                             },
                             block_bytes: hello_world_bytes().freeze(),
@@ -381,7 +383,7 @@ fn script_defrag_disturb() {
         ScriptOp::Expect(ExpectOp::Idle),
         // defragmentation #0 continue (write task)
         ScriptOp::Expect(ExpectOp::InterpretTask {
-            expect_offset: 78,
+            expect_offset: data_offset(62),
             expect_task: ExpectTask {
                 block_id: block::Id::init().next(),
                 kind: ExpectTaskKind::WriteBlock(ExpectTaskWriteBlock {
@@ -397,7 +399,7 @@ fn script_defrag_disturb() {
         }),
         ScriptOp::Do(DoOp::RequestAndInterpreterIncomingTaskDone {
             task_done: task::Done {
-                current_offset: 139,
+                current_offset: data_offset(131),
                 task: task::TaskDone {
                     block_id: block::Id::init().next(),
                     kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
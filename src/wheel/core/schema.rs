@@ -1,4 +1,10 @@
-use std::mem::drop;
+use std::{
+    mem::drop,
+    collections::{
+        HashMap,
+        BTreeMap,
+    },
+};
 
 use alloc_pool::bytes::Bytes;
 
@@ -17,9 +23,15 @@ use super::{
 
 use crate::Info;
 
+// keeps `changes_since` reply header small enough for a network frame
+pub const TOMBSTONES_MAX: usize = 65536;
+
 #[derive(Debug)]
 pub struct Schema {
     next_block_id: block::Id,
+    next_change_seq: u64,
+    pending_deletes: HashMap<block::Id, u64>,
+    tombstones: Tombstones,
     storage_layout: storage::Layout,
     blocks_index: blocks::Index,
     gaps_index: gaps::Index,
@@ -30,6 +42,7 @@ pub enum WriteBlockOp {
     Perform(WriteBlockPerform),
     QueuePendingDefrag { space_required: usize, },
     ReplyNoSpaceLeft,
+    ReplyBlockIdTaken,
 }
 
 #[derive(Debug)]
@@ -49,6 +62,7 @@ pub enum DefragOp {
 pub struct WriteBlockTaskOp {
     pub block_id: block::Id,
     pub block_offset: u64,
+    pub change_seq: u64,
 }
 
 #[derive(Debug)]
@@ -69,7 +83,9 @@ pub enum DeleteBlockOp {
 }
 
 #[derive(Debug)]
-pub struct DeleteBlockPerform;
+pub struct DeleteBlockPerform {
+    pub change_seq: u64,
+}
 
 #[derive(Debug)]
 pub enum ReadBlockTaskDoneOp {
@@ -104,6 +120,14 @@ pub struct DeleteBlockTaskDoneDefragPerform {
     pub freed_space_key: Option<SpaceKey>,
 }
 
+#[derive(Debug)]
+pub struct ChangesSince {
+    pub change_seq: u64,
+    pub blocks_count: usize,
+    pub blocks_total_size: usize,
+    pub deleted_block_ids: Vec<block::Id>,
+}
+
 impl Schema {
     pub fn storage_layout(&self) -> &storage::Layout {
         &self.storage_layout
@@ -136,13 +160,48 @@ impl Schema {
         -> WriteBlockOp
     {
         let block_id = self.next_block_id.clone();
-        self.next_block_id = self.next_block_id.next();
+        let op = self.allocate_write_block(block_id, block_bytes, defrag_pending_bytes);
+        // id is used up only by a block actually placed
+        if let WriteBlockOp::Perform(..) = op {
+            self.next_block_id = self.next_block_id.next();
+        }
+        op
+    }
+
+    pub fn process_write_block_with_id_request(
+        &mut self,
+        block_id: block::Id,
+        block_bytes: &Bytes,
+        defrag_pending_bytes: Option<usize>,
+    )
+        -> WriteBlockOp
+    {
+        if self.blocks_index.get(&block_id).is_some() {
+            return WriteBlockOp::ReplyBlockIdTaken;
+        }
+        let next_block_id = block_id.next();
+        let op = self.allocate_write_block(block_id, block_bytes, defrag_pending_bytes);
+        if let WriteBlockOp::Perform(..) = op {
+            if next_block_id > self.next_block_id {
+                self.next_block_id = next_block_id;
+            }
+        }
+        op
+    }
 
+    fn allocate_write_block(
+        &mut self,
+        block_id: block::Id,
+        block_bytes: &Bytes,
+        defrag_pending_bytes: Option<usize>,
+    )
+        -> WriteBlockOp
+    {
         let mut defrag_op = DefragOp::None;
         let mut right_space_key = None;
+        let change_seq = self.next_change_seq;
 
-        let space_required = block_bytes.len()
-            + self.storage_layout.data_size_block_min();
+        let space_required = self.storage_layout.block_chunk_size(block_bytes.len());
 
         let blocks_index = &self.blocks_index;
         let block_offset = match self.gaps_index.allocate(space_required, defrag_pending_bytes, |block_id| blocks_index.get(block_id)) {
@@ -150,7 +209,7 @@ impl Schema {
             // before: ^| ....... | A | ... |$
             // after:  ^| R | ... | A | ... |$
             Ok(gaps::Allocated::Success { space_available, between: gaps::GapBetween::StartAndBlock { right_block, }, }) => {
-                let block_offset = self.storage_layout.wheel_header_size as u64;
+                let block_offset = self.storage_layout.data_offset_start() as u64;
                 let right_block_id = right_block.block_id.clone();
 
                 let space_left = space_available - space_required;
@@ -187,6 +246,7 @@ impl Schema {
                             left: LeftEnvirons::Start,
                             right: self_env,
                         },
+                        change_seq,
                         tasks_head: Default::default(),
                     },
                 );
@@ -199,8 +259,7 @@ impl Schema {
             // after:  ^| ... | A | R | ... | B | ... |$
             Ok(gaps::Allocated::Success { space_available, between: gaps::GapBetween::TwoBlocks { left_block, right_block, }, }) => {
                 let block_offset = left_block.block_entry.offset
-                    + self.storage_layout.data_size_block_min() as u64
-                    + left_block.block_entry.header.block_size as u64;
+                    + self.storage_layout.block_chunk_size(left_block.block_entry.header.block_size) as u64;
                 let left_block_id = left_block.block_id.clone();
                 let right_block_id = right_block.block_id.clone();
 
@@ -240,6 +299,7 @@ impl Schema {
                             left: LeftEnvirons::Block { block_id: left_block_id.clone(), },
                             right: self_env,
                         },
+                        change_seq,
                         tasks_head: Default::default(),
                     },
                 );
@@ -253,8 +313,7 @@ impl Schema {
             // after:  ^| ... | A | R | ... |$
             Ok(gaps::Allocated::Success { space_available, between: gaps::GapBetween::BlockAndEnd { left_block, }, }) => {
                 let block_offset = left_block.block_entry.offset
-                    + self.storage_layout.data_size_block_min() as u64
-                    + left_block.block_entry.header.block_size as u64;
+                    + self.storage_layout.block_chunk_size(left_block.block_entry.header.block_size) as u64;
                 let left_block_id = left_block.block_id.clone();
 
                 let space_left = space_available - space_required;
@@ -283,6 +342,7 @@ impl Schema {
                             left: LeftEnvirons::Block { block_id: left_block_id.clone(), },
                             right: self_env,
                         },
+                        change_seq,
                         tasks_head: Default::default(),
                     },
                 );
@@ -293,7 +353,7 @@ impl Schema {
             // before: ^| ....... |$
             // after:  ^| R | ... |$
             Ok(gaps::Allocated::Success { space_available, between: gaps::GapBetween::StartAndEnd, }) => {
-                let block_offset = self.storage_layout.wheel_header_size as u64;
+                let block_offset = self.storage_layout.data_offset_start() as u64;
 
                 let space_left = space_available - space_required;
                 let environs = if space_left > 0 {
@@ -324,6 +384,7 @@ impl Schema {
                             ..Default::default()
                         },
                         environs,
+                        change_seq,
                         tasks_head: Default::default(),
                     },
                 );
@@ -338,12 +399,17 @@ impl Schema {
 
         };
 
+        self.next_change_seq += 1;
+        // block id could be reused after delete: it is not a deleted one anymore
+        self.tombstones.remove(&block_id);
+
         WriteBlockOp::Perform(
             WriteBlockPerform {
                 defrag_op,
                 task_op: WriteBlockTaskOp {
                     block_id,
                     block_offset,
+                    change_seq,
                 },
                 right_space_key,
             },
@@ -363,8 +429,17 @@ impl Schema {
 
     pub fn process_delete_block_request(&mut self, block_id: &block::Id) -> DeleteBlockOp {
         match self.blocks_index.get(&block_id) {
-            Some(..) =>
-                DeleteBlockOp::Perform(DeleteBlockPerform),
+            Some(..) => {
+                let next_change_seq = &mut self.next_change_seq;
+                let change_seq = *self.pending_deletes
+                    .entry(block_id.clone())
+                    .or_insert_with(|| {
+                        let change_seq = *next_change_seq;
+                        *next_change_seq += 1;
+                        change_seq
+                    });
+                DeleteBlockOp::Perform(DeleteBlockPerform { change_seq, })
+            },
             None =>
                 DeleteBlockOp::NotFound,
         }
@@ -381,6 +456,8 @@ impl Schema {
 
     pub fn process_delete_block_task_done(&mut self, removed_block_id: block::Id) -> DeleteBlockTaskDoneOp {
         let block_entry = self.blocks_index.remove(&removed_block_id).unwrap();
+        let change_seq = self.pending_deletes.remove(&removed_block_id).unwrap();
+        self.tombstones.insert(removed_block_id.clone(), change_seq);
         let mut defrag_op = DefragOp::None;
 
         let freed_space_key = match &block_entry.environs {
@@ -389,9 +466,8 @@ impl Schema {
             // after:  ^| . |$
             Environs { left: LeftEnvirons::Start, right: RightEnvirons::End, } => {
                 assert_eq!(self.gaps_index.space_total(), 0);
-                assert_eq!(block_entry.offset, self.storage_layout.wheel_header_size as u64);
-                let space_available = block_entry.header.block_size
-                    + self.storage_layout.data_size_block_min();
+                assert_eq!(block_entry.offset, self.storage_layout.data_offset_start() as u64);
+                let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size);
                 self.gaps_index.insert(space_available, gaps::GapBetween::StartAndEnd)
             },
 
@@ -404,15 +480,14 @@ impl Schema {
                     Some(gaps::GapBetween::TwoBlocks { left_block, right_block, }) => {
                         assert_eq!(left_block, removed_block_id);
                         drop(left_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         let space_key = self.gaps_index.insert(
                             space_available,
                             gaps::GapBetween::StartAndBlock { right_block: right_block.clone(), },
                         );
                         self.blocks_index.update_env_left(&right_block, LeftEnvirons::Space { space_key, });
-                        assert_eq!(block_entry.offset, self.storage_layout.wheel_header_size as u64);
+                        assert_eq!(block_entry.offset, self.storage_layout.data_offset_start() as u64);
                         defrag_op = self.make_defrag_op(space_key, right_block.clone());
                         space_key
                     },
@@ -421,9 +496,8 @@ impl Schema {
                     Some(gaps::GapBetween::BlockAndEnd { left_block, }) => {
                         assert_eq!(left_block, removed_block_id);
                         drop(left_block);
-                        assert_eq!(block_entry.offset, self.storage_layout.wheel_header_size as u64);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        assert_eq!(block_entry.offset, self.storage_layout.data_offset_start() as u64);
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         self.gaps_index.insert(space_available, gaps::GapBetween::StartAndEnd)
                     },
@@ -435,11 +509,10 @@ impl Schema {
                 left: LeftEnvirons::Start,
                 right: RightEnvirons::Block { block_id, },
             } => {
-                let space_available = block_entry.header.block_size
-                    + self.storage_layout.data_size_block_min();
+                let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size);
                 let space_key = self.gaps_index.insert(space_available, gaps::GapBetween::StartAndBlock { right_block: block_id.clone(), });
                 self.blocks_index.update_env_left(&block_id, LeftEnvirons::Space { space_key, });
-                assert_eq!(block_entry.offset, self.storage_layout.wheel_header_size as u64);
+                assert_eq!(block_entry.offset, self.storage_layout.data_offset_start() as u64);
                 defrag_op = self.make_defrag_op(space_key, block_id.clone());
                 space_key
             },
@@ -456,8 +529,7 @@ impl Schema {
                     Some(gaps::GapBetween::TwoBlocks { left_block, right_block, }) => {
                         assert_eq!(right_block, removed_block_id);
                         drop(right_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         let space_key = self.gaps_index.insert(
                             space_available,
@@ -471,8 +543,7 @@ impl Schema {
                     Some(gaps::GapBetween::StartAndBlock { right_block, }) => {
                         assert_eq!(right_block, removed_block_id);
                         drop(right_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         self.gaps_index.insert(space_available, gaps::GapBetween::StartAndEnd)
                     },
@@ -499,8 +570,7 @@ impl Schema {
                         drop(right_block_left);
                         assert_eq!(left_block_right, removed_block_id);
                         drop(left_block_right);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key_left.space_available()
                             + space_key_right.space_available();
                         self.gaps_index.insert(space_available, gaps::GapBetween::StartAndEnd)
@@ -515,8 +585,7 @@ impl Schema {
                         drop(right_block_left);
                         assert_eq!(left_block_right, removed_block_id);
                         drop(left_block_right);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key_left.space_available()
                             + space_key_right.space_available();
                         let space_key = self.gaps_index.insert(
@@ -537,8 +606,7 @@ impl Schema {
                         drop(right_block_left);
                         assert_eq!(left_block_right, removed_block_id);
                         drop(left_block_right);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key_left.space_available()
                             + space_key_right.space_available();
                         let space_key = self.gaps_index.insert(
@@ -558,8 +626,7 @@ impl Schema {
                         drop(right_block_left);
                         assert_eq!(left_block_right, removed_block_id);
                         drop(left_block_right);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key_left.space_available()
                             + space_key_right.space_available();
                         let space_key = self.gaps_index.insert(
@@ -588,8 +655,7 @@ impl Schema {
                     Some(gaps::GapBetween::TwoBlocks { left_block, right_block, }) => {
                         assert_eq!(right_block, removed_block_id);
                         drop(right_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         let space_key = self.gaps_index.insert(
                             space_available,
@@ -608,8 +674,7 @@ impl Schema {
                     Some(gaps::GapBetween::StartAndBlock { right_block, }) => {
                         assert_eq!(right_block, removed_block_id);
                         drop(right_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         let space_key = self.gaps_index.insert(
                             space_available,
@@ -627,8 +692,7 @@ impl Schema {
                 left: LeftEnvirons::Block { block_id, },
                 right: RightEnvirons::End,
             } => {
-                let space_available = block_entry.header.block_size
-                    + self.storage_layout.data_size_block_min();
+                let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size);
                 let space_key = self.gaps_index.insert(
                     space_available,
                     gaps::GapBetween::BlockAndEnd { left_block: block_id.clone(), },
@@ -649,8 +713,7 @@ impl Schema {
                     Some(gaps::GapBetween::TwoBlocks { left_block, right_block, }) => {
                         assert_eq!(left_block, removed_block_id);
                         drop(left_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         let space_key = self.gaps_index.insert(
                             space_available,
@@ -669,8 +732,7 @@ impl Schema {
                     Some(gaps::GapBetween::BlockAndEnd { left_block, }) => {
                         assert_eq!(left_block, removed_block_id);
                         drop(left_block);
-                        let space_available = block_entry.header.block_size
-                            + self.storage_layout.data_size_block_min()
+                        let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size)
                             + space_key.space_available();
                         let space_key = self.gaps_index.insert(
                             space_available,
@@ -687,8 +749,7 @@ impl Schema {
                 left: LeftEnvirons::Block { block_id: block_id_left, },
                 right: RightEnvirons::Block { block_id: block_id_right, },
             } => {
-                let space_available = block_entry.header.block_size
-                    + self.storage_layout.data_size_block_min();
+                let space_available = self.storage_layout.block_chunk_size(block_entry.header.block_size);
                 let space_key = self.gaps_index.insert(
                     space_available,
                     gaps::GapBetween::TwoBlocks {
@@ -713,8 +774,8 @@ impl Schema {
         -> DeleteBlockTaskDoneDefragOp
     {
         let block_entry = self.blocks_index.get_mut(&removed_block_id).unwrap();
-        let start_offset = self.storage_layout.wheel_header_size as u64;
-        let data_size_block_min = self.storage_layout.data_size_block_min() as u64;
+        let start_offset = self.storage_layout.data_offset_start() as u64;
+        let storage_layout = self.storage_layout.clone();
         let mut defrag_op = DefragOp::None;
 
         let freed_space_key = match block_entry.environs.clone() {
@@ -763,8 +824,7 @@ impl Schema {
                         let block_offset = self.blocks_index.with_mut(&left_block, |block_entry| {
                             block_entry.environs.right = RightEnvirons::Block { block_id: removed_block_id.clone(), };
                             block_entry.offset
-                                + data_size_block_min
-                                + block_entry.header.block_size as u64
+                                + storage_layout.block_chunk_size(block_entry.header.block_size) as u64
                         }).unwrap();
                         self.blocks_index.with_mut(&removed_block_id, |block_entry| {
                             block_entry.offset = block_offset;
@@ -845,8 +905,7 @@ impl Schema {
                         let block_offset = self.blocks_index.with_mut(&left_block_left, |block_entry| {
                             block_entry.environs.right = RightEnvirons::Block { block_id: removed_block_id.clone(), };
                             block_entry.offset
-                                + data_size_block_min
-                                + block_entry.header.block_size as u64
+                                + storage_layout.block_chunk_size(block_entry.header.block_size) as u64
                         }).unwrap();
                         self.blocks_index.with_mut(&removed_block_id, |block_entry| {
                             block_entry.offset = block_offset;
@@ -875,8 +934,7 @@ impl Schema {
                         let block_offset = self.blocks_index.with_mut(&left_block_left, |block_entry| {
                             block_entry.environs.right = RightEnvirons::Block { block_id: removed_block_id.clone(), };
                             block_entry.offset
-                                + data_size_block_min
-                                + block_entry.header.block_size as u64
+                                + storage_layout.block_chunk_size(block_entry.header.block_size) as u64
                         }).unwrap();
                         self.blocks_index.update_env_left(&right_block_right, LeftEnvirons::Space { space_key: moved_space_key, });
                         self.blocks_index.with_mut(&removed_block_id, |block_entry| {
@@ -926,8 +984,7 @@ impl Schema {
                         let block_offset = self.blocks_index.with_mut(&left_block, |block_entry| {
                             block_entry.environs.right = RightEnvirons::Block { block_id: removed_block_id.clone(), };
                             block_entry.offset
-                                + data_size_block_min
-                                + block_entry.header.block_size as u64
+                                + storage_layout.block_chunk_size(block_entry.header.block_size) as u64
                         }).unwrap();
                         self.blocks_index.update_env_left(&block_id, LeftEnvirons::Space { space_key: moved_space_key, });
                         self.blocks_index.with_mut(&removed_block_id, |block_entry| {
@@ -954,8 +1011,36 @@ impl Schema {
         BlockGet { blocks_index: &mut self.blocks_index, }
     }
 
-    pub fn next_block_id_from(&self, offset: block::Id) -> Option<block::Id> {
-        self.blocks_index.next_block_id_from(offset)
+    pub fn next_block_id_changed_since(&self, offset: block::Id, change_seq: u64) -> Option<block::Id> {
+        self.blocks_index.next_block_id_changed_since(offset, change_seq)
+    }
+
+    // deletions made at or before this change are not tracked anymore
+    pub fn changes_horizon(&self) -> u64 {
+        self.tombstones.horizon
+    }
+
+    pub fn changes_since(&self, change_seq: u64) -> ChangesSince {
+        // every change up to this one is either visible in blocks index or is recorded as a tombstone
+        let last_change_seq = self.next_change_seq - 1;
+        let complete_change_seq = self.pending_deletes.values()
+            .min()
+            .map_or(last_change_seq, |pending_change_seq| pending_change_seq - 1);
+        let mut changes_since = ChangesSince {
+            change_seq: complete_change_seq,
+            blocks_count: 0,
+            blocks_total_size: 0,
+            deleted_block_ids: self.tombstones
+                .by_change_seq
+                .range(change_seq + 1 ..)
+                .map(|kv| kv.1.clone())
+                .collect(),
+        };
+        for block_entry in self.blocks_index.changed_since(change_seq) {
+            changes_since.blocks_count += 1;
+            changes_since.blocks_total_size += block_entry.header.block_size;
+        }
+        changes_since
     }
 
     pub fn is_last_block(&self, block_id: &block::Id) -> bool {
@@ -987,6 +1072,42 @@ impl Schema {
     }
 }
 
+#[derive(Debug)]
+struct Tombstones {
+    by_change_seq: BTreeMap<u64, block::Id>,
+    by_block_id: HashMap<block::Id, u64>,
+    horizon: u64,
+}
+
+impl Tombstones {
+    fn new() -> Tombstones {
+        Tombstones {
+            by_change_seq: BTreeMap::new(),
+            by_block_id: HashMap::new(),
+            horizon: 0,
+        }
+    }
+
+    fn insert(&mut self, block_id: block::Id, change_seq: u64) {
+        if let Some(prev_change_seq) = self.by_block_id.insert(block_id.clone(), change_seq) {
+            self.by_change_seq.remove(&prev_change_seq);
+        }
+        self.by_change_seq.insert(change_seq, block_id);
+        while self.by_change_seq.len() > TOMBSTONES_MAX {
+            let oldest_change_seq = *self.by_change_seq.keys().next().unwrap();
+            let oldest_block_id = self.by_change_seq.remove(&oldest_change_seq).unwrap();
+            self.by_block_id.remove(&oldest_block_id);
+            self.horizon = oldest_change_seq;
+        }
+    }
+
+    fn remove(&mut self, block_id: &block::Id) {
+        if let Some(change_seq) = self.by_block_id.remove(block_id) {
+            self.by_change_seq.remove(&change_seq);
+        }
+    }
+}
+
 pub struct BlockGet<'a> {
     blocks_index: &'a mut blocks::Index,
 }
@@ -1003,6 +1124,8 @@ pub struct Builder {
     blocks_index: blocks::Index,
     gaps_index: gaps::Index,
     tracker: Option<BlocksTracker>,
+    tombstones: HashMap<block::Id, u64>,
+    max_change_seq: u64,
 }
 
 struct BlocksTracker {
@@ -1020,6 +1143,8 @@ impl Builder {
             blocks_index: blocks::Index::new(),
             gaps_index: gaps::Index::new(),
             tracker: None,
+            tombstones: HashMap::new(),
+            max_change_seq: 0,
         }
     }
 
@@ -1027,11 +1152,19 @@ impl Builder {
         &self.storage_layout
     }
 
-    pub fn push_block(&mut self, offset: u64, block_header: storage::BlockHeader) -> DefragOp {
+    pub fn push_tombstone(&mut self, block_id: block::Id, change_seq: u64) {
+        self.max_change_seq = self.max_change_seq.max(change_seq);
+        let tombstone_change_seq = self.tombstones.entry(block_id).or_insert(change_seq);
+        *tombstone_change_seq = change_seq.max(*tombstone_change_seq);
+    }
+
+    pub fn push_block(&mut self, offset: u64, block_header: storage::BlockHeader, change_seq: u64) -> DefragOp {
+        self.max_change_seq = self.max_change_seq.max(change_seq);
+
         let (left, max_block_id) = match self.tracker.take() {
             None => {
-                assert!(offset >= self.storage_layout.wheel_header_size as u64);
-                match (offset - self.storage_layout.wheel_header_size as u64) as usize {
+                assert!(offset >= self.storage_layout.data_offset_start() as u64);
+                match (offset - self.storage_layout.data_offset_start() as u64) as usize {
                     0 =>
                         (LeftEnvirons::Start, block_header.block_id.clone()),
                     space_available => {
@@ -1102,8 +1235,7 @@ impl Builder {
             prev_block_id: block_header.block_id.clone(),
             prev_block_left_env: left.clone(),
             prev_block_offset: offset,
-            prev_block_size: self.storage_layout.data_size_block_min()
-                + block_header.block_size,
+            prev_block_size: self.storage_layout.block_chunk_size(block_header.block_size),
             max_block_id,
         });
         self.blocks_index.insert(
@@ -1112,6 +1244,7 @@ impl Builder {
                 offset,
                 header: block_header,
                 environs: Environs { left, right: RightEnvirons::End, },
+                change_seq,
                 tasks_head: Default::default(),
             },
         );
@@ -1180,8 +1313,23 @@ impl Builder {
             },
         };
 
+        // tombstones of blocks still alive are left by defrag moves or followed by a block rewrite
+        let mut tombstones = Tombstones::new();
+        let blocks_index = &self.blocks_index;
+        let mut dead_tombstones: Vec<_> = self.tombstones
+            .into_iter()
+            .filter(|(block_id, _)| blocks_index.get(block_id).is_none())
+            .collect();
+        dead_tombstones.sort_by_key(|(_, change_seq)| *change_seq);
+        for (block_id, change_seq) in dead_tombstones {
+            tombstones.insert(block_id, change_seq);
+        }
+
         let schema = Schema {
             next_block_id,
+            next_change_seq: self.max_change_seq + 1,
+            pending_deletes: HashMap::new(),
+            tombstones,
             storage_layout: self.storage_layout,
             blocks_index: self.blocks_index,
             gaps_index: self.gaps_index,
@@ -1220,11 +1368,19 @@ mod tests {
         DeleteBlockTaskDonePerform,
         DeleteBlockTaskDoneDefragOp,
         DeleteBlockTaskDoneDefragPerform,
+        Tombstones,
+        TOMBSTONES_MAX,
     };
 
     fn init() -> Schema {
         let storage_layout = storage::Layout::calculate(&mut Vec::new()).unwrap();
-        Builder::new(storage_layout).finish(160).1
+        Builder::new(storage_layout).finish(data_offset(152) as usize).1
+    }
+
+    // offsets are counted from the first block, so they do not depend on wheel header size
+    fn data_offset(offset: u64) -> u64 {
+        let storage_layout = storage::Layout::calculate(&mut Vec::new()).unwrap();
+        storage_layout.data_offset_start() as u64 + offset
     }
 
     fn sample_hello_world() -> Bytes {
@@ -1236,23 +1392,24 @@ mod tests {
     #[test]
     fn process_write_block_request() {
         let mut schema = init();
-        assert_eq!(schema.gaps_index.space_total(), 128);
+        assert_eq!(schema.gaps_index.space_total(), 144);
 
         let op = schema.process_write_block_request(&sample_hello_world(), None);
         assert!(matches!(op, WriteBlockOp::Perform(WriteBlockPerform {
             defrag_op: DefragOp::None,
             task_op: WriteBlockTaskOp {
                 block_id,
-                block_offset: 24,
+                block_offset,
+                change_seq: 1,
             },
             ..
-        }) if block_id == block::Id::init()));
+        }) if block_id == block::Id::init() && block_offset == data_offset(0)));
 
         assert_eq!(schema.next_block_id, block::Id::init().next());
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init()),
             Some(&BlockEntry {
-                offset: 24,
+                offset,
                 header: storage::BlockHeader {
                     ref block_id,
                     block_size: 13,
@@ -1260,13 +1417,13 @@ mod tests {
                 },
                 environs: Environs {
                     left: LeftEnvirons::Start,
-                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 67, serial: 2, }, },
+                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 75, serial: 2, }, },
                 },
                 ..
-            }) if block_id == &block::Id::init()
+            }) if block_id == &block::Id::init() && offset == data_offset(0)
         ));
         assert_eq!(schema.blocks_index.get(&block::Id::init().next()), None);
-        assert_eq!(schema.gaps_index.space_total(), 67);
+        assert_eq!(schema.gaps_index.space_total(), 75);
 
         let op = schema.process_write_block_request(&sample_hello_world(), None);
         assert!(matches!(op, WriteBlockOp::Perform(
@@ -1274,17 +1431,18 @@ mod tests {
                 defrag_op: DefragOp::None,
                 task_op: WriteBlockTaskOp {
                     block_id,
-                    block_offset: 85,
+                    block_offset,
+                    change_seq: 2,
                 },
                 ..
             },
-        ) if block_id == block::Id::init().next()));
+        ) if block_id == block::Id::init().next() && block_offset == data_offset(69)));
 
         assert_eq!(schema.next_block_id, block::Id::init().next().next());
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init()),
             Some(&BlockEntry {
-                offset: 24,
+                offset,
                 header: storage::BlockHeader {
                     block_id: ref block_id_a,
                     block_size: 13,
@@ -1295,12 +1453,12 @@ mod tests {
                     right: RightEnvirons::Block { block_id: ref block_id_b, },
                 },
                 ..
-            }) if block_id_a == &block::Id::init() && block_id_b == &block::Id::init().next()
+            }) if block_id_a == &block::Id::init() && block_id_b == &block::Id::init().next() && offset == data_offset(0)
         ));
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init().next()),
             Some(&BlockEntry {
-                offset: 85,
+                offset,
                 header: storage::BlockHeader {
                     block_id: ref block_id_a,
                     block_size: 13,
@@ -1311,19 +1469,23 @@ mod tests {
                     right: RightEnvirons::Space { space_key: SpaceKey { space_available: 6, serial: 3, }, },
                 },
                 ..
-            }) if block_id_a == &block::Id::init().next() && block_id_b == &block::Id::init()
+            }) if block_id_a == &block::Id::init().next() && block_id_b == &block::Id::init() && offset == data_offset(69)
         ));
         assert_eq!(schema.blocks_index.get(&block::Id::init().next().next()), None);
         assert_eq!(schema.gaps_index.space_total(), 6);
 
         let op = schema.process_write_block_request(&sample_hello_world(), None);
         assert!(matches!(op, WriteBlockOp::ReplyNoSpaceLeft));
+        assert_eq!(schema.next_block_id, block::Id::init().next().next());
+        let op = schema.process_write_block_with_id_request(block::Id::init().next().next().next(), &sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::ReplyNoSpaceLeft));
+        assert_eq!(schema.next_block_id, block::Id::init().next().next());
     }
 
     #[test]
     fn process_write_read_block_requests() {
         let mut schema = init();
-        assert_eq!(schema.gaps_index.space_total(), 128);
+        assert_eq!(schema.gaps_index.space_total(), 144);
 
         let op = schema.process_read_block_request(&block::Id::init());
         assert!(matches!(op, ReadBlockOp::NotFound));
@@ -1334,17 +1496,18 @@ mod tests {
                 defrag_op: DefragOp::None,
                 task_op: WriteBlockTaskOp {
                     ref block_id,
-                    block_offset: 24,
+                    block_offset,
+                    change_seq: 1,
                 },
                 ..
             },
-        ) if block_id == &block::Id::init()));
+        ) if block_id == &block::Id::init() && block_offset == data_offset(0)));
 
         assert_eq!(schema.next_block_id, block::Id::init().next());
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init()),
             Some(&BlockEntry {
-                offset: 24,
+                offset,
                 header: storage::BlockHeader {
                     ref block_id,
                     block_size: 13,
@@ -1352,22 +1515,56 @@ mod tests {
                 },
                 environs: Environs {
                     left: LeftEnvirons::Start,
-                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 67, serial: 2, }, },
+                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 75, serial: 2, }, },
                 },
                 ..
-            }) if block_id == &block::Id::init()
+            }) if block_id == &block::Id::init() && offset == data_offset(0)
         ));
         assert_eq!(schema.blocks_index.get(&block::Id::init().next()), None);
-        assert_eq!(schema.gaps_index.space_total(), 67);
+        assert_eq!(schema.gaps_index.space_total(), 75);
 
         let op = schema.process_read_block_request(&block::Id::init());
         assert!(matches!(op, ReadBlockOp::Perform(ReadBlockPerform { .. })));
     }
 
+    #[test]
+    fn process_write_block_with_id_request() {
+        let mut schema = init();
+        let block_id = block::Id::init().next().next().next();
+
+        let op = schema.process_write_block_with_id_request(block_id.clone(), &sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::Perform(
+            WriteBlockPerform {
+                defrag_op: DefragOp::None,
+                task_op: WriteBlockTaskOp {
+                    block_id: ref block_id_a,
+                    block_offset,
+                    change_seq: 1,
+                },
+                ..
+            },
+        ) if block_id_a == &block_id && block_offset == data_offset(0)));
+        assert_eq!(schema.next_block_id, block_id.next());
+
+        let op = schema.process_write_block_with_id_request(block_id.clone(), &sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::ReplyBlockIdTaken));
+
+        let op = schema.process_write_block_request(&sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::Perform(
+            WriteBlockPerform {
+                task_op: WriteBlockTaskOp {
+                    block_id: ref block_id_a,
+                    ..
+                },
+                ..
+            },
+        ) if block_id_a == &block_id.next()));
+    }
+
     #[test]
     fn process_delete_block_request() {
         let mut schema = init();
-        assert_eq!(schema.gaps_index.space_total(), 128);
+        assert_eq!(schema.gaps_index.space_total(), 144);
 
         let op = schema.process_write_block_request(&sample_hello_world(), None);
         assert!(matches!(op, WriteBlockOp::Perform(..)));
@@ -1380,7 +1577,7 @@ mod tests {
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init()),
             Some(&BlockEntry {
-                offset: 24,
+                offset,
                 header: storage::BlockHeader {
                     block_id: ref block_id_a,
                     block_size: 13,
@@ -1391,14 +1588,14 @@ mod tests {
                     right: RightEnvirons::Block { block_id: ref block_id_b, },
                 },
                 ..
-            }) if block_id_a == &block::Id::init() && block_id_b == &block::Id::init().next()
+            }) if block_id_a == &block::Id::init() && block_id_b == &block::Id::init().next() && offset == data_offset(0)
         ));
 
         let op = schema.process_delete_block_task_done(block::Id::init());
         assert!(matches!(op, DeleteBlockTaskDoneOp::Perform(DeleteBlockTaskDonePerform {
             defrag_op: DefragOp::Queue {
                 defrag_gaps: DefragGaps::Both {
-                    space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                    space_key_left: SpaceKey { space_available: 69, serial: 4, },
                     space_key_right: SpaceKey { space_available: 6, serial: 3 },
                 },
                 moving_block_id,
@@ -1410,20 +1607,20 @@ mod tests {
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init().next()),
             Some(&BlockEntry {
-                offset: 85,
+                offset,
                 header: storage::BlockHeader {
                     ref block_id,
                     block_size: 13,
                     ..
                 },
                 environs: Environs {
-                    left: LeftEnvirons::Space { space_key: SpaceKey { space_available: 61, serial: 4, }, },
+                    left: LeftEnvirons::Space { space_key: SpaceKey { space_available: 69, serial: 4, }, },
                     right: RightEnvirons::Space { space_key: SpaceKey { space_available: 6, serial: 3, }, },
                 },
                 ..
-            }) if block_id == &block::Id::init().next()
+            }) if block_id == &block::Id::init().next() && offset == data_offset(69)
         ));
-        assert_eq!(schema.gaps_index.space_total(), 67);
+        assert_eq!(schema.gaps_index.space_total(), 75);
 
         let op = schema.process_write_block_request(&sample_hello_world(), None);
         assert!(matches!(op, WriteBlockOp::Perform(..)));
@@ -1434,7 +1631,7 @@ mod tests {
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init().next()),
             Some(&BlockEntry {
-                offset: 85,
+                offset,
                 header: storage::BlockHeader {
                     block_id: ref block_id_a,
                     block_size: 13,
@@ -1445,7 +1642,7 @@ mod tests {
                     right: RightEnvirons::Space { space_key: SpaceKey { space_available: 6, serial: 3, }, },
                 },
                 ..
-            }) if block_id_a == &block::Id::init().next() && block_id_b == &block::Id::init().next().next()
+            }) if block_id_a == &block::Id::init().next() && block_id_b == &block::Id::init().next().next() && offset == data_offset(69)
         ));
 
         let op = schema.process_delete_block_task_done(block::Id::init().next());
@@ -1455,7 +1652,7 @@ mod tests {
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init().next().next()),
             Some(&BlockEntry {
-                offset: 24,
+                offset,
                 header: storage::BlockHeader {
                     ref block_id,
                     block_size: 13,
@@ -1463,18 +1660,88 @@ mod tests {
                 },
                 environs: Environs {
                     left: LeftEnvirons::Start,
-                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 67, serial: 5, }, },
+                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 75, serial: 5, }, },
                 },
                 ..
-            }) if block_id == &block::Id::init().next().next()
+            }) if block_id == &block::Id::init().next().next() && offset == data_offset(0)
         ));
-        assert_eq!(schema.gaps_index.space_total(), 67);
+        assert_eq!(schema.gaps_index.space_total(), 75);
+    }
+
+    #[test]
+    fn changes_since() {
+        let mut schema = init();
+
+        let op = schema.process_write_block_request(&sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::Perform(..)));
+        let op = schema.process_write_block_request(&sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::Perform(..)));
+
+        let op = schema.process_delete_block_request(&block::Id::init());
+        assert!(matches!(op, DeleteBlockOp::Perform(DeleteBlockPerform { change_seq: 3, })));
+
+        // pending delete is not complete yet
+        let changes = schema.changes_since(0);
+        assert_eq!(changes.change_seq, 2);
+        assert_eq!(changes.blocks_count, 2);
+        assert_eq!(changes.deleted_block_ids, Vec::<block::Id>::new());
+
+        let op = schema.process_delete_block_task_done(block::Id::init());
+        assert!(matches!(op, DeleteBlockTaskDoneOp::Perform(..)));
+
+        let changes = schema.changes_since(0);
+        assert_eq!(changes.change_seq, 3);
+        assert_eq!(changes.blocks_count, 1);
+        assert_eq!(changes.blocks_total_size, 13);
+        assert_eq!(changes.deleted_block_ids, vec![block::Id::init()]);
+
+        let changes = schema.changes_since(2);
+        assert_eq!(changes.change_seq, 3);
+        assert_eq!(changes.blocks_count, 0);
+        assert_eq!(changes.deleted_block_ids, vec![block::Id::init()]);
+        assert_eq!(schema.next_block_id_changed_since(block::Id::init(), 2), None);
+        assert_eq!(schema.next_block_id_changed_since(block::Id::init(), 1), Some(block::Id::init().next()));
+
+        let op = schema.process_write_block_with_id_request(block::Id::init(), &sample_hello_world(), None);
+        assert!(matches!(op, WriteBlockOp::Perform(WriteBlockPerform {
+            task_op: WriteBlockTaskOp { change_seq: 4, .. },
+            ..
+        })));
+
+        let changes = schema.changes_since(2);
+        assert_eq!(changes.change_seq, 4);
+        assert_eq!(changes.blocks_count, 1);
+        assert_eq!(changes.deleted_block_ids, Vec::<block::Id>::new());
+    }
+
+    #[test]
+    fn tombstones_capped() {
+        let mut tombstones = Tombstones::new();
+        let mut block_id = block::Id::init();
+        for change_seq in 1 ..= TOMBSTONES_MAX as u64 + 2 {
+            tombstones.insert(block_id.clone(), change_seq);
+            block_id = block_id.next();
+        }
+        assert_eq!(tombstones.by_change_seq.len(), TOMBSTONES_MAX);
+        assert_eq!(tombstones.by_block_id.len(), TOMBSTONES_MAX);
+        assert_eq!(tombstones.horizon, 2);
+        assert_eq!(tombstones.by_block_id.get(&block::Id::init()), None);
+
+        tombstones.remove(&block::Id::init().next().next());
+        assert_eq!(tombstones.by_change_seq.get(&3), None);
+        assert_eq!(tombstones.by_change_seq.len(), TOMBSTONES_MAX - 1);
+
+        // reinserted tombstone replaces the previous one
+        tombstones.insert(block::Id::init().next().next().next(), TOMBSTONES_MAX as u64 + 3);
+        assert_eq!(tombstones.by_change_seq.get(&4), None);
+        assert_eq!(tombstones.by_change_seq.len(), TOMBSTONES_MAX - 1);
+        assert_eq!(tombstones.horizon, 2);
     }
 
     #[test]
     fn process_delete_block_task_done_defrag() {
         let mut schema = init();
-        assert_eq!(schema.gaps_index.space_total(), 128);
+        assert_eq!(schema.gaps_index.space_total(), 144);
 
         let op = schema.process_write_block_request(&sample_hello_world(), Some(0));
         assert!(matches!(op, WriteBlockOp::Perform(..)));
@@ -1488,7 +1755,7 @@ mod tests {
         assert!(matches!(op, DeleteBlockTaskDoneOp::Perform(DeleteBlockTaskDonePerform {
             defrag_op: DefragOp::Queue {
                 defrag_gaps: DefragGaps::Both {
-                    space_key_left: SpaceKey { space_available: 61, serial: 4, },
+                    space_key_left: SpaceKey { space_available: 69, serial: 4, },
                     space_key_right: SpaceKey { space_available: 6, serial: 3 },
                 },
                 moving_block_id,
@@ -1497,21 +1764,21 @@ mod tests {
         }) if moving_block_id == block::Id::init().next()));
 
         // defrag delete
-        assert_eq!(schema.gaps_index.space_total(), 67);
+        assert_eq!(schema.gaps_index.space_total(), 75);
 
         let op = schema.process_delete_block_request(&block::Id::init().next());
         assert!(matches!(op, DeleteBlockOp::Perform(DeleteBlockPerform { .. })));
 
         let op = schema.process_delete_block_task_done_defrag(block::Id::init().next());
         assert!(matches!(op, DeleteBlockTaskDoneDefragOp::Perform(DeleteBlockTaskDoneDefragPerform {
-            block_offset: 24,
+            block_offset,
             ..
-        })));
+        }) if block_offset == data_offset(0)));
 
         assert!(matches!(
             schema.blocks_index.get(&block::Id::init().next()),
             Some(&BlockEntry {
-                offset: 24,
+                offset,
                 header: storage::BlockHeader {
                     block_id: ref block_id_a,
                     block_size: 13,
@@ -1519,12 +1786,12 @@ mod tests {
                 },
                 environs: Environs {
                     left: LeftEnvirons::Start,
-                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 67, serial: 5, }, },
+                    right: RightEnvirons::Space { space_key: SpaceKey { space_available: 75, serial: 5, }, },
                 },
                 ..
-            }) if block_id_a == &block::Id::init().next()
+            }) if block_id_a == &block::Id::init().next() && offset == data_offset(0)
         ));
 
-        assert_eq!(schema.gaps_index.space_total(), 67);
+        assert_eq!(schema.gaps_index.space_total(), 75);
     }
 }
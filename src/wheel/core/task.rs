@@ -121,6 +121,7 @@ pub enum ReadBlockProcessContext<C> where C: Context {
     IterBlocks {
         iter_blocks_stream_context: C::IterBlocksStream,
         next_block_id: block::Id,
+        changed_since: u64,
     },
 }
 
@@ -180,6 +181,8 @@ impl<C> fmt::Debug for ReadBlockContext<C> where C: Context {
 
 pub struct DeleteBlock<C> {
     pub delete_block_bytes: Bytes,
+    // whole area occupied by the block being deleted: header, contents and commit tag
+    pub total_chunk_size: usize,
     pub commit: Commit,
     pub context: DeleteBlockContext<C>,
 }
@@ -188,6 +191,7 @@ impl<C> fmt::Debug for DeleteBlock<C> {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("DeleteBlock")
             .field("delete_block_bytes", &self.delete_block_bytes)
+            .field("total_chunk_size", &self.total_chunk_size)
             .field("context", &self.context)
             .finish()
     }
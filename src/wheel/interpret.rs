@@ -1,4 +1,7 @@
 use std::{
+    fs,
+    io,
+    path::Path,
     sync::{
         mpsc,
     },
@@ -18,8 +21,11 @@ use alloc_pool::bytes::{
     BytesPool,
 };
 
+use rand::RngCore;
+
 use crate::{
     InterpretStats,
+    SecureDelete,
     context::Context,
     wheel::{
         block,
@@ -28,8 +34,19 @@ use crate::{
     },
 };
 
+mod block_io;
+
 pub mod ram;
 pub mod fixed_file;
+pub mod faulty;
+pub mod custom;
+pub mod mirror;
+#[cfg(unix)]
+pub mod positional;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod io_uring;
+#[cfg(feature = "mmap")]
+pub mod mmap;
 
 struct Request<C> where C: Context {
     offset: u64,
@@ -55,23 +72,38 @@ enum Command<C> where C: Context {
 pub enum CreateError {
     FixedFile(fixed_file::WheelCreateError),
     Ram(ram::WheelCreateError),
+    Custom(custom::WheelCreateError),
 }
 
 #[derive(Debug)]
 pub enum OpenError {
     FixedFile(fixed_file::WheelOpenError),
+    Ram(ram::WheelOpenError),
+    Custom(custom::WheelOpenError),
+    Mirror(mirror::WheelOpenError),
 }
 
 #[derive(Debug)]
 pub enum RunError {
     FixedFile(fixed_file::Error),
+    #[cfg(unix)]
+    Positional(positional::Error),
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    IoUring(io_uring::Error),
+    #[cfg(feature = "mmap")]
+    Mmap(mmap::Error),
     Ram(ram::Error),
+    Faulty(faulty::Error),
+    Custom(custom::Error),
+    Mirror(mirror::Error),
 }
 
 #[derive(Debug)]
 pub enum TaskJoinError {
     FixedFile(fixed_file::TaskJoinError),
     Ram(ram::TaskJoinError),
+    Custom(custom::TaskJoinError),
+    Mirror(mirror::TaskJoinError),
 }
 
 #[derive(Clone)]
@@ -126,6 +158,9 @@ pub struct BlockPrepareWriteJobDone {
 pub struct BlockPrepareWriteJobArgs {
     pub block_id: block::Id,
     pub block_bytes: Bytes,
+    pub change_seq: u64,
+    // prepared bytes are zero padded up to this size, see `storage::Layout::block_chunk_size`
+    pub total_chunk_size: usize,
     pub blocks_pool: BytesPool,
 }
 
@@ -135,6 +170,8 @@ pub fn block_prepare_write_job(
     BlockPrepareWriteJobArgs {
         block_id,
         block_bytes,
+        change_seq,
+        total_chunk_size,
         blocks_pool,
     }: BlockPrepareWriteJobArgs,
 )
@@ -161,6 +198,7 @@ pub fn block_prepare_write_job(
     let commit_tag = storage::CommitTag {
         block_id: block_id.clone(),
         crc: block::crc(&block_bytes),
+        change_seq,
         ..Default::default()
     };
     storage::bincode_options()
@@ -168,6 +206,11 @@ pub fn block_prepare_write_job(
         .map_err(BlockPrepareWriteJobError::CommitTagSerialize)?;
     assert!(write_block_bytes.len() > write_block_bytes_len);
 
+    // padding keeps aligned writes whole sectors
+    if write_block_bytes.len() < total_chunk_size {
+        write_block_bytes.resize(total_chunk_size, 0);
+    }
+
     Ok(BlockPrepareWriteJobDone { write_block_bytes, })
 }
 
@@ -181,6 +224,10 @@ pub struct BlockPrepareDeleteJobDone {
 }
 
 pub struct BlockPrepareDeleteJobArgs {
+    pub block_id: block::Id,
+    pub change_seq: u64,
+    pub secure_delete: Option<SecureDelete>,
+    pub total_chunk_size: usize,
     pub blocks_pool: BytesPool,
 }
 
@@ -188,6 +235,10 @@ pub type BlockPrepareDeleteJobOutput = Result<BlockPrepareDeleteJobDone, BlockPr
 
 pub fn block_prepare_delete_job(
     BlockPrepareDeleteJobArgs {
+        block_id,
+        change_seq,
+        secure_delete,
+        total_chunk_size,
         blocks_pool,
     }: BlockPrepareDeleteJobArgs,
 )
@@ -195,11 +246,26 @@ pub fn block_prepare_delete_job(
 {
     let mut delete_block_bytes = blocks_pool.lend();
 
-    let tombstone_tag = storage::TombstoneTag::default();
+    let tombstone_tag = storage::TombstoneTag {
+        block_id,
+        change_seq,
+        ..Default::default()
+    };
     storage::bincode_options()
         .serialize_into(&mut **delete_block_bytes, &tombstone_tag)
         .map_err(BlockPrepareDeleteJobError::TombstoneTagSerialize)?;
 
+    // tombstone is still required for recovery, the rest of block area is overwritten along with it
+    if let Some(secure_delete) = secure_delete {
+        let tombstone_size = delete_block_bytes.len();
+        if total_chunk_size > tombstone_size {
+            delete_block_bytes.resize(total_chunk_size, 0);
+            if let SecureDelete::Random = secure_delete {
+                rand::thread_rng().fill_bytes(&mut delete_block_bytes[tombstone_size ..]);
+            }
+        }
+    }
+
     Ok(BlockPrepareDeleteJobDone { delete_block_bytes, })
 }
 
@@ -212,6 +278,11 @@ pub enum BlockProcessReadJobError {
 
 #[derive(Debug)]
 pub enum CorruptedDataError {
+    BlockLengthMismatch {
+        block_id: block::Id,
+        block_size: usize,
+        length_actual: usize,
+    },
     BlockIdMismatch {
         block_id_expected: block::Id,
         block_id_actual: block::Id,
@@ -253,6 +324,29 @@ pub fn block_process_read_job(
 )
     -> BlockProcessReadJobOutput
 {
+    block_verify(&storage_layout, &block_header, &block_bytes)?;
+    let block_buffer_end = block_bytes.len() - storage_layout.commit_tag_size;
+    let block_bytes = block_bytes.subrange(storage_layout.block_header_size .. block_buffer_end);
+    let block_id = block_header.block_id;
+    Ok(BlockProcessReadJobDone { block_id, block_bytes, })
+}
+
+// checks block chunk as it is read from the wheel: header, commit tag and crc of contents
+pub fn block_verify(
+    storage_layout: &storage::Layout,
+    block_header: &storage::BlockHeader,
+    block_bytes: &[u8],
+)
+    -> Result<(), BlockProcessReadJobError>
+{
+    if storage_layout.data_size_block_min().checked_add(block_header.block_size) != Some(block_bytes.len()) {
+        return Err(BlockProcessReadJobError::CorruptedData(CorruptedDataError::BlockLengthMismatch {
+            block_id: block_header.block_id.clone(),
+            block_size: block_header.block_size,
+            length_actual: block_bytes.len(),
+        }));
+    }
+
     let block_buffer_start = storage_layout.block_header_size;
     let block_buffer_end = block_bytes.len() - storage_layout.commit_tag_size;
 
@@ -261,14 +355,14 @@ pub fn block_process_read_job(
         .map_err(BlockProcessReadJobError::BlockHeaderDeserialize)?;
     if storage_block_header.block_id != block_header.block_id {
         return Err(BlockProcessReadJobError::CorruptedData(CorruptedDataError::BlockIdMismatch {
-            block_id_expected: block_header.block_id,
+            block_id_expected: block_header.block_id.clone(),
             block_id_actual: storage_block_header.block_id,
         }));
     }
 
     if storage_block_header.block_size != block_header.block_size {
         return Err(BlockProcessReadJobError::CorruptedData(CorruptedDataError::BlockSizeMismatch {
-            block_id: block_header.block_id,
+            block_id: block_header.block_id.clone(),
             block_size_expected: block_header.block_size,
             block_size_actual: storage_block_header.block_size,
         }));
@@ -278,14 +372,12 @@ pub fn block_process_read_job(
         .map_err(BlockProcessReadJobError::CommitTagDeserialize)?;
     if commit_tag.block_id != block_header.block_id {
         return Err(BlockProcessReadJobError::CorruptedData(CorruptedDataError::CommitTagBlockIdMismatch {
-            block_id_expected: block_header.block_id,
+            block_id_expected: block_header.block_id.clone(),
             block_id_actual: commit_tag.block_id,
         }));
     }
-    let block_bytes = block_bytes.subrange(block_buffer_start .. block_buffer_end);
-    let block_id = block_header.block_id;
 
-    let crc_expected = block::crc(&block_bytes);
+    let crc_expected = block::crc(&block_bytes[block_buffer_start .. block_buffer_end]);
     if commit_tag.crc != crc_expected {
         return Err(BlockProcessReadJobError::CorruptedData(CorruptedDataError::CommitTagCrcMismatch {
             crc_expected,
@@ -293,5 +385,22 @@ pub fn block_process_read_job(
         }));
     }
 
-    Ok(BlockProcessReadJobDone { block_id, block_bytes, })
+    Ok(())
+}
+
+// rename is durable only after its directory entry is synced
+#[cfg(unix)]
+fn sync_parent_dir(filename: &Path) -> Result<(), io::Error> {
+    let parent_dir = match filename.parent() {
+        Some(parent_dir) if !parent_dir.as_os_str().is_empty() =>
+            parent_dir,
+        Some(..) | None =>
+            Path::new("."),
+    };
+    fs::File::open(parent_dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_filename: &Path) -> Result<(), io::Error> {
+    Ok(())
 }
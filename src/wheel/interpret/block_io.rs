@@ -0,0 +1,180 @@
+use std::{
+    sync::mpsc,
+};
+
+use alloc_pool::bytes::{
+    BytesMut,
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        block,
+        storage,
+        core::task,
+        interpret::{
+            Synced,
+            Command,
+            Request,
+            DoneTask,
+        },
+    },
+    InterpretStats,
+};
+
+// positional access to a wheel image for the synchronous `busyloop`
+pub(super) trait BlockIo {
+    type Error;
+
+    fn write_block(&mut self, block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Self::Error>;
+
+    // `block_id` is the one of the block being committed
+    fn write_terminator(&mut self, block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Self::Error>;
+
+    // appends `total_chunk_size` bytes stored at `offset` to `block_bytes`
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Self::Error>;
+
+    // tombstone is written over the start of the block area `total_chunk_size` long
+    fn delete_block(&mut self, block_id: &block::Id, tombstone_bytes: &[u8], _total_chunk_size: usize, offset: u64) -> Result<(), Self::Error> {
+        self.write_block(block_id, tombstone_bytes, offset)
+    }
+
+    fn sync(&mut self) -> Result<(), Self::Error>;
+}
+
+pub(super) fn busyloop<C, B>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    mut block_io: B,
+    storage_layout: storage::Layout,
+    terminator_block_bytes: BytesMut,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), B::Error>
+where C: Context,
+      B: BlockIo,
+{
+    let mut stats = InterpretStats::default();
+
+    let mut cursor = storage_layout.data_offset_start() as u64;
+    let mut pending_terminator = None;
+    loop {
+        let command = match request_rx.recv() {
+            Ok(command) =>
+                command,
+            Err(mpsc::RecvError) =>
+                break,
+        };
+
+        match command {
+
+            Command::Request(Request { offset, task, reply_tx, }) => {
+                stats.count_total += 1;
+
+                if cursor < offset {
+                    stats.count_seek_forward += 1;
+                } else if cursor > offset {
+                    stats.count_seek_backward += 1;
+                    if let Some(block_id) = pending_terminator.take() {
+                        log::debug!("writing pending_terminator during seek @ {}", cursor);
+                        block_io.write_terminator(&block_id, &terminator_block_bytes, cursor)?;
+                    }
+                } else {
+                    stats.count_no_seek += 1;
+                }
+                cursor = offset;
+
+                let kind = match task.kind {
+                    task::TaskKind::WriteBlock(write_block) => {
+                        log::debug!(
+                            "write block {:?} @ {} of {} bytes, context: {:?}",
+                            task.block_id,
+                            cursor,
+                            write_block.write_block_bytes.len(),
+                            write_block.context,
+                        );
+                        block_io.write_block(&task.block_id, &write_block.write_block_bytes, cursor)?;
+                        cursor += write_block.write_block_bytes.len() as u64;
+                        pending_terminator = match write_block.commit {
+                            task::Commit::None =>
+                                None,
+                            task::Commit::WithTerminator =>
+                                Some(task.block_id.clone()),
+                        };
+                        task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
+                            context: write_block.context,
+                        })
+                    },
+                    task::TaskKind::ReadBlock(task::ReadBlock { block_header, context, }) => {
+                        let total_chunk_size = storage_layout.data_size_block_min()
+                            + block_header.block_size;
+                        log::debug!(
+                            "read block {:?} @ {} of {} bytes, context = {:?}",
+                            task.block_id,
+                            cursor,
+                            total_chunk_size,
+                            context,
+                        );
+                        let mut block_bytes = blocks_pool.lend();
+                        block_io.read_block(&mut block_bytes, &block_header, total_chunk_size, cursor)?;
+                        cursor += total_chunk_size as u64;
+                        task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
+                            block_bytes,
+                            context,
+                        })
+                    },
+                    task::TaskKind::DeleteBlock(delete_block) => {
+                        log::debug!("delete block {:?} @ {}, context: {:?}", task.block_id, cursor, delete_block.context);
+                        block_io.delete_block(&task.block_id, &delete_block.delete_block_bytes, delete_block.total_chunk_size, cursor)?;
+                        cursor += delete_block.delete_block_bytes.len() as u64;
+                        pending_terminator = match delete_block.commit {
+                            task::Commit::None =>
+                                None,
+                            task::Commit::WithTerminator =>
+                                Some(task.block_id.clone()),
+                        };
+                        task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
+                            context: delete_block.context,
+                        })
+                    },
+                };
+
+                let task_done = task::Done {
+                    current_offset: cursor,
+                    task: task::TaskDone {
+                        block_id: task.block_id,
+                        kind,
+                    },
+                };
+                if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats: stats.clone(), }) {
+                    break;
+                }
+            },
+
+            Command::DeviceSync { reply_tx, } => {
+                if let Some(block_id) = pending_terminator.take() {
+                    log::debug!("writing pending_terminator during flush @ {}", cursor);
+                    block_io.write_terminator(&block_id, &terminator_block_bytes, cursor)?;
+                    cursor += terminator_block_bytes.len() as u64;
+                } else {
+                    log::debug!("flushed with no pending_terminator (cursor @ {})", cursor);
+                }
+                block_io.sync()?;
+                if let Err(_send_error) = reply_tx.send(Synced) {
+                    break;
+                }
+            },
+
+        }
+    }
+
+    log::debug!("master channel closed in busyloop, shutting down");
+    Ok(())
+}
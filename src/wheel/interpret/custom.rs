@@ -0,0 +1,355 @@
+use std::{
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    thread,
+    sync::{
+        mpsc,
+    },
+};
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+};
+
+use bincode::Options;
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    block,
+    interpreter::{
+        self,
+        Interpreter,
+    },
+    context::Context,
+    wheel::{
+        storage,
+        core::performer,
+        interpret::{
+            fixed_file,
+            block_io,
+            Pid,
+            Command,
+            AppendTerminatorError,
+            block_append_terminator,
+        },
+    },
+};
+
+#[derive(Debug)]
+pub enum Error {
+    AppendTerminator(AppendTerminatorError),
+    ThreadSpawn(io::Error),
+    Run(io::Error),
+    BlockWrite(io::Error),
+    TerminatorWrite(io::Error),
+    BlockRead(io::Error),
+    DeviceSync(io::Error),
+}
+
+#[derive(Debug)]
+pub enum WheelCreateError {
+    InitWheelSizeIsTooSmall {
+        provided: usize,
+        required_min: usize,
+    },
+    HeaderSerialize(bincode::Error),
+    TerminatorTagSerialize(bincode::Error),
+    Create(io::Error),
+}
+
+#[derive(Debug)]
+pub enum WheelOpenError {
+    Open(io::Error),
+    WheelSize(io::Error),
+    HeaderSeek(io::Error),
+    HeaderRead(io::Error),
+    Load(fixed_file::WheelOpenError),
+    WheelSizeMismatch {
+        header: u64,
+        actual: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum TaskJoinError {
+    Create(tokio::task::JoinError),
+    Open(tokio::task::JoinError),
+}
+
+pub struct WheelData<C> where C: Context {
+    pub sync_gen_server: SyncGenServer<C>,
+    pub performer: performer::Performer<C>,
+}
+
+pub enum WheelOpenStatus<C> where C: Context {
+    Success(WheelData<C>),
+    WheelNotFound {
+        interpreter: Box<dyn Interpreter>,
+        performer_builder: performer::PerformerBuilderInit<C>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct CreateParams {
+    pub init_wheel_size_bytes: usize,
+}
+
+pub struct SyncGenServer<C> where C: Context {
+    interpreter: Box<dyn Interpreter>,
+    request_tx: mpsc::Sender<Command<C>>,
+    request_rx: mpsc::Receiver<Command<C>>,
+    storage_layout: storage::Layout,
+}
+
+impl<C> SyncGenServer<C> where C: Context {
+    pub fn create(
+        mut interpreter: Box<dyn Interpreter>,
+        params: CreateParams,
+        performer_builder: performer::PerformerBuilderInit<C>,
+    )
+        -> Result<WheelData<C>, WheelCreateError>
+    {
+        log::debug!("creating new custom wheel of {:?} bytes on {:?}", params.init_wheel_size_bytes, interpreter);
+
+        let mut wheel_init = Vec::new();
+
+        let wheel_header = storage::WheelHeader {
+            wheel_id: storage::generate_wheel_id(),
+            size_bytes: params.init_wheel_size_bytes as u64,
+            block_align: performer_builder.storage_layout().block_align,
+            ..storage::WheelHeader::default()
+        };
+        storage::bincode_options()
+            .serialize_into(&mut wheel_init, &wheel_header)
+            .map_err(WheelCreateError::HeaderSerialize)?;
+
+        let terminator_tag = storage::TerminatorTag::default();
+        storage::bincode_options()
+            .serialize_into(&mut wheel_init, &terminator_tag)
+            .map_err(WheelCreateError::TerminatorTagSerialize)?;
+
+        let min_wheel_file_size = performer_builder.storage_layout().wheel_header_size
+            + performer_builder.storage_layout().terminator_tag_size;
+        assert_eq!(wheel_init.len(), min_wheel_file_size);
+        if params.init_wheel_size_bytes < min_wheel_file_size {
+            return Err(WheelCreateError::InitWheelSizeIsTooSmall {
+                provided: params.init_wheel_size_bytes,
+                required_min: min_wheel_file_size,
+            });
+        }
+
+        interpreter.create(params.init_wheel_size_bytes, &wheel_init)
+            .map_err(WheelCreateError::Create)?;
+
+        log::debug!("custom wheel create success");
+        let storage_layout = performer_builder.storage_layout().clone();
+
+        let (request_tx, request_rx) = mpsc::channel();
+
+        let (performer_builder, _work_block) = performer_builder.start_fill();
+
+        Ok(WheelData {
+            sync_gen_server: SyncGenServer {
+                interpreter,
+                request_tx,
+                request_rx,
+                storage_layout,
+            },
+            performer: performer_builder
+                .finish(wheel_header.wheel_id, params.init_wheel_size_bytes),
+        })
+    }
+
+    // wheel is scanned through interpreter reader the same way as a `fixed_file` one
+    pub fn open(
+        mut interpreter: Box<dyn Interpreter>,
+        performer_builder: performer::PerformerBuilderInit<C>,
+    )
+        -> Result<WheelOpenStatus<C>, WheelOpenError>
+    {
+        log::debug!("opening custom wheel on {:?}", interpreter);
+
+        let loaded = match interpreter.open().map_err(WheelOpenError::Open)? {
+            Some(mut wheel) =>
+                Ok(load(&mut wheel, performer_builder)?),
+            None =>
+                Err(performer_builder),
+        };
+        let (storage_layout, performer) = match loaded {
+            Ok(loaded) =>
+                loaded,
+            Err(performer_builder) =>
+                return Ok(WheelOpenStatus::WheelNotFound { interpreter, performer_builder, }),
+        };
+
+        log::debug!("custom wheel open success");
+
+        let (request_tx, request_rx) = mpsc::channel();
+
+        Ok(WheelOpenStatus::Success(WheelData {
+            sync_gen_server: SyncGenServer {
+                interpreter,
+                request_tx,
+                request_rx,
+                storage_layout,
+            },
+            performer,
+        }))
+    }
+
+    pub fn pid(&self) -> Pid<C> {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub fn run<F, E>(
+        self,
+        blocks_pool: BytesPool,
+        error_tx: oneshot::Sender<E>,
+        error_map: F,
+    )
+        -> Result<(), Error>
+    where F: FnOnce(Error) -> E + Send + 'static,
+          E: Send + 'static,
+          C: 'static,
+          C::WriteBlock: Send,
+          C::ReadBlock: Send,
+          C::DeleteBlock: Send,
+          C::IterBlocksStream: Send,
+    {
+        let SyncGenServer { interpreter, request_rx, storage_layout, .. } = self;
+        thread::Builder::new()
+            .name("wheel::interpret::custom".to_string())
+            .spawn(move || {
+                let result = busyloop(
+                    request_rx,
+                    interpreter,
+                    storage_layout,
+                    blocks_pool,
+                );
+                if let Err(error) = result {
+                    log::error!("wheel::interpret::custom terminated with {:?}", error);
+                    error_tx.send(error_map(error)).ok();
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+        Ok(())
+    }
+}
+
+fn load<C, R>(
+    wheel: &mut R,
+    mut performer_builder: performer::PerformerBuilderInit<C>,
+)
+    -> Result<(storage::Layout, performer::Performer<C>), WheelOpenError>
+where C: Context,
+      R: Read + Seek,
+{
+    let wheel_size = wheel.seek(SeekFrom::End(0))
+        .map_err(WheelOpenError::WheelSize)?;
+    wheel.seek(SeekFrom::Start(0))
+        .map_err(WheelOpenError::HeaderSeek)?;
+
+    let wheel_header_size = performer_builder
+        .storage_layout()
+        .wheel_header_size;
+    performer_builder
+        .work_block_cleared()
+        .extend((0 .. wheel_header_size).map(|_| 0));
+    wheel.read_exact(performer_builder.work_block())
+        .map_err(WheelOpenError::HeaderRead)?;
+    let wheel_header = fixed_file::parse_wheel_header(performer_builder.work_block())
+        .map_err(WheelOpenError::Load)?;
+    if wheel_header.size_bytes != wheel_size {
+        return Err(WheelOpenError::WheelSizeMismatch {
+            header: wheel_header.size_bytes,
+            actual: wheel_size,
+        });
+    }
+
+    fixed_file::scan_blocks(wheel, performer_builder, &wheel_header)
+        .map_err(WheelOpenError::Load)
+}
+
+// public `Interpreter` driven by the shared `block_io::busyloop`
+struct CustomIo {
+    interpreter: Box<dyn Interpreter>,
+}
+
+impl CustomIo {
+    fn write_at(&mut self, block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), io::Error> {
+        self.interpreter.push_request(interpreter::Task {
+            offset,
+            block_id,
+            kind: interpreter::TaskKind::Write { bytes, },
+        })
+    }
+}
+
+impl block_io::BlockIo for CustomIo {
+    type Error = Error;
+
+    fn write_block(&mut self, block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(block_id, bytes, offset)
+            .map_err(Error::BlockWrite)
+    }
+
+    fn write_terminator(&mut self, block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(block_id, bytes, offset)
+            .map_err(Error::TerminatorWrite)
+    }
+
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Error>
+    {
+        let start = block_bytes.len();
+        block_bytes.resize(start + total_chunk_size, 0);
+        self.interpreter
+            .push_request(interpreter::Task {
+                offset,
+                block_id: &block_header.block_id,
+                kind: interpreter::TaskKind::Read { bytes: &mut block_bytes[start ..], },
+            })
+            .map_err(Error::BlockRead)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.interpreter.device_sync()
+            .map_err(Error::DeviceSync)
+    }
+}
+
+fn busyloop<C>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    mut interpreter: Box<dyn Interpreter>,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    interpreter.run()
+        .map_err(Error::Run)?;
+
+    block_io::busyloop(request_rx, CustomIo { interpreter, }, storage_layout, terminator_block_bytes, blocks_pool)
+}
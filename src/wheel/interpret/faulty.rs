@@ -0,0 +1,260 @@
+use std::{
+    fs,
+    thread,
+    io::{
+        self,
+        Seek,
+        Read,
+        Write,
+    },
+    sync::mpsc,
+};
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+};
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        block,
+        storage,
+        interpret::{
+            fixed_file,
+            block_io,
+            Pid,
+            Command,
+            AppendTerminatorError,
+            block_append_terminator,
+        },
+    },
+    Faults,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    AppendTerminator(AppendTerminatorError),
+    BlockWrite {
+        offset: u64,
+        error: io::Error,
+    },
+    TerminatorWrite {
+        offset: u64,
+        error: io::Error,
+    },
+    BlockRead {
+        offset: u64,
+        error: io::Error,
+    },
+    DeviceSyncFlush(io::Error),
+    ThreadSpawn(io::Error),
+}
+
+pub struct SyncGenServer<C> where C: Context {
+    wheel_file: fs::File,
+    request_tx: mpsc::Sender<Command<C>>,
+    request_rx: mpsc::Receiver<Command<C>>,
+    storage_layout: storage::Layout,
+}
+
+impl<C> SyncGenServer<C> where C: Context {
+    // faults are injected over a regular `fixed_file` wheel, so the image left after a crash opens with it as well
+    pub fn new(fixed_file_gen_server: fixed_file::SyncGenServer<C>) -> SyncGenServer<C> {
+        let (wheel_file, request_tx, request_rx, storage_layout) =
+            fixed_file_gen_server.into_parts();
+        SyncGenServer { wheel_file, request_tx, request_rx, storage_layout, }
+    }
+
+    pub fn pid(&self) -> Pid<C> {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub fn run<F, E>(
+        self,
+        faults: Faults,
+        blocks_pool: BytesPool,
+        error_tx: oneshot::Sender<E>,
+        error_map: F,
+    )
+        -> Result<(), Error>
+    where F: FnOnce(Error) -> E + Send + 'static,
+          E: Send + 'static,
+          C: 'static,
+          C::WriteBlock: Send,
+          C::ReadBlock: Send,
+          C::DeleteBlock: Send,
+          C::IterBlocksStream: Send,
+    {
+        let SyncGenServer { wheel_file, request_rx, storage_layout, .. } = self;
+        thread::Builder::new()
+            .name("wheel::interpret::faulty".to_string())
+            .spawn(move || {
+                let result = busyloop(
+                    request_rx,
+                    FaultyFile::new(wheel_file, faults),
+                    storage_layout,
+                    blocks_pool,
+                );
+                if let Err(error) = result {
+                    log::error!("wheel::interpret::faulty terminated with {:?}", error);
+                    error_tx.send(error_map(error)).ok();
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+        Ok(())
+    }
+}
+
+struct FaultyFile {
+    wheel_file: fs::File,
+    faults: Faults,
+    writes_count: usize,
+    reads_count: usize,
+    bytes_written: u64,
+    powered_off: bool,
+    // previous contents of areas written since the last sync, reverted on power cut
+    unsynced: Vec<(u64, Vec<u8>)>,
+}
+
+impl FaultyFile {
+    fn new(wheel_file: fs::File, faults: Faults) -> FaultyFile {
+        FaultyFile {
+            wheel_file,
+            faults,
+            writes_count: 0,
+            reads_count: 0,
+            bytes_written: 0,
+            powered_off: false,
+            unsynced: Vec::new(),
+        }
+    }
+
+    fn write_at(&mut self, bytes: &[u8], offset: u64) -> Result<(), io::Error> {
+        self.writes_count += 1;
+        if self.powered_off {
+            log::debug!("dropping write #{} @ {} of {} bytes after power cut", self.writes_count, offset, bytes.len());
+            return Ok(());
+        }
+        if self.faults.fail_write_nth == Some(self.writes_count) {
+            log::debug!("failing write #{} @ {} of {} bytes", self.writes_count, offset, bytes.len());
+            // EIO
+            return Err(io::Error::from_raw_os_error(5));
+        }
+        let mut keep_bytes = bytes.len();
+        if let Some(tear_write) = self.faults.tear_write {
+            if tear_write.nth_write == self.writes_count {
+                keep_bytes = keep_bytes.min(tear_write.keep_bytes);
+                self.powered_off = true;
+            }
+        }
+        if let Some(power_cut_bytes) = self.faults.power_cut_after_write_bytes {
+            let bytes_left = power_cut_bytes.saturating_sub(self.bytes_written);
+            if bytes_left < keep_bytes as u64 {
+                keep_bytes = bytes_left as usize;
+                self.powered_off = true;
+            }
+        }
+        if self.powered_off {
+            log::debug!("power cut during write #{} @ {}: {} of {} bytes written", self.writes_count, offset, keep_bytes, bytes.len());
+        }
+
+        if self.faults.drop_unsynced_on_power_cut {
+            let mut prev_bytes = vec![0; keep_bytes];
+            self.wheel_file.seek(io::SeekFrom::Start(offset))?;
+            self.wheel_file.read_exact(&mut prev_bytes)?;
+            self.unsynced.push((offset, prev_bytes));
+        }
+        self.wheel_file.seek(io::SeekFrom::Start(offset))?;
+        self.wheel_file.write_all(&bytes[.. keep_bytes])?;
+        self.bytes_written += keep_bytes as u64;
+
+        if self.powered_off {
+            while let Some((offset, prev_bytes)) = self.unsynced.pop() {
+                log::debug!("power cut reverts unsynced write @ {} of {} bytes", offset, prev_bytes.len());
+                self.wheel_file.seek(io::SeekFrom::Start(offset))?;
+                self.wheel_file.write_all(&prev_bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_at(&mut self, target: &mut [u8], offset: u64) -> Result<(), io::Error> {
+        self.reads_count += 1;
+        self.wheel_file.seek(io::SeekFrom::Start(offset))?;
+        self.wheel_file.read_exact(target)?;
+        if let Some(flip_bit) = self.faults.flip_bit_on_read {
+            if flip_bit.nth_read == self.reads_count && !target.is_empty() {
+                let bit = flip_bit.bit % (target.len() * 8);
+                log::debug!("flipping bit {} of read #{} @ {}", bit, self.reads_count, offset);
+                target[bit / 8] ^= 1 << (bit % 8);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl block_io::BlockIo for FaultyFile {
+    type Error = Error;
+
+    fn write_block(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+            .map_err(|error| Error::BlockWrite { offset, error, })
+    }
+
+    fn write_terminator(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+            .map_err(|error| Error::TerminatorWrite { offset, error, })
+    }
+
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        _block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Error>
+    {
+        block_bytes.resize(total_chunk_size, 0);
+        self.read_at(&mut block_bytes[..], offset)
+            .map_err(|error| Error::BlockRead { offset, error, })
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        if self.powered_off {
+            if self.faults.report_power_cut_on_sync {
+                // EIO
+                return Err(Error::DeviceSyncFlush(io::Error::from_raw_os_error(5)));
+            }
+            return Ok(());
+        }
+        self.wheel_file.sync_data()
+            .map_err(Error::DeviceSyncFlush)?;
+        self.unsynced.clear();
+        Ok(())
+    }
+}
+
+fn busyloop<C>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    wheel_file: FaultyFile,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    block_io::busyloop(request_rx, wheel_file, storage_layout, terminator_block_bytes, blocks_pool)
+}
@@ -1,6 +1,11 @@
 use std::{
     fs,
     thread,
+    collections::{
+        hash_map,
+        HashMap,
+        BTreeMap,
+    },
     io::{
         self,
         Seek,
@@ -37,23 +42,24 @@ use crate::{
     wheel::{
         block,
         storage,
-        core::{
-            task,
-            performer,
-        },
+        core::performer,
         interpret::{
+            block_io,
             Pid,
-            Synced,
             Command,
-            Request,
-            DoneTask,
             AppendTerminatorError,
             block_append_terminator,
         },
     },
-    InterpretStats,
+    WheelCreateMode,
 };
 
+#[cfg(target_os = "linux")]
+mod direct;
+
+// `O_DIRECT` access granularity, blocks of wheels used with `RunParams::direct_io` are aligned to it
+pub const SECTOR_ALIGN: usize = 4096;
+
 #[cfg(test)]
 mod tests;
 
@@ -71,6 +77,18 @@ pub enum Error {
     BlockRead(io::Error),
     DeviceSyncFlush(io::Error),
     ThreadSpawn(io::Error),
+    PunchHole {
+        offset: u64,
+        len: u64,
+        error: io::Error,
+    },
+    PunchHoleUnsupported,
+    DirectIoUnsupported,
+    DirectOpen(io::Error),
+    DirectWheelSizeNotAligned {
+        wheel_size_bytes: u64,
+        align: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -79,14 +97,34 @@ pub enum WheelCreateError {
         wheel_filename: PathBuf,
         error: io::Error,
     },
+    FileLock {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+    Locked {
+        wheel_filename: PathBuf,
+    },
     InitWheelSizeIsTooSmall {
         provided: usize,
         required_min: usize,
     },
+    WheelSizeNotAligned {
+        provided: usize,
+        block_align: usize,
+    },
     HeaderSerialize(bincode::Error),
     TerminatorTagSerialize(bincode::Error),
+    HeaderSeek(io::Error),
     HeaderTagWrite(io::Error),
     ZeroChunkWrite(io::Error),
+    SparseSetLen(io::Error),
+    DeviceSize(io::Error),
+    DeviceTooSmall {
+        provided: usize,
+        device_size: u64,
+    },
+    Fallocate(io::Error),
+    FallocateUnsupported,
     Flush(io::Error),
 }
 
@@ -101,6 +139,13 @@ pub enum WheelOpenError {
         wheel_filename: PathBuf,
         error: io::Error,
     },
+    FileLock {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+    Locked {
+        wheel_filename: PathBuf,
+    },
     HeaderRead(io::Error),
     HeaderDeserialize(bincode::Error),
     HeaderInvalidMagic {
@@ -111,11 +156,24 @@ pub enum WheelOpenError {
         provided: usize,
         expected: usize,
     },
+    BlockAlignMismatch {
+        header: usize,
+        expected: usize,
+    },
     WheelSizeMismatch {
         header: u64,
         actual: u64,
     },
+    DeviceSize(io::Error),
+    WheelSizeExceedsDevice {
+        header: u64,
+        device_size: u64,
+    },
     LocateBlock(io::Error),
+    ScanTruncated {
+        cursor: u64,
+        file_size: u64,
+    },
     BlockSizeTooLarge {
         work_block_size_bytes: usize,
         block_size: usize,
@@ -131,6 +189,22 @@ pub enum WheelOpenError {
         block_crc: u64,
     },
     BlockSeekEnd(io::Error),
+    BlockSeekEndMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    BlockIdExhausted {
+        block_id: block::Id,
+    },
+    ChangeSeqExhausted {
+        block_id: block::Id,
+    },
+    // block is placed off the alignment recorded in the wheel header
+    BlockNotAligned {
+        block_id: block::Id,
+        offset: u64,
+        block_align: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -149,21 +223,42 @@ pub enum WheelOpenStatus<C> where C: Context {
     FileNotFound {
         performer_builder: performer::PerformerBuilderInit<C>,
     },
+    DeviceBlank {
+        performer_builder: performer::PerformerBuilderInit<C>,
+    },
 }
 
 #[derive(Clone, Debug)]
 pub struct CreateParams<P> {
     pub wheel_filename: P,
     pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct RunParams {
+    pub direct_io: bool,
+    pub punch_holes_min_bytes: Option<usize>,
 }
 
 #[derive(Clone, Debug)]
 pub struct OpenParams<P> {
     pub wheel_filename: P,
+    pub lock_mode: LockMode,
+    pub read_only: bool,
+}
+
+// advisory lock on the wheel file held for the whole interpreter lifetime
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockMode {
+    Exclusive,
+    Shared,
 }
 
 pub struct SyncGenServer<C> where C: Context {
     wheel_file: fs::File,
+    wheel_filename: PathBuf,
+    read_only: bool,
     request_tx: mpsc::Sender<Command<C>>,
     request_rx: mpsc::Receiver<Command<C>>,
     storage_layout: storage::Layout,
@@ -178,6 +273,22 @@ impl<C> SyncGenServer<C> where C: Context {
     {
         log::debug!("creating new wheel file [ {:?} ]", params.wheel_filename.as_ref());
 
+        let size_bytes_total = params.init_wheel_size_bytes;
+        let required_min = performer_builder.storage_layout().service_size_min();
+        if size_bytes_total < required_min {
+            return Err(WheelCreateError::InitWheelSizeIsTooSmall {
+                provided: size_bytes_total,
+                required_min,
+            });
+        }
+        let block_align = performer_builder.storage_layout().block_align;
+        if size_bytes_total % block_align != 0 {
+            return Err(WheelCreateError::WheelSizeNotAligned {
+                provided: size_bytes_total,
+                block_align,
+            });
+        }
+
         let mut wheel_file = fs::OpenOptions::new()
             .read(true)
             .write(true)
@@ -187,50 +298,89 @@ impl<C> SyncGenServer<C> where C: Context {
                 wheel_filename: params.wheel_filename.as_ref().to_owned(),
                 error,
             })?;
+        let locked = try_lock(&wheel_file, LockMode::Exclusive)
+            .map_err(|error| WheelCreateError::FileLock {
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+                error,
+            })?;
+        if !locked {
+            return Err(WheelCreateError::Locked {
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+            });
+        }
+
+        let target_kind = wheel_file.metadata()
+            .map(|metadata| TargetKind::of(&metadata))
+            .map_err(|error| WheelCreateError::FileCreate {
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+                error,
+            })?;
+        if let Some(TargetKind::BlockDevice) = target_kind {
+            let device_size = device_size(&mut wheel_file)
+                .map_err(WheelCreateError::DeviceSize)?;
+            if params.init_wheel_size_bytes as u64 > device_size {
+                return Err(WheelCreateError::DeviceTooSmall {
+                    provided: params.init_wheel_size_bytes,
+                    device_size,
+                });
+            }
+        }
+
+        let work_block_size_bytes = performer_builder.work_block().capacity();
+
+        // space is reserved before the header is written, so a file with a valid header always has its full size;
+        // unwritten regions read as zeroes, so open-time scan passes them the same way as zero filled ones
+        match params.create_mode {
+            WheelCreateMode::Sparse | WheelCreateMode::Fallocate if target_kind == Some(TargetKind::BlockDevice) =>
+                // device space is already there, scan stops at the terminator written below
+                log::debug!("{:?} create mode is a no-op for a block device", params.create_mode),
+            WheelCreateMode::ZeroFill => {
+                performer_builder
+                    .work_block_cleared()
+                    .extend((0 .. work_block_size_bytes).map(|_| 0));
+
+                let mut cursor = 0;
+                while cursor < size_bytes_total {
+                    let bytes_remain = size_bytes_total - cursor;
+                    let write_amount = if bytes_remain < work_block_size_bytes {
+                        bytes_remain
+                    } else {
+                        work_block_size_bytes
+                    };
+                    wheel_file.write_all(&performer_builder.work_block()[.. write_amount])
+                        .map_err(WheelCreateError::ZeroChunkWrite)?;
+                    cursor += write_amount;
+                }
+            },
+            WheelCreateMode::Sparse =>
+                wheel_file.set_len(size_bytes_total as u64)
+                    .map_err(WheelCreateError::SparseSetLen)?,
+            WheelCreateMode::Fallocate =>
+                fallocate(&wheel_file, size_bytes_total as u64)?,
+        }
 
         let wheel_header = storage::WheelHeader {
+            wheel_id: storage::generate_wheel_id(),
             size_bytes: params.init_wheel_size_bytes as u64,
+            block_align,
             ..storage::WheelHeader::default()
         };
         storage::bincode_options()
             .serialize_into(performer_builder.work_block_cleared(), &wheel_header)
             .map_err(WheelCreateError::HeaderSerialize)?;
+        let data_offset_start = performer_builder.storage_layout().data_offset_start();
+        performer_builder.work_block().resize(data_offset_start, 0);
         let terminator_tag = storage::TerminatorTag::default();
         storage::bincode_options()
             .serialize_into(performer_builder.work_block(), &terminator_tag)
             .map_err(WheelCreateError::TerminatorTagSerialize)?;
 
-        let mut cursor = performer_builder.work_block().len();
-        let min_wheel_file_size = performer_builder.storage_layout().wheel_header_size
-            + performer_builder.storage_layout().terminator_tag_size;
-        assert_eq!(cursor, min_wheel_file_size);
+        assert_eq!(performer_builder.work_block().len(), performer_builder.storage_layout().service_size_min());
+        wheel_file.seek(io::SeekFrom::Start(0))
+            .map_err(WheelCreateError::HeaderSeek)?;
         wheel_file.write_all(performer_builder.work_block())
             .map_err(WheelCreateError::HeaderTagWrite)?;
 
-        let work_block_size_bytes = performer_builder.work_block().capacity();
-        let size_bytes_total = params.init_wheel_size_bytes;
-        if size_bytes_total < min_wheel_file_size {
-            return Err(WheelCreateError::InitWheelSizeIsTooSmall {
-                provided: size_bytes_total,
-                required_min: min_wheel_file_size,
-            });
-        }
-
-        performer_builder
-            .work_block_cleared()
-            .extend((0 .. work_block_size_bytes).map(|_| 0));
-
-        while cursor < size_bytes_total {
-            let bytes_remain = size_bytes_total - cursor;
-            let write_amount = if bytes_remain < work_block_size_bytes {
-                bytes_remain
-            } else {
-                work_block_size_bytes
-            };
-            wheel_file.write_all(&performer_builder.work_block()[.. write_amount])
-                .map_err(WheelCreateError::ZeroChunkWrite)?;
-            cursor += write_amount;
-        }
         wheel_file.flush()
             .map_err(WheelCreateError::Flush)?;
 
@@ -244,12 +394,14 @@ impl<C> SyncGenServer<C> where C: Context {
         Ok(WheelData {
             sync_gen_server: SyncGenServer {
                 wheel_file,
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+                read_only: false,
                 request_tx,
                 request_rx,
                 storage_layout,
             },
             performer: performer_builder
-                .finish(params.init_wheel_size_bytes),
+                .finish(wheel_header.wheel_id, params.init_wheel_size_bytes),
         })
     }
 
@@ -261,11 +413,14 @@ impl<C> SyncGenServer<C> where C: Context {
     {
         log::debug!("opening existing wheel file [ {:?} ]", params.wheel_filename.as_ref());
 
-        let file_size = match fs::metadata(&params.wheel_filename) {
-            Ok(ref metadata) if metadata.file_type().is_file() =>
-                metadata.len(),
-            Ok(..) =>
-                return Err(WheelOpenError::FileWrongType),
+        let target_kind = match fs::metadata(&params.wheel_filename) {
+            Ok(ref metadata) =>
+                match TargetKind::of(metadata) {
+                    Some(target_kind) =>
+                        target_kind,
+                    None =>
+                        return Err(WheelOpenError::FileWrongType),
+                },
             Err(ref error) if error.kind() == io::ErrorKind::NotFound =>
                 return Ok(WheelOpenStatus::FileNotFound { performer_builder, }),
             Err(error) =>
@@ -276,124 +431,69 @@ impl<C> SyncGenServer<C> where C: Context {
         };
         let mut wheel_file = fs::OpenOptions::new()
             .read(true)
-            .write(true)
+            .write(!params.read_only)
             .create(false)
             .open(params.wheel_filename.as_ref())
             .map_err(|error| WheelOpenError::FileOpen {
                 wheel_filename: params.wheel_filename.as_ref().to_owned(),
                 error,
             })?;
+        let locked = try_lock(&wheel_file, params.lock_mode)
+            .map_err(|error| WheelOpenError::FileLock {
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+                error,
+            })?;
+        if !locked {
+            return Err(WheelOpenError::Locked {
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+            });
+        }
+        let actual_size = match target_kind {
+            TargetKind::RegularFile { file_size, } =>
+                file_size,
+            TargetKind::BlockDevice =>
+                device_size(&mut wheel_file)
+                    .map_err(WheelOpenError::DeviceSize)?,
+        };
 
         let wheel_header_size = performer_builder
             .storage_layout()
             .wheel_header_size;
 
         // read wheel header
-        performer_builder
-            .work_block_cleared()
-            .extend((0 .. wheel_header_size).map(|_| 0));
-        wheel_file.read_exact(performer_builder.work_block())
-            .map_err(WheelOpenError::HeaderRead)?;
-        let wheel_header: storage::WheelHeader = storage::bincode_options()
-            .deserialize_from(&performer_builder.work_block()[..])
-            .map_err(WheelOpenError::HeaderDeserialize)?;
-        if wheel_header.magic != storage::WHEEL_MAGIC {
-            return Err(WheelOpenError::HeaderInvalidMagic {
-                provided: wheel_header.magic,
-                expected: storage::WHEEL_MAGIC,
-            });
-        }
-        if wheel_header.version != storage::WHEEL_VERSION {
-            return Err(WheelOpenError::HeaderVersionMismatch {
-                provided: wheel_header.version,
-                expected: storage::WHEEL_VERSION,
-            });
-        }
-        if wheel_header.size_bytes != file_size {
-            return Err(WheelOpenError::WheelSizeMismatch {
-                header: wheel_header.size_bytes,
-                actual: file_size,
-            });
+        let maybe_wheel_header = read_wheel_header(
+            &mut wheel_file,
+            target_kind,
+            performer_builder.work_block_cleared(),
+            wheel_header_size,
+        )?;
+        let wheel_header = match maybe_wheel_header {
+            Some(wheel_header) =>
+                wheel_header,
+            None => {
+                log::debug!("block device [ {:?} ] has no wheel header yet", params.wheel_filename.as_ref());
+                return Ok(WheelOpenStatus::DeviceBlank { performer_builder, });
+            },
+        };
+        match target_kind {
+            TargetKind::RegularFile { .. } if wheel_header.size_bytes != actual_size =>
+                return Err(WheelOpenError::WheelSizeMismatch {
+                    header: wheel_header.size_bytes,
+                    actual: actual_size,
+                }),
+            // a device may be larger than the wheel placed on it
+            TargetKind::BlockDevice if wheel_header.size_bytes > actual_size =>
+                return Err(WheelOpenError::WheelSizeExceedsDevice {
+                    header: wheel_header.size_bytes,
+                    device_size: actual_size,
+                }),
+            TargetKind::RegularFile { .. } | TargetKind::BlockDevice =>
+                (),
         }
 
         log::debug!("wheel_header read: {:?}", wheel_header);
 
-        // read blocks and gaps
-        let (mut builder, mut work_block) = performer_builder.start_fill();
-
-        work_block.clear();
-        let mut cursor = wheel_header_size as u64;
-
-        let work_block_size_bytes = work_block.capacity();
-        work_block.resize(work_block_size_bytes, 0);
-        let mut offset = 0;
-        'outer: loop {
-            let bytes_read = match wheel_file.read(&mut work_block[offset ..]) {
-                Ok(0) => {
-                    assert!(
-                        cursor + builder.storage_layout().block_header_size as u64 >= file_size,
-                        "assertion failed: cursor = {} + block_header_size = {} >= file_size = {}",
-                        cursor,
-                        builder.storage_layout().block_header_size,
-                        file_size,
-                    );
-                    break;
-                },
-                Ok(bytes_read) =>
-                    bytes_read,
-                Err(ref error) if error.kind() == io::ErrorKind::Interrupted =>
-                    continue,
-                Err(error) =>
-                    return Err(WheelOpenError::LocateBlock(error)),
-            };
-            offset += bytes_read;
-            let mut start = 0;
-            while offset - start >= builder.storage_layout().block_header_size {
-                let area = &work_block[start .. start + builder.storage_layout().block_header_size];
-                match storage::bincode_options().deserialize_from::<_, storage::BlockHeader>(area) {
-                    Ok(block_header) if block_header.magic == storage::BLOCK_MAGIC => {
-                        let try_read_block_status = try_read_block(
-                            &mut wheel_file,
-                            &mut work_block,
-                            cursor,
-                            &block_header,
-                            builder.storage_layout(),
-                        )?;
-                        work_block.resize(work_block_size_bytes, 0);
-                        offset = 0;
-                        start = 0;
-
-                        match try_read_block_status {
-                            ReadBlockStatus::NotABlock { next_cursor, } =>
-                                cursor = next_cursor,
-                            ReadBlockStatus::BlockFound { next_cursor, } => {
-
-                                log::debug!("restored block @ {}: {:?}, next_cursor = {}", cursor, block_header, next_cursor);
-
-                                builder.push_block(cursor, block_header);
-                                cursor = next_cursor;
-                            },
-                        }
-                        break;
-                    },
-                    Ok(..) | Err(..) =>
-                        match storage::bincode_options().deserialize_from::<_, storage::TerminatorTag>(area) {
-                            Ok(terminator_tag) if terminator_tag.magic == storage::TERMINATOR_TAG_MAGIC => {
-                                log::debug!("terminator found @ {:?}, loading done", cursor);
-                                break 'outer;
-                            },
-                            Ok(..) | Err(..) =>
-                                (),
-                        },
-                };
-                start += 1;
-                cursor += 1;
-            }
-            if start > 0 {
-                work_block.copy_within(start .. offset, 0);
-                offset -= start;
-            }
-        }
+        let (storage_layout, performer) = scan_blocks(&mut wheel_file, performer_builder, &wheel_header)?;
 
         log::debug!("loaded wheel schema");
 
@@ -402,14 +502,13 @@ impl<C> SyncGenServer<C> where C: Context {
         Ok(WheelOpenStatus::Success(WheelData {
             sync_gen_server: SyncGenServer {
                 wheel_file,
+                wheel_filename: params.wheel_filename.as_ref().to_owned(),
+                read_only: params.read_only,
                 request_tx,
                 request_rx,
-                storage_layout: builder
-                    .storage_layout()
-                    .clone(),
+                storage_layout,
             },
-            performer: builder
-                .finish(wheel_header.size_bytes as usize),
+            performer,
         }))
     }
 
@@ -419,8 +518,13 @@ impl<C> SyncGenServer<C> where C: Context {
         }
     }
 
+    pub(super) fn into_parts(self) -> (fs::File, mpsc::Sender<Command<C>>, mpsc::Receiver<Command<C>>, storage::Layout) {
+        (self.wheel_file, self.request_tx, self.request_rx, self.storage_layout)
+    }
+
     pub fn run<F, E>(
         self,
+        params: RunParams,
         blocks_pool: BytesPool,
         error_tx: oneshot::Sender<E>,
         error_map: F,
@@ -434,13 +538,54 @@ impl<C> SyncGenServer<C> where C: Context {
           C::DeleteBlock: Send,
           C::IterBlocksStream: Send,
     {
+        // pid sender of `self` is dropped here, so the thread stops when all pids are gone
+        let SyncGenServer { wheel_file, wheel_filename, read_only, request_rx, storage_layout, .. } = self;
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            if params.direct_io {
+                return Err(Error::DirectIoUnsupported);
+            }
+            if params.punch_holes_min_bytes.is_some() {
+                return Err(Error::PunchHoleUnsupported);
+            }
+        }
+
+        if params.direct_io {
+            #[cfg(target_os = "linux")]
+            {
+                // wheel was opened and scanned with regular buffered access, reopen it for the rest
+                let direct_wheel_file = direct::open(&wheel_filename, read_only)?;
+                thread::Builder::new()
+                    .name("wheel::interpret::fixed_file::direct".to_string())
+                    .spawn(move || {
+                        // original file is kept open because it holds the wheel lock
+                        let _locked_wheel_file = wheel_file;
+                        let result = direct::busyloop(
+                            request_rx,
+                            direct_wheel_file,
+                            params,
+                            storage_layout,
+                            blocks_pool,
+                        );
+                        if let Err(error) = result {
+                            log::error!("wheel::interpret::fixed_file::direct terminated with {:?}", error);
+                            error_tx.send(error_map(error)).ok();
+                        }
+                    })
+                    .map_err(Error::ThreadSpawn)?;
+                return Ok(());
+            }
+        }
+
         thread::Builder::new()
             .name("wheel::interpret::fixed_file".to_string())
             .spawn(move || {
                 let result = busyloop(
-                    self.request_rx,
-                    self.wheel_file,
-                    self.storage_layout,
+                    request_rx,
+                    wheel_file,
+                    params,
+                    storage_layout,
                     blocks_pool,
                 );
                 if let Err(error) = result {
@@ -453,20 +598,398 @@ impl<C> SyncGenServer<C> where C: Context {
     }
 }
 
+// block areas freed since the wheel has been opened, adjacent ones are merged the same way gaps index merges them
+struct FreedAreas {
+    punch_holes_min_bytes: Option<usize>,
+    // start offset -> end offset
+    areas: BTreeMap<u64, u64>,
+}
+
+impl FreedAreas {
+    fn new(punch_holes_min_bytes: Option<usize>) -> FreedAreas {
+        FreedAreas {
+            punch_holes_min_bytes,
+            areas: BTreeMap::new(),
+        }
+    }
+
+    // bytes at `offset` have been overwritten by a block or a terminator
+    fn occupy(&mut self, offset: u64, len: u64) {
+        if self.punch_holes_min_bytes.is_none() || len == 0 {
+            return;
+        }
+        let end = offset + len;
+        let overlapping: Vec<(u64, u64)> = self.areas
+            .range(.. end)
+            .rev()
+            .take_while(|&(_, &area_end)| area_end > offset)
+            .map(|(&area_start, &area_end)| (area_start, area_end))
+            .collect();
+        for (area_start, area_end) in overlapping {
+            self.areas.remove(&area_start);
+            if area_start < offset {
+                self.areas.insert(area_start, offset);
+            }
+            if area_end > end {
+                self.areas.insert(end, area_end);
+            }
+        }
+    }
+
+    // returns ranges to deallocate when the merged area around the deleted block is at least `min_bytes` long:
+    // the fresh tombstone is kept, older ones inside the area are dropped like any write into a gap drops them
+    fn free(&mut self, offset: u64, tombstone_size: usize, total_chunk_size: usize) -> Option<[(u64, u64); 2]> {
+        let min_bytes = self.punch_holes_min_bytes?;
+        let tombstone_size = tombstone_size as u64;
+        let mut start = offset;
+        let mut end = offset + total_chunk_size as u64;
+        if let Some((&left_start, &left_end)) = self.areas.range(.. start).next_back() {
+            if left_end == start {
+                self.areas.remove(&left_start);
+                start = left_start;
+            }
+        }
+        if let Some(right_end) = self.areas.remove(&end) {
+            end = right_end;
+        }
+        self.areas.insert(start, end);
+
+        let tombstone_end = offset + tombstone_size;
+        if end < tombstone_end || end - start < tombstone_size + min_bytes as u64 {
+            return None;
+        }
+        Some([(start, offset - start), (tombstone_end, end - tombstone_end)])
+    }
+}
+
+fn punch_holes(wheel_file: &fs::File, holes: [(u64, u64); 2]) -> Result<(), Error> {
+    for &(offset, len) in holes.iter() {
+        if len > 0 {
+            punch_hole(wheel_file, offset, len)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn punch_hole(wheel_file: &fs::File, offset: u64, len: u64) -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+
+    log::debug!("punching hole @ {} of {} bytes", offset, len);
+    nix::fcntl::fallocate(
+        wheel_file.as_raw_fd(),
+        nix::fcntl::FallocateFlags::FALLOC_FL_PUNCH_HOLE | nix::fcntl::FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        offset as libc::off_t,
+        len as libc::off_t,
+    ).map_err(|errno| Error::PunchHole { offset, len, error: errno.into(), })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_wheel_file: &fs::File, _offset: u64, _len: u64) -> Result<(), Error> {
+    Err(Error::PunchHoleUnsupported)
+}
+
+#[cfg(target_os = "linux")]
+fn fallocate(wheel_file: &fs::File, size_bytes_total: u64) -> Result<(), WheelCreateError> {
+    use std::os::unix::io::AsRawFd;
+
+    nix::fcntl::fallocate(
+        wheel_file.as_raw_fd(),
+        nix::fcntl::FallocateFlags::empty(),
+        0,
+        size_bytes_total as libc::off_t,
+    ).map_err(|errno| WheelCreateError::Fallocate(errno.into()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn fallocate(_wheel_file: &fs::File, _size_bytes_total: u64) -> Result<(), WheelCreateError> {
+    Err(WheelCreateError::FallocateUnsupported)
+}
+
+// `None` is for a block device which has never been initialized: its header area is all zeros
+fn read_wheel_header<R>(
+    wheel: &mut R,
+    target_kind: TargetKind,
+    work_block: &mut Vec<u8>,
+    wheel_header_size: usize,
+)
+    -> Result<Option<storage::WheelHeader>, WheelOpenError>
+where R: Read,
+{
+    work_block.clear();
+    work_block.extend((0 .. wheel_header_size).map(|_| 0));
+    wheel.read_exact(work_block)
+        .map_err(WheelOpenError::HeaderRead)?;
+    if target_kind == TargetKind::BlockDevice && work_block.iter().all(|&byte| byte == 0) {
+        return Ok(None);
+    }
+    parse_wheel_header(work_block)
+        .map(Some)
+}
+
+pub(super) fn parse_wheel_header(wheel_header_bytes: &[u8]) -> Result<storage::WheelHeader, WheelOpenError> {
+    let wheel_header: storage::WheelHeader = storage::bincode_options()
+        .deserialize_from(wheel_header_bytes)
+        .map_err(WheelOpenError::HeaderDeserialize)?;
+    if wheel_header.magic != storage::WHEEL_MAGIC {
+        return Err(WheelOpenError::HeaderInvalidMagic {
+            provided: wheel_header.magic,
+            expected: storage::WHEEL_MAGIC,
+        });
+    }
+    // version 1 wheels are converted offline with `upgrade::upgrade_v1`
+    if wheel_header.version != storage::WHEEL_VERSION {
+        return Err(WheelOpenError::HeaderVersionMismatch {
+            provided: wheel_header.version,
+            expected: storage::WHEEL_VERSION,
+        });
+    }
+    Ok(wheel_header)
+}
+
+// scans blocks and tombstones of a wheel starting from its data offset right after the header
+pub(super) fn scan_blocks<C, R>(
+    wheel: &mut R,
+    performer_builder: performer::PerformerBuilderInit<C>,
+    wheel_header: &storage::WheelHeader,
+)
+    -> Result<(storage::Layout, performer::Performer<C>), WheelOpenError>
+where C: Context,
+      R: Read + Seek,
+{
+    // layout depends on the alignment, so a wheel is opened with the same one it has been created with
+    let block_align = performer_builder.storage_layout().block_align;
+    if wheel_header.block_align != block_align {
+        return Err(WheelOpenError::BlockAlignMismatch {
+            header: wheel_header.block_align,
+            expected: block_align,
+        });
+    }
+
+    let file_size = wheel_header.size_bytes;
+    let data_offset_start = performer_builder
+        .storage_layout()
+        .data_offset_start();
+
+    // read blocks and gaps
+    let (mut builder, mut work_block) = performer_builder.start_fill();
+
+    work_block.clear();
+    let mut cursor = data_offset_start as u64;
+    wheel.seek(io::SeekFrom::Start(cursor))
+        .map_err(WheelOpenError::LocateBlock)?;
+
+    let work_block_size_bytes = work_block.capacity();
+    work_block.resize(work_block_size_bytes, 0);
+    let mut offset = 0;
+    let mut found_blocks = Vec::new();
+    'outer: loop {
+        let bytes_read = match wheel.read(&mut work_block[offset ..]) {
+            Ok(0) => {
+                if cursor + (builder.storage_layout().block_header_size as u64) < file_size {
+                    return Err(WheelOpenError::ScanTruncated { cursor, file_size, });
+                }
+                break;
+            },
+            Ok(bytes_read) =>
+                bytes_read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted =>
+                continue,
+            Err(error) =>
+                return Err(WheelOpenError::LocateBlock(error)),
+        };
+        offset += bytes_read;
+        let mut start = 0;
+        while offset - start >= builder.storage_layout().block_header_size {
+            let area = &work_block[start .. start + builder.storage_layout().block_header_size];
+            match storage::bincode_options().deserialize_from::<_, storage::BlockHeader>(area) {
+                Ok(block_header) if block_header.magic == storage::BLOCK_MAGIC => {
+                    let try_read_block_status = try_read_block(
+                        wheel,
+                        &mut work_block,
+                        cursor,
+                        &block_header,
+                        builder.storage_layout(),
+                        file_size,
+                    )?;
+                    work_block.resize(work_block_size_bytes, 0);
+                    offset = 0;
+                    start = 0;
+
+                    match try_read_block_status {
+                        ReadBlockStatus::NotABlock { next_cursor, } =>
+                            cursor = next_cursor,
+                        ReadBlockStatus::BlockFound { next_cursor, change_seq, } => {
+                            // schema continues both sequences right after the maximum found
+                            if block_header.block_id.is_exhausted() {
+                                return Err(WheelOpenError::BlockIdExhausted { block_id: block_header.block_id, });
+                            }
+                            if change_seq == u64::MAX {
+                                return Err(WheelOpenError::ChangeSeqExhausted { block_id: block_header.block_id, });
+                            }
+
+                            log::debug!("restored block @ {}: {:?}, next_cursor = {}", cursor, block_header, next_cursor);
+
+                            found_blocks.push((cursor, block_header, change_seq));
+                            cursor = next_cursor;
+                        },
+                    }
+                    break;
+                },
+                Ok(..) | Err(..) =>
+                    match storage::bincode_options().deserialize_from::<_, storage::TerminatorTag>(area) {
+                        Ok(terminator_tag) if terminator_tag.magic == storage::TERMINATOR_TAG_MAGIC => {
+                            log::debug!("terminator found @ {:?}, loading done", cursor);
+                            break 'outer;
+                        },
+                        Ok(..) | Err(..) =>
+                            match storage::bincode_options().deserialize_from::<_, storage::TombstoneTag>(area) {
+                                Ok(tombstone_tag) if tombstone_tag.magic == storage::TOMBSTONE_TAG_MAGIC => {
+                                    log::debug!("tombstone found @ {:?}: {:?}", cursor, tombstone_tag);
+                                    if tombstone_tag.change_seq == u64::MAX {
+                                        return Err(WheelOpenError::ChangeSeqExhausted { block_id: tombstone_tag.block_id, });
+                                    }
+                                    builder.push_tombstone(tombstone_tag.block_id, tombstone_tag.change_seq);
+                                },
+                                Ok(..) | Err(..) =>
+                                    (),
+                            },
+                    },
+            };
+            start += 1;
+            cursor += 1;
+        }
+        if start > 0 {
+            work_block.copy_within(start .. offset, 0);
+            offset -= start;
+        }
+    }
+
+    // the same block id could be found twice if a write has reached the disk but a tombstone of its previous
+    // location has not (or the image is crafted): the latest change wins, the other copy becomes free space
+    let mut latest_blocks = HashMap::with_capacity(found_blocks.len());
+    for (index, (offset, block_header, change_seq)) in found_blocks.iter().enumerate() {
+        match latest_blocks.entry(block_header.block_id.clone()) {
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(index);
+            },
+            hash_map::Entry::Occupied(mut entry) => {
+                let (prev_offset, _, prev_change_seq) = &found_blocks[*entry.get()];
+                log::warn!(
+                    "block {:?} is found both @ {} (change_seq = {}) and @ {} (change_seq = {})",
+                    block_header.block_id,
+                    prev_offset,
+                    prev_change_seq,
+                    offset,
+                    change_seq,
+                );
+                if change_seq >= prev_change_seq {
+                    entry.insert(index);
+                }
+            },
+        }
+    }
+    for (index, (offset, block_header, change_seq)) in found_blocks.into_iter().enumerate() {
+        if latest_blocks[&block_header.block_id] == index {
+            let chunk_end = offset
+                + builder.storage_layout().block_chunk_size(block_header.block_size) as u64
+                + builder.storage_layout().terminator_tag_size as u64;
+            if offset % block_align as u64 != 0 || chunk_end > file_size {
+                return Err(WheelOpenError::BlockNotAligned { block_id: block_header.block_id, offset, block_align, });
+            }
+            builder.push_block(offset, block_header, change_seq);
+        }
+    }
+
+    let storage_layout = builder
+        .storage_layout()
+        .clone();
+    Ok((storage_layout, builder.finish(wheel_header.wheel_id, file_size as usize)))
+}
+
+// flock on unix, so two opens conflict even within the same process
+pub(super) fn try_lock(wheel_file: &fs::File, lock_mode: LockMode) -> Result<bool, io::Error> {
+    let result = match lock_mode {
+        LockMode::Exclusive =>
+            fs2::FileExt::try_lock_exclusive(wheel_file),
+        LockMode::Shared =>
+            fs2::FileExt::try_lock_shared(wheel_file),
+    };
+    match result {
+        Ok(()) =>
+            Ok(true),
+        Err(ref error) if error.raw_os_error() == fs2::lock_contended_error().raw_os_error() =>
+            Ok(false),
+        Err(error) =>
+            Err(error),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TargetKind {
+    RegularFile { file_size: u64, },
+    BlockDevice,
+}
+
+impl TargetKind {
+    fn of(metadata: &fs::Metadata) -> Option<TargetKind> {
+        if metadata.file_type().is_file() {
+            Some(TargetKind::RegularFile { file_size: metadata.len(), })
+        } else if is_block_device(metadata) {
+            Some(TargetKind::BlockDevice)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_block_device(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    metadata.file_type().is_block_device()
+}
+
+#[cfg(not(unix))]
+fn is_block_device(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+// `metadata.len()` is zero for block devices, seeking to the end reports the real size (same as BLKGETSIZE64)
+fn device_size(wheel_file: &mut fs::File) -> Result<u64, io::Error> {
+    let device_size = wheel_file.seek(io::SeekFrom::End(0))?;
+    wheel_file.seek(io::SeekFrom::Start(0))?;
+    Ok(device_size)
+}
+
 enum ReadBlockStatus {
     NotABlock { next_cursor: u64, },
-    BlockFound { next_cursor: u64, },
+    BlockFound { next_cursor: u64, change_seq: u64, },
 }
 
-fn try_read_block(
-    wheel_file: &mut fs::File,
+fn try_read_block<R>(
+    wheel_file: &mut R,
     work_block: &mut Vec<u8>,
     cursor: u64,
     block_header: &storage::BlockHeader,
     storage_layout: &storage::Layout,
+    file_size: u64,
 )
     -> Result<ReadBlockStatus, WheelOpenError>
+where R: Read + Seek,
 {
+    // a real block always leaves space for terminator before the end of wheel
+    let block_end = cursor
+        .checked_add((storage_layout.data_size_block_min() + storage_layout.terminator_tag_size) as u64)
+        .and_then(|block_end| block_end.checked_add(block_header.block_size as u64));
+    if block_end.map_or(true, |block_end| block_end > file_size) {
+        let next_cursor = cursor + 1;
+        wheel_file.seek(io::SeekFrom::Start(next_cursor))
+            .map_err(WheelOpenError::BlockRewindCommitTag)?;
+
+        log::debug!("NotABlock because of block_size {} does not fit into the wheel", block_header.block_size);
+
+        return Ok(ReadBlockStatus::NotABlock { next_cursor, });
+    }
     // seek to commit tag position
     let commit_offset = wheel_file
         .seek(io::SeekFrom::Start(cursor + storage_layout.block_header_size as u64 + block_header.block_size as u64))
@@ -522,216 +1045,137 @@ fn try_read_block(
     let next_cursor = wheel_file.seek(io::SeekFrom::Current(storage_layout.commit_tag_size as i64))
         .map_err(WheelOpenError::BlockSeekEnd)?;
 
-    assert_eq!(next_cursor, commit_offset + storage_layout.commit_tag_size as u64);
+    if next_cursor != commit_offset + storage_layout.commit_tag_size as u64 {
+        return Err(WheelOpenError::BlockSeekEndMismatch {
+            expected: commit_offset + storage_layout.commit_tag_size as u64,
+            actual: next_cursor,
+        });
+    }
 
-    Ok(ReadBlockStatus::BlockFound { next_cursor, })
+    Ok(ReadBlockStatus::BlockFound { next_cursor, change_seq: commit_tag.change_seq, })
 }
 
 #[derive(Debug, Default)]
 struct Timings {
-    event_wait: Duration,
     seek: Duration,
     write_write: Duration,
     read: Duration,
     write_delete: Duration,
     flush: Duration,
-    total: Duration,
+}
+
+// wheel file accessed through a single cursor, seeking only when the next task is not adjacent
+struct SeekFile {
+    wheel_file: fs::File,
+    cursor: u64,
+    freed_areas: FreedAreas,
+    timings: Timings,
+}
+
+impl SeekFile {
+    fn seek(&mut self, offset: u64) -> Result<(), Error> {
+        if self.cursor != offset {
+            let now = Instant::now();
+            self.wheel_file.seek(io::SeekFrom::Start(offset))
+                .map_err(|error| Error::WheelFileSeek { offset, cursor: self.cursor, error, })?;
+            self.timings.seek += now.elapsed();
+            self.cursor = offset;
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, bytes: &[u8], offset: u64) -> Result<(), io::Error> {
+        self.wheel_file.write_all(bytes)?;
+        self.freed_areas.occupy(offset, bytes.len() as u64);
+        self.cursor += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+impl block_io::BlockIo for SeekFile {
+    type Error = Error;
+
+    fn write_block(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.seek(offset)?;
+        let now = Instant::now();
+        self.write_at(bytes, offset)
+            .map_err(Error::BlockWrite)?;
+        self.timings.write_write += now.elapsed();
+        Ok(())
+    }
+
+    fn write_terminator(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.seek(offset)?;
+        self.write_at(bytes, offset)
+            .map_err(Error::TerminatorWrite)
+    }
+
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        _block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Error>
+    {
+        self.seek(offset)?;
+        block_bytes.reserve(total_chunk_size);
+        let now = Instant::now();
+        let bytes_read = Read::by_ref(&mut self.wheel_file)
+            .take(total_chunk_size as u64)
+            .read_to_end(block_bytes)
+            .map_err(Error::BlockRead)?;
+        self.cursor += bytes_read as u64;
+        self.timings.read += now.elapsed();
+        Ok(())
+    }
+
+    fn delete_block(&mut self, _block_id: &block::Id, tombstone_bytes: &[u8], total_chunk_size: usize, offset: u64) -> Result<(), Error> {
+        self.seek(offset)?;
+        let now = Instant::now();
+        self.wheel_file.write_all(tombstone_bytes)
+            .map_err(Error::BlockWrite)?;
+        self.cursor += tombstone_bytes.len() as u64;
+        self.timings.write_delete += now.elapsed();
+        if let Some(holes) = self.freed_areas.free(offset, tombstone_bytes.len(), total_chunk_size) {
+            punch_holes(&self.wheel_file, holes)?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+        self.wheel_file.sync_data()
+            .map_err(Error::DeviceSyncFlush)?;
+        self.timings.flush += now.elapsed();
+        log::info!("current timings: {:?}", self.timings);
+        Ok(())
+    }
 }
 
 fn busyloop<C>(
     request_rx: mpsc::Receiver<Command<C>>,
     mut wheel_file: fs::File,
+    params: RunParams,
     storage_layout: storage::Layout,
     blocks_pool: BytesPool,
 )
     -> Result<(), Error>
 where C: Context,
 {
-    let mut stats = InterpretStats::default();
-
     let mut terminator_block_bytes = blocks_pool.lend();
     block_append_terminator(&mut terminator_block_bytes)
         .map_err(Error::AppendTerminator)?;
 
-    let mut cursor = storage_layout.wheel_header_size as u64;
+    let cursor = storage_layout.data_offset_start() as u64;
     wheel_file.seek(io::SeekFrom::Start(cursor))
         .map_err(Error::WheelFileInitialSeek)?;
-    let mut pending_terminator = false;
-    let mut timings = Timings::default();
-    loop {
-        let now_loop = Instant::now();
-
-        enum Event<C> { Command(C), }
-        let event = match request_rx.recv() {
-            Ok(command) =>
-                Event::Command(Some(command)),
-            Err(mpsc::RecvError) =>
-                Event::Command(None),
-        };
-        timings.event_wait += now_loop.elapsed();
-
-        match event {
-
-            Event::Command(None) =>
-                break,
-
-            Event::Command(Some(Command::Request(Request { offset, task, reply_tx, }))) => {
-                stats.count_total += 1;
-
-                if cursor != offset {
-                    if cursor < offset {
-                        stats.count_seek_forward += 1;
-                    } else if cursor > offset {
-                        stats.count_seek_backward += 1;
-                        if pending_terminator {
-                            log::debug!("writing pending_terminator during seek @ {}", cursor);
-                            wheel_file.write_all(&terminator_block_bytes)
-                                .map_err(Error::TerminatorWrite)?;
-                            pending_terminator = false;
-                        }
-                    }
-                    let now = Instant::now();
-                    wheel_file.seek(io::SeekFrom::Start(offset))
-                        .map_err(|error| Error::WheelFileSeek { offset, cursor, error, })?;
-                    timings.seek += now.elapsed();
-                    cursor = offset;
-                } else {
-                    stats.count_no_seek += 1;
-                }
-
-                match task.kind {
-                    task::TaskKind::WriteBlock(write_block) => {
-
-                        log::debug!(
-                            "write block {:?} @ {} of {} bytes, context: {:?}",
-                            task.block_id,
-                            cursor,
-                            write_block.write_block_bytes.len(),
-                            write_block.context,
-                        );
-
-                        let now = Instant::now();
-                        wheel_file.write_all(&write_block.write_block_bytes)
-                            .map_err(Error::BlockWrite)?;
-                        cursor += write_block.write_block_bytes.len() as u64;
-                        timings.write_write += now.elapsed();
-
-                        pending_terminator = match write_block.commit {
-                            task::Commit::None =>
-                                false,
-                            task::Commit::WithTerminator =>
-                                true,
-                        };
-
-                        let task_done = task::Done {
-                            current_offset: cursor,
-                            task: task::TaskDone {
-                                block_id: task.block_id,
-                                kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
-                                    context: write_block.context,
-                                }),
-                            },
-                        };
-                        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
-                            break;
-                        }
-                    },
-
-                    task::TaskKind::ReadBlock(task::ReadBlock { block_header, context, }) => {
-                        let total_chunk_size = storage_layout.data_size_block_min()
-                            + block_header.block_size;
-
-                        log::debug!(
-                            "read block {:?} @ {} of {} bytes, context = {:?}",
-                            task.block_id,
-                            cursor,
-                            total_chunk_size,
-                            context,
-                        );
-
-                        let mut block_bytes = blocks_pool.lend();
-                        block_bytes.reserve(total_chunk_size);
-                        let now = Instant::now();
-                        let wheel_file_ref = Read::by_ref(&mut wheel_file);
-                        wheel_file_ref
-                            .take(total_chunk_size as u64)
-                            .read_to_end(&mut block_bytes)
-                            .map_err(Error::BlockRead)?;
-                        cursor += block_bytes.len() as u64;
-                        timings.read += now.elapsed();
-
-                        let task_done = task::Done {
-                            current_offset: cursor,
-                            task: task::TaskDone {
-                                block_id: block_header.block_id,
-                                kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
-                                    block_bytes,
-                                    context,
-                                }),
-                            },
-                        };
-                        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
-                            break;
-                        }
-                    },
-
-                    task::TaskKind::DeleteBlock(delete_block) => {
-
-                        log::debug!("delete block {:?} @ {}, context: {:?}", task.block_id, cursor, delete_block.context);
-
-                        let now = Instant::now();
-                        wheel_file.write_all(&delete_block.delete_block_bytes)
-                            .map_err(Error::BlockWrite)?;
-                        cursor += delete_block.delete_block_bytes.len() as u64;
-                        timings.write_delete += now.elapsed();
-
-                        pending_terminator = match delete_block.commit {
-                            task::Commit::None =>
-                                false,
-                            task::Commit::WithTerminator =>
-                                true,
-                        };
-
-                        let task_done = task::Done {
-                            current_offset: cursor,
-                            task: task::TaskDone {
-                                block_id: task.block_id,
-                                kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
-                                    context: delete_block.context,
-                                }),
-                            },
-                        };
-                        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
-                            break;
-                        }
-                    },
-                }
-            },
-
-            Event::Command(Some(Command::DeviceSync { reply_tx, })) => {
-                let now = Instant::now();
-                if pending_terminator {
-                    log::debug!("writing pending_terminator during flush @ {}", cursor);
-                    wheel_file.write_all(&terminator_block_bytes)
-                        .map_err(Error::TerminatorWrite)?;
-                    pending_terminator = false;
-                    cursor += terminator_block_bytes.len() as u64;
-                } else {
-                    log::debug!("flushed with no pending_terminator (cursor @ {})", cursor);
-                }
-                wheel_file.flush()
-                    .map_err(Error::DeviceSyncFlush)?;
-                timings.flush += now.elapsed();
-                if let Err(_send_error) = reply_tx.send(Synced) {
-                    break;
-                }
-                log::info!("current timings: {:?}", timings);
-            },
-
-        }
-        timings.total += now_loop.elapsed();
-    }
-
-    log::debug!("master channel closed in interpret_loop, shutting down");
-    Ok(())
+    let seek_file = SeekFile {
+        wheel_file,
+        cursor,
+        freed_areas: FreedAreas::new(params.punch_holes_min_bytes),
+        timings: Timings::default(),
+    };
+    block_io::busyloop(request_rx, seek_file, storage_layout, terminator_block_bytes, blocks_pool)
 }
@@ -0,0 +1,167 @@
+//! `O_DIRECT` mode: all file access goes through sector aligned windows of an aligned bounce buffer.
+//!
+//! Blocks are placed on sector boundaries and padded to whole sectors, so only tombstones and terminators
+//! touching partial sectors read those sectors first.
+
+use std::{
+    fs,
+    io,
+    path::Path,
+    os::unix::fs::{
+        FileExt,
+        OpenOptionsExt,
+    },
+    sync::mpsc,
+};
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        block,
+        storage,
+        interpret::{
+            block_io,
+            Command,
+            block_append_terminator,
+        },
+    },
+};
+
+use super::{
+    Error,
+    RunParams,
+    FreedAreas,
+    SECTOR_ALIGN,
+    punch_holes,
+};
+
+pub fn open<P>(wheel_filename: P, read_only: bool) -> Result<fs::File, Error> where P: AsRef<Path> {
+    let wheel_file = fs::OpenOptions::new()
+        .read(true)
+        .write(!read_only)
+        .custom_flags(libc::O_DIRECT)
+        .open(wheel_filename)
+        .map_err(Error::DirectOpen)?;
+    let wheel_size_bytes = wheel_file.metadata()
+        .map_err(Error::DirectOpen)?
+        .len();
+    if wheel_size_bytes % SECTOR_ALIGN as u64 != 0 {
+        return Err(Error::DirectWheelSizeNotAligned { wheel_size_bytes, align: SECTOR_ALIGN, });
+    }
+    Ok(wheel_file)
+}
+
+struct Sectors {
+    wheel_file: fs::File,
+    bounce: Vec<u8>,
+    freed_areas: FreedAreas,
+}
+
+impl Sectors {
+    fn window(bounce: &mut Vec<u8>, len: usize) -> &mut [u8] {
+        bounce.resize(len + SECTOR_ALIGN, 0);
+        let shift = bounce.as_ptr().align_offset(SECTOR_ALIGN);
+        &mut bounce[shift .. shift + len]
+    }
+
+    fn write_at(&mut self, bytes: &[u8], offset: u64) -> Result<(), io::Error> {
+        let align = SECTOR_ALIGN as u64;
+        let start = offset - offset % align;
+        let end = offset + bytes.len() as u64;
+        let end_aligned = (end + align - 1) / align * align;
+        let window = Self::window(&mut self.bounce, (end_aligned - start) as usize);
+        if start < offset {
+            self.wheel_file.read_exact_at(&mut window[.. SECTOR_ALIGN], start)?;
+        }
+        if end < end_aligned && (end_aligned - start > align || start == offset) {
+            let last = window.len() - SECTOR_ALIGN;
+            self.wheel_file.read_exact_at(&mut window[last ..], end_aligned - align)?;
+        }
+        let shift = (offset - start) as usize;
+        window[shift .. shift + bytes.len()].copy_from_slice(bytes);
+        self.wheel_file.write_all_at(window, start)
+    }
+
+    fn read_at(&mut self, target: &mut Vec<u8>, len: usize, offset: u64) -> Result<(), io::Error> {
+        let align = SECTOR_ALIGN as u64;
+        let start = offset - offset % align;
+        let end_aligned = (offset + len as u64 + align - 1) / align * align;
+        let window = Self::window(&mut self.bounce, (end_aligned - start) as usize);
+        self.wheel_file.read_exact_at(window, start)?;
+        let shift = (offset - start) as usize;
+        target.extend_from_slice(&window[shift .. shift + len]);
+        Ok(())
+    }
+}
+
+impl block_io::BlockIo for Sectors {
+    type Error = Error;
+
+    fn write_block(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+            .map_err(Error::BlockWrite)?;
+        self.freed_areas.occupy(offset, bytes.len() as u64);
+        Ok(())
+    }
+
+    fn write_terminator(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+            .map_err(Error::TerminatorWrite)?;
+        self.freed_areas.occupy(offset, bytes.len() as u64);
+        Ok(())
+    }
+
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        _block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Error>
+    {
+        self.read_at(block_bytes, total_chunk_size, offset)
+            .map_err(Error::BlockRead)
+    }
+
+    fn delete_block(&mut self, _block_id: &block::Id, tombstone_bytes: &[u8], total_chunk_size: usize, offset: u64) -> Result<(), Error> {
+        self.write_at(tombstone_bytes, offset)
+            .map_err(Error::BlockWrite)?;
+        if let Some(holes) = self.freed_areas.free(offset, tombstone_bytes.len(), total_chunk_size) {
+            punch_holes(&self.wheel_file, holes)?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.wheel_file.sync_data()
+            .map_err(Error::DeviceSyncFlush)
+    }
+}
+
+pub fn busyloop<C>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    wheel_file: fs::File,
+    params: RunParams,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let sectors = Sectors {
+        wheel_file,
+        bounce: Vec::new(),
+        freed_areas: FreedAreas::new(params.punch_holes_min_bytes),
+    };
+
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    block_io::busyloop(request_rx, sectors, storage_layout, terminator_block_bytes, blocks_pool)
+}
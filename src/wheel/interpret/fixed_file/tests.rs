@@ -1,7 +1,11 @@
 use std::{
     fs,
+    io,
+    path::Path,
 };
 
+use bincode::Options;
+
 use futures::{
     channel::{
         oneshot,
@@ -20,6 +24,8 @@ use alloc_pool::bytes::{
 
 use crate::{
     block,
+    storage,
+    WheelCreateMode,
     context::Context,
     wheel::{
         lru,
@@ -33,6 +39,14 @@ use crate::{
 };
 
 use super::{
+    TargetKind,
+    WheelOpenError,
+    WheelCreateError,
+    SECTOR_ALIGN,
+    read_wheel_header,
+    FreedAreas,
+    RunParams,
+    LockMode,
     OpenParams,
     CreateParams,
     WheelOpenStatus,
@@ -46,10 +60,11 @@ fn create_read_empty() {
         .unwrap();
     let wheel_filename = "/tmp/blockwheel_create_read_empty";
     runtime.block_on(async {
-        let _wheel_data = GenServer::create(
+        let wheel_data = GenServer::create(
             CreateParams {
                 wheel_filename,
                 init_wheel_size_bytes: 256 * 1024,
+                create_mode: WheelCreateMode::ZeroFill,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -57,9 +72,12 @@ fn create_read_empty() {
                 64 * 1024,
             ).map_err(Error::PerformerBuild)?,
         ).map_err(Error::Create)?;
+        drop(wheel_data);
         let _wheel_open_status = GenServer::open(
             OpenParams {
                 wheel_filename,
+                lock_mode: LockMode::Exclusive,
+                read_only: false,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -72,6 +90,93 @@ fn create_read_empty() {
     fs::remove_file(wheel_filename).unwrap();
 }
 
+#[test]
+fn create_sparse_read_empty() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let wheel_filename = "/tmp/blockwheel_create_sparse_read_empty";
+    runtime.block_on(async {
+        let wheel_data = GenServer::create(
+            CreateParams {
+                wheel_filename,
+                init_wheel_size_bytes: 256 * 1024,
+                create_mode: WheelCreateMode::Sparse,
+            },
+            performer::PerformerBuilderInit::new(
+                lru::Cache::new(0),
+                None,
+                64 * 1024,
+            ).map_err(Error::PerformerBuild)?,
+        ).map_err(Error::Create)?;
+        drop(wheel_data);
+        assert_eq!(fs::metadata(wheel_filename).unwrap().len(), 256 * 1024);
+        let wheel_open_status = GenServer::open(
+            OpenParams {
+                wheel_filename,
+                lock_mode: LockMode::Exclusive,
+                read_only: false,
+            },
+            performer::PerformerBuilderInit::new(
+                lru::Cache::new(0),
+                None,
+                64 * 1024,
+            ).map_err(Error::PerformerBuild)?,
+        ).map_err(Error::Open)?;
+        assert!(matches!(wheel_open_status, WheelOpenStatus::Success(..)));
+        Ok::<_, Error>(())
+    }).unwrap();
+    fs::remove_file(wheel_filename).unwrap();
+}
+
+#[test]
+fn open_locked() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let wheel_filename = "/tmp/blockwheel_open_locked";
+    let open = |lock_mode| GenServer::open(
+        OpenParams {
+            wheel_filename,
+            lock_mode,
+            read_only: false,
+        },
+        performer::PerformerBuilderInit::new(
+            lru::Cache::new(0),
+            None,
+            64 * 1024,
+        ).unwrap(),
+    );
+    runtime.block_on(async {
+        let wheel_data = GenServer::create(
+            CreateParams {
+                wheel_filename,
+                init_wheel_size_bytes: 256 * 1024,
+                create_mode: WheelCreateMode::ZeroFill,
+            },
+            performer::PerformerBuilderInit::new(
+                lru::Cache::new(0),
+                None,
+                64 * 1024,
+            ).map_err(Error::PerformerBuild)?,
+        ).map_err(Error::Create)?;
+        assert!(matches!(open(LockMode::Exclusive), Err(super::WheelOpenError::Locked { .. })));
+        assert!(matches!(open(LockMode::Shared), Err(super::WheelOpenError::Locked { .. })));
+        drop(wheel_data);
+
+        let shared_a = open(LockMode::Shared).map_err(Error::Open)?;
+        let shared_b = open(LockMode::Shared).map_err(Error::Open)?;
+        assert!(matches!(open(LockMode::Exclusive), Err(super::WheelOpenError::Locked { .. })));
+        drop(shared_a);
+        drop(shared_b);
+
+        let exclusive = open(LockMode::Exclusive).map_err(Error::Open)?;
+        assert!(matches!(exclusive, WheelOpenStatus::Success(..)));
+        Ok::<_, Error>(())
+    }).unwrap();
+    fs::remove_file(wheel_filename).unwrap();
+}
+
 #[test]
 fn create_read_one() {
     let runtime = tokio::runtime::Builder::new_current_thread()
@@ -84,6 +189,7 @@ fn create_read_one() {
             CreateParams {
                 wheel_filename,
                 init_wheel_size_bytes: 256 * 1024,
+                create_mode: WheelCreateMode::ZeroFill,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -98,6 +204,8 @@ fn create_read_one() {
                 interpret::BlockPrepareWriteJobArgs {
                     block_id: block_id.clone(),
                     block_bytes: hello_world_bytes(),
+                    change_seq: 1,
+                    total_chunk_size: schema.storage_layout().block_chunk_size(hello_world_bytes().len()),
                     blocks_pool: blocks_pool.clone(),
                 },
             ).map_err(Error::WriteBlockPrepare)?;
@@ -133,6 +241,8 @@ fn create_read_one() {
         let open_status = GenServer::open(
             OpenParams {
                 wheel_filename,
+                lock_mode: LockMode::Exclusive,
+                read_only: false,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -143,7 +253,7 @@ fn create_read_one() {
         let WheelData { sync_gen_server: gen_server, performer, } = match open_status {
             WheelOpenStatus::Success(wheel_data) =>
                 wheel_data,
-            WheelOpenStatus::FileNotFound { .. } =>
+            WheelOpenStatus::FileNotFound { .. } | WheelOpenStatus::DeviceBlank { .. } =>
                 panic!("file not found: {:?}", wheel_filename),
         };
         let mut schema = performer.decompose();
@@ -211,6 +321,7 @@ fn create_write_overlap_read_one() {
             CreateParams {
                 wheel_filename,
                 init_wheel_size_bytes: 256 * 1024,
+                create_mode: WheelCreateMode::ZeroFill,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -227,6 +338,8 @@ fn create_write_overlap_read_one() {
                 interpret::BlockPrepareWriteJobArgs {
                     block_id: block_id.clone(),
                     block_bytes: hello_world_bytes(),
+                    change_seq: 1,
+                    total_chunk_size: schema.storage_layout().block_chunk_size(hello_world_bytes().len()),
                     blocks_pool: blocks_pool.clone(),
                 },
             ).map_err(Error::WriteBlockPrepare)?;
@@ -247,6 +360,8 @@ fn create_write_overlap_read_one() {
                 interpret::BlockPrepareWriteJobArgs {
                     block_id: block_id.clone(),
                     block_bytes: hello_world_bytes(),
+                    change_seq: 2,
+                    total_chunk_size: schema.storage_layout().block_chunk_size(hello_world_bytes().len()),
                     blocks_pool: blocks_pool.clone(),
                 },
             ).map_err(Error::WriteBlockPrepare)?;
@@ -268,6 +383,8 @@ fn create_write_overlap_read_one() {
         let open_status = GenServer::open(
             OpenParams {
                 wheel_filename,
+                lock_mode: LockMode::Exclusive,
+                read_only: false,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -278,7 +395,7 @@ fn create_write_overlap_read_one() {
         let WheelData { sync_gen_server: gen_server, performer, } = match open_status {
             WheelOpenStatus::Success(wheel_data) =>
                 wheel_data,
-            WheelOpenStatus::FileNotFound { .. } =>
+            WheelOpenStatus::FileNotFound { .. } | WheelOpenStatus::DeviceBlank { .. } =>
                 panic!("file not found: {:?}", wheel_filename),
         };
         let mut schema = performer.decompose();
@@ -354,6 +471,7 @@ fn create_write_delete_read_one() {
             CreateParams {
                 wheel_filename,
                 init_wheel_size_bytes: 256 * 1024,
+                create_mode: WheelCreateMode::ZeroFill,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -370,6 +488,8 @@ fn create_write_delete_read_one() {
                 interpret::BlockPrepareWriteJobArgs {
                     block_id: block_id.clone(),
                     block_bytes: hello_world_bytes(),
+                    change_seq: 1,
+                    total_chunk_size: schema.storage_layout().block_chunk_size(hello_world_bytes().len()),
                     blocks_pool: blocks_pool.clone(),
                 },
             ).map_err(Error::WriteBlockPrepare)?;
@@ -390,6 +510,8 @@ fn create_write_delete_read_one() {
                 interpret::BlockPrepareWriteJobArgs {
                     block_id: block_id.clone(),
                     block_bytes: hello_world_bytes(),
+                    change_seq: 2,
+                    total_chunk_size: schema.storage_layout().block_chunk_size(hello_world_bytes().len()),
                     blocks_pool: blocks_pool.clone(),
                 },
             ).map_err(Error::WriteBlockPrepare)?;
@@ -407,6 +529,10 @@ fn create_write_delete_read_one() {
             // delete first block
             let interpret::BlockPrepareDeleteJobDone { delete_block_bytes, } = interpret::block_prepare_delete_job(
                 interpret::BlockPrepareDeleteJobArgs {
+                    block_id: block::Id::init(),
+                    change_seq: 3,
+                    secure_delete: None,
+                    total_chunk_size: schema.storage_layout().data_size_block_min() + hello_world_bytes().len(),
                     blocks_pool: blocks_pool.clone(),
                 },
             ).map_err(Error::DeleteBlockPrepare)?;
@@ -416,6 +542,7 @@ fn create_write_delete_read_one() {
                 block::Id::init(),
                 task::TaskKind::DeleteBlock(task::DeleteBlock {
                     delete_block_bytes: delete_block_bytes.freeze(),
+                    total_chunk_size: schema.storage_layout().data_size_block_min() + hello_world_bytes().len(),
                     commit: task::Commit::None,
                     context: task::DeleteBlockContext::External(context),
                 }),
@@ -441,6 +568,8 @@ fn create_write_delete_read_one() {
         let open_status = GenServer::open(
             OpenParams {
                 wheel_filename,
+                lock_mode: LockMode::Exclusive,
+                read_only: false,
             },
             performer::PerformerBuilderInit::new(
                 lru::Cache::new(0),
@@ -451,7 +580,7 @@ fn create_write_delete_read_one() {
         let WheelData { sync_gen_server: gen_server, performer, } = match open_status {
             WheelOpenStatus::Success(wheel_data) =>
                 wheel_data,
-            WheelOpenStatus::FileNotFound { .. } =>
+            WheelOpenStatus::FileNotFound { .. } | WheelOpenStatus::DeviceBlank { .. } =>
                 panic!("file not found: {:?}", wheel_filename),
         };
         let mut schema = performer.decompose();
@@ -463,6 +592,10 @@ fn create_write_delete_read_one() {
                 schema::ReadBlockOp::NotFound =>
                     (),
             }
+            let changes = schema.changes_since(0);
+            assert_eq!(changes.change_seq, 3);
+            assert_eq!(changes.blocks_count, 1);
+            assert_eq!(changes.deleted_block_ids, vec![block_id.clone()]);
             let block_id = block_id.next();
             let expected_offset = schema.storage_layout().wheel_header_size as u64
                 + schema.storage_layout().data_size_block_min() as u64
@@ -516,6 +649,256 @@ fn create_write_delete_read_one() {
     fs::remove_file(wheel_filename).unwrap();
 }
 
+#[test]
+fn read_header_blank_device() {
+    let storage_layout = storage::Layout::calculate(&mut Vec::new()).unwrap();
+    let wheel_header_size = storage_layout.wheel_header_size;
+    let zeros = vec![0; 4096];
+    let mut work_block = Vec::new();
+
+    // an all-zero header area is a blank device
+    let maybe_header = read_wheel_header(&mut io::Cursor::new(&zeros), TargetKind::BlockDevice, &mut work_block, wheel_header_size)
+        .unwrap();
+    assert!(maybe_header.is_none());
+
+    // but a broken regular file
+    let result = read_wheel_header(
+        &mut io::Cursor::new(&zeros),
+        TargetKind::RegularFile { file_size: zeros.len() as u64, },
+        &mut work_block,
+        wheel_header_size,
+    );
+    assert!(matches!(result, Err(WheelOpenError::HeaderInvalidMagic { provided: 0, .. })));
+
+    // a device with a wheel on it
+    let mut image = Vec::new();
+    let wheel_header = storage::WheelHeader { wheel_id: 7, size_bytes: 4096, ..Default::default() };
+    storage::bincode_options().serialize_into(&mut image, &wheel_header).unwrap();
+    image.resize(4096, 0);
+    let maybe_header = read_wheel_header(&mut io::Cursor::new(&image), TargetKind::BlockDevice, &mut work_block, wheel_header_size)
+        .unwrap();
+    assert!(matches!(maybe_header, Some(storage::WheelHeader { wheel_id: 7, size_bytes: 4096, .. })));
+
+    // a device with a garbage header is not blank
+    image[0] = 0xff;
+    let result = read_wheel_header(&mut io::Cursor::new(&image), TargetKind::BlockDevice, &mut work_block, wheel_header_size);
+    assert!(matches!(result, Err(WheelOpenError::HeaderInvalidMagic { .. })));
+}
+
+#[test]
+fn freed_areas_merge() {
+    let mut freed_areas = FreedAreas::new(None);
+    assert_eq!(freed_areas.free(0, 16, 4096), None);
+
+    let mut freed_areas = FreedAreas::new(Some(1000));
+    // small blocks are not punched one by one
+    assert_eq!(freed_areas.free(100, 16, 400), None);
+    assert_eq!(freed_areas.free(500, 16, 400), None);
+    // a block written at the start of the area
+    freed_areas.occupy(100, 50);
+    // but the merged area is, keeping only the latest tombstone
+    assert_eq!(freed_areas.free(900, 16, 600), Some([(150, 750), (916, 584)]));
+
+    assert_eq!(freed_areas.free(2000, 16, 2000), Some([(2000, 0), (2016, 1984)]));
+    // a write in the middle splits the area
+    freed_areas.occupy(2500, 100);
+    assert_eq!(freed_areas.free(4000, 16, 100), Some([(2600, 1400), (4016, 84)]));
+}
+
+#[test]
+fn open_duplicate_block_ids() {
+    use crate::wheel::core::BlockGet;
+
+    let wheel_filename = "/tmp/blockwheel_fixed_file_duplicate_block_ids";
+    let size_bytes: u64 = 4096;
+
+    let mut image = Vec::new();
+    let wheel_header = storage::WheelHeader { wheel_id: 1, size_bytes, ..Default::default() };
+    storage::bincode_options().serialize_into(&mut image, &wheel_header).unwrap();
+    let mut push_block = |block_id: block::Id, block_bytes: &[u8], change_seq: u64| {
+        let offset = image.len() as u64;
+        let block_header = storage::BlockHeader { block_id: block_id.clone(), block_size: block_bytes.len(), ..Default::default() };
+        storage::bincode_options().serialize_into(&mut image, &block_header).unwrap();
+        image.extend(block_bytes.iter().cloned());
+        let commit_tag = storage::CommitTag { block_id, crc: block::crc(block_bytes), change_seq, ..Default::default() };
+        storage::bincode_options().serialize_into(&mut image, &commit_tag).unwrap();
+        offset
+    };
+    push_block(block::Id::init(), b"stale copy", 1);
+    push_block(block::Id::init().next(), b"other block", 2);
+    let latest_offset = push_block(block::Id::init(), b"latest copy", 3);
+    storage::bincode_options().serialize_into(&mut image, &storage::TerminatorTag::default()).unwrap();
+    image.resize(size_bytes as usize, 0);
+    fs::write(wheel_filename, &image).unwrap();
+
+    let open_status = GenServer::open(
+        OpenParams {
+            wheel_filename,
+            lock_mode: LockMode::Exclusive,
+            read_only: false,
+        },
+        performer::PerformerBuilderInit::new(lru::Cache::new(0), None, 64 * 1024).unwrap(),
+    ).unwrap();
+    let WheelData { performer, .. } = match open_status {
+        WheelOpenStatus::Success(wheel_data) =>
+            wheel_data,
+        WheelOpenStatus::FileNotFound { .. } | WheelOpenStatus::DeviceBlank { .. } =>
+            panic!("file not found: {:?}", wheel_filename),
+    };
+    let mut schema = performer.decompose();
+    let changes = schema.changes_since(0);
+    assert_eq!(changes.blocks_count, 2);
+    assert_eq!(changes.change_seq, 3);
+    match schema.process_read_block_request(&block::Id::init()) {
+        schema::ReadBlockOp::Perform(schema::ReadBlockPerform { block_header, }) =>
+            assert_eq!(block_header.block_size, b"latest copy".len()),
+        schema::ReadBlockOp::NotFound =>
+            panic!("block {:?} not found", block::Id::init()),
+    }
+    assert!(matches!(
+        schema.block_get().by_id(&block::Id::init()),
+        Some(entry) if entry.offset == latest_offset,
+    ));
+
+    // stale copy space is reused by new writes
+    let mut block_bytes = BytesMut::new_detached(Vec::new());
+    block_bytes.extend(b"new".iter().cloned());
+    assert!(matches!(
+        schema.process_write_block_request(&block_bytes.freeze(), None),
+        schema::WriteBlockOp::Perform(schema::WriteBlockPerform {
+            task_op: schema::WriteBlockTaskOp { block_offset: 32, .. },
+            ..
+        }),
+    ));
+
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[test]
+fn create_open_aligned() {
+    let wheel_filename = "/tmp/blockwheel_fixed_file_create_open_aligned";
+    fs::remove_file(wheel_filename).ok();
+    let aligned_builder = || performer::PerformerBuilderInit::new(lru::Cache::new(0), None, 64 * 1024)
+        .unwrap()
+        .block_align(SECTOR_ALIGN);
+
+    let result = GenServer::create(
+        CreateParams {
+            wheel_filename,
+            init_wheel_size_bytes: 64 * 1024 + 512,
+            create_mode: WheelCreateMode::Sparse,
+        },
+        aligned_builder(),
+    );
+    assert!(matches!(result, Err(WheelCreateError::WheelSizeNotAligned { provided, block_align: SECTOR_ALIGN, }) if provided == 64 * 1024 + 512));
+    // rejected before anything is written
+    assert!(!Path::new(wheel_filename).exists());
+
+    let wheel_data = GenServer::create(
+        CreateParams {
+            wheel_filename,
+            init_wheel_size_bytes: 64 * 1024,
+            create_mode: WheelCreateMode::ZeroFill,
+        },
+        aligned_builder(),
+    ).unwrap();
+    drop(wheel_data);
+    let open_status = GenServer::open(
+        OpenParams {
+            wheel_filename,
+            lock_mode: LockMode::Exclusive,
+            read_only: false,
+        },
+        aligned_builder(),
+    ).unwrap();
+    let WheelData { performer, .. } = match open_status {
+        WheelOpenStatus::Success(wheel_data) =>
+            wheel_data,
+        WheelOpenStatus::FileNotFound { .. } | WheelOpenStatus::DeviceBlank { .. } =>
+            panic!("file not found: {:?}", wheel_filename),
+    };
+    let mut schema = performer.decompose();
+    // blocks start on sector boundaries and are padded to whole sectors
+    for expected_offset in [SECTOR_ALIGN as u64, 2 * SECTOR_ALIGN as u64] {
+        assert!(matches!(
+            schema.process_write_block_request(&hello_world_bytes(), None),
+            schema::WriteBlockOp::Perform(schema::WriteBlockPerform {
+                task_op: schema::WriteBlockTaskOp { block_offset, .. },
+                ..
+            }) if block_offset == expected_offset,
+        ));
+    }
+    fs::remove_file(wheel_filename).ok();
+
+    // alignment recorded in the header has to match the one requested
+    let open_status = GenServer::open(
+        OpenParams {
+            wheel_filename,
+            lock_mode: LockMode::Exclusive,
+            read_only: false,
+        },
+        performer::PerformerBuilderInit::new(lru::Cache::new(0), None, 64 * 1024).unwrap(),
+    );
+    assert!(matches!(
+        open_status,
+        Err(WheelOpenError::BlockAlignMismatch { header: SECTOR_ALIGN, expected: 1, }),
+    ));
+    fs::remove_file(wheel_filename).ok();
+
+    // a block placed off the alignment is refused
+    let size_bytes: u64 = 64 * 1024;
+    let mut image = Vec::new();
+    let wheel_header = storage::WheelHeader { wheel_id: 1, size_bytes, block_align: SECTOR_ALIGN, ..Default::default() };
+    storage::bincode_options().serialize_into(&mut image, &wheel_header).unwrap();
+    image.resize(SECTOR_ALIGN + 512, 0);
+    let block_offset = image.len() as u64;
+    let block_header = storage::BlockHeader { block_id: block::Id::init(), block_size: hello_world_bytes().len(), ..Default::default() };
+    storage::bincode_options().serialize_into(&mut image, &block_header).unwrap();
+    image.extend(hello_world_bytes().iter().cloned());
+    let commit_tag = storage::CommitTag { block_id: block::Id::init(), crc: block::crc(&hello_world_bytes()), change_seq: 1, ..Default::default() };
+    storage::bincode_options().serialize_into(&mut image, &commit_tag).unwrap();
+    storage::bincode_options().serialize_into(&mut image, &storage::TerminatorTag::default()).unwrap();
+    image.resize(size_bytes as usize, 0);
+    fs::write(wheel_filename, &image).unwrap();
+    let result = GenServer::open(
+        OpenParams {
+            wheel_filename,
+            lock_mode: LockMode::Exclusive,
+            read_only: false,
+        },
+        aligned_builder(),
+    );
+    assert!(matches!(
+        result,
+        Err(WheelOpenError::BlockNotAligned { offset, block_align: SECTOR_ALIGN, .. }) if offset == block_offset,
+    ));
+    fs::remove_file(wheel_filename).ok();
+}
+
+#[test]
+fn read_truncated_block() {
+    let storage_layout = storage::Layout::calculate(&mut Vec::new()).unwrap();
+    let block_header = storage::BlockHeader {
+        block_id: block::Id::init(),
+        block_size: usize::MAX,
+        ..Default::default()
+    };
+    let blocks_pool = BytesPool::new();
+    let mut block_bytes = blocks_pool.lend();
+    block_bytes.extend(hello_world_bytes().iter().cloned());
+    let result = interpret::block_process_read_job(interpret::BlockProcessReadJobArgs {
+        storage_layout,
+        block_header,
+        block_bytes: block_bytes.freeze(),
+    });
+    assert!(matches!(
+        result,
+        Err(interpret::BlockProcessReadJobError::CorruptedData(
+            interpret::CorruptedDataError::BlockLengthMismatch { block_size: usize::MAX, .. },
+        )),
+    ));
+}
+
 #[derive(Debug)]
 enum Error {
     PerformerBuild(performer::BuilderError),
@@ -583,7 +966,7 @@ where F: FnOnce(Pid, BytesPool) -> FF,
     let blocks_pool = BytesPool::new();
 
     let (error_tx, mut error_rx) = oneshot::channel();
-    gen_server.run(blocks_pool.clone(), error_tx, std::convert::identity)
+    gen_server.run(RunParams::default(), blocks_pool.clone(), error_tx, std::convert::identity)
         .map_err(Error::Run)?;
 
     let body_task = body(pid, blocks_pool);
@@ -592,8 +975,10 @@ where F: FnOnce(Pid, BytesPool) -> FF,
 
     loop {
         select! {
-            result = body_task_fused =>
-                return result,
+            result = body_task_fused => {
+                result?;
+                break;
+            },
             result = &mut error_rx =>
                 match result {
                     Err(oneshot::Canceled) =>
@@ -603,6 +988,14 @@ where F: FnOnce(Pid, BytesPool) -> FF,
                 },
         }
     }
+
+    // wait for interpreter thread to finish so the wheel file lock is released
+    match error_rx.await {
+        Err(oneshot::Canceled) =>
+            Ok(()),
+        Ok(error) =>
+            Err(Error::Run(error)),
+    }
 }
 
 async fn request_reply(
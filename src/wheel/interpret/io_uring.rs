@@ -0,0 +1,403 @@
+use std::{
+    fs,
+    thread,
+    io,
+    rc::Rc,
+    cell::Cell,
+    sync::mpsc,
+};
+
+use futures::{
+    channel::{
+        oneshot,
+        mpsc as async_mpsc,
+    },
+    StreamExt,
+};
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        storage,
+        core::task,
+        interpret::{
+            fixed_file,
+            Pid,
+            Synced,
+            Command,
+            Request,
+            DoneTask,
+            AppendTerminatorError,
+            block_append_terminator,
+        },
+    },
+    InterpretStats,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    AppendTerminator(AppendTerminatorError),
+    BlockWrite {
+        offset: u64,
+        error: io::Error,
+    },
+    TerminatorWrite {
+        offset: u64,
+        error: io::Error,
+    },
+    BlockRead {
+        offset: u64,
+        error: io::Error,
+    },
+    BlockReadUnexpectedEof {
+        offset: u64,
+    },
+    DeviceSync(io::Error),
+    ThreadSpawn(io::Error),
+}
+
+#[derive(Clone, Debug)]
+pub struct RunParams {
+    pub queue_depth: u32,
+}
+
+pub struct SyncGenServer<C> where C: Context {
+    wheel_file: fs::File,
+    request_tx: mpsc::Sender<Command<C>>,
+    request_rx: mpsc::Receiver<Command<C>>,
+    storage_layout: storage::Layout,
+}
+
+impl<C> SyncGenServer<C> where C: Context {
+    // wheel file format is the same as for `fixed_file` interpreter, so create and open are reused from there
+    pub fn new(fixed_file_gen_server: fixed_file::SyncGenServer<C>) -> SyncGenServer<C> {
+        let (wheel_file, request_tx, request_rx, storage_layout) =
+            fixed_file_gen_server.into_parts();
+        SyncGenServer { wheel_file, request_tx, request_rx, storage_layout, }
+    }
+
+    pub fn pid(&self) -> Pid<C> {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub fn run<F, E>(
+        self,
+        params: RunParams,
+        blocks_pool: BytesPool,
+        error_tx: oneshot::Sender<E>,
+        error_map: F,
+    )
+        -> Result<(), Error>
+    where F: FnOnce(Error) -> E + Send + 'static,
+          E: Send + 'static,
+          C: 'static,
+          C::WriteBlock: Send,
+          C::ReadBlock: Send,
+          C::DeleteBlock: Send,
+          C::IterBlocksStream: Send,
+    {
+        // ring runtime is not able to wait on blocking channel, so commands are forwarded from a separate thread
+        let (commands_tx, commands_rx) = async_mpsc::unbounded();
+        let request_rx = self.request_rx;
+        thread::Builder::new()
+            .name("wheel::interpret::io_uring::forward".to_string())
+            .spawn(move || {
+                while let Ok(command) = request_rx.recv() {
+                    if let Err(_send_error) = commands_tx.unbounded_send(command) {
+                        log::debug!("io_uring ring thread is gone, terminating forward");
+                        break;
+                    }
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+
+        let wheel_file = self.wheel_file;
+        let storage_layout = self.storage_layout;
+        thread::Builder::new()
+            .name("wheel::interpret::io_uring".to_string())
+            .spawn(move || {
+                let result = tokio_uring::builder()
+                    .entries(params.queue_depth)
+                    .start(busyloop(commands_rx, wheel_file, storage_layout, blocks_pool));
+                if let Err(error) = result {
+                    log::error!("wheel::interpret::io_uring terminated with {:?}", error);
+                    error_tx.send(error_map(error)).ok();
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+        Ok(())
+    }
+}
+
+async fn busyloop<C>(
+    mut commands_rx: async_mpsc::UnboundedReceiver<Command<C>>,
+    wheel_file: fs::File,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context + 'static,
+{
+    let wheel_file = Rc::new(tokio_uring::fs::File::from_std(wheel_file));
+    let mut stats = InterpretStats::default();
+
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    let (read_error_tx, mut read_error_rx) = async_mpsc::unbounded();
+    let readers_busy = Rc::new(Cell::new(0));
+    // ring operations take buffers by value, so the same vector is passed back and forth for writes
+    let mut write_buf = Vec::new();
+
+    // cursor is the position right after the last write, reads do not affect it
+    let mut cursor = storage_layout.wheel_header_size as u64;
+    let mut pending_terminator = false;
+    loop {
+        let command = futures::select! {
+            maybe_command = commands_rx.next() =>
+                match maybe_command {
+                    Some(command) =>
+                        command,
+                    None =>
+                        break,
+                },
+            maybe_read_error = read_error_rx.next() =>
+                match maybe_read_error {
+                    Some(error) =>
+                        return Err(error),
+                    None =>
+                        unreachable!(),
+                },
+        };
+
+        match command {
+
+            Command::Request(Request { offset, task: task @ task::Task { kind: task::TaskKind::ReadBlock(..), .. }, reply_tx, }) => {
+                stats.count_total += 1;
+                stats.count_readers += 1;
+                readers_busy.set(readers_busy.get() + 1);
+                if readers_busy.get() > stats.readers_busy_max {
+                    stats.readers_busy_max = readers_busy.get();
+                }
+                let read_future = read_block(
+                    wheel_file.clone(),
+                    offset,
+                    task,
+                    stats.clone(),
+                    reply_tx,
+                    storage_layout.clone(),
+                    blocks_pool.clone(),
+                );
+                let readers_busy = readers_busy.clone();
+                let read_error_tx = read_error_tx.clone();
+                tokio_uring::spawn(async move {
+                    let result = read_future.await;
+                    readers_busy.set(readers_busy.get() - 1);
+                    if let Err(error) = result {
+                        read_error_tx.unbounded_send(error).ok();
+                    }
+                });
+            },
+
+            Command::Request(Request { offset, task, reply_tx, }) => {
+                stats.count_total += 1;
+                stats.count_writer += 1;
+
+                if cursor < offset {
+                    stats.count_seek_forward += 1;
+                } else if cursor > offset {
+                    stats.count_seek_backward += 1;
+                    if pending_terminator {
+                        log::debug!("writing pending_terminator before moving backward @ {}", cursor);
+                        write_buf.clear();
+                        write_buf.extend_from_slice(&terminator_block_bytes);
+                        write_buf = write_all_at(&wheel_file, write_buf, cursor).await
+                            .map_err(|error| Error::TerminatorWrite { offset: cursor, error, })?;
+                        pending_terminator = false;
+                    }
+                } else {
+                    stats.count_no_seek += 1;
+                }
+
+                let (commit, kind) = match task.kind {
+                    task::TaskKind::WriteBlock(write_block) => {
+                        log::debug!(
+                            "write block {:?} @ {} of {} bytes, context: {:?}",
+                            task.block_id,
+                            offset,
+                            write_block.write_block_bytes.len(),
+                            write_block.context,
+                        );
+                        write_buf.clear();
+                        write_buf.extend_from_slice(&write_block.write_block_bytes);
+                        (
+                            write_block.commit,
+                            task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
+                                context: write_block.context,
+                            }),
+                        )
+                    },
+                    task::TaskKind::DeleteBlock(delete_block) => {
+                        log::debug!("delete block {:?} @ {}, context: {:?}", task.block_id, offset, delete_block.context);
+                        write_buf.clear();
+                        write_buf.extend_from_slice(&delete_block.delete_block_bytes);
+                        (
+                            delete_block.commit,
+                            task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
+                                context: delete_block.context,
+                            }),
+                        )
+                    },
+                    task::TaskKind::ReadBlock(..) =>
+                        unreachable!(),
+                };
+
+                let write_len = write_buf.len() as u64;
+                write_buf = write_all_at(&wheel_file, write_buf, offset).await
+                    .map_err(|error| Error::BlockWrite { offset, error, })?;
+                cursor = offset + write_len;
+
+                pending_terminator = match commit {
+                    task::Commit::None =>
+                        false,
+                    task::Commit::WithTerminator =>
+                        true,
+                };
+
+                let task_done = task::Done {
+                    current_offset: cursor,
+                    task: task::TaskDone {
+                        block_id: task.block_id,
+                        kind,
+                    },
+                };
+                if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats: stats.clone(), }) {
+                    break;
+                }
+            },
+
+            Command::DeviceSync { reply_tx, } => {
+                if pending_terminator {
+                    log::debug!("writing pending_terminator during flush @ {}", cursor);
+                    write_buf.clear();
+                    write_buf.extend_from_slice(&terminator_block_bytes);
+                    write_buf = write_all_at(&wheel_file, write_buf, cursor).await
+                        .map_err(|error| Error::TerminatorWrite { offset: cursor, error, })?;
+                    pending_terminator = false;
+                    cursor += terminator_block_bytes.len() as u64;
+                } else {
+                    log::debug!("flushed with no pending_terminator (cursor @ {})", cursor);
+                }
+                wheel_file.sync_data().await
+                    .map_err(Error::DeviceSync)?;
+                if let Err(_send_error) = reply_tx.send(Synced) {
+                    break;
+                }
+                log::info!("current stats: {:?}", stats);
+            },
+
+        }
+    }
+
+    log::debug!("master channel closed in io_uring busyloop, shutting down");
+    Ok(())
+}
+
+async fn write_all_at(wheel_file: &tokio_uring::fs::File, mut buf: Vec<u8>, offset: u64) -> Result<Vec<u8>, io::Error> {
+    let mut written = 0;
+    let total = buf.len();
+    while written < total {
+        let (result, returned_buf) = wheel_file.write_at(buf, offset + written as u64).await;
+        buf = returned_buf;
+        match result {
+            Ok(0) =>
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer")),
+            Ok(bytes_written) if bytes_written == buf.len() =>
+                written += bytes_written,
+            Ok(bytes_written) => {
+                buf.drain(.. bytes_written);
+                written += bytes_written;
+            },
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted =>
+                (),
+            Err(error) =>
+                return Err(error),
+        }
+    }
+    Ok(buf)
+}
+
+async fn read_block<C>(
+    wheel_file: Rc<tokio_uring::fs::File>,
+    offset: u64,
+    task: task::Task<C>,
+    stats: InterpretStats,
+    reply_tx: oneshot::Sender<DoneTask<C>>,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let (block_header, context) = match task.kind {
+        task::TaskKind::ReadBlock(task::ReadBlock { block_header, context, }) =>
+            (block_header, context),
+        task::TaskKind::WriteBlock(..) | task::TaskKind::DeleteBlock(..) =>
+            unreachable!(),
+    };
+
+    let total_chunk_size = storage_layout.data_size_block_min()
+        + block_header.block_size;
+
+    log::debug!(
+        "read block {:?} @ {} of {} bytes, context = {:?}",
+        task.block_id,
+        offset,
+        total_chunk_size,
+        context,
+    );
+
+    let mut block_bytes = blocks_pool.lend();
+    let mut read_buf = Vec::with_capacity(total_chunk_size);
+    while block_bytes.len() < total_chunk_size {
+        read_buf.clear();
+        read_buf.reserve(total_chunk_size - block_bytes.len());
+        let read_offset = offset + block_bytes.len() as u64;
+        let (result, returned_buf) = wheel_file.read_at(read_buf, read_offset).await;
+        read_buf = returned_buf;
+        match result {
+            Ok(0) =>
+                return Err(Error::BlockReadUnexpectedEof { offset: read_offset, }),
+            Ok(bytes_read) => {
+                let bytes_wanted = total_chunk_size - block_bytes.len();
+                block_bytes.extend_from_slice(&read_buf[.. bytes_read.min(bytes_wanted)]);
+            },
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted =>
+                (),
+            Err(error) =>
+                return Err(Error::BlockRead { offset: read_offset, error, }),
+        }
+    }
+
+    let task_done = task::Done {
+        current_offset: offset + total_chunk_size as u64,
+        task: task::TaskDone {
+            block_id: block_header.block_id,
+            kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
+                block_bytes,
+                context,
+            }),
+        },
+    };
+    if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
+        log::debug!("performer is gone, dropping read block reply");
+    }
+    Ok(())
+}
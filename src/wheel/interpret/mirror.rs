@@ -0,0 +1,684 @@
+use std::{
+    fs,
+    cmp,
+    thread,
+    io::{
+        self,
+        Seek,
+        Read,
+        Write,
+    },
+    path::PathBuf,
+    convert::TryInto,
+    time::{
+        Instant,
+        Duration,
+    },
+    sync::mpsc,
+};
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+};
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        block,
+        storage,
+        core::performer,
+        interpret::{
+            fixed_file,
+            block_io,
+            block_verify,
+            Pid,
+            Command,
+            AppendTerminatorError,
+            block_append_terminator,
+            sync_parent_dir,
+        },
+    },
+    WheelCreateMode,
+};
+
+// resync compares and copies member contents with chunks of this size
+const RESYNC_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    AppendTerminator(AppendTerminatorError),
+    ThreadSpawn(io::Error),
+    AllMembersFailed,
+    ResyncRead {
+        wheel_filename: PathBuf,
+        offset: u64,
+        error: io::Error,
+    },
+}
+
+#[derive(Debug)]
+pub enum WheelOpenError {
+    NoMembers,
+    PerformerBuild(performer::BuilderError),
+    Create(fixed_file::WheelCreateError),
+    Locked {
+        wheel_filename: PathBuf,
+    },
+    PrimaryHeaderRead(io::Error),
+    PrimaryHeader(fixed_file::WheelOpenError),
+    GenerationRead {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+    // member with newer contents than the ones which could be opened is broken
+    FresherMemberOpen {
+        wheel_filename: PathBuf,
+        error: fixed_file::WheelOpenError,
+    },
+    // members of the same generation hold different wheels, none of them can be trusted
+    FreshestMembersDisagree {
+        wheel_filename: PathBuf,
+        primary_wheel_filename: PathBuf,
+    },
+    MemberOpen {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+    MemberLock {
+        wheel_filename: PathBuf,
+        error: io::Error,
+    },
+}
+
+#[derive(Debug)]
+pub enum TaskJoinError {
+    Open(tokio::task::JoinError),
+}
+
+pub struct WheelData<C> where C: Context {
+    pub sync_gen_server: SyncGenServer<C>,
+    pub performer: performer::Performer<C>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpenParams {
+    pub wheel_filenames: Vec<PathBuf>,
+    pub init_wheel_size_bytes: usize,
+    pub create_mode: WheelCreateMode,
+    pub resync_on_open: bool,
+}
+
+pub struct SyncGenServer<C> where C: Context {
+    members: Vec<Member>,
+    request_tx: mpsc::Sender<Command<C>>,
+    request_rx: mpsc::Receiver<Command<C>>,
+    storage_layout: storage::Layout,
+    wheel_size_bytes: u64,
+    primary_index: usize,
+    generation: u64,
+}
+
+impl<C> SyncGenServer<C> where C: Context {
+    // wheel is loaded from the member of the latest generation holding one, the rest are attached to it and
+    // resynced if they are older or required; a new wheel is created on the first member and copied to the rest
+    pub fn open<F>(
+        params: OpenParams,
+        mut performer_builder: F,
+    )
+        -> Result<WheelData<C>, WheelOpenError>
+    where F: FnMut() -> Result<performer::PerformerBuilderInit<C>, performer::BuilderError>,
+    {
+        if params.wheel_filenames.is_empty() {
+            return Err(WheelOpenError::NoMembers);
+        }
+
+        let generations = params.wheel_filenames.iter()
+            .map(|wheel_filename| {
+                read_generation(wheel_filename)
+                    .map_err(|error| WheelOpenError::GenerationRead { wheel_filename: wheel_filename.clone(), error, })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut open_order: Vec<usize> = (0 .. params.wheel_filenames.len()).collect();
+        open_order.sort_by_key(|&index| cmp::Reverse(generations[index]));
+
+        let mut maybe_primary = None;
+        let mut maybe_open_failed = None;
+        for index in open_order {
+            let wheel_filename = &params.wheel_filenames[index];
+            let open_result = fixed_file::SyncGenServer::open(
+                fixed_file::OpenParams {
+                    wheel_filename,
+                    lock_mode: fixed_file::LockMode::Exclusive,
+                    read_only: false,
+                },
+                performer_builder().map_err(WheelOpenError::PerformerBuild)?,
+            );
+            match open_result {
+                Ok(fixed_file::WheelOpenStatus::Success(wheel_data)) => {
+                    maybe_primary = Some((index, wheel_data));
+                    break;
+                },
+                Ok(fixed_file::WheelOpenStatus::FileNotFound { .. }) |
+                Ok(fixed_file::WheelOpenStatus::DeviceBlank { .. }) =>
+                    log::info!("mirror member [ {:?} ] has no wheel", wheel_filename),
+                Err(fixed_file::WheelOpenError::Locked { wheel_filename, }) =>
+                    return Err(WheelOpenError::Locked { wheel_filename, }),
+                Err(error) => {
+                    log::warn!("mirror member [ {:?} ] cannot be opened: {:?}", wheel_filename, error);
+                    if maybe_open_failed.is_none() {
+                        maybe_open_failed = Some((index, error));
+                    }
+                },
+            }
+        }
+
+        let (primary_index, generation, fixed_file::WheelData { sync_gen_server, performer, }) = match maybe_primary {
+            Some((primary_index, wheel_data)) =>
+                (primary_index, generations[primary_index], wheel_data),
+            None => {
+                let wheel_data = fixed_file::SyncGenServer::create(
+                    fixed_file::CreateParams {
+                        wheel_filename: &params.wheel_filenames[0],
+                        init_wheel_size_bytes: params.init_wheel_size_bytes,
+                        create_mode: params.create_mode,
+                    },
+                    performer_builder().map_err(WheelOpenError::PerformerBuild)?,
+                ).map_err(WheelOpenError::Create)?;
+                (0, 0, wheel_data)
+            },
+        };
+        // members are tried from the freshest, so the one failed before could only be of the same generation or newer
+        if let Some((index, error)) = maybe_open_failed {
+            if generations[index] > generation {
+                return Err(WheelOpenError::FresherMemberOpen {
+                    wheel_filename: params.wheel_filenames[index].clone(),
+                    error,
+                });
+            }
+        }
+        log::debug!("mirror primary member is [ {:?} ] of generation {}", params.wheel_filenames[primary_index], generation);
+
+        let (mut primary_file, request_tx, request_rx, storage_layout) = sync_gen_server.into_parts();
+        let mut wheel_header_bytes = vec![0; storage_layout.wheel_header_size];
+        primary_file.seek(io::SeekFrom::Start(0))
+            .and_then(|_| primary_file.read_exact(&mut wheel_header_bytes))
+            .map_err(WheelOpenError::PrimaryHeaderRead)?;
+        let wheel_header = fixed_file::parse_wheel_header(&wheel_header_bytes)
+            .map_err(WheelOpenError::PrimaryHeader)?;
+
+        let mut members = Vec::with_capacity(params.wheel_filenames.len());
+        for (index, wheel_filename) in params.wheel_filenames.iter().enumerate() {
+            if index == primary_index {
+                continue;
+            }
+            let (wheel_file, maybe_member_header_bytes) = attach_member(wheel_filename, wheel_header_bytes.len())?;
+            let holds_wheel = match maybe_member_header_bytes {
+                Some(ref member_header_bytes) if member_header_bytes == &wheel_header_bytes =>
+                    true,
+                Some(ref member_header_bytes) if generations[index] == generation && fixed_file::parse_wheel_header(member_header_bytes).is_ok() =>
+                    return Err(WheelOpenError::FreshestMembersDisagree {
+                        wheel_filename: wheel_filename.clone(),
+                        primary_wheel_filename: params.wheel_filenames[primary_index].clone(),
+                    }),
+                Some(..) | None =>
+                    false,
+            };
+            let resync = if !holds_wheel {
+                log::info!("mirror member [ {:?} ] does not hold the wheel, it is going to be resynced", wheel_filename);
+                true
+            } else if generations[index] < generation {
+                log::info!(
+                    "mirror member [ {:?} ] is behind with generation {} of {}, it is going to be resynced",
+                    wheel_filename,
+                    generations[index],
+                    generation,
+                );
+                true
+            } else {
+                params.resync_on_open
+            };
+            members.push(Member::new(wheel_filename.clone(), wheel_file, resync));
+        }
+        members.insert(
+            primary_index,
+            Member::new(params.wheel_filenames[primary_index].clone(), primary_file, false),
+        );
+
+        Ok(WheelData {
+            sync_gen_server: SyncGenServer {
+                members,
+                request_tx,
+                request_rx,
+                storage_layout,
+                wheel_size_bytes: wheel_header.size_bytes,
+                primary_index,
+                generation,
+            },
+            performer,
+        })
+    }
+
+    pub fn pid(&self) -> Pid<C> {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub fn run<F, E>(
+        self,
+        blocks_pool: BytesPool,
+        error_tx: oneshot::Sender<E>,
+        error_map: F,
+    )
+        -> Result<(), Error>
+    where F: FnOnce(Error) -> E + Send + 'static,
+          E: Send + 'static,
+          C: 'static,
+          C::WriteBlock: Send,
+          C::ReadBlock: Send,
+          C::DeleteBlock: Send,
+          C::IterBlocksStream: Send,
+    {
+        let SyncGenServer { members, request_rx, storage_layout, wheel_size_bytes, primary_index, generation, .. } = self;
+        thread::Builder::new()
+            .name("wheel::interpret::mirror".to_string())
+            .spawn(move || {
+                let result = busyloop(
+                    request_rx,
+                    members,
+                    storage_layout,
+                    wheel_size_bytes,
+                    primary_index,
+                    generation,
+                    blocks_pool,
+                );
+                if let Err(error) = result {
+                    log::error!("wheel::interpret::mirror terminated with {:?}", error);
+                    error_tx.send(error_map(error)).ok();
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+        Ok(())
+    }
+}
+
+// returns member file locked and its wheel header area, `None` if the file is too short to hold one
+fn attach_member(wheel_filename: &PathBuf, wheel_header_size: usize) -> Result<(fs::File, Option<Vec<u8>>), WheelOpenError> {
+    let mut wheel_file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(wheel_filename)
+        .map_err(|error| WheelOpenError::MemberOpen {
+            wheel_filename: wheel_filename.clone(),
+            error,
+        })?;
+    let locked = fixed_file::try_lock(&wheel_file, fixed_file::LockMode::Exclusive)
+        .map_err(|error| WheelOpenError::MemberLock {
+            wheel_filename: wheel_filename.clone(),
+            error,
+        })?;
+    if !locked {
+        return Err(WheelOpenError::Locked {
+            wheel_filename: wheel_filename.clone(),
+        });
+    }
+
+    let mut member_header_bytes = vec![0; wheel_header_size];
+    match wheel_file.read_exact(&mut member_header_bytes) {
+        Ok(()) =>
+            Ok((wheel_file, Some(member_header_bytes))),
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof =>
+            Ok((wheel_file, None)),
+        Err(error) =>
+            Err(WheelOpenError::MemberOpen {
+                wheel_filename: wheel_filename.clone(),
+                error,
+            }),
+    }
+}
+
+// generation of member contents is kept next to its wheel file, zero for a member which has never had one
+fn generation_filename(wheel_filename: &PathBuf) -> PathBuf {
+    let mut filename = wheel_filename.clone().into_os_string();
+    filename.push(".generation");
+    filename.into()
+}
+
+fn read_generation(wheel_filename: &PathBuf) -> Result<u64, io::Error> {
+    match fs::read(generation_filename(wheel_filename)) {
+        Ok(bytes) => {
+            let generation_bytes = bytes.as_slice().try_into()
+                .map_err(|_error| io::Error::new(io::ErrorKind::InvalidData, "generation file of unexpected size"))?;
+            Ok(u64::from_le_bytes(generation_bytes))
+        },
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound =>
+            Ok(0),
+        Err(error) =>
+            Err(error),
+    }
+}
+
+// replaced with rename, so a crash leaves either the previous generation or the new one
+fn write_generation(wheel_filename: &PathBuf, generation: u64) -> Result<(), io::Error> {
+    let filename = generation_filename(wheel_filename);
+    let mut tmp_filename = filename.clone().into_os_string();
+    tmp_filename.push(".tmp");
+    let mut file = fs::File::create(&tmp_filename)?;
+    file.write_all(&generation.to_le_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_filename, &filename)?;
+    sync_parent_dir(&filename)
+}
+
+struct Member {
+    wheel_filename: PathBuf,
+    wheel_file: fs::File,
+    resync: bool,
+    failed: bool,
+    // moving average of read latency, the fastest member serves reads
+    read_latency: Duration,
+}
+
+impl Member {
+    fn new(wheel_filename: PathBuf, wheel_file: fs::File, resync: bool) -> Member {
+        Member {
+            wheel_filename,
+            wheel_file,
+            resync,
+            failed: false,
+            read_latency: Duration::from_secs(0),
+        }
+    }
+
+    fn write_at(&mut self, bytes: &[u8], offset: u64) -> Result<(), io::Error> {
+        self.wheel_file.seek(io::SeekFrom::Start(offset))?;
+        self.wheel_file.write_all(bytes)
+    }
+
+    fn read_at(&mut self, target: &mut [u8], offset: u64) -> Result<(), io::Error> {
+        let now = Instant::now();
+        self.wheel_file.seek(io::SeekFrom::Start(offset))?;
+        self.wheel_file.read_exact(target)?;
+        self.read_latency = (self.read_latency * 7 + now.elapsed()) / 8;
+        Ok(())
+    }
+
+    fn fail(&mut self, reason: &str, error: io::Error) {
+        log::error!("mirror member [ {:?} ] failed on {}: {:?}, it is detached", self.wheel_filename, reason, error);
+        self.failed = true;
+    }
+}
+
+struct Members {
+    members: Vec<Member>,
+    storage_layout: storage::Layout,
+    // generation of the contents shared by active members
+    generation: u64,
+}
+
+impl Members {
+    fn write_at(&mut self, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        let failed_before = self.failed_count();
+        for member in self.members.iter_mut().filter(|member| !member.failed) {
+            if let Err(error) = member.write_at(bytes, offset) {
+                member.fail("write", error);
+            }
+        }
+        self.ensure_alive()?;
+        self.bump_generation_on_detach(failed_before)
+    }
+
+    fn ensure_alive(&self) -> Result<(), Error> {
+        if self.members.iter().all(|member| member.failed) {
+            return Err(Error::AllMembersFailed);
+        }
+        Ok(())
+    }
+
+    fn failed_count(&self) -> usize {
+        self.members.iter().filter(|member| member.failed).count()
+    }
+
+    // active members move to the next generation, so the ones left behind are never taken for fresh on open;
+    // contents are made durable first, so a generation never claims data which could still be lost
+    fn bump_generation(&mut self) -> Result<(), Error> {
+        loop {
+            self.generation += 1;
+            let mut detached = false;
+            for member in self.members.iter_mut().filter(|member| !member.failed) {
+                if let Err(error) = member.wheel_file.sync_data() {
+                    member.fail("sync", error);
+                    detached = true;
+                    continue;
+                }
+                if let Err(error) = write_generation(&member.wheel_filename, self.generation) {
+                    member.fail("generation write", error);
+                    detached = true;
+                }
+            }
+            self.ensure_alive()?;
+            if !detached {
+                return Ok(());
+            }
+        }
+    }
+
+    fn bump_generation_on_detach(&mut self, failed_before: usize) -> Result<(), Error> {
+        if self.failed_count() > failed_before {
+            self.bump_generation()
+        } else {
+            Ok(())
+        }
+    }
+
+    // active members from the fastest one
+    fn read_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0 .. self.members.len())
+            .filter(|&index| !self.members[index].failed)
+            .collect();
+        order.sort_by_key(|&index| self.members[index].read_latency);
+        order
+    }
+
+    // block is read from the fastest member, a copy failing verification is rewritten from the next good one
+    fn read_verified(
+        &mut self,
+        block_bytes: &mut [u8],
+        offset: u64,
+        block_header: &storage::BlockHeader,
+    )
+        -> Result<(), Error>
+    {
+        let failed_before = self.failed_count();
+        let result = self.read_verified_members(block_bytes, offset, block_header);
+        self.bump_generation_on_detach(failed_before)?;
+        result
+    }
+
+    fn read_verified_members(
+        &mut self,
+        block_bytes: &mut [u8],
+        offset: u64,
+        block_header: &storage::BlockHeader,
+    )
+        -> Result<(), Error>
+    {
+        let mut corrupted = Vec::new();
+        let mut read_any = false;
+        for index in self.read_order() {
+            let member = &mut self.members[index];
+            if let Err(error) = member.read_at(block_bytes, offset) {
+                member.fail("read", error);
+                continue;
+            }
+            read_any = true;
+            match block_verify(&self.storage_layout, block_header, block_bytes) {
+                Ok(()) => {
+                    for corrupted_index in corrupted {
+                        let corrupted_member: &mut Member = &mut self.members[corrupted_index];
+                        log::warn!(
+                            "read repair of block {:?} @ {} on mirror member [ {:?} ]",
+                            block_header.block_id,
+                            offset,
+                            corrupted_member.wheel_filename,
+                        );
+                        if let Err(error) = corrupted_member.write_at(block_bytes, offset) {
+                            corrupted_member.fail("read repair", error);
+                        }
+                    }
+                    return Ok(());
+                },
+                Err(error) => {
+                    log::warn!(
+                        "block {:?} @ {} on mirror member [ {:?} ] is corrupted: {:?}",
+                        block_header.block_id,
+                        offset,
+                        member.wheel_filename,
+                        error,
+                    );
+                    corrupted.push(index);
+                },
+            }
+        }
+        self.ensure_alive()?;
+        if !read_any {
+            return Err(Error::AllMembersFailed);
+        }
+        // no good copy: the last one read is reported as corrupted by the wheel itself
+        Ok(())
+    }
+
+    // every chunk of member differing from the primary one is rewritten
+    fn resync(&mut self, primary_index: usize, wheel_size_bytes: u64) -> Result<(), Error> {
+        let resync_indices: Vec<_> = (0 .. self.members.len())
+            .filter(|&index| self.members[index].resync && !self.members[index].failed)
+            .collect();
+        if resync_indices.is_empty() {
+            return Ok(());
+        }
+
+        let mut primary_chunk = vec![0; RESYNC_CHUNK_SIZE];
+        let mut member_chunk = vec![0; RESYNC_CHUNK_SIZE];
+        let mut chunks_repaired = vec![0; resync_indices.len()];
+        let mut offset = 0;
+        while offset < wheel_size_bytes {
+            let chunk_size = (wheel_size_bytes - offset).min(RESYNC_CHUNK_SIZE as u64) as usize;
+            let primary = &mut self.members[primary_index];
+            primary.wheel_file.seek(io::SeekFrom::Start(offset))
+                .and_then(|_| primary.wheel_file.read_exact(&mut primary_chunk[.. chunk_size]))
+                .map_err(|error| Error::ResyncRead {
+                    wheel_filename: primary.wheel_filename.clone(),
+                    offset,
+                    error,
+                })?;
+            for (&index, repaired) in resync_indices.iter().zip(chunks_repaired.iter_mut()) {
+                let member = &mut self.members[index];
+                if member.failed {
+                    continue;
+                }
+                let same = member.wheel_file.seek(io::SeekFrom::Start(offset))
+                    .and_then(|_| member.wheel_file.read_exact(&mut member_chunk[.. chunk_size]))
+                    .map(|()| member_chunk[.. chunk_size] == primary_chunk[.. chunk_size])
+                    .unwrap_or(false);
+                if !same {
+                    if let Err(error) = member.write_at(&primary_chunk[.. chunk_size], offset) {
+                        member.fail("resync", error);
+                        continue;
+                    }
+                    *repaired += 1;
+                }
+            }
+            offset += chunk_size as u64;
+        }
+
+        for (&index, repaired) in resync_indices.iter().zip(chunks_repaired) {
+            let member = &mut self.members[index];
+            if member.failed {
+                continue;
+            }
+            let result = member.wheel_file.metadata()
+                .and_then(|metadata| if metadata.is_file() {
+                    member.wheel_file.set_len(wheel_size_bytes)
+                } else {
+                    Ok(())
+                })
+                .and_then(|()| member.wheel_file.sync_all());
+            match result {
+                Ok(()) =>
+                    log::info!("mirror member [ {:?} ] resynced: {} chunks rewritten", member.wheel_filename, repaired),
+                Err(error) =>
+                    member.fail("resync", error),
+            }
+            member.resync = false;
+        }
+        self.ensure_alive()
+    }
+}
+
+impl block_io::BlockIo for Members {
+    type Error = Error;
+
+    fn write_block(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+    }
+
+    fn write_terminator(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+    }
+
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Error>
+    {
+        block_bytes.resize(total_chunk_size, 0);
+        self.read_verified(block_bytes, offset, block_header)
+    }
+
+    // generation is bumped once on start and on every detach, members in sync keep it across flushes
+    fn sync(&mut self) -> Result<(), Error> {
+        let failed_before = self.failed_count();
+        for member in self.members.iter_mut().filter(|member| !member.failed) {
+            if let Err(error) = member.wheel_file.sync_data() {
+                member.fail("sync", error);
+            }
+        }
+        self.ensure_alive()?;
+        self.bump_generation_on_detach(failed_before)
+    }
+}
+
+fn busyloop<C>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    members: Vec<Member>,
+    storage_layout: storage::Layout,
+    wheel_size_bytes: u64,
+    primary_index: usize,
+    generation: u64,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let mut members = Members { members, storage_layout: storage_layout.clone(), generation, };
+    members.resync(primary_index, wheel_size_bytes)?;
+    // members in sync, including the resynced ones, are moved to the next generation together
+    members.bump_generation()?;
+
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    block_io::busyloop(request_rx, members, storage_layout, terminator_block_bytes, blocks_pool)
+}
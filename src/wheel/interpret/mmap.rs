@@ -0,0 +1,175 @@
+use std::{
+    fs,
+    thread,
+    io,
+    sync::mpsc,
+};
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+};
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        block,
+        storage,
+        interpret::{
+            fixed_file,
+            block_io,
+            Pid,
+            Command,
+            AppendTerminatorError,
+            block_append_terminator,
+        },
+    },
+};
+
+mod region;
+
+#[derive(Debug)]
+pub enum Error {
+    AppendTerminator(AppendTerminatorError),
+    Map(io::Error),
+    OutOfBounds {
+        offset: u64,
+        size: usize,
+        wheel_size_bytes: usize,
+    },
+    DeviceSync(io::Error),
+    ThreadSpawn(io::Error),
+}
+
+pub struct SyncGenServer<C> where C: Context {
+    wheel_file: fs::File,
+    request_tx: mpsc::Sender<Command<C>>,
+    request_rx: mpsc::Receiver<Command<C>>,
+    storage_layout: storage::Layout,
+}
+
+impl<C> SyncGenServer<C> where C: Context {
+    // wheel file format is the same as for `fixed_file` interpreter, so create and open are reused from there
+    pub fn new(fixed_file_gen_server: fixed_file::SyncGenServer<C>) -> SyncGenServer<C> {
+        let (wheel_file, request_tx, request_rx, storage_layout) =
+            fixed_file_gen_server.into_parts();
+        SyncGenServer { wheel_file, request_tx, request_rx, storage_layout, }
+    }
+
+    pub fn pid(&self) -> Pid<C> {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub fn run<F, E>(
+        self,
+        blocks_pool: BytesPool,
+        error_tx: oneshot::Sender<E>,
+        error_map: F,
+    )
+        -> Result<(), Error>
+    where F: FnOnce(Error) -> E + Send + 'static,
+          E: Send + 'static,
+          C: 'static,
+          C::WriteBlock: Send,
+          C::ReadBlock: Send,
+          C::DeleteBlock: Send,
+          C::IterBlocksStream: Send,
+    {
+        let SyncGenServer { wheel_file, request_rx, storage_layout, .. } = self;
+        let region = region::Region::map(&wheel_file)
+            .map_err(Error::Map)?;
+        thread::Builder::new()
+            .name("wheel::interpret::mmap".to_string())
+            .spawn(move || {
+                let result = busyloop(
+                    request_rx,
+                    wheel_file,
+                    region,
+                    storage_layout,
+                    blocks_pool,
+                );
+                if let Err(error) = result {
+                    log::error!("wheel::interpret::mmap terminated with {:?}", error);
+                    error_tx.send(error_map(error)).ok();
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+        Ok(())
+    }
+}
+
+// file is kept open while it is mapped
+struct Mapped {
+    _wheel_file: fs::File,
+    region: region::Region,
+}
+
+impl Mapped {
+    fn write_at(&mut self, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        let start = offset as usize;
+        let wheel_size_bytes = self.region.len();
+        let target = self.region.slice_mut(start .. start + bytes.len())
+            .ok_or(Error::OutOfBounds { offset, size: bytes.len(), wheel_size_bytes, })?;
+        target.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl block_io::BlockIo for Mapped {
+    type Error = Error;
+
+    fn write_block(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+    }
+
+    fn write_terminator(&mut self, _block_id: &block::Id, bytes: &[u8], offset: u64) -> Result<(), Error> {
+        self.write_at(bytes, offset)
+    }
+
+    fn read_block(
+        &mut self,
+        block_bytes: &mut Vec<u8>,
+        _block_header: &storage::BlockHeader,
+        total_chunk_size: usize,
+        offset: u64,
+    )
+        -> Result<(), Error>
+    {
+        // `Bytes` are always backed by `blocks_pool`, so contents are copied out of the mapping once
+        let start = offset as usize;
+        let source = self.region.slice(start .. start + total_chunk_size)
+            .ok_or(Error::OutOfBounds { offset, size: total_chunk_size, wheel_size_bytes: self.region.len(), })?;
+        block_bytes.extend_from_slice(source);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.region.flush()
+            .map_err(Error::DeviceSync)
+    }
+}
+
+fn busyloop<C>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    wheel_file: fs::File,
+    region: region::Region,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    let mapped = Mapped { _wheel_file: wheel_file, region, };
+    block_io::busyloop(request_rx, mapped, storage_layout, terminator_block_bytes, blocks_pool)
+}
@@ -0,0 +1,43 @@
+//! The only place in the crate where `unsafe` is allowed (enabled with `mmap` feature).
+//!
+//! Mapping a file is unsound if the file is truncated or modified by someone else while mapped.
+//! Wheel file is owned exclusively by the interpreter thread for the whole lifetime of `Region`,
+//! so the mapping is never resized and all access goes through `&self`/`&mut self` borrows below.
+
+#![allow(unsafe_code)]
+
+use std::{
+    fs,
+    io,
+    ops::Range,
+};
+
+use memmap2::MmapMut;
+
+pub struct Region {
+    map: MmapMut,
+}
+
+impl Region {
+    pub fn map(wheel_file: &fs::File) -> Result<Region, io::Error> {
+        // SAFETY: see module level comment, `wheel_file` is not shared with anyone else and its size is fixed
+        let map = unsafe { MmapMut::map_mut(wheel_file)? };
+        Ok(Region { map, })
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Option<&[u8]> {
+        self.map.get(range)
+    }
+
+    pub fn slice_mut(&mut self, range: Range<usize>) -> Option<&mut [u8]> {
+        self.map.get_mut(range)
+    }
+
+    pub fn flush(&self) -> Result<(), io::Error> {
+        self.map.flush()
+    }
+}
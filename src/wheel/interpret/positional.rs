@@ -0,0 +1,420 @@
+use std::{
+    fs,
+    thread,
+    io,
+    os::unix::fs::FileExt,
+    sync::{
+        mpsc,
+        Arc,
+        Mutex,
+        atomic::{
+            self,
+            AtomicUsize,
+        },
+    },
+};
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+};
+
+use alloc_pool::bytes::{
+    BytesPool,
+};
+
+use crate::{
+    context::Context,
+    wheel::{
+        storage,
+        core::task,
+        interpret::{
+            fixed_file,
+            Pid,
+            Synced,
+            Command,
+            Request,
+            DoneTask,
+            AppendTerminatorError,
+            block_append_terminator,
+        },
+    },
+    InterpretStats,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    AppendTerminator(AppendTerminatorError),
+    BlockWrite {
+        offset: u64,
+        error: io::Error,
+    },
+    TerminatorWrite {
+        offset: u64,
+        error: io::Error,
+    },
+    BlockRead {
+        offset: u64,
+        error: io::Error,
+    },
+    DeviceSyncFlush(io::Error),
+    WheelFileClone(io::Error),
+    ThreadSpawn(io::Error),
+    ReadersGone,
+}
+
+#[derive(Clone, Debug)]
+pub struct RunParams {
+    pub reader_threads: usize,
+}
+
+pub struct SyncGenServer<C> where C: Context {
+    wheel_file: fs::File,
+    request_tx: mpsc::Sender<Command<C>>,
+    request_rx: mpsc::Receiver<Command<C>>,
+    storage_layout: storage::Layout,
+}
+
+impl<C> SyncGenServer<C> where C: Context {
+    // wheel file format is the same as for `fixed_file` interpreter, so create and open are reused from there
+    pub fn new(fixed_file_gen_server: fixed_file::SyncGenServer<C>) -> SyncGenServer<C> {
+        let (wheel_file, request_tx, request_rx, storage_layout) =
+            fixed_file_gen_server.into_parts();
+        SyncGenServer { wheel_file, request_tx, request_rx, storage_layout, }
+    }
+
+    pub fn pid(&self) -> Pid<C> {
+        Pid {
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    pub fn run<F, E>(
+        self,
+        params: RunParams,
+        blocks_pool: BytesPool,
+        error_tx: oneshot::Sender<E>,
+        error_map: F,
+    )
+        -> Result<(), Error>
+    where F: FnOnce(Error) -> E + Send + 'static,
+          E: Send + 'static,
+          C: 'static,
+          C::WriteBlock: Send,
+          C::ReadBlock: Send,
+          C::DeleteBlock: Send,
+          C::IterBlocksStream: Send,
+    {
+        let SyncGenServer { wheel_file, request_rx, storage_layout, .. } = self;
+        let error_report = ErrorReport::new(error_tx, error_map);
+        let readers_busy = Arc::new(AtomicUsize::new(0));
+        let readers_count = params.reader_threads.max(1);
+        let reads_per_reader: Arc<Vec<_>> = Arc::new((0 .. readers_count).map(|_| AtomicUsize::new(0)).collect());
+
+        let (read_job_tx, read_job_rx) = mpsc::channel();
+        let read_job_rx = Arc::new(Mutex::new(read_job_rx));
+        for reader_index in 0 .. readers_count {
+            let reader_file = wheel_file.try_clone()
+                .map_err(Error::WheelFileClone)?;
+            let read_job_rx = read_job_rx.clone();
+            let readers_busy = readers_busy.clone();
+            let reads_per_reader = reads_per_reader.clone();
+            let storage_layout = storage_layout.clone();
+            let blocks_pool = blocks_pool.clone();
+            let error_report = error_report.clone();
+            thread::Builder::new()
+                .name(format!("wheel::interpret::positional::reader{}", reader_index))
+                .spawn(move || {
+                    let result = reader_loop(
+                        reader_index,
+                        read_job_rx,
+                        reader_file,
+                        readers_busy,
+                        reads_per_reader,
+                        storage_layout,
+                        blocks_pool,
+                    );
+                    if let Err(error) = result {
+                        log::error!("wheel::interpret::positional reader {} terminated with {:?}", reader_index, error);
+                        error_report.report(error);
+                    }
+                })
+                .map_err(Error::ThreadSpawn)?;
+        }
+
+        thread::Builder::new()
+            .name("wheel::interpret::positional::writer".to_string())
+            .spawn(move || {
+                let result = writer_loop(
+                    request_rx,
+                    wheel_file,
+                    read_job_tx,
+                    readers_busy,
+                    reads_per_reader,
+                    storage_layout,
+                    blocks_pool,
+                );
+                if let Err(error) = result {
+                    log::error!("wheel::interpret::positional writer terminated with {:?}", error);
+                    error_report.report(error);
+                }
+            })
+            .map_err(Error::ThreadSpawn)?;
+        Ok(())
+    }
+}
+
+struct ErrorReport<F, E> {
+    inner: Arc<Mutex<Option<(oneshot::Sender<E>, F)>>>,
+}
+
+impl<F, E> Clone for ErrorReport<F, E> {
+    fn clone(&self) -> Self {
+        ErrorReport { inner: self.inner.clone(), }
+    }
+}
+
+impl<F, E> ErrorReport<F, E> where F: FnOnce(Error) -> E {
+    fn new(error_tx: oneshot::Sender<E>, error_map: F) -> Self {
+        ErrorReport { inner: Arc::new(Mutex::new(Some((error_tx, error_map)))), }
+    }
+
+    fn report(&self, error: Error) {
+        let maybe_report = match self.inner.lock() {
+            Ok(mut lock) =>
+                lock.take(),
+            Err(poisoned) =>
+                poisoned.into_inner().take(),
+        };
+        if let Some((error_tx, error_map)) = maybe_report {
+            error_tx.send(error_map(error)).ok();
+        }
+    }
+}
+
+struct ReadJob<C> where C: Context {
+    offset: u64,
+    task: task::Task<C>,
+    stats: InterpretStats,
+    reply_tx: oneshot::Sender<DoneTask<C>>,
+}
+
+fn writer_loop<C>(
+    request_rx: mpsc::Receiver<Command<C>>,
+    mut wheel_file: fs::File,
+    read_job_tx: mpsc::Sender<ReadJob<C>>,
+    readers_busy: Arc<AtomicUsize>,
+    reads_per_reader: Arc<Vec<AtomicUsize>>,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    let mut stats = InterpretStats::default();
+
+    let mut terminator_block_bytes = blocks_pool.lend();
+    block_append_terminator(&mut terminator_block_bytes)
+        .map_err(Error::AppendTerminator)?;
+
+    // cursor is the position right after the last write, reads do not affect it
+    let mut cursor = storage_layout.wheel_header_size as u64;
+    let mut pending_terminator = false;
+    loop {
+        let command = match request_rx.recv() {
+            Ok(command) =>
+                command,
+            Err(mpsc::RecvError) =>
+                break,
+        };
+
+        match command {
+
+            Command::Request(Request { offset, task: task @ task::Task { kind: task::TaskKind::ReadBlock(..), .. }, reply_tx, }) => {
+                stats.count_total += 1;
+                stats.count_readers += 1;
+                let busy = readers_busy.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+                if busy > stats.readers_busy_max {
+                    stats.readers_busy_max = busy;
+                }
+                let read_job = ReadJob { offset, task, stats: stats.clone(), reply_tx, };
+                if let Err(_send_error) = read_job_tx.send(read_job) {
+                    return Err(Error::ReadersGone);
+                }
+            },
+
+            Command::Request(Request { offset, task, reply_tx, }) => {
+                stats.count_total += 1;
+                stats.count_writer += 1;
+
+                if cursor < offset {
+                    stats.count_seek_forward += 1;
+                } else if cursor > offset {
+                    stats.count_seek_backward += 1;
+                    if pending_terminator {
+                        log::debug!("writing pending_terminator before moving backward @ {}", cursor);
+                        wheel_file.write_all_at(&terminator_block_bytes, cursor)
+                            .map_err(|error| Error::TerminatorWrite { offset: cursor, error, })?;
+                        pending_terminator = false;
+                    }
+                } else {
+                    stats.count_no_seek += 1;
+                }
+
+                let (write_bytes, commit, kind) = match task.kind {
+                    task::TaskKind::WriteBlock(write_block) => {
+                        log::debug!(
+                            "write block {:?} @ {} of {} bytes, context: {:?}",
+                            task.block_id,
+                            offset,
+                            write_block.write_block_bytes.len(),
+                            write_block.context,
+                        );
+                        (
+                            write_block.write_block_bytes,
+                            write_block.commit,
+                            task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
+                                context: write_block.context,
+                            }),
+                        )
+                    },
+                    task::TaskKind::DeleteBlock(delete_block) => {
+                        log::debug!("delete block {:?} @ {}, context: {:?}", task.block_id, offset, delete_block.context);
+                        (
+                            delete_block.delete_block_bytes,
+                            delete_block.commit,
+                            task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
+                                context: delete_block.context,
+                            }),
+                        )
+                    },
+                    task::TaskKind::ReadBlock(..) =>
+                        unreachable!(),
+                };
+
+                wheel_file.write_all_at(&write_bytes, offset)
+                    .map_err(|error| Error::BlockWrite { offset, error, })?;
+                cursor = offset + write_bytes.len() as u64;
+
+                pending_terminator = match commit {
+                    task::Commit::None =>
+                        false,
+                    task::Commit::WithTerminator =>
+                        true,
+                };
+
+                let task_done = task::Done {
+                    current_offset: cursor,
+                    task: task::TaskDone {
+                        block_id: task.block_id,
+                        kind,
+                    },
+                };
+                stats.count_per_reader = load_reads_per_reader(&reads_per_reader);
+                if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats: stats.clone(), }) {
+                    break;
+                }
+            },
+
+            Command::DeviceSync { reply_tx, } => {
+                if pending_terminator {
+                    log::debug!("writing pending_terminator during flush @ {}", cursor);
+                    wheel_file.write_all_at(&terminator_block_bytes, cursor)
+                        .map_err(|error| Error::TerminatorWrite { offset: cursor, error, })?;
+                    pending_terminator = false;
+                    cursor += terminator_block_bytes.len() as u64;
+                } else {
+                    log::debug!("flushed with no pending_terminator (cursor @ {})", cursor);
+                }
+                wheel_file.sync_data()
+                    .map_err(Error::DeviceSyncFlush)?;
+                if let Err(_send_error) = reply_tx.send(Synced) {
+                    break;
+                }
+                log::info!("current stats: {:?}", stats);
+            },
+
+        }
+    }
+
+    log::debug!("master channel closed in writer_loop, shutting down");
+    Ok(())
+}
+
+fn reader_loop<C>(
+    reader_index: usize,
+    read_job_rx: Arc<Mutex<mpsc::Receiver<ReadJob<C>>>>,
+    wheel_file: fs::File,
+    readers_busy: Arc<AtomicUsize>,
+    reads_per_reader: Arc<Vec<AtomicUsize>>,
+    storage_layout: storage::Layout,
+    blocks_pool: BytesPool,
+)
+    -> Result<(), Error>
+where C: Context,
+{
+    loop {
+        let maybe_read_job = match read_job_rx.lock() {
+            Ok(lock) =>
+                lock.recv(),
+            Err(..) =>
+                return Ok(()),
+        };
+        let ReadJob { offset, task, mut stats, reply_tx, } = match maybe_read_job {
+            Ok(read_job) =>
+                read_job,
+            Err(mpsc::RecvError) =>
+                break,
+        };
+        let (block_header, context) = match task.kind {
+            task::TaskKind::ReadBlock(task::ReadBlock { block_header, context, }) =>
+                (block_header, context),
+            task::TaskKind::WriteBlock(..) | task::TaskKind::DeleteBlock(..) =>
+                unreachable!(),
+        };
+
+        let total_chunk_size = storage_layout.data_size_block_min()
+            + block_header.block_size;
+
+        log::debug!(
+            "read block {:?} @ {} of {} bytes, context = {:?}",
+            task.block_id,
+            offset,
+            total_chunk_size,
+            context,
+        );
+
+        let mut block_bytes = blocks_pool.lend();
+        block_bytes.resize(total_chunk_size, 0);
+        let read_result = wheel_file.read_exact_at(&mut block_bytes[..], offset);
+        readers_busy.fetch_sub(1, atomic::Ordering::SeqCst);
+        reads_per_reader[reader_index].fetch_add(1, atomic::Ordering::SeqCst);
+        stats.count_per_reader = load_reads_per_reader(&reads_per_reader);
+        read_result
+            .map_err(|error| Error::BlockRead { offset, error, })?;
+
+        let task_done = task::Done {
+            current_offset: offset + total_chunk_size as u64,
+            task: task::TaskDone {
+                block_id: block_header.block_id,
+                kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
+                    block_bytes,
+                    context,
+                }),
+            },
+        };
+        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
+            break;
+        }
+    }
+
+    log::debug!("read jobs channel closed in reader_loop, shutting down");
+    Ok(())
+}
+
+fn load_reads_per_reader(reads_per_reader: &[AtomicUsize]) -> Vec<usize> {
+    reads_per_reader.iter()
+        .map(|reads| reads.load(atomic::Ordering::SeqCst))
+        .collect()
+}
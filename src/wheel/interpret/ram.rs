@@ -1,5 +1,14 @@
 use std::{
-    io,
+    fs,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
     thread,
     sync::{
         mpsc,
@@ -15,6 +24,7 @@ use futures::{
 use bincode::Options;
 
 use alloc_pool::bytes::{
+    BytesMut,
     BytesPool,
 };
 
@@ -27,6 +37,7 @@ use crate::{
             performer,
         },
         interpret::{
+            fixed_file,
             Pid,
             Synced,
             Command,
@@ -34,6 +45,7 @@ use crate::{
             DoneTask,
             AppendTerminatorError,
             block_append_terminator,
+            sync_parent_dir,
         },
     },
     InterpretStats,
@@ -43,6 +55,10 @@ use crate::{
 pub enum Error {
     AppendTerminator(AppendTerminatorError),
     ThreadSpawn(io::Error),
+    SnapshotWrite {
+        snapshot_filename: PathBuf,
+        error: io::Error,
+    },
 }
 
 #[derive(Debug)]
@@ -55,9 +71,24 @@ pub enum WheelCreateError {
     TerminatorTagSerialize(bincode::Error),
 }
 
+#[derive(Debug)]
+pub enum WheelOpenError {
+    FileRead {
+        snapshot_filename: PathBuf,
+        error: io::Error,
+    },
+    HeaderRead(io::Error),
+    Load(fixed_file::WheelOpenError),
+    WheelSizeMismatch {
+        header: u64,
+        actual: u64,
+    },
+}
+
 #[derive(Debug)]
 pub enum TaskJoinError {
     Create(tokio::task::JoinError),
+    Open(tokio::task::JoinError),
 }
 
 pub struct WheelData<C> where C: Context {
@@ -65,13 +96,27 @@ pub struct WheelData<C> where C: Context {
     pub performer: performer::Performer<C>,
 }
 
+pub enum WheelOpenStatus<C> where C: Context {
+    Success(WheelData<C>),
+    FileNotFound {
+        performer_builder: performer::PerformerBuilderInit<C>,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub struct CreateParams {
     pub init_wheel_size_bytes: usize,
+    pub snapshot_filename: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpenParams<P> {
+    pub snapshot_filename: P,
 }
 
 pub struct SyncGenServer<C> where C: Context {
     memory: Vec<u8>,
+    snapshot_filename: Option<PathBuf>,
     request_tx: mpsc::Sender<Command<C>>,
     request_rx: mpsc::Receiver<Command<C>>,
     storage_layout: storage::Layout,
@@ -89,7 +134,9 @@ impl<C> SyncGenServer<C> where C: Context {
         let mut memory = Vec::with_capacity(params.init_wheel_size_bytes);
 
         let wheel_header = storage::WheelHeader {
+            wheel_id: storage::generate_wheel_id(),
             size_bytes: params.init_wheel_size_bytes as u64,
+            block_align: performer_builder.storage_layout().block_align,
             ..storage::WheelHeader::default()
         };
         storage::bincode_options()
@@ -124,12 +171,87 @@ impl<C> SyncGenServer<C> where C: Context {
         Ok(WheelData {
             sync_gen_server: SyncGenServer {
                 memory,
+                snapshot_filename: params.snapshot_filename,
                 request_tx,
                 request_rx,
                 storage_layout,
             },
             performer: performer_builder
-                .finish(params.init_wheel_size_bytes),
+                .finish(wheel_header.wheel_id, params.init_wheel_size_bytes),
+        })
+    }
+
+    // snapshot has the same format as a `fixed_file` wheel, so it is loaded with the same scan
+    pub fn open<P>(
+        params: OpenParams<P>,
+        performer_builder: performer::PerformerBuilderInit<C>,
+    )
+        -> Result<WheelOpenStatus<C>, WheelOpenError> where P: AsRef<Path>
+    {
+        log::debug!("loading ram file from snapshot [ {:?} ]", params.snapshot_filename.as_ref());
+
+        let memory = match fs::read(params.snapshot_filename.as_ref()) {
+            Ok(memory) =>
+                memory,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound =>
+                return Ok(WheelOpenStatus::FileNotFound { performer_builder, }),
+            Err(error) =>
+                return Err(WheelOpenError::FileRead {
+                    snapshot_filename: params.snapshot_filename.as_ref().to_owned(),
+                    error,
+                }),
+        };
+        let wheel_data = SyncGenServer::load(
+            memory,
+            Some(params.snapshot_filename.as_ref().to_owned()),
+            performer_builder,
+        )?;
+        Ok(WheelOpenStatus::Success(wheel_data))
+    }
+
+    // wheel image is untrusted here: any corruption is reported as an error
+    pub fn load(
+        memory: Vec<u8>,
+        snapshot_filename: Option<PathBuf>,
+        mut performer_builder: performer::PerformerBuilderInit<C>,
+    )
+        -> Result<WheelData<C>, WheelOpenError>
+    {
+        let mut cursor = io::Cursor::new(memory);
+
+        let wheel_header_size = performer_builder
+            .storage_layout()
+            .wheel_header_size;
+        performer_builder
+            .work_block_cleared()
+            .extend((0 .. wheel_header_size).map(|_| 0));
+        cursor.read_exact(performer_builder.work_block())
+            .map_err(WheelOpenError::HeaderRead)?;
+        let wheel_header = fixed_file::parse_wheel_header(performer_builder.work_block())
+            .map_err(WheelOpenError::Load)?;
+        if wheel_header.size_bytes != cursor.get_ref().len() as u64 {
+            return Err(WheelOpenError::WheelSizeMismatch {
+                header: wheel_header.size_bytes,
+                actual: cursor.get_ref().len() as u64,
+            });
+        }
+
+        let (storage_layout, performer) = fixed_file::scan_blocks(&mut cursor, performer_builder, &wheel_header)
+            .map_err(WheelOpenError::Load)?;
+
+        log::debug!("ram file loaded");
+
+        let (request_tx, request_rx) = mpsc::channel();
+
+        Ok(WheelData {
+            sync_gen_server: SyncGenServer {
+                memory: cursor.into_inner(),
+                snapshot_filename,
+                request_tx,
+                request_rx,
+                storage_layout,
+            },
+            performer,
         })
     }
 
@@ -139,6 +261,12 @@ impl<C> SyncGenServer<C> where C: Context {
         }
     }
 
+    // drive the wheel image directly without spawning the interpreter thread, snapshot is not written
+    pub fn into_memory(self, blocks_pool: BytesPool) -> Result<Memory, Error> {
+        let SyncGenServer { memory, storage_layout, .. } = self;
+        Memory::new(memory, storage_layout, blocks_pool)
+    }
+
     pub fn run<F, E>(
         self,
         blocks_pool: BytesPool,
@@ -154,13 +282,15 @@ impl<C> SyncGenServer<C> where C: Context {
           C::DeleteBlock: Send,
           C::IterBlocksStream: Send,
     {
+        let SyncGenServer { memory, snapshot_filename, request_rx, storage_layout, .. } = self;
         thread::Builder::new()
             .name("wheel::interpret::ram".to_string())
             .spawn(move || {
                 let result = busyloop(
-                    self.request_rx,
-                    self.memory,
-                    self.storage_layout,
+                    request_rx,
+                    memory,
+                    snapshot_filename,
+                    storage_layout,
                     blocks_pool,
                 );
                 if let Err(error) = result {
@@ -176,20 +306,14 @@ impl<C> SyncGenServer<C> where C: Context {
 fn busyloop<C>(
     request_rx: mpsc::Receiver<Command<C>>,
     memory: Vec<u8>,
+    snapshot_filename: Option<PathBuf>,
     storage_layout: storage::Layout,
     blocks_pool: BytesPool,
 )
     -> Result<(), Error>
 where C: Context,
 {
-    let mut stats = InterpretStats::default();
-
-    let mut terminator_block_bytes = blocks_pool.lend();
-    block_append_terminator(&mut terminator_block_bytes)
-        .map_err(Error::AppendTerminator)?;
-
-    let mut cursor = io::Cursor::new(memory);
-    cursor.set_position(storage_layout.wheel_header_size as u64);
+    let mut memory = Memory::new(memory, storage_layout, blocks_pool)?;
 
     loop {
         enum Event<C> { Command(C), }
@@ -207,121 +331,158 @@ where C: Context,
                 break,
 
             Event::Command(Some(Command::Request(Request { offset, task, reply_tx, }))) => {
-                stats.count_total += 1;
-
-                if cursor.position() != offset {
-                    if cursor.position() < offset {
-                        stats.count_seek_forward += 1;
-                    } else if cursor.position() > offset {
-                        stats.count_seek_backward += 1;
-                    }
-                    cursor.set_position(offset);
-                } else {
-                    stats.count_no_seek += 1;
-                }
-
-                match task.kind {
-                    task::TaskKind::WriteBlock(write_block) => {
-                        let start = cursor.position() as usize;
-                        let slice = cursor.get_mut();
-                        slice[start .. start + write_block.write_block_bytes.len()]
-                            .copy_from_slice(&write_block.write_block_bytes);
-                        let written = write_block.write_block_bytes.len();
-
-                        match write_block.commit {
-                            task::Commit::None =>
-                                (),
-                            task::Commit::WithTerminator => {
-                                slice[start + written .. start + written + terminator_block_bytes.len()]
-                                    .copy_from_slice(&terminator_block_bytes);
-                                // note: do not count terminator length in cursor in order to overwrite it during next write
-                            }
-                        }
-
-                        cursor.set_position(start as u64 + written as u64);
-
-                        let task_done = task::Done {
-                            current_offset: cursor.position(),
-                            task: task::TaskDone {
-                                block_id: task.block_id,
-                                kind: task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
-                                    context: write_block.context,
-                                }),
-                            },
-                        };
-                        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
-                            break;
-                        }
-                    },
-
-                    task::TaskKind::ReadBlock(task::ReadBlock { block_header, context, }) => {
-                        let total_chunk_size = storage_layout.data_size_block_min()
-                            + block_header.block_size;
-                        let mut block_bytes = blocks_pool.lend();
-                        block_bytes.resize(total_chunk_size, 0);
-                        let start = cursor.position() as usize;
-                        let slice = cursor.get_ref();
-                        block_bytes.copy_from_slice(&slice[start .. start + total_chunk_size]);
-                        cursor.set_position(start as u64 + block_bytes.len() as u64);
-
-                        let task_done = task::Done {
-                            current_offset: cursor.position(),
-                            task: task::TaskDone {
-                                block_id: block_header.block_id,
-                                kind: task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
-                                    block_bytes,
-                                    context,
-                                }),
-                            },
-                        };
-                        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
-                            break;
-                        }
-                    },
-
-                    task::TaskKind::DeleteBlock(delete_block) => {
-                        let start = cursor.position() as usize;
-                        let slice = cursor.get_mut();
-                        slice[start .. start + delete_block.delete_block_bytes.len()]
-                            .copy_from_slice(&delete_block.delete_block_bytes);
-                        let written = delete_block.delete_block_bytes.len();
-
-                        match delete_block.commit {
-                            task::Commit::None =>
-                                (),
-                            task::Commit::WithTerminator => {
-                                slice[start + written .. start + written + terminator_block_bytes.len()]
-                                    .copy_from_slice(&terminator_block_bytes);
-                                // note: do not count terminator length in cursor in order to overwrite it during next write
-                            }
-                        }
-
-                        cursor.set_position(start as u64 + written as u64);
-
-                        let task_done = task::Done {
-                            current_offset: cursor.position(),
-                            task: task::TaskDone {
-                                block_id: task.block_id,
-                                kind: task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
-                                    context: delete_block.context,
-                                }),
-                            },
-                        };
-                        if let Err(_send_error) = reply_tx.send(DoneTask { task_done, stats, }) {
-                            break;
-                        }
-                    },
+                let done_task = memory.interpret(offset, task);
+                if let Err(_send_error) = reply_tx.send(done_task) {
+                    break;
                 }
             },
 
-            Event::Command(Some(Command::DeviceSync { reply_tx, })) =>
+            Event::Command(Some(Command::DeviceSync { reply_tx, })) => {
+                if let Some(ref snapshot_filename) = snapshot_filename {
+                    snapshot(snapshot_filename, memory.image())?;
+                }
                 if let Err(_send_error) = reply_tx.send(Synced) {
                     break;
-                },
+                }
+            },
 
         }
     }
 
     log::debug!("master channel closed in interpret_loop, shutting down");
+    if let Some(ref snapshot_filename) = snapshot_filename {
+        snapshot(snapshot_filename, memory.image())?;
+    }
     Ok(())
 }
+
+// wheel image along with interpreter cursor, tasks are executed synchronously in the caller thread
+pub struct Memory {
+    cursor: io::Cursor<Vec<u8>>,
+    storage_layout: storage::Layout,
+    terminator_block_bytes: BytesMut,
+    blocks_pool: BytesPool,
+    stats: InterpretStats,
+}
+
+impl Memory {
+    pub fn new(memory: Vec<u8>, storage_layout: storage::Layout, blocks_pool: BytesPool) -> Result<Memory, Error> {
+        let mut terminator_block_bytes = blocks_pool.lend();
+        block_append_terminator(&mut terminator_block_bytes)
+            .map_err(Error::AppendTerminator)?;
+
+        let mut cursor = io::Cursor::new(memory);
+        cursor.set_position(storage_layout.wheel_header_size as u64);
+
+        Ok(Memory {
+            cursor,
+            storage_layout,
+            terminator_block_bytes,
+            blocks_pool,
+            stats: InterpretStats::default(),
+        })
+    }
+
+    pub fn image(&self) -> &[u8] {
+        self.cursor.get_ref()
+    }
+
+    pub fn interpret<C>(&mut self, offset: u64, task: task::Task<C>) -> DoneTask<C> where C: Context {
+        self.stats.count_total += 1;
+
+        if self.cursor.position() != offset {
+            if self.cursor.position() < offset {
+                self.stats.count_seek_forward += 1;
+            } else if self.cursor.position() > offset {
+                self.stats.count_seek_backward += 1;
+            }
+            self.cursor.set_position(offset);
+        } else {
+            self.stats.count_no_seek += 1;
+        }
+
+        let kind = match task.kind {
+            task::TaskKind::WriteBlock(write_block) => {
+                self.write_commit(&write_block.write_block_bytes, write_block.commit);
+                task::TaskDoneKind::WriteBlock(task::TaskDoneWriteBlock {
+                    context: write_block.context,
+                })
+            },
+
+            task::TaskKind::ReadBlock(task::ReadBlock { block_header, context, }) => {
+                let total_chunk_size = self.storage_layout.data_size_block_min()
+                    + block_header.block_size;
+                let mut block_bytes = self.blocks_pool.lend();
+                block_bytes.resize(total_chunk_size, 0);
+                let start = self.cursor.position() as usize;
+                let slice = self.cursor.get_ref();
+                block_bytes.copy_from_slice(&slice[start .. start + total_chunk_size]);
+                self.cursor.set_position(start as u64 + block_bytes.len() as u64);
+                task::TaskDoneKind::ReadBlock(task::TaskDoneReadBlock {
+                    block_bytes,
+                    context,
+                })
+            },
+
+            task::TaskKind::DeleteBlock(delete_block) => {
+                self.write_commit(&delete_block.delete_block_bytes, delete_block.commit);
+                task::TaskDoneKind::DeleteBlock(task::TaskDoneDeleteBlock {
+                    context: delete_block.context,
+                })
+            },
+        };
+
+        DoneTask {
+            task_done: task::Done {
+                current_offset: self.cursor.position(),
+                task: task::TaskDone {
+                    block_id: task.block_id,
+                    kind,
+                },
+            },
+            stats: self.stats.clone(),
+        }
+    }
+
+    fn write_commit(&mut self, bytes: &[u8], commit: task::Commit) {
+        let start = self.cursor.position() as usize;
+        let slice = self.cursor.get_mut();
+        slice[start .. start + bytes.len()]
+            .copy_from_slice(bytes);
+        let written = bytes.len();
+
+        match commit {
+            task::Commit::None =>
+                (),
+            task::Commit::WithTerminator => {
+                slice[start + written .. start + written + self.terminator_block_bytes.len()]
+                    .copy_from_slice(&self.terminator_block_bytes);
+                // note: do not count terminator length in cursor in order to overwrite it during next write
+            }
+        }
+
+        self.cursor.set_position(start as u64 + written as u64);
+    }
+}
+
+// memory is dumped aside and renamed over, so a crash during dump keeps the previous snapshot,
+// the whole image is written every time regardless of how much of it has changed
+fn snapshot(snapshot_filename: &Path, memory: &[u8]) -> Result<(), Error> {
+    log::debug!("dumping ram file of {} bytes to [ {:?} ]", memory.len(), snapshot_filename);
+
+    let mut tmp_filename = snapshot_filename.as_os_str().to_owned();
+    tmp_filename.push(".tmp");
+    let tmp_filename = PathBuf::from(tmp_filename);
+
+    let result = fs::File::create(&tmp_filename)
+        .and_then(|mut snapshot_file| {
+            snapshot_file.write_all(memory)?;
+            snapshot_file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp_filename, snapshot_filename))
+        .and_then(|()| sync_parent_dir(snapshot_filename));
+    result.map_err(|error| Error::SnapshotWrite {
+        snapshot_filename: snapshot_filename.to_owned(),
+        error,
+    })
+}